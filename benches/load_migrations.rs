@@ -0,0 +1,72 @@
+//! Times loading and checksumming migrations, and how long another task on
+//! the same runtime is held up meanwhile
+//!
+//! Run with `cargo bench --bench load_migrations`.
+
+use scylla_migrate::{EmbeddedMigrations, MigrationSource};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Loads measured per set of migrations
+const RUNS: u32 = 20;
+
+/// Interval of the task whose lateness shows how long the runtime is blocked
+const TICK: Duration = Duration::from_millis(1);
+
+/// Versioned migrations of 20 statements each
+fn migrations(count: usize) -> EmbeddedMigrations {
+    let files: Vec<(&'static str, &'static str)> = (1..=count)
+        .map(|version| {
+            let filename = format!("{:014}_create_table_{}.cql", version, version);
+            let cql = (0..20)
+                .map(|i| {
+                    format!(
+                        "CREATE TABLE IF NOT EXISTS app.table_{}_{} (id uuid PRIMARY KEY, value text);\n",
+                        version, i
+                    )
+                })
+                .collect::<String>();
+            (&*filename.leak(), &*cql.leak())
+        })
+        .collect();
+    EmbeddedMigrations::new(files.leak())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    for count in [10, 100, 1_000, 10_000] {
+        let source = migrations(count);
+        source.migrations().await.unwrap();
+
+        let done = Arc::new(AtomicBool::new(false));
+        let ticker = tokio::spawn({
+            let done = Arc::clone(&done);
+            async move {
+                let mut longest = Duration::ZERO;
+                while !done.load(Ordering::Relaxed) {
+                    let started = Instant::now();
+                    tokio::time::sleep(TICK).await;
+                    longest = longest.max(started.elapsed().saturating_sub(TICK));
+                }
+                longest
+            }
+        });
+
+        let started = Instant::now();
+        for _ in 0..RUNS {
+            source.migrations().await.unwrap();
+        }
+        let per_load = started.elapsed() / RUNS;
+        done.store(true, Ordering::Relaxed);
+        let stall = ticker.await.unwrap();
+
+        println!(
+            "{:>6} migrations: {:>10.3?} per load, {:>8.3?} per migration, other tasks late by {:.3?} at most",
+            count,
+            per_load,
+            per_load / count as u32,
+            stall
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Procedural macros for scylla-migrate
+//!
+//! Use them through the `scylla-migrate` crate with its `embed` feature enabled
+//! rather than depending on this crate directly.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use std::path::PathBuf;
+use syn::{parse_macro_input, LitStr};
+
+/// Embeds the .cql files of a migrations directory into the binary
+///
+/// The directory is resolved relative to the manifest of the crate invoking
+/// the macro and defaults to `migrations`.
+#[proc_macro]
+pub fn embed_migrations(input: TokenStream) -> TokenStream {
+    let dir = if input.is_empty() {
+        LitStr::new("migrations", proc_macro2::Span::call_site())
+    } else {
+        parse_macro_input!(input as LitStr)
+    };
+
+    match expand(&dir) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(dir: &LitStr) -> syn::Result<proc_macro2::TokenStream> {
+    let error = |msg: String| syn::Error::new(dir.span(), msg);
+
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| error("CARGO_MANIFEST_DIR is not set".to_string()))?;
+    let path = PathBuf::from(manifest_dir).join(dir.value());
+
+    let entries = std::fs::read_dir(&path)
+        .map_err(|err| error(format!("Could not read {}: {}", path.display(), err)))?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|err| error(err.to_string()))?;
+        let file = entry.path();
+        if !file.is_file() || file.extension().and_then(|ext| ext.to_str()) != Some("cql") {
+            continue;
+        }
+
+        let filename = entry.file_name().to_string_lossy().into_owned();
+        let file = file
+            .canonicalize()
+            .map_err(|err| error(format!("Could not read {}: {}", file.display(), err)))?;
+        files.push((filename, file.to_string_lossy().into_owned()));
+    }
+    files.sort();
+
+    let files = files.iter().map(|(filename, file)| {
+        // include_str! makes cargo rebuild whenever one of the files changes
+        quote! { (#filename, include_str!(#file)) }
+    });
+
+    Ok(quote! {
+        ::scylla_migrate::EmbeddedMigrations::new(&[#(#files),*])
+    })
+}
@@ -0,0 +1,104 @@
+use crate::error::{Error, Result};
+use crate::migration::{Migration, StatementOptions};
+use crate::server::Comparison;
+use crate::session::{Execute, Prepared};
+use futures::TryStreamExt;
+use std::fmt;
+use tracing::debug;
+
+/// A query checked after a migration is applied, from a comment like
+/// `-- assert: count >= 100: SELECT id FROM app.users_by_email`
+///
+/// The expectation is `exists`, `empty` or `count` compared to a number, and
+/// the query returns the rows it is about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assertion {
+    pub query: String,
+    pub comparison: Comparison,
+    pub rows: u64,
+}
+
+impl Assertion {
+    /// Parses what follows `assert:`, e.g. `exists: SELECT * FROM app.users WHERE id = 1`
+    pub(crate) fn parse(assertion: &str) -> Option<Self> {
+        let (expectation, query) = assertion.split_once(':')?;
+        let (comparison, rows) = match expectation.trim().to_ascii_lowercase().as_str() {
+            "exists" => (Comparison::GreaterOrEqual, 1),
+            "empty" => (Comparison::Equal, 0),
+            expectation => {
+                let count = expectation.strip_prefix("count")?;
+                let (comparison, rows) = Comparison::parse_prefix(count.trim())?;
+                (comparison, rows.trim().parse().ok()?)
+            }
+        };
+        let query = query.trim().trim_end_matches(';').trim_end();
+        if !query
+            .get(..6)
+            .is_some_and(|select| select.eq_ignore_ascii_case("SELECT"))
+        {
+            return None;
+        }
+        Some(Self {
+            query: query.to_string(),
+            comparison,
+            rows,
+        })
+    }
+
+    /// Runs the query with the statement options of `migration` and fails
+    /// with [`Error::AssertionFailed`] if its rows don't match
+    ///
+    /// Pages of rows are only read until the outcome is certain, one row past
+    /// the expected number.
+    pub(crate) async fn check(
+        &self,
+        session: &dyn Execute,
+        migration: &Migration,
+        options: &StatementOptions,
+    ) -> Result<()> {
+        let mut prepared = Prepared::new(self.query.as_str());
+        if let Some(consistency) = options.consistency {
+            prepared.set_consistency(consistency);
+        }
+        prepared.set_request_timeout(options.timeout);
+
+        let mut pages = options
+            .retry
+            .run(|| session.execute_paged(&prepared, &()))
+            .await?;
+        let limit = self.rows.saturating_add(1);
+        let mut found = 0;
+        while found < limit {
+            match pages.try_next().await? {
+                Some(page) => found += page.len() as u64,
+                None => break,
+            }
+        }
+        let found = found.min(limit);
+
+        if self.comparison.holds(found.cmp(&self.rows)) {
+            debug!("Assertion holds: {}", self);
+            return Ok(());
+        }
+        Err(Error::AssertionFailed {
+            migration: migration.to_string(),
+            assertion: self.clone(),
+            found: if found == limit {
+                format!("more than {} rows", self.rows)
+            } else {
+                format!("{} rows", found)
+            },
+        })
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.comparison, self.rows) {
+            (Comparison::GreaterOrEqual, 1) => write!(f, "exists")?,
+            (Comparison::Equal, 0) => write!(f, "empty")?,
+            (comparison, rows) => write!(f, "count {} {}", comparison, rows)?,
+        }
+        write!(f, ": {}", self.query)
+    }
+}
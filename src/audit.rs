@@ -0,0 +1,221 @@
+use crate::error::Result;
+use crate::history::HistoryTable;
+use crate::migration::Migration;
+use crate::session::Execute;
+use scylla::query::Query;
+use std::fmt;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Something a runner did, as recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    RunStarted,
+    RunFinished,
+    RunFailed,
+    LockAcquired,
+    LockReleased,
+    /// The lock of another runner was released with [`Migrator::force_unlock`](crate::Migrator::force_unlock)
+    LockForceReleased,
+    MigrationApplied,
+    MigrationFailed,
+    MigrationSkipped,
+    MigrationReverted,
+    /// A discrepancy was fixed by [`Migrator::repair`](crate::Migrator::repair)
+    Repaired,
+    Baselined,
+    Faked,
+    Unfaked,
+    /// A keyspace was emptied by [`Migrator::clean`](crate::Migrator::clean)
+    Cleaned,
+    /// Migrations were replaced in the history by
+    /// [`Migrator::squash_history`](crate::Migrator::squash_history)
+    Squashed,
+}
+
+/// Every action recorded in the audit log, oldest first
+///
+/// See [`MigratorBuilder::audit`](crate::MigratorBuilder::audit).
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    pub entries: Vec<AuditEntry>,
+}
+
+/// A runner action recorded in the audit log
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub at: OffsetDateTime,
+    pub action: AuditAction,
+    /// Version of the migration acted on, if any; always 0 for repeatable migrations
+    pub version: Option<i64>,
+    /// The migration acted on, if any
+    pub description: Option<String>,
+    /// Who ran the runner, the cluster user or else the OS user
+    pub actor: Option<String>,
+    /// Host of the runner
+    pub host: Option<String>,
+    /// What else there is to know, e.g. why a migration failed or was skipped
+    pub detail: Option<String>,
+}
+
+impl AuditAction {
+    /// Returns the name the action is stored as, e.g. `migration_applied`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::RunStarted => "run_started",
+            AuditAction::RunFinished => "run_finished",
+            AuditAction::RunFailed => "run_failed",
+            AuditAction::LockAcquired => "lock_acquired",
+            AuditAction::LockReleased => "lock_released",
+            AuditAction::LockForceReleased => "lock_force_released",
+            AuditAction::MigrationApplied => "migration_applied",
+            AuditAction::MigrationFailed => "migration_failed",
+            AuditAction::MigrationSkipped => "migration_skipped",
+            AuditAction::MigrationReverted => "migration_reverted",
+            AuditAction::Repaired => "repaired",
+            AuditAction::Baselined => "baselined",
+            AuditAction::Faked => "faked",
+            AuditAction::Unfaked => "unfaked",
+            AuditAction::Cleaned => "cleaned",
+            AuditAction::Squashed => "squashed",
+        }
+    }
+
+    /// Parses the name an action is stored as
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "run_started" => AuditAction::RunStarted,
+            "run_finished" => AuditAction::RunFinished,
+            "run_failed" => AuditAction::RunFailed,
+            "lock_acquired" => AuditAction::LockAcquired,
+            "lock_released" => AuditAction::LockReleased,
+            "lock_force_released" => AuditAction::LockForceReleased,
+            "migration_applied" => AuditAction::MigrationApplied,
+            "migration_failed" => AuditAction::MigrationFailed,
+            "migration_skipped" => AuditAction::MigrationSkipped,
+            "migration_reverted" => AuditAction::MigrationReverted,
+            "repaired" => AuditAction::Repaired,
+            "baselined" => AuditAction::Baselined,
+            "faked" => AuditAction::Faked,
+            "unfaked" => AuditAction::Unfaked,
+            "cleaned" => AuditAction::Cleaned,
+            "squashed" => AuditAction::Squashed,
+            _ => return None,
+        })
+    }
+}
+
+impl AuditEntry {
+    /// Returns an entry for `action` taking place now, about no migration
+    pub(crate) fn new(action: AuditAction) -> Self {
+        Self {
+            at: OffsetDateTime::now_utc(),
+            action,
+            version: None,
+            description: None,
+            actor: None,
+            host: None,
+            detail: None,
+        }
+    }
+
+    /// Sets the migration acted on
+    pub(crate) fn migration(self, migration: &Migration) -> Self {
+        self.version(migration.version, &migration.description)
+    }
+
+    /// Sets the migration acted on by its version and description
+    pub(crate) fn version(mut self, version: i64, description: &str) -> Self {
+        self.version = Some(version);
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub(crate) fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// Returns the audit table of the history keyspace
+///
+/// Like the lock table, it is shared by the history tables of a keyspace and
+/// keyed by their name.
+pub(crate) fn table(history: &HistoryTable) -> String {
+    format!("{}.migration_audit", history.keyspace)
+}
+
+pub(crate) async fn create_table(session: &dyn Execute, history: &HistoryTable) -> Result<()> {
+    session
+        .query(
+            Query::new(format!(
+                r#"CREATE TABLE IF NOT EXISTS {} (
+                    name text,
+                    at timestamp,
+                    id uuid,
+                    action text,
+                    version bigint,
+                    description text,
+                    actor text,
+                    host text,
+                    detail text,
+                    PRIMARY KEY (name, at, id)
+                )"#,
+                table(history)
+            )),
+            &[],
+        )
+        .await?;
+    session.await_schema_agreement().await?;
+    Ok(())
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.entries.is_empty() {
+            return writeln!(f, "No actions recorded");
+        }
+
+        writeln!(
+            f,
+            "{:<25} {:<20} {:<16} {:<16} DETAIL",
+            "AT", "ACTION", "VERSION", "ACTOR"
+        )?;
+
+        for entry in &self.entries {
+            let at = entry
+                .at
+                .format(&Rfc3339)
+                .unwrap_or_else(|_| "-".to_string());
+            let version = entry
+                .version
+                .map(|version| version.to_string())
+                .unwrap_or_else(|| "-".to_string());
+            let mut detail = entry.description.clone().unwrap_or_default();
+            if let Some(extra) = &entry.detail {
+                if !detail.is_empty() {
+                    detail.push_str(": ");
+                }
+                detail.push_str(extra);
+            }
+
+            writeln!(
+                f,
+                "{:<25} {:<20} {:<16} {:<16} {}",
+                at,
+                entry.action,
+                version,
+                entry.actor.as_deref().unwrap_or("-"),
+                detail
+            )?;
+        }
+
+        Ok(())
+    }
+}
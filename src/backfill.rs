@@ -0,0 +1,278 @@
+use crate::error::{BoxError, Error, Result};
+use crate::retry::RetryPolicy;
+use crate::schema::ident;
+use futures::{stream, StreamExt, TryStreamExt};
+use scylla::deserialize::DeserializeRow;
+use scylla::prepared_statement::PreparedStatement;
+use scylla::serialize::row::SerializeRow;
+use scylla::statement::{Consistency, PagingState};
+use scylla::Session;
+use std::ops::ControlFlow;
+use tracing::{debug, info};
+
+/// Scans a table by token ranges, writing what a transform makes of every row
+///
+/// This is the building block of backfills too large or too involved for a
+/// CQL script, to be run from a [`CodeMigration`](crate::CodeMigration). The
+/// token ring is split into ranges scanned a few at a time, page by page.
+/// Each row is deserialized like a query result, e.g. into a tuple of its
+/// columns, and passed to the transform; the values it returns are bound to
+/// the prepared write statement. Reads and writes failing with transient
+/// errors are retried with backoff.
+///
+/// Writes should be idempotent, e.g. an `UPDATE` or `INSERT` of computed
+/// values: a retried write, or a backfill run again after it failed halfway,
+/// writes a row more than once.
+///
+/// # Example
+/// ```no_run
+/// use async_trait::async_trait;
+/// use scylla::Session;
+/// use scylla_migrate::{Backfill, BoxError, CodeMigration};
+///
+/// struct LowercaseEmails;
+///
+/// #[async_trait]
+/// impl CodeMigration for LowercaseEmails {
+///     async fn up(&self, session: &Session) -> Result<(), BoxError> {
+///         Backfill::new("app", "users", "UPDATE app.users SET email = ? WHERE id = ?")
+///             .columns(&["id", "email"])
+///             .concurrency(8)
+///             .run(session, |(id, email): (uuid::Uuid, Option<String>)| {
+///                 Ok(email.map(|email| (email.to_lowercase(), id)))
+///             })
+///             .await?;
+///         Ok(())
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Backfill {
+    keyspace: String,
+    table: String,
+    columns: Vec<String>,
+    write: String,
+    ranges: usize,
+    concurrency: usize,
+    page_size: i32,
+    consistency: Option<Consistency>,
+    retry: RetryPolicy,
+}
+
+/// The outcome of [`Backfill::run`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackfillReport {
+    /// Rows read
+    pub rows: u64,
+    /// Rows the transform returned values for, and which were written
+    pub written: u64,
+    /// Token ranges scanned
+    pub ranges: usize,
+}
+
+impl Backfill {
+    /// Scans `keyspace.table`, writing with the CQL statement `write`
+    ///
+    /// By default every column is read, 256 token ranges are scanned 4 at a
+    /// time in pages of 1000 rows, and queries are attempted up to 5 times.
+    pub fn new(keyspace: &str, table: &str, write: impl Into<String>) -> Self {
+        Self {
+            keyspace: keyspace.to_string(),
+            table: table.to_string(),
+            columns: Vec::new(),
+            write: write.into(),
+            ranges: 256,
+            concurrency: 4,
+            page_size: 1000,
+            consistency: None,
+            retry: RetryPolicy::new(5),
+        }
+    }
+
+    /// Only reads `columns`, in this order
+    pub fn columns(mut self, columns: &[&str]) -> Self {
+        self.columns = columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// Splits the token ring into `ranges` ranges of equal size
+    pub fn ranges(mut self, ranges: usize) -> Self {
+        self.ranges = ranges.max(1);
+        self
+    }
+
+    /// Scans up to `concurrency` ranges at once
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Reads `page_size` rows per page
+    pub fn page_size(mut self, page_size: i32) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Sets the consistency level of the reads and writes
+    pub fn consistency(mut self, consistency: Consistency) -> Self {
+        self.consistency = Some(consistency);
+        self
+    }
+
+    /// Sets how reads and writes failing with transient errors are retried
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Scans the table, writing the values `transform` returns for each row
+    ///
+    /// Rows the transform returns `None` for are left alone. The first error
+    /// of the transform or of a query stops the backfill with
+    /// [`Error::Backfill`]; ranges already scanned stay written.
+    pub async fn run<R, F, V>(&self, session: &Session, transform: F) -> Result<BackfillReport>
+    where
+        R: for<'frame, 'metadata> DeserializeRow<'frame, 'metadata>,
+        F: Fn(R) -> std::result::Result<Option<V>, BoxError> + Sync,
+        V: SerializeRow + Send,
+    {
+        let table = format!("{}.{}", self.keyspace, self.table);
+        let partition_key = partition_key(session, &self.keyspace, &self.table).await?;
+        let columns = if self.columns.is_empty() {
+            "*".to_string()
+        } else {
+            self.columns
+                .iter()
+                .map(|column| ident(column))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let token = format!("token({})", partition_key);
+        let mut read = session
+            .prepare(format!(
+                "SELECT {} FROM {}.{} WHERE {} >= ? AND {} <= ?",
+                columns,
+                ident(&self.keyspace),
+                ident(&self.table),
+                token,
+                token
+            ))
+            .await?;
+        read.set_page_size(self.page_size);
+        let mut write = session.prepare(self.write.as_str()).await?;
+        if let Some(consistency) = self.consistency {
+            read.set_consistency(consistency);
+            write.set_consistency(consistency);
+        }
+
+        let ranges = token_ranges(self.ranges);
+        info!(
+            "Backfilling {} in {} token ranges, {} at a time",
+            table,
+            ranges.len(),
+            self.concurrency
+        );
+        let report = stream::iter(ranges)
+            .map(|range| self.scan(session, &read, &write, range, &transform))
+            .buffer_unordered(self.concurrency)
+            .map_err(|source| Error::Backfill {
+                table: table.clone(),
+                source,
+            })
+            .try_fold(BackfillReport::default(), |mut report, (rows, written)| {
+                report.rows += rows;
+                report.written += written;
+                report.ranges += 1;
+                debug!(
+                    "Backfilled {} of {} token ranges of {}",
+                    report.ranges, self.ranges, table
+                );
+                futures::future::ready(Ok(report))
+            })
+            .await?;
+        info!(
+            "Backfilled {}: {} rows read, {} written",
+            table, report.rows, report.written
+        );
+        Ok(report)
+    }
+
+    /// Scans the tokens `range`, returning the rows read and written
+    async fn scan<R, F, V>(
+        &self,
+        session: &Session,
+        read: &PreparedStatement,
+        write: &PreparedStatement,
+        (start, end): (i64, i64),
+        transform: &F,
+    ) -> std::result::Result<(u64, u64), BoxError>
+    where
+        R: for<'frame, 'metadata> DeserializeRow<'frame, 'metadata>,
+        F: Fn(R) -> std::result::Result<Option<V>, BoxError>,
+        V: SerializeRow,
+    {
+        let (mut rows, mut written) = (0, 0);
+        let mut paging_state = PagingState::start();
+        loop {
+            let (page, paging) = self
+                .retry
+                .run(|| session.execute_single_page(read, (start, end), paging_state.clone()))
+                .await?;
+            for row in page.into_rows_result()?.rows::<R>()? {
+                rows += 1;
+                if let Some(values) = transform(row?)? {
+                    self.retry
+                        .run(|| session.execute_unpaged(write, &values))
+                        .await?;
+                    written += 1;
+                }
+            }
+            match paging.into_paging_control_flow() {
+                ControlFlow::Continue(next) => paging_state = next,
+                ControlFlow::Break(()) => return Ok((rows, written)),
+            }
+        }
+    }
+}
+
+/// Returns the partition key columns of `keyspace.table`, as the argument of `token()`
+async fn partition_key(session: &Session, keyspace: &str, table: &str) -> Result<String> {
+    let query_rows = session
+        .query_unpaged(
+            "SELECT column_name, kind, position FROM system_schema.columns WHERE keyspace_name = ? AND table_name = ?",
+            (keyspace, table),
+        )
+        .await?
+        .into_rows_result()?;
+    let mut columns = Vec::new();
+    for row in query_rows.rows()? {
+        let (name, kind, position): (String, String, i32) = row?;
+        if kind == "partition_key" {
+            columns.push((position, name));
+        }
+    }
+    if columns.is_empty() {
+        return Err(Error::UnknownTable(format!("{}.{}", keyspace, table)));
+    }
+    columns.sort();
+    Ok(columns
+        .iter()
+        .map(|(_, name)| ident(name))
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+/// Splits the Murmur3 token ring into `count` ranges of equal size, each
+/// inclusive of both ends
+fn token_ranges(count: usize) -> Vec<(i64, i64)> {
+    let count = count.max(1) as i128;
+    let min = i64::MIN as i128;
+    let size = i64::MAX as i128 - min + 1;
+    (0..count)
+        .map(|i| {
+            let start = min + size * i / count;
+            let end = min + size * (i + 1) / count - 1;
+            (start as i64, end as i64)
+        })
+        .collect()
+}
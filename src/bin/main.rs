@@ -1,106 +1,2995 @@
-use anyhow::{Context, Result};
-use clap::Parser;
-use scylla::SessionBuilder;
-use scylla_migrate::Migrator;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use clap::builder::PossibleValuesParser;
+use clap::{ArgAction, Args as ClapArgs, CommandFactory, Parser, ValueEnum};
+use scylla::statement::Consistency;
+use scylla::Session;
+#[cfg(feature = "ssl")]
+use scylla_migrate::TlsOptions;
+use scylla_migrate::{
+    AuditLog, BoxError, BuiltinRule, ChecksumPolicy, ConnectOptions, Destructive,
+    DestructivePolicy, Difference, Discrepancy, History, HistoryFormat, Linter, Migration,
+    MigrationEvent, MigrationHook, MigrationKind, MigrationReport, MigrationState, MigrationStatus,
+    Migrator, MigratorBuilder, MissingPolicy, OutOfOrderPolicy, Plan, Replication,
+    RequirementPolicy, RetryPolicy, RunHook, RunOptions, Schema, SchemaAgreement, SchemaDifference,
+    SchemaObject, SkippedMigration, Status, Tenants, Validation,
+};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
 use std::fs;
+use std::future;
+use std::io::{self, BufRead, IsTerminal, Write};
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
+use tracing_subscriber::EnvFilter;
 
 // cargo invokes this binary as `scylla-migrate <args>`
 #[derive(Debug, Parser)]
 #[command(bin_name = "scylla-migrate")]
 #[command(version, about, long_about = None)]
+// Options of the configuration file come first and are overridden by the command line
+#[command(args_override_self = true)]
 enum Args {
     /// Add a new migration
     Add {
         /// Name of the migration
         name: String,
         /// Directory to store migrations (optional)
-        #[arg(short, long)]
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        /// Scaffold the migration from this template of --template-dir, or a
+        /// built-in one: create-table, create-type or create-materialized-view
+        #[arg(short, long, env = "SCYLLA_MIGRATE_TEMPLATE")]
+        template: Option<String>,
+        /// Directory of templates [default: templates in the migrations directory]
+        #[arg(long, env = "SCYLLA_MIGRATE_TEMPLATE_DIR")]
+        template_dir: Option<PathBuf>,
+        #[command(flatten)]
+        naming: NamingArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Check migration files for common mistakes, failing if any are found
+    Lint {
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
         path: Option<PathBuf>,
+        /// Skip these rules
+        #[arg(long, value_delimiter = ',', value_parser = lint_rule_names(), env = "SCYLLA_MIGRATE_ALLOW")]
+        allow: Vec<String>,
+        #[command(flatten)]
+        config: ConfigArgs,
     },
     /// Run pending migrations
     Run {
         /// Directory containing migrations
-        #[arg(short, long)]
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
         path: Option<PathBuf>,
-        /// ScyllaDB connection string
-        #[arg(short, long)]
-        uri: String,
-        /// ScyllaDB username (optional)
+        /// Only apply migrations up to and including this version
+        #[arg(long, env = "SCYLLA_MIGRATE_TO_VERSION")]
+        to_version: Option<i64>,
+        /// Only apply the next N pending migrations
+        #[arg(long, env = "SCYLLA_MIGRATE_STEPS")]
+        steps: Option<usize>,
+        /// Pause between the statements of a migration, e.g. "200ms", unless
+        /// its delay directive says otherwise
+        #[arg(long, value_parser = parse_duration, env = "SCYLLA_MIGRATE_STATEMENT_DELAY")]
+        statement_delay: Option<Duration>,
+        /// Pause between migrations, e.g. "5s"
+        #[arg(long, value_parser = parse_duration, env = "SCYLLA_MIGRATE_MIGRATION_DELAY")]
+        migration_delay: Option<Duration>,
+        /// Print the pending migrations and their statements without applying them
+        #[arg(long, env = "SCYLLA_MIGRATE_DRY_RUN")]
+        dry_run: bool,
+        /// Apply DROP, TRUNCATE and ALTER TABLE ... DROP statements without asking for confirmation
+        #[arg(short, long, env = "SCYLLA_MIGRATE_YES")]
+        yes: bool,
+        /// Lint the pending migrations before applying them, failing on any finding
+        #[arg(long, env = "SCYLLA_MIGRATE_LINT")]
+        lint: bool,
+        /// Refuse to apply anything while pending migrations contain statements
+        /// of these kinds, even with --yes
+        #[arg(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            env = "SCYLLA_MIGRATE_DENY_DESTRUCTIVE"
+        )]
+        deny_destructive: Vec<DestructiveKind>,
+        /// Print a warning for pending statements of these kinds
+        #[arg(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            env = "SCYLLA_MIGRATE_WARN_DESTRUCTIVE"
+        )]
+        warn_destructive: Vec<DestructiveKind>,
+        /// What to do with applied migrations whose file has changed
+        #[arg(long, value_enum, default_value_t = OnChecksumMismatch::Error, env = "SCYLLA_MIGRATE_ON_CHECKSUM_MISMATCH")]
+        on_checksum_mismatch: OnChecksumMismatch,
+        /// What to do with pending migrations older than the newest applied one;
+        /// `--out-of-order` alone applies them
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = OutOfOrder::Error,
+            num_args = 0..=1,
+            default_missing_value = "apply",
+            env = "SCYLLA_MIGRATE_OUT_OF_ORDER"
+        )]
+        out_of_order: OutOfOrder,
+        /// What to do with applied migrations that are not present locally
+        #[arg(long, value_enum, default_value_t = OnMissingMigration::Error, env = "SCYLLA_MIGRATE_ON_MISSING_MIGRATION")]
+        on_missing_migration: OnMissingMigration,
+        /// What to do with pending migrations whose `requires` directive the cluster doesn't meet
+        #[arg(long, value_enum, default_value_t = OnUnmetRequirement::Error, env = "SCYLLA_MIGRATE_ON_UNMET_REQUIREMENT")]
+        on_unmet_requirement: OnUnmetRequirement,
+        /// Apply the migrations once per tenant keyspace, e.g. "customer_1,customer_2";
+        /// ${tenant} in migrations is replaced by the keyspace name
+        #[arg(
+            long,
+            value_delimiter = ',',
+            conflicts_with = "tenant_pattern",
+            env = "SCYLLA_MIGRATE_TENANTS"
+        )]
+        tenants: Vec<String>,
+        /// Apply the migrations once per keyspace matching a pattern, e.g. "customer_*"
+        #[arg(long, env = "SCYLLA_MIGRATE_TENANT_PATTERN")]
+        tenant_pattern: Option<String>,
+        /// How many tenant keyspaces to migrate at once
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..), env = "SCYLLA_MIGRATE_TENANT_CONCURRENCY")]
+        tenant_concurrency: u16,
+        #[command(flatten)]
+        hooks: ShellHooks,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Apply pending migrations whenever the migrations directory changes, for local development
+    Watch {
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        /// How long the directory has to stay unchanged before migrations are applied
+        #[arg(long, value_parser = parse_duration, default_value = "500ms", env = "SCYLLA_MIGRATE_DEBOUNCE")]
+        debounce: Duration,
+        /// What to do with applied migrations whose file has changed
+        #[arg(long, value_enum, default_value_t = OnChecksumMismatch::Error, env = "SCYLLA_MIGRATE_ON_CHECKSUM_MISMATCH")]
+        on_checksum_mismatch: OnChecksumMismatch,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Revert the most recently applied migrations
+    Revert {
+        /// Number of migrations to revert
+        #[arg(short = 'n', long, default_value_t = 1)]
+        count: usize,
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Record migrations up to a version as applied without executing them
+    Baseline {
+        /// Last version already present in the schema
+        #[arg(long)]
+        version: i64,
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Record the migrations another tool applied as applied, reading its history table
+    Import {
+        /// Tool whose history to import
+        #[arg(long, value_enum, env = "SCYLLA_MIGRATE_FORMAT")]
+        format: ImportFormat,
+        /// History table of the tool, qualified or in the keyspace of
+        /// --history-table; the default table of the tool if not given
+        #[arg(long, env = "SCYLLA_MIGRATE_TABLE")]
+        table: Option<String>,
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Load seed data from a CSV file into a table, outside of the migration history
+    ///
+    /// The first line names the columns. Values are converted to the column
+    /// types, empty ones are inserted as null; rows already present are
+    /// overwritten.
+    Seed {
+        /// CSV file to load
+        #[arg(long, value_name = "FILE", env = "SCYLLA_MIGRATE_FILE")]
+        file: PathBuf,
+        /// Table to insert the rows into, as keyspace.table
+        #[arg(long, env = "SCYLLA_MIGRATE_TABLE")]
+        table: String,
+        /// Number of rows inserted per batch
+        #[arg(long, default_value_t = 100, env = "SCYLLA_MIGRATE_BATCH_SIZE")]
+        batch_size: usize,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Merge the migrations up to a version into a single migration of that version
+    ///
+    /// Rewrites the migrations directory, then the history of the cluster, which
+    /// has to have applied them. Once the squashed files are deployed, run it
+    /// with --history-only against every other database that applied them.
+    Squash {
+        /// Last version to squash, taken by the merged migration
+        #[arg(long)]
+        up_to: i64,
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        /// Only rewrite the history, for migrations squashed before
+        #[arg(long)]
+        history_only: bool,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Record a migration as applied without executing it
+    Fake {
+        /// Version of the migration
+        version: i64,
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Remove a migration from the history without reverting it
+    Unfake {
+        /// Version of the migration
+        version: i64,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// List the applied migrations and failed attempts recorded in the history
+    History {
+        /// Only show the most recent entries
+        #[arg(long, env = "SCYLLA_MIGRATE_LIMIT")]
+        limit: Option<usize>,
+        /// Only show entries recorded at or after this date (YYYY-MM-DD, UTC) or RFC 3339 time
+        #[arg(long, value_parser = parse_since, env = "SCYLLA_MIGRATE_SINCE")]
+        since: Option<OffsetDateTime>,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// List the runner actions recorded in the audit log
+    Audit {
+        /// Only show the most recent entries
+        #[arg(long, env = "SCYLLA_MIGRATE_LIMIT")]
+        limit: Option<usize>,
+        /// Only show entries recorded at or after this date (YYYY-MM-DD, UTC) or RFC 3339 time
+        #[arg(long, value_parser = parse_since, env = "SCYLLA_MIGRATE_SINCE")]
+        since: Option<OffsetDateTime>,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Print the CQL an applied migration executed, as recorded in the history
+    Show {
+        /// Version of the migration
+        version: i64,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Show applied and pending migrations
+    Status {
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Check applied migrations against the local files, failing on any discrepancy
+    Validate {
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        /// What to do with applied migrations that are not present locally
+        #[arg(long, value_enum, default_value_t = OnMissingMigration::Error, env = "SCYLLA_MIGRATE_ON_MISSING_MIGRATION")]
+        on_missing_migration: OnMissingMigration,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Check whether the database is up to date without applying anything
+    ///
+    /// Exits with 0 when it is, 1 when migrations are pending and 2 when applied
+    /// migrations don't match the local files or the check itself fails.
+    Check {
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        /// What to do with applied migrations that are not present locally
+        #[arg(long, value_enum, default_value_t = OnMissingMigration::Error, env = "SCYLLA_MIGRATE_ON_MISSING_MIGRATION")]
+        on_missing_migration: OnMissingMigration,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Print the keyspaces, types, tables and views of the cluster as a CQL script
+    DumpSchema {
+        /// Keyspaces to dump; all but the system keyspaces if not given
+        #[arg(
+            short,
+            long = "keyspace",
+            value_delimiter = ',',
+            env = "SCYLLA_MIGRATE_KEYSPACE"
+        )]
+        keyspaces: Vec<String>,
+        /// Write the script to this file instead of stdout
+        #[arg(short, long = "output-file", env = "SCYLLA_MIGRATE_OUTPUT_FILE")]
+        output_file: Option<PathBuf>,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Compare the live schema with the applied migrations, failing on changes
+    /// made outside of them
+    Drift {
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Compare the schemas of two clusters, e.g. staging and prod, failing on differences
+    ///
+    /// The other cluster is reached with the same options as the first, except
+    /// for its connection string and credentials.
+    Diff {
+        /// Connection string of the cluster to compare the one of --uri with
+        #[arg(long, env = "SCYLLA_MIGRATE_OTHER_URI")]
+        other_uri: String,
+        /// Username on the other cluster; --user if not given
+        #[arg(long, env = "SCYLLA_MIGRATE_OTHER_USER")]
+        other_user: Option<String>,
+        /// Password on the other cluster; asked for on the terminal when
+        /// --other-user is given without it
+        #[arg(long, env = "SCYLLA_MIGRATE_OTHER_PASSWORD", hide_env_values = true)]
+        other_password: Option<String>,
+        /// Keyspaces to compare; all but the system keyspaces if not given
+        #[arg(
+            short,
+            long = "keyspace",
+            value_delimiter = ',',
+            env = "SCYLLA_MIGRATE_KEYSPACE"
+        )]
+        keyspaces: Vec<String>,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Generate a migration turning the live schema into a target one
+    ///
+    /// The target is a CQL script, e.g. written by dump-schema, or another
+    /// cluster reached with the same options as the first except for its
+    /// connection string. Review the migration before applying it: changes
+    /// CQL can't make in place are left as comments.
+    MakeMigration {
+        /// Start from the live schema of --uri, currently the only source
+        #[arg(long)]
+        from_live: bool,
+        /// CQL script with the schema to converge to
+        #[arg(
+            long,
+            value_name = "FILE",
+            required_unless_present = "to_uri",
+            conflicts_with = "to_uri",
+            env = "SCYLLA_MIGRATE_TO"
+        )]
+        to: Option<PathBuf>,
+        /// Connection string of a cluster whose schema to converge to
+        #[arg(long, env = "SCYLLA_MIGRATE_TO_URI")]
+        to_uri: Option<String>,
+        /// Keyspaces to converge; all of the target schema if not given
+        #[arg(
+            short,
+            long = "keyspace",
+            value_delimiter = ',',
+            env = "SCYLLA_MIGRATE_KEYSPACE"
+        )]
+        keyspaces: Vec<String>,
+        /// Name of the migration
+        #[arg(long, default_value = "converge_schema", env = "SCYLLA_MIGRATE_NAME")]
+        name: String,
+        /// Directory to store migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        naming: NamingArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Update the history to match modified and deleted local migrations
+    Repair {
+        /// Repair without asking for confirmation
         #[arg(short, long)]
-        user: Option<String>,
-        /// ScyllaDB password (optional)
+        yes: bool,
+        /// Directory containing migrations
+        #[arg(short, long, env = "SCYLLA_MIGRATE_PATH")]
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Drop every table, view, function and type of a keyspace and remove the
+    /// migrations that changed it from the history, to migrate a development
+    /// database from scratch
+    ///
+    /// Refuses on clusters, keyspaces and --env values matching *prod* or a
+    /// --protect pattern. The keyspace itself and the audit log are kept.
+    Clean {
+        /// Keyspace to empty
+        #[arg(short, long, env = "SCYLLA_MIGRATE_KEYSPACE")]
+        keyspace: String,
+        /// Confirm that everything in the keyspace is to be dropped
+        #[arg(long)]
+        force: bool,
+        /// Patterns of cluster names, keyspaces and environments never cleaned,
+        /// ignoring case, besides *prod*
+        #[arg(long, value_delimiter = ',', env = "SCYLLA_MIGRATE_PROTECT")]
+        protect: Vec<String>,
+        /// Allow cleaning names matching *prod*, protected by default
+        #[arg(long)]
+        no_default_protection: bool,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+    /// Inspect and force-release a stuck migration lock
+    Unlock {
+        /// Release the lock without asking for confirmation
         #[arg(short, long)]
-        password: Option<String>,
+        yes: bool,
+        #[command(flatten)]
+        connect: ConnectArgs,
     },
 }
 
+/// Shell commands `run` executes around the migrations, usually set in the
+/// configuration file
+///
+/// Commands run with `sh -c`, or `cmd /C` on Windows, and their output goes to
+/// stderr. They get SCYLLA_MIGRATE_HOOK set to their option name, and
+/// SCYLLA_MIGRATE_MIGRATION_VERSION, _DESCRIPTION and _KIND for the migration
+/// concerned.
+#[derive(Debug, ClapArgs)]
+struct ShellHooks {
+    /// Shell command run once before the pending migrations are applied, with
+    /// SCYLLA_MIGRATE_PENDING set to their number; the run is aborted if it fails
+    #[arg(long, value_name = "COMMAND", env = "SCYLLA_MIGRATE_BEFORE_RUN")]
+    before_run: Option<String>,
+    /// Shell command run after every applied migration; the run stops if it fails
+    #[arg(long, value_name = "COMMAND", env = "SCYLLA_MIGRATE_AFTER_MIGRATION")]
+    after_migration: Option<String>,
+    /// Shell command run when a migration fails, with SCYLLA_MIGRATE_ERROR set
+    /// to the error
+    #[arg(long, value_name = "COMMAND", env = "SCYLLA_MIGRATE_ON_FAILURE")]
+    on_failure: Option<String>,
+}
+
+/// How results are printed
+#[derive(Debug, ClapArgs)]
+struct OutputArgs {
+    /// Output format; json prints a single JSON document on stdout and logs on stderr
+    #[arg(long, value_enum, default_value_t = Output::Text, env = "SCYLLA_MIGRATE_OUTPUT")]
+    output: Output,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Output {
+    /// Human readable text
+    Text,
+    /// Machine readable JSON
+    Json,
+}
+
+impl Args {
+    fn output(&self) -> Output {
+        match self {
+            Args::Run { output, .. }
+            | Args::Baseline { output, .. }
+            | Args::Import { output, .. }
+            | Args::Fake { output, .. }
+            | Args::Unfake { output, .. }
+            | Args::Clean { output, .. }
+            | Args::History { output, .. }
+            | Args::Audit { output, .. }
+            | Args::Status { output, .. }
+            | Args::Validate { output, .. }
+            | Args::Check { output, .. }
+            | Args::Drift { output, .. }
+            | Args::Diff { output, .. } => output.output,
+            Args::Add { .. }
+            | Args::Lint { .. }
+            | Args::Watch { .. }
+            | Args::Revert { .. }
+            | Args::Seed { .. }
+            | Args::Squash { .. }
+            | Args::DumpSchema { .. }
+            | Args::MakeMigration { .. }
+            | Args::Repair { .. }
+            | Args::Show { .. }
+            | Args::Unlock { .. } => Output::Text,
+        }
+    }
+}
+
+/// Error of a command that already printed its failure as JSON
+#[derive(Debug)]
+struct Reported;
+
+impl fmt::Display for Reported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Command failed")
+    }
+}
+
+impl std::error::Error for Reported {}
+
+/// Returns [`Reported`], keeping whether the run was cancelled for the exit status
+fn reported(cancelled: bool) -> anyhow::Error {
+    if cancelled {
+        anyhow::Error::new(scylla_migrate::Error::Cancelled).context(Reported)
+    } else {
+        Reported.into()
+    }
+}
+
+/// Files read by `with_config` and `load_env_file` before the arguments are parsed
+#[derive(Debug, ClapArgs)]
+#[allow(dead_code)]
+struct ConfigArgs {
+    /// Configuration file providing defaults for these options
+    /// [default: scylla-migrate.toml if present]
+    #[arg(long, value_name = "FILE", env = "SCYLLA_MIGRATE_CONFIG")]
+    config: Option<PathBuf>,
+    /// Load environment variables from a .env file, or the given one, before
+    /// anything else; variables already set are kept
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = DOTENV_FILE)]
+    env_file: Option<PathBuf>,
+}
+
+/// Options shared by every subcommand talking to the cluster
+#[derive(Debug, ClapArgs)]
+struct ConnectArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// ScyllaDB connection string
+    #[arg(env = "SCYLLA_MIGRATE_URI")]
+    #[cfg_attr(not(feature = "cloud"), arg(short, long, required = true))]
+    #[cfg_attr(
+        feature = "cloud",
+        arg(short, long, required_unless_present = "cloud_config")
+    )]
+    uri: Option<String>,
+    /// ScyllaDB Cloud connection bundle, instead of --uri and the --ssl-* options
+    #[cfg(feature = "cloud")]
+    #[arg(long, conflicts_with_all = ["uri", "ssl", "ssl_ca", "ssl_cert", "ssl_key"], env = "SCYLLA_MIGRATE_CLOUD_CONFIG")]
+    cloud_config: Option<PathBuf>,
+    /// ScyllaDB username (optional)
+    #[arg(short = 'U', long, env = "SCYLLA_MIGRATE_USER")]
+    user: Option<String>,
+    /// ScyllaDB password; asked for on the terminal when --user is given without it
+    #[arg(
+        short = 'P',
+        long,
+        env = "SCYLLA_MIGRATE_PASSWORD",
+        hide_env_values = true
+    )]
+    password: Option<String>,
+    /// Look the password of --user up in the OS keyring, and store it there once asked for
+    #[cfg(feature = "keyring")]
+    #[arg(long, requires = "user", env = "SCYLLA_MIGRATE_KEYRING")]
+    keyring: bool,
+    /// Table recording applied migrations, as keyspace.table
+    #[arg(
+        long,
+        default_value = "public.migrations",
+        env = "SCYLLA_MIGRATE_HISTORY_TABLE"
+    )]
+    history_table: String,
+    /// Replication of the history keyspace when it is created, as a replication
+    /// factor or datacenters, e.g. "dc1:3,dc2:3"; checked on later runs
+    #[arg(long, value_parser = parse_replication, env = "SCYLLA_MIGRATE_HISTORY_REPLICATION")]
+    history_replication: Option<Replication>,
+    /// Alter the history keyspace if it is replicated differently than --history-replication
+    #[arg(
+        long,
+        requires = "history_replication",
+        env = "SCYLLA_MIGRATE_ALTER_HISTORY_REPLICATION"
+    )]
+    alter_history_replication: bool,
+    /// Expect the history keyspace and tables to exist instead of creating them
+    #[arg(
+        long,
+        conflicts_with = "history_replication",
+        env = "SCYLLA_MIGRATE_NO_MANAGE_HISTORY_SCHEMA"
+    )]
+    no_manage_history_schema: bool,
+    /// Record runs, locks and every other action in the migration_audit table
+    #[arg(long, env = "SCYLLA_MIGRATE_AUDIT")]
+    audit: bool,
+    /// Keep trying to connect for this long while the cluster is still starting, e.g. 120s
+    #[arg(long, value_parser = parse_duration, env = "SCYLLA_MIGRATE_WAIT_FOR_DB")]
+    wait_for_db: Option<Duration>,
+    /// Consistency level of migration statements, e.g. ALL; the driver default if unset
+    #[arg(long, value_parser = parse_consistency, env = "SCYLLA_MIGRATE_CONSISTENCY")]
+    consistency: Option<Consistency>,
+    /// Consistency level of reads and writes of the history, e.g. LOCAL_QUORUM
+    #[arg(long, value_parser = parse_consistency, env = "SCYLLA_MIGRATE_HISTORY_CONSISTENCY")]
+    history_consistency: Option<Consistency>,
+    /// When to wait for all nodes to agree on the schema after DDL statements
+    #[arg(long, value_enum, default_value_t = AwaitSchema::Auto, env = "SCYLLA_MIGRATE_SCHEMA_AGREEMENT")]
+    schema_agreement: AwaitSchema,
+    /// Wait up to this long for created materialized views and indexes to be
+    /// built before running the next statement, e.g. 10m
+    #[arg(long, value_parser = parse_duration, env = "SCYLLA_MIGRATE_WAIT_FOR_BUILDS")]
+    wait_for_builds: Option<Duration>,
+    /// Attempts of statements and history queries failing with timeouts or
+    /// overloaded or unavailable nodes
+    #[arg(long, default_value_t = 1, env = "SCYLLA_MIGRATE_MAX_ATTEMPTS")]
+    max_attempts: u32,
+    /// Wait before the first retry, doubled for every further one, e.g. 500ms
+    #[arg(long, value_parser = parse_duration, default_value = "100ms", env = "SCYLLA_MIGRATE_RETRY_BACKOFF")]
+    retry_backoff: Duration,
+    /// Environment being migrated, e.g. prod; migrations limited to other
+    /// environments with an env= directive are left out
+    #[arg(long = "env", env = "SCYLLA_MIGRATE_ENV")]
+    environment: Option<String>,
+    /// Value of a ${KEY} placeholder in migrations, as KEY=VALUE; placeholders
+    /// without one are taken from environment variables
+    #[arg(short = 'D', long = "define", value_name = "KEY=VALUE", value_parser = parse_variable)]
+    variables: Vec<(String, String)>,
+    #[cfg(feature = "ssl")]
+    #[command(flatten)]
+    tls: TlsArgs,
+}
+
+/// TLS settings, enabled by `--ssl` or any of the other `--ssl-*` options
+#[cfg(feature = "ssl")]
+#[derive(Debug, ClapArgs)]
+struct TlsArgs {
+    /// Encrypt connections, verifying the server against the system's CA certificates
+    #[arg(long, env = "SCYLLA_MIGRATE_SSL")]
+    ssl: bool,
+    /// PEM file of CA certificates to verify the server certificate with
+    #[arg(long, env = "SCYLLA_MIGRATE_SSL_CA")]
+    ssl_ca: Option<PathBuf>,
+    /// PEM client certificate, for clusters requiring client authentication
+    #[arg(long, requires = "ssl_key", env = "SCYLLA_MIGRATE_SSL_CERT")]
+    ssl_cert: Option<PathBuf>,
+    /// PEM private key of the client certificate
+    #[arg(long, requires = "ssl_cert", env = "SCYLLA_MIGRATE_SSL_KEY")]
+    ssl_key: Option<PathBuf>,
+    /// Verify the server certificate; only disable against test clusters
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, env = "SCYLLA_MIGRATE_SSL_VERIFY")]
+    ssl_verify: bool,
+}
+
+#[cfg(feature = "ssl")]
+impl TlsArgs {
+    fn options(&self) -> Option<TlsOptions> {
+        if !self.ssl && self.ssl_ca.is_none() && self.ssl_cert.is_none() && self.ssl_verify {
+            return None;
+        }
+
+        let mut tls = TlsOptions::new().verify(self.ssl_verify);
+        if let Some(ca) = &self.ssl_ca {
+            tls = tls.ca_file(ca);
+        }
+        if let (Some(cert), Some(key)) = (&self.ssl_cert, &self.ssl_key) {
+            tls = tls.client_cert(cert, key);
+        }
+        Some(tls)
+    }
+}
+
+/// How new migration files are named
+#[derive(Debug, Clone, Copy, ClapArgs)]
+struct NamingArgs {
+    /// How to name the new migration files
+    #[arg(long, value_enum, default_value_t = Naming::Plain, env = "SCYLLA_MIGRATE_NAMING")]
+    naming: Naming,
+    /// How to number the new migration [default: timestamp, or sequential with
+    /// --naming golang-migrate]
+    #[arg(long, value_enum, env = "SCYLLA_MIGRATE_NUMBERING")]
+    numbering: Option<Numbering>,
+    /// Also create a down script, as <version>_<name>.up.cql and .down.cql;
+    /// always done with --naming golang-migrate
+    #[arg(long, env = "SCYLLA_MIGRATE_REVERSIBLE")]
+    reversible: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Naming {
+    /// <version>_<name>.cql
+    Plain,
+    /// <version>_<name>.up.cql and .down.cql, as golang-migrate names them
+    GolangMigrate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Numbering {
+    /// The current date and time, e.g. 20240117093000
+    Timestamp,
+    /// The version after the highest one in the directory, e.g. 0004, or 000004
+    /// with --naming golang-migrate
+    Sequential,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ImportFormat {
+    /// The flyway_schema_history table of Flyway
+    Flyway,
+    /// The database_migrations table of cassandra-migrate
+    CassandraMigrate,
+}
+
+impl From<ImportFormat> for HistoryFormat {
+    fn from(value: ImportFormat) -> Self {
+        match value {
+            ImportFormat::Flyway => HistoryFormat::Flyway,
+            ImportFormat::CassandraMigrate => HistoryFormat::CassandraMigrate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OnChecksumMismatch {
+    /// Fail without applying anything
+    Error,
+    /// Print a warning and leave the migration as applied
+    Warn,
+    /// Apply the changed migration again
+    Reapply,
+}
+
+impl From<OnChecksumMismatch> for ChecksumPolicy {
+    fn from(value: OnChecksumMismatch) -> Self {
+        match value {
+            OnChecksumMismatch::Error => ChecksumPolicy::Error,
+            OnChecksumMismatch::Warn => ChecksumPolicy::Warn,
+            OnChecksumMismatch::Reapply => ChecksumPolicy::Reapply,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutOfOrder {
+    /// Fail without applying anything
+    Error,
+    /// Print a warning and skip them
+    Skip,
+    /// Apply them
+    Apply,
+}
+
+impl From<OutOfOrder> for OutOfOrderPolicy {
+    fn from(value: OutOfOrder) -> Self {
+        match value {
+            OutOfOrder::Error => OutOfOrderPolicy::Error,
+            OutOfOrder::Skip => OutOfOrderPolicy::Skip,
+            OutOfOrder::Apply => OutOfOrderPolicy::Apply,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DestructiveKind {
+    /// DROP KEYSPACE
+    DropKeyspace,
+    /// DROP TABLE
+    DropTable,
+    /// Any other DROP, e.g. of an index, view or type
+    Drop,
+    /// TRUNCATE
+    Truncate,
+    /// ALTER TABLE ... DROP
+    DropColumn,
+}
+
+impl From<DestructiveKind> for Destructive {
+    fn from(value: DestructiveKind) -> Self {
+        match value {
+            DestructiveKind::DropKeyspace => Destructive::DropKeyspace,
+            DestructiveKind::DropTable => Destructive::DropTable,
+            DestructiveKind::Drop => Destructive::Drop,
+            DestructiveKind::Truncate => Destructive::Truncate,
+            DestructiveKind::DropColumn => Destructive::DropColumn,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OnMissingMigration {
+    /// Fail, or report a discrepancy when validating
+    Error,
+    /// Print a warning and carry on
+    Warn,
+}
+
+impl From<OnMissingMigration> for MissingPolicy {
+    fn from(value: OnMissingMigration) -> Self {
+        match value {
+            OnMissingMigration::Error => MissingPolicy::Error,
+            OnMissingMigration::Warn => MissingPolicy::Warn,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OnUnmetRequirement {
+    /// Fail before anything is applied
+    Error,
+    /// Leave the migration pending and carry on
+    Skip,
+}
+
+impl From<OnUnmetRequirement> for RequirementPolicy {
+    fn from(value: OnUnmetRequirement) -> Self {
+        match value {
+            OnUnmetRequirement::Error => RequirementPolicy::Error,
+            OnUnmetRequirement::Skip => RequirementPolicy::Skip,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum AwaitSchema {
+    /// When the cluster has more than one node
+    Auto,
+    /// After every DDL statement
+    Always,
+    /// Never
+    Never,
+}
+
+impl From<AwaitSchema> for SchemaAgreement {
+    fn from(value: AwaitSchema) -> Self {
+        match value {
+            AwaitSchema::Auto => SchemaAgreement::Auto,
+            AwaitSchema::Always => SchemaAgreement::Always,
+            AwaitSchema::Never => SchemaAgreement::Never,
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let args: Vec<OsString> = env::args_os().collect();
+    load_env_file(&args)?;
+    let args = Args::parse_from(with_config(args)?);
+    let output = args.output();
+    // `check` reserves 1 for pending migrations
+    let failure = match args {
+        Args::Check { .. } => CHECK_INVALID,
+        _ => 1,
+    };
+
+    // Library progress is reported through tracing; RUST_LOG=debug also shows
+    // every executed statement. Logs go to stderr when stdout carries JSON.
+    let logs = tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .with_target(false)
+        .without_time();
+    match output {
+        Output::Text => logs.init(),
+        Output::Json => logs.with_writer(io::stderr).init(),
+    }
+
+    match execute(args).await {
+        Err(err) if output == Output::Json => {
+            if !err.is::<Reported>() {
+                print_json(&json!({ "error": format!("{:#}", err) }));
+            }
+            std::process::exit(exit_status(&err, failure));
+        }
+        Err(err) if failure != 1 || is_cancelled(&err) => {
+            eprintln!("Error: {:?}", err);
+            std::process::exit(exit_status(&err, failure));
+        }
+        result => result,
+    }
+}
+
+/// Exit status of a run stopped by Ctrl-C or SIGTERM at a statement boundary
+const CANCELLED: i32 = 3;
+
+/// Exit status of a run stopped by a second Ctrl-C, possibly mid-statement
+const INTERRUPTED: i32 = 130;
+
+fn exit_status(err: &anyhow::Error, failure: i32) -> i32 {
+    if is_cancelled(err) {
+        CANCELLED
+    } else {
+        failure
+    }
+}
+
+/// Returns true if `err` is, or was caused by, a cancelled run
+fn is_cancelled(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<scylla_migrate::Error>(),
+            Some(scylla_migrate::Error::Cancelled)
+        )
+    })
+}
 
+async fn execute(args: Args) -> Result<()> {
     match args {
-        Args::Add { name, path } => {
+        Args::Add {
+            name,
+            path,
+            template,
+            template_dir,
+            naming,
+            ..
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            let template = match template {
+                Some(template) => {
+                    let dir = template_dir.unwrap_or_else(|| migrations_path.join("templates"));
+                    Template::load(&dir, &template)?
+                }
+                None => Template::stub(),
+            };
+            create_migration(&migrations_path, &name, naming, &template)?;
+        }
+        Args::Lint { path, allow, .. } => {
             let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
-            create_migration(&migrations_path, &name)?;
+            let linter = allow
+                .iter()
+                .fold(Linter::new(), |linter, rule| linter.allow(rule));
+            lint(&linter, &migrations_path)?;
         }
         Args::Run {
             path,
-            uri,
-            user,
-            password,
+            to_version,
+            steps,
+            statement_delay,
+            migration_delay,
+            dry_run,
+            yes,
+            lint,
+            deny_destructive,
+            warn_destructive,
+            on_checksum_mismatch,
+            out_of_order,
+            on_missing_migration,
+            on_unmet_requirement,
+            tenants,
+            tenant_pattern,
+            tenant_concurrency,
+            hooks,
+            output,
+            connect: args,
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            let tenants = match tenant_pattern {
+                Some(pattern) => Some(Tenants::Matching(pattern)),
+                None if !tenants.is_empty() => Some(Tenants::Keyspaces(tenants)),
+                None => None,
+            };
+            let mut options = RunOptions::new();
+            if let Some(version) = to_version {
+                options = options.to_version(version);
+            }
+            if let Some(n) = steps {
+                options = options.steps(n);
+            }
+            if let Some(delay) = statement_delay {
+                options = options.statement_delay(delay);
+            }
+            if let Some(delay) = migration_delay {
+                options = options.migration_delay(delay);
+            }
+            let mut builder = builder(&args)
+                .on_checksum_mismatch(on_checksum_mismatch.into())
+                .out_of_order(out_of_order.into())
+                .on_missing_migration(on_missing_migration.into())
+                .on_unmet_requirement(on_unmet_requirement.into())
+                .tenant_concurrency(tenant_concurrency.into());
+            if lint {
+                // Placeholders are replaced by then, their values are literals
+                builder =
+                    builder.lint(Linter::new().allow(BuiltinRule::LiteralReplicationFactor.name()));
+            }
+            for kind in warn_destructive {
+                builder = builder.on_destructive(kind.into(), DestructivePolicy::Warn);
+            }
+            for kind in deny_destructive {
+                builder = builder.on_destructive(kind.into(), DestructivePolicy::Deny);
+            }
+            let builder = hooks.register(builder);
+            let session = connect(&args).await?;
+            let runner = builder.build(&session, migrations_path.to_str().unwrap());
+            run_migrations(
+                &session,
+                &runner,
+                &options,
+                tenants.as_ref(),
+                dry_run,
+                yes,
+                output.output,
+            )
+            .await?;
+        }
+        Args::Watch {
+            path,
+            debounce,
+            on_checksum_mismatch,
+            connect,
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            let builder = builder(&connect).on_checksum_mismatch(on_checksum_mismatch.into());
+            watch(connect, builder, &migrations_path, debounce).await?;
+        }
+        Args::Revert {
+            count,
+            path,
+            connect,
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            revert_migrations(connect, &migrations_path, count).await?;
+        }
+        Args::Baseline {
+            version,
+            path,
+            output,
+            connect,
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            baseline(connect, &migrations_path, version, output.output).await?;
+        }
+        Args::Import {
+            format,
+            table,
+            path,
+            output,
+            connect,
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            import(
+                connect,
+                &migrations_path,
+                format.into(),
+                table.as_deref(),
+                output.output,
+            )
+            .await?;
+        }
+        Args::Seed {
+            file,
+            table,
+            batch_size,
+            connect,
+        } => {
+            seed(connect, &file, &table, batch_size).await?;
+        }
+        Args::Squash {
+            up_to,
+            path,
+            history_only,
+            connect,
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            squash(connect, &migrations_path, up_to, history_only).await?;
+        }
+        Args::Fake {
+            version,
+            path,
+            output,
+            connect,
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            fake(connect, &migrations_path, version, output.output).await?;
+        }
+        Args::Unfake {
+            version,
+            output,
+            connect,
+        } => {
+            unfake(connect, version, output.output).await?;
+        }
+        Args::History {
+            limit,
+            since,
+            output,
+            connect,
+        } => {
+            show_history(connect, limit, since, output.output).await?;
+        }
+        Args::Audit {
+            limit,
+            since,
+            output,
+            connect,
+        } => {
+            show_audit_log(connect, limit, since, output.output).await?;
+        }
+        Args::Show { version, connect } => {
+            show_cql(connect, version).await?;
+        }
+        Args::Status {
+            path,
+            output,
+            connect,
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            show_status(connect, &migrations_path, output.output).await?;
+        }
+        Args::Validate {
+            path,
+            on_missing_migration,
+            output,
+            connect,
         } => {
             let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
-            run_migrations(&uri, &migrations_path, user, password).await?;
+            validate(
+                connect,
+                &migrations_path,
+                on_missing_migration.into(),
+                output.output,
+            )
+            .await?;
+        }
+        Args::Check {
+            path,
+            on_missing_migration,
+            output,
+            connect,
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            let code = check(
+                connect,
+                &migrations_path,
+                on_missing_migration.into(),
+                output.output,
+            )
+            .await?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        Args::DumpSchema {
+            keyspaces,
+            output_file,
+            connect,
+        } => {
+            dump_schema(connect, &keyspaces, output_file.as_deref()).await?;
+        }
+        Args::Drift {
+            path,
+            output,
+            connect,
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            drift(connect, &migrations_path, output.output).await?;
+        }
+        Args::Diff {
+            other_uri,
+            other_user,
+            other_password,
+            keyspaces,
+            output,
+            connect,
+        } => {
+            let other = OtherCluster {
+                uri: other_uri,
+                user: other_user,
+                password: other_password,
+            };
+            diff(connect, other, &keyspaces, output.output).await?;
+        }
+        Args::MakeMigration {
+            from_live: _,
+            to,
+            to_uri,
+            keyspaces,
+            name,
+            path,
+            naming,
+            connect,
+        } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            let target = match (to, to_uri) {
+                (Some(file), _) => Target::Script(
+                    fs::read_to_string(&file)
+                        .with_context(|| format!("Failed to read {}", file.display()))?,
+                ),
+                (None, uri) => Target::Cluster(uri.unwrap_or_default()),
+            };
+            make_migration(connect, target, &keyspaces, &name, naming, &migrations_path).await?;
+        }
+        Args::Repair { yes, path, connect } => {
+            let migrations_path = path.unwrap_or_else(|| PathBuf::from("migrations"));
+            repair(connect, &migrations_path, yes).await?;
+        }
+        Args::Clean {
+            keyspace,
+            force,
+            protect,
+            no_default_protection,
+            output,
+            connect,
+        } => {
+            let protect: Vec<&str> = protect.iter().map(String::as_str).collect();
+            clean(
+                connect,
+                &keyspace,
+                force,
+                &protect,
+                !no_default_protection,
+                output.output,
+            )
+            .await?;
+        }
+        Args::Unlock { yes, connect } => {
+            unlock(connect, yes).await?;
         }
     }
 
     Ok(())
 }
 
-fn create_migration(migrations_path: &PathBuf, name: &str) -> Result<()> {
-    fs::create_dir_all(migrations_path).context("Unable to create migrations directory")?;
+/// Environment file read by `--env-file` without a value
+const DOTENV_FILE: &str = ".env";
+
+/// Returns the value of the option `long` in unparsed arguments, `Some(None)`
+/// if it is given without a value
+fn find_option(args: &[OsString], long: &str) -> Option<Option<PathBuf>> {
+    args.iter().enumerate().find_map(|(i, arg)| {
+        let arg = arg.to_str()?;
+        match arg.strip_prefix(long)? {
+            "" => Some(
+                args.get(i + 1)
+                    .filter(|next| !next.to_string_lossy().starts_with('-'))
+                    .map(PathBuf::from),
+            ),
+            value => value
+                .strip_prefix('=')
+                .map(|value| Some(PathBuf::from(value))),
+        }
+    })
+}
 
-    let dt = OffsetDateTime::now_utc()
-        .format(&time::format_description::well_known::Rfc3339)?
-        .replace([':', '-', '.'], "")
-        .split('T')
-        .next()
-        .unwrap()
-        .to_string();
+/// Loads the file of `--env-file` into the environment, so it can provide
+/// `SCYLLA_MIGRATE_*` variables and placeholder values
+fn load_env_file(args: &[OsString]) -> Result<()> {
+    let Some(path) = find_option(args, "--env-file") else {
+        return Ok(());
+    };
+    let path = path.unwrap_or_else(|| PathBuf::from(DOTENV_FILE));
+    dotenvy::from_path(&path).with_context(|| format!("Failed to load {}", path.display()))
+}
 
-    let filename = format!("{}_{}.cql", dt, name);
-    let filepath = migrations_path.join(filename);
+/// Configuration file read from the working directory unless `--config` names another
+const CONFIG_FILE: &str = "scylla-migrate.toml";
 
-    let content = format!(
-        "-- Migration: {}\n-- Timestamp: {}\n\n-- Add your CQL queries here\n",
-        name, dt
-    );
+/// Inserts the options of the configuration file right after the subcommand
+///
+/// Keys are the long names of command line options, e.g. `history_table` or
+/// `history-table`, so that options given on the command line override the
+/// file. Options set through their `SCYLLA_MIGRATE_*` environment variable
+/// override the file as well. Keys the subcommand has no option for are
+/// ignored, as long as another subcommand has. `password_env` names an
+/// environment variable holding the password, to keep it out of the file.
+fn with_config(mut args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let explicit = find_option(&args, "--config").flatten();
+    let path = match explicit.or_else(|| env::var_os("SCYLLA_MIGRATE_CONFIG").map(PathBuf::from)) {
+        Some(path) => path,
+        None if Path::new(CONFIG_FILE).is_file() => PathBuf::from(CONFIG_FILE),
+        None => return Ok(args),
+    };
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let config: toml::Table = content
+        .parse()
+        .with_context(|| format!("Invalid configuration file {}", path.display()))?;
 
-    fs::write(&filepath, content)?;
-    println!("Created migration: {:?}", filepath);
+    // Without a known subcommand, there is nothing to configure
+    let command = Args::command();
+    let Some((position, subcommand)) = args.iter().enumerate().skip(1).find_map(|(i, arg)| {
+        command
+            .find_subcommand(arg.to_str()?)
+            .map(|subcommand| (i, subcommand))
+    }) else {
+        return Ok(args);
+    };
 
-    Ok(())
+    let mut options = Vec::new();
+    for (key, value) in config {
+        let (key, value) = match key.as_str() {
+            "password_env" | "password-env" => {
+                let Some(var) = value.as_str() else {
+                    bail!("{} in {} must be a string", key, path.display());
+                };
+                let password = env::var(var).with_context(|| {
+                    format!("Environment variable {} of password_env is not set", var)
+                })?;
+                ("password".to_string(), toml::Value::String(password))
+            }
+            _ => (key.replace('_', "-"), value),
+        };
+
+        let Some(arg) = subcommand
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key.as_str()))
+        else {
+            let known = command.get_subcommands().any(|subcommand| {
+                subcommand
+                    .get_arguments()
+                    .any(|arg| arg.get_long() == Some(key.as_str()))
+            });
+            if known {
+                continue;
+            }
+            bail!("Unknown option {} in {}", key, path.display());
+        };
+        if arg.get_env().is_some_and(|var| env::var_os(var).is_some()) {
+            continue;
+        }
+
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::Boolean(set) if matches!(arg.get_action(), ArgAction::SetTrue) => {
+                    if set {
+                        options.push(OsString::from(format!("--{}", key)));
+                    }
+                    continue;
+                }
+                toml::Value::String(value) => value,
+                toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                    value.to_string()
+                }
+                _ => bail!("Unsupported value of {} in {}", key, path.display()),
+            };
+            options.push(OsString::from(format!("--{}={}", key, value)));
+        }
+    }
+
+    args.splice(position + 1..position + 1, options);
+    Ok(args)
 }
 
-async fn run_migrations(
-    node: &String,
-    migrations_path: &Path,
-    user: Option<String>,
-    password: Option<String>,
-) -> Result<()> {
-    let mut builder = SessionBuilder::new().known_node(node);
+/// The contents of a new migration, with `{{name}}`, `{{object}}` and
+/// `{{version}}` placeholders
+struct Template {
+    up: String,
+    down: String,
+}
+
+/// Templates `add --template` falls back to, as `(name, up, down)`
+const BUILTIN_TEMPLATES: &[(&str, &str, &str)] = &[
+    (
+        "create-table",
+        "CREATE TABLE IF NOT EXISTS {{object}} (\n    id uuid,\n    PRIMARY KEY (id)\n);\n",
+        "DROP TABLE IF EXISTS {{object}};\n",
+    ),
+    (
+        "create-type",
+        "CREATE TYPE IF NOT EXISTS {{object}} (\n    value text\n);\n",
+        "DROP TYPE IF EXISTS {{object}};\n",
+    ),
+    (
+        "create-materialized-view",
+        "CREATE MATERIALIZED VIEW IF NOT EXISTS {{object}} AS\n    SELECT * FROM base_table\n    WHERE id IS NOT NULL\n    PRIMARY KEY (id);\n",
+        "DROP MATERIALIZED VIEW IF EXISTS {{object}};\n",
+    ),
+];
 
-    if let (Some(username), Some(pass)) = (user, password) {
-        builder = builder.user(username, pass);
+impl Template {
+    /// The stub of a migration created without a template
+    fn stub() -> Self {
+        Self {
+            up: "-- Add your CQL queries here\n".to_string(),
+            down: "-- Undo the up migration here\n".to_string(),
+        }
     }
 
-    let session = builder.build().await?;
+    /// Reads `<name>.cql`, and `<name>.down.cql` if present, from `dir`, or
+    /// returns the built-in template of that name
+    fn load(dir: &Path, name: &str) -> Result<Self> {
+        let up = dir.join(format!("{}.cql", name));
+        if up.is_file() {
+            let read = |path: &Path| {
+                fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))
+            };
+            let down = dir.join(format!("{}.down.cql", name));
+            return Ok(Self {
+                up: read(&up)?,
+                down: if down.is_file() {
+                    read(&down)?
+                } else {
+                    Self::stub().down
+                },
+            });
+        }
+
+        match BUILTIN_TEMPLATES
+            .iter()
+            .find(|(builtin, _, _)| *builtin == name)
+        {
+            Some((_, up, down)) => Ok(Self {
+                up: up.to_string(),
+                down: down.to_string(),
+            }),
+            None => bail!(
+                "No template {}.cql in {}; the built-in templates are {}",
+                name,
+                dir.display(),
+                BUILTIN_TEMPLATES
+                    .iter()
+                    .map(|(name, _, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
 
-    // Migrate the scylla database
-    let runner = Migrator::new(&session, migrations_path.to_str().unwrap());
-    runner.run().await?;
+    /// Fills in the placeholders of `text` for the migration `name`
+    fn render(text: &str, name: &str, version: &str) -> String {
+        // `create_users` creates `users`
+        let object = ["create_", "add_", "new_"]
+            .iter()
+            .find_map(|verb| name.strip_prefix(verb))
+            .filter(|object| !object.is_empty())
+            .unwrap_or(name);
+        text.replace("{{name}}", name)
+            .replace("{{object}}", object)
+            .replace("{{version}}", version)
+    }
+}
+
+fn create_migration(
+    migrations_path: &PathBuf,
+    name: &str,
+    naming: NamingArgs,
+    template: &Template,
+) -> Result<()> {
+    fs::create_dir_all(migrations_path).context("Unable to create migrations directory")?;
+
+    let golang_migrate = naming.naming == Naming::GolangMigrate;
+    let numbering = naming.numbering.unwrap_or(if golang_migrate {
+        Numbering::Sequential
+    } else {
+        Numbering::Timestamp
+    });
+    let (version, header) = match numbering {
+        Numbering::Timestamp => {
+            let dt = OffsetDateTime::now_utc()
+                .format(&time::format_description::well_known::Rfc3339)?
+                .replace([':', '-', '.'], "")
+                .split('T')
+                .next()
+                .unwrap()
+                .to_string();
+            let header = format!("-- Migration: {}\n-- Timestamp: {}\n\n", name, dt);
+            (dt, header)
+        }
+        Numbering::Sequential => {
+            let version = next_version(migrations_path)?;
+            let header = format!("-- Migration: {}\n-- Version: {}\n\n", name, version);
+            let width = if golang_migrate { 6 } else { 4 };
+            (format!("{:0width$}", version, width = width), header)
+        }
+    };
+
+    let render = |text| Template::render(text, name, version.trim_start_matches('0'));
+    if golang_migrate || naming.reversible {
+        let up = migrations_path.join(format!("{}_{}.up.cql", version, name));
+        let down = migrations_path.join(format!("{}_{}.down.cql", version, name));
+        fs::write(&up, format!("{}{}", header, render(&template.up)))?;
+        fs::write(&down, format!("{}{}", header, render(&template.down)))?;
+        println!("Created migration: {:?}", up);
+        println!("Created down migration: {:?}", down);
+        return Ok(());
+    }
+
+    let filepath = migrations_path.join(format!("{}_{}.cql", version, name));
+    fs::write(&filepath, format!("{}{}", header, render(&template.up)))?;
+    println!("Created migration: {:?}", filepath);
 
     Ok(())
 }
+
+/// Returns the version after the highest one of the migration files in a directory
+fn next_version(migrations_path: &Path) -> Result<i64> {
+    let mut highest = 0;
+    for entry in fs::read_dir(migrations_path).context("Unable to read migrations directory")? {
+        let filename = entry?.file_name();
+        let version = filename
+            .to_str()
+            .and_then(|name| name.split('_').next()?.parse::<i64>().ok());
+        if let Some(version) = version {
+            highest = highest.max(version);
+        }
+    }
+    Ok(highest + 1)
+}
+
+async fn connect(args: &ConnectArgs) -> Result<Session> {
+    let mut options = connect_options(args)?;
+
+    // A password asked for is only stored in the keyring once it proved right
+    let mut prompted = None;
+    if let Some(user) = &args.user {
+        let password = match &args.password {
+            Some(password) => Some(password.clone()),
+            None => match stored_password(args, user)? {
+                Some(password) => Some(password),
+                None => {
+                    prompted = prompt_password(user)?;
+                    prompted.clone()
+                }
+            },
+        };
+        if let Some(password) = &password {
+            options = options.credentials(user, password);
+        }
+    }
+    if let Some(timeout) = args.wait_for_db {
+        options = options.wait_for(timeout);
+    }
+
+    let session = options.connect().await?;
+    if let (Some(user), Some(password)) = (&args.user, &prompted) {
+        store_password(args, user, password)?;
+    }
+    Ok(session)
+}
+
+/// Asks for the password of `user` on the terminal, without echoing it
+///
+/// Returns None without a terminal, e.g. in CI, so the connection is
+/// attempted without credentials instead of waiting for input forever.
+fn prompt_password(user: &str) -> Result<Option<String>> {
+    if !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    let password = rpassword::prompt_password(format!("Password for {}: ", user))
+        .context("Failed to read the password")?;
+    Ok(Some(password))
+}
+
+/// Returns the keyring entry of `user` on the cluster being connected to
+#[cfg(feature = "keyring")]
+fn keyring_entry(args: &ConnectArgs, user: &str) -> Result<keyring::Entry> {
+    #[cfg(feature = "cloud")]
+    let target = match &args.cloud_config {
+        Some(bundle) => bundle.display().to_string(),
+        None => args.uri.clone().unwrap_or_default(),
+    };
+    #[cfg(not(feature = "cloud"))]
+    let target = args.uri.clone().unwrap_or_default();
+
+    keyring::Entry::new("scylla-migrate", &format!("{}@{}", user, target))
+        .context("Failed to open the keyring")
+}
+
+/// Returns the password of `user` stored in the OS keyring, with `--keyring`
+#[cfg(feature = "keyring")]
+fn stored_password(args: &ConnectArgs, user: &str) -> Result<Option<String>> {
+    if !args.keyring {
+        return Ok(None);
+    }
+    match keyring_entry(args, user)?.get_password() {
+        Ok(password) => Ok(Some(password)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(err).context("Failed to read the password from the keyring"),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+fn stored_password(_args: &ConnectArgs, _user: &str) -> Result<Option<String>> {
+    Ok(None)
+}
+
+/// Stores the password of `user` in the OS keyring, with `--keyring`
+#[cfg(feature = "keyring")]
+fn store_password(args: &ConnectArgs, user: &str, password: &str) -> Result<()> {
+    if !args.keyring {
+        return Ok(());
+    }
+    keyring_entry(args, user)?
+        .set_password(password)
+        .context("Failed to store the password in the keyring")
+}
+
+#[cfg(not(feature = "keyring"))]
+fn store_password(_args: &ConnectArgs, _user: &str, _password: &str) -> Result<()> {
+    Ok(())
+}
+
+/// Returns how to reach the cluster, before credentials
+fn connect_options(args: &ConnectArgs) -> Result<ConnectOptions> {
+    #[cfg(feature = "cloud")]
+    if let Some(bundle) = &args.cloud_config {
+        return Ok(ConnectOptions::cloud(bundle)?);
+    }
+
+    let uri = args.uri.as_deref().context("--uri is required")?;
+    #[allow(unused_mut)]
+    let mut options = ConnectOptions::new(uri);
+    #[cfg(feature = "ssl")]
+    if let Some(tls) = args.tls.options() {
+        options = options.tls(tls);
+    }
+    Ok(options)
+}
+
+fn builder(args: &ConnectArgs) -> MigratorBuilder {
+    let mut builder = Migrator::builder()
+        .history_table(&args.history_table)
+        .schema_agreement(args.schema_agreement.into())
+        .manage_history_schema(!args.no_manage_history_schema)
+        .audit(args.audit)
+        .variables_from_env(true);
+    for (name, value) in &args.variables {
+        builder = builder.variable(name, value);
+    }
+    if let Some(user) = &args.user {
+        builder = builder.applied_by(user);
+    }
+    if let Some(timeout) = args.wait_for_builds {
+        builder = builder.wait_for_builds(timeout);
+    }
+    if let Some(replication) = &args.history_replication {
+        builder = builder
+            .history_replication(replication.clone())
+            .alter_history_replication(args.alter_history_replication);
+    }
+    if let Some(environment) = &args.environment {
+        builder = builder.environment(environment);
+    }
+    if let Some(consistency) = args.consistency {
+        builder = builder.statement_consistency(consistency);
+    }
+    if let Some(consistency) = args.history_consistency {
+        builder = builder.history_consistency(consistency);
+    }
+    builder.retry(RetryPolicy::new(args.max_attempts).backoff(args.retry_backoff))
+}
+
+/// Parses a number followed by `ms`, `s`, `m` or `h`
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    scylla_migrate::parse_duration(value)
+        .ok_or_else(|| format!("invalid duration {}, expected e.g. 500ms or 30s", value))
+}
+
+fn parse_variable(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("invalid variable {}, expected KEY=VALUE", value))
+}
+
+fn lint_rule_names() -> PossibleValuesParser {
+    PossibleValuesParser::new(BuiltinRule::ALL.map(|rule| rule.name()))
+}
+
+fn parse_since(value: &str) -> Result<OffsetDateTime, String> {
+    OffsetDateTime::parse(value, &Rfc3339)
+        // A date alone stands for its start in UTC
+        .or_else(|_| OffsetDateTime::parse(&format!("{}T00:00:00Z", value), &Rfc3339))
+        .map_err(|_| {
+            format!(
+                "invalid time {}, expected YYYY-MM-DD or an RFC 3339 time",
+                value
+            )
+        })
+}
+
+fn parse_replication(value: &str) -> Result<Replication, String> {
+    Replication::parse(value).ok_or_else(|| {
+        format!(
+            "invalid replication {}, expected a replication factor or e.g. dc1:3,dc2:3",
+            value
+        )
+    })
+}
+
+/// Parses a consistency level as written in CQL, e.g. `LOCAL_QUORUM`
+fn parse_consistency(value: &str) -> Result<Consistency, String> {
+    Ok(
+        match value.to_ascii_uppercase().replace('-', "_").as_str() {
+            "ANY" => Consistency::Any,
+            "ONE" => Consistency::One,
+            "TWO" => Consistency::Two,
+            "THREE" => Consistency::Three,
+            "QUORUM" => Consistency::Quorum,
+            "ALL" => Consistency::All,
+            "LOCAL_QUORUM" => Consistency::LocalQuorum,
+            "EACH_QUORUM" => Consistency::EachQuorum,
+            "LOCAL_ONE" => Consistency::LocalOne,
+            "SERIAL" => Consistency::Serial,
+            "LOCAL_SERIAL" => Consistency::LocalSerial,
+            _ => return Err(format!("unknown consistency level {}", value)),
+        },
+    )
+}
+
+fn migrator<'a>(
+    session: &'a Session,
+    args: &ConnectArgs,
+    migrations_path: &'a Path,
+) -> Migrator<'a> {
+    builder(args).build(session, migrations_path.to_str().unwrap())
+}
+
+async fn run_migrations(
+    session: &Session,
+    runner: &Migrator<'_>,
+    options: &RunOptions,
+    tenants: Option<&Tenants>,
+    dry_run: bool,
+    yes: bool,
+    output: Output,
+) -> Result<()> {
+    if !dry_run && !yes {
+        confirm_destructive(session, runner, options, tenants, output).await?;
+    }
+    if output == Output::Json {
+        return run_json(session, runner, options, tenants, dry_run).await;
+    }
+    if let Some(tenants) = tenants {
+        if dry_run {
+            for keyspace in tenants.keyspaces(session).await? {
+                println!("Tenant {}:", keyspace);
+                print!("{}", runner.for_tenant(&keyspace).plan_with(options).await?);
+            }
+        } else {
+            runner
+                .run_tenants_with_cancel(tenants, options, shutdown_signal())
+                .await?;
+        }
+        return Ok(());
+    }
+    if dry_run {
+        print!("{}", runner.plan_with(options).await?);
+        return Ok(());
+    }
+
+    // Migrate the scylla database
+    runner.run_with_cancel(options, shutdown_signal()).await?;
+
+    Ok(())
+}
+
+/// Completes on Ctrl-C, or SIGTERM on Unix
+///
+/// The run then stops after the statement being executed; another Ctrl-C
+/// exits at once.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            if result.is_err() {
+                return future::pending().await;
+            }
+        }
+        _ = terminate => {}
+    }
+    eprintln!("Stopping after the current statement, press Ctrl-C again to exit now");
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(INTERRUPTED);
+        }
+    });
+}
+
+impl ShellHooks {
+    fn register(self, mut builder: MigratorBuilder) -> MigratorBuilder {
+        if let Some(command) = self.before_run {
+            builder = builder.before_all(ShellHook {
+                name: "before_run",
+                command,
+            });
+        }
+        if let Some(command) = self.after_migration {
+            builder = builder.after_each(ShellHook {
+                name: "after_migration",
+                command,
+            });
+        }
+        if let Some(command) = self.on_failure {
+            builder = builder.on_event(move |event| {
+                let MigrationEvent::MigrationFailed {
+                    migration, error, ..
+                } = event
+                else {
+                    return;
+                };
+                let mut env = migration_env(migration);
+                env.push(("SCYLLA_MIGRATE_ERROR", error_chain(error)));
+                // The run stops anyway, with the error of the migration
+                if let Err(err) =
+                    tokio::task::block_in_place(|| shell(&command, "on_failure", &env))
+                {
+                    tracing::warn!("{:#}", err);
+                }
+            });
+        }
+        builder
+    }
+}
+
+/// A command of [`ShellHooks`] registered as a library hook
+struct ShellHook {
+    name: &'static str,
+    command: String,
+}
+
+#[async_trait]
+impl RunHook for ShellHook {
+    async fn call(&self, _session: &Session, migrations: &[&Migration]) -> Result<(), BoxError> {
+        let env = [("SCYLLA_MIGRATE_PENDING", migrations.len().to_string())];
+        tokio::task::block_in_place(|| shell(&self.command, self.name, &env))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MigrationHook for ShellHook {
+    async fn call(&self, _session: &Session, migration: &Migration) -> Result<(), BoxError> {
+        let env = migration_env(migration);
+        tokio::task::block_in_place(|| shell(&self.command, self.name, &env))?;
+        Ok(())
+    }
+}
+
+fn migration_env(migration: &Migration) -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "SCYLLA_MIGRATE_MIGRATION_VERSION",
+            migration.version.to_string(),
+        ),
+        (
+            "SCYLLA_MIGRATE_MIGRATION_DESCRIPTION",
+            migration.description.to_string(),
+        ),
+        (
+            "SCYLLA_MIGRATE_MIGRATION_KIND",
+            kind_json(migration.kind).to_string(),
+        ),
+    ]
+}
+
+/// Runs `command` of the `hook` option with the platform shell, failing
+/// unless it exits successfully
+///
+/// Its stdout is redirected to stderr, which keeps JSON output intact.
+fn shell(command: &str, hook: &str, env: &[(&str, String)]) -> Result<()> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let status = shell
+        .arg(command)
+        .env("SCYLLA_MIGRATE_HOOK", hook)
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdout(io::stderr())
+        .status()
+        .with_context(|| format!("Failed to run {} command {}", hook, command))?;
+    if !status.success() {
+        bail!("{} command {} failed with {}", hook, command, status);
+    }
+    Ok(())
+}
+
+/// Lints every .cql file of the migrations directory, printing the findings per file
+fn lint(linter: &Linter, migrations_path: &Path) -> Result<()> {
+    let mut files: Vec<PathBuf> = fs::read_dir(migrations_path)
+        .with_context(|| format!("Failed to read {}", migrations_path.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "cql"))
+        .collect();
+    files.sort();
+
+    let (mut findings, mut failed_files) = (0, 0);
+    for file in &files {
+        let cql = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let file_findings = linter.lint(&cql);
+        for finding in &file_findings {
+            println!(
+                "{}:{}: {} ({})",
+                file.display(),
+                finding.line,
+                finding.message,
+                finding.rule
+            );
+        }
+        if !file_findings.is_empty() {
+            findings += file_findings.len();
+            failed_files += 1;
+        }
+    }
+
+    if findings > 0 {
+        bail!(
+            "Found {} problems in {} of {} migration files",
+            findings,
+            failed_files,
+            files.len()
+        );
+    }
+    println!("Checked {} migration files, no problems found", files.len());
+    Ok(())
+}
+
+/// How often `watch` looks for changes in the migrations directory
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Runs the pending migrations, then again whenever the migrations directory
+/// has changed and stayed unchanged for `debounce`
+///
+/// The directory is polled rather than subscribed to, which also works on
+/// mounted volumes that don't report changes. Failures are printed and the
+/// watch goes on, so the next save can fix them.
+async fn watch(
+    args: ConnectArgs,
+    builder: MigratorBuilder,
+    migrations_path: &Path,
+    debounce: Duration,
+) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = builder.build(&session, migrations_path.to_str().unwrap());
+    let mut seen = snapshot(migrations_path)
+        .with_context(|| format!("Failed to read {}", migrations_path.display()))?;
+    apply_watched(&runner).await;
+    println!(
+        "Watching {} for changes, press Ctrl-C to stop",
+        migrations_path.display()
+    );
+
+    loop {
+        tokio::time::sleep(WATCH_INTERVAL).await;
+        // The directory may briefly vanish, e.g. while switching branches
+        let mut current = snapshot(migrations_path).unwrap_or_default();
+        if current == seen {
+            continue;
+        }
+
+        // Editors often write a file in several steps, wait for them to finish
+        loop {
+            tokio::time::sleep(debounce).await;
+            let next = snapshot(migrations_path).unwrap_or_default();
+            if next == current {
+                break;
+            }
+            current = next;
+        }
+        seen = current;
+        apply_watched(&runner).await;
+    }
+}
+
+/// Modification time and size of every file in the migrations directory
+fn snapshot(path: &Path) -> io::Result<BTreeMap<PathBuf, (Option<SystemTime>, u64)>> {
+    let mut files = BTreeMap::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_file() {
+            files.insert(entry.path(), (meta.modified().ok(), meta.len()));
+        }
+    }
+    Ok(files)
+}
+
+async fn apply_watched(runner: &Migrator<'_>) {
+    match runner.run().await {
+        Ok(report) if report.applied.is_empty() => println!("Up to date"),
+        Ok(report) => println!(
+            "Applied {} migrations: {}",
+            report.applied.len(),
+            report
+                .applied
+                .iter()
+                .map(|m| m.description.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Err(err) => eprintln!("Failed: {:#}", anyhow::Error::from(err)),
+    }
+}
+
+/// Lists the destructive statements of the pending migrations and asks
+/// whether to apply them, failing unless confirmed
+///
+/// Without a terminal to ask on, destructive statements require `--yes`.
+async fn confirm_destructive(
+    session: &Session,
+    runner: &Migrator<'_>,
+    options: &RunOptions,
+    tenants: Option<&Tenants>,
+    output: Output,
+) -> Result<()> {
+    let mut plans = Vec::new();
+    match tenants {
+        Some(tenants) => {
+            for keyspace in tenants.keyspaces(session).await? {
+                let plan = runner.for_tenant(&keyspace).plan_with(options).await?;
+                plans.push((Some(keyspace), plan));
+            }
+        }
+        None => plans.push((None, runner.plan_with(options).await?)),
+    }
+
+    let mut destructive = Vec::new();
+    for (keyspace, plan) in &plans {
+        for migration in &plan.migrations {
+            for (index, statement, kind) in migration.destructive_statements() {
+                let tenant = keyspace
+                    .as_ref()
+                    .map(|keyspace| format!(" (tenant {})", keyspace))
+                    .unwrap_or_default();
+                let name = match migration.kind {
+                    MigrationKind::Versioned => {
+                        format!("{}/migrate {}", migration.version, migration.description)
+                    }
+                    MigrationKind::Repeatable => format!("repeatable {}", migration.description),
+                };
+                destructive.push(format!(
+                    "  {}{} [{}] {}: {};",
+                    name, tenant, index, kind, statement
+                ));
+            }
+        }
+    }
+    if destructive.is_empty() {
+        return Ok(());
+    }
+
+    if output == Output::Json || !io::stdin().is_terminal() {
+        bail!(
+            "Pending migrations contain {} destructive statements, pass --yes to apply them:\n{}",
+            destructive.len(),
+            destructive.join("\n")
+        );
+    }
+    println!("Pending migrations contain destructive statements:");
+    for line in &destructive {
+        println!("{}", line);
+    }
+    if !confirm("Apply them?")? {
+        bail!("Aborted, nothing was applied");
+    }
+    Ok(())
+}
+
+async fn revert_migrations(args: ConnectArgs, migrations_path: &Path, count: usize) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, migrations_path);
+    runner.revert(count).await?;
+
+    Ok(())
+}
+
+/// Runs or plans migrations like `run_migrations`, printing the reports as JSON
+///
+/// The reports of the tenants migrated before a failing one, or alongside it,
+/// are printed as well.
+async fn run_json(
+    session: &Session,
+    runner: &Migrator<'_>,
+    options: &RunOptions,
+    tenants: Option<&Tenants>,
+    dry_run: bool,
+) -> Result<()> {
+    let Some(tenants) = tenants else {
+        if dry_run {
+            print_json(&plan_json(&runner.plan_with(options).await?));
+            return Ok(());
+        }
+        return match runner.run_with_cancel(options, shutdown_signal()).await {
+            Ok(report) => {
+                print_json(&report_json(&report, None));
+                Ok(())
+            }
+            Err(scylla_migrate::Error::RunFailed(failed)) => {
+                print_json(&report_json(&failed.report, Some(&failed.error)));
+                Err(reported(matches!(
+                    failed.error,
+                    scylla_migrate::Error::Cancelled
+                )))
+            }
+            Err(err) => Err(err.into()),
+        };
+    };
+
+    let mut results = Vec::new();
+    let mut failed = false;
+    let mut cancelled = false;
+    if dry_run {
+        for keyspace in tenants.keyspaces(session).await? {
+            let mut result = plan_json(&runner.for_tenant(&keyspace).plan_with(options).await?);
+            result["keyspace"] = json!(keyspace);
+            results.push(result);
+        }
+    } else {
+        for (keyspace, report) in runner
+            .run_each_tenant_with_cancel(tenants, options, shutdown_signal())
+            .await?
+        {
+            let mut result = match report {
+                Ok(report) => report_json(&report, None),
+                Err(scylla_migrate::Error::RunFailed(run)) => {
+                    failed = true;
+                    cancelled |= matches!(run.error, scylla_migrate::Error::Cancelled);
+                    report_json(&run.report, Some(&run.error))
+                }
+                Err(err) => {
+                    failed = true;
+                    json!({ "error": error_chain(&err) })
+                }
+            };
+            result["keyspace"] = json!(keyspace);
+            results.push(result);
+        }
+    }
+
+    print_json(&json!({ "tenants": results }));
+    if failed {
+        return Err(reported(cancelled));
+    }
+    Ok(())
+}
+
+async fn baseline(
+    args: ConnectArgs,
+    migrations_path: &Path,
+    version: i64,
+    output: Output,
+) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, migrations_path);
+    let recorded = runner.baseline(version).await?;
+    match output {
+        Output::Text => println!("Recorded {} migrations as applied", recorded),
+        Output::Json => print_json(&json!({ "version": version, "recorded": recorded })),
+    }
+
+    Ok(())
+}
+
+async fn seed(args: ConnectArgs, file: &Path, table: &str, batch_size: usize) -> Result<()> {
+    let Some((keyspace, table)) = table.split_once('.') else {
+        bail!(
+            "--table must be qualified with its keyspace, e.g. app.{}",
+            table
+        );
+    };
+    let csv =
+        fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let session = connect(&args).await?;
+
+    let runner = builder(&args).build(&session, "migrations");
+    let seed = runner
+        .seed_csv(keyspace, table, &csv, batch_size)
+        .await
+        .with_context(|| format!("Failed to seed {}.{}", keyspace, table))?;
+    println!(
+        "Inserted {} rows into {}.{} in {} batches",
+        seed.rows, keyspace, table, seed.batches
+    );
+    Ok(())
+}
+
+async fn import(
+    args: ConnectArgs,
+    migrations_path: &Path,
+    format: HistoryFormat,
+    table: Option<&str>,
+    output: Output,
+) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, migrations_path);
+    let import = runner.import_history(format, table).await?;
+    match output {
+        Output::Text => {
+            println!("Imported {} migrations as applied", import.imported.len());
+            if !import.existing.is_empty() {
+                println!(
+                    "Already recorded: {}",
+                    import
+                        .existing
+                        .iter()
+                        .map(i64::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            if !import.unmatched.is_empty() {
+                println!(
+                    "No local migration for versions {}; they were not imported",
+                    import.unmatched.join(", ")
+                );
+            }
+        }
+        Output::Json => print_json(&json!({
+            "imported": import.imported,
+            "existing": import.existing,
+            "unmatched": import.unmatched,
+        })),
+    }
+
+    Ok(())
+}
+
+async fn squash(
+    args: ConnectArgs,
+    migrations_path: &Path,
+    up_to: i64,
+    history_only: bool,
+) -> Result<()> {
+    let session = connect(&args).await?;
+    let runner = migrator(&session, &args, migrations_path);
+    if !history_only {
+        // Checked before the files are rewritten, the history can't be afterwards
+        // Including those a run skips, which would be folded in unapplied
+        if let Some(pending) = runner
+            .status()
+            .await?
+            .pending()
+            .find(|m| m.kind == MigrationKind::Versioned && m.version <= up_to)
+        {
+            bail!(
+                "Migration {} {} is not applied yet; apply the migrations up to {} before squashing them",
+                pending.version,
+                pending.description,
+                up_to
+            );
+        }
+        let squash = scylla_migrate::squash(migrations_path, up_to).await?;
+        println!(
+            "Squashed {} migrations into {}",
+            squash.versions.len(),
+            squash.path.display()
+        );
+    }
+
+    if runner.squash_history(up_to).await? {
+        println!("Recorded the squashed migration in the history");
+    } else {
+        println!("None of the squashed migrations is applied, the history is left alone");
+    }
+
+    Ok(())
+}
+
+async fn fake(
+    args: ConnectArgs,
+    migrations_path: &Path,
+    version: i64,
+    output: Output,
+) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, migrations_path);
+    let recorded = runner.fake(version).await?;
+    match output {
+        Output::Text if !recorded => println!("Migration {} is already applied", version),
+        Output::Text => {}
+        Output::Json => print_json(&json!({ "version": version, "recorded": recorded })),
+    }
+
+    Ok(())
+}
+
+async fn unfake(args: ConnectArgs, version: i64, output: Output) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, Path::new(""));
+    let removed = runner.unfake(version).await?;
+    match output {
+        Output::Text if !removed => println!("Migration {} is not applied", version),
+        Output::Text => {}
+        Output::Json => print_json(&json!({ "version": version, "removed": removed })),
+    }
+
+    Ok(())
+}
+
+async fn clean(
+    args: ConnectArgs,
+    keyspace: &str,
+    force: bool,
+    protect: &[&str],
+    default_protection: bool,
+    output: Output,
+) -> Result<()> {
+    if !force {
+        bail!(
+            "Cleaning drops every table of {} and its data; pass --force to clean it",
+            keyspace
+        );
+    }
+    let session = connect(&args).await?;
+
+    let runner = builder(&args)
+        .protect(protect)
+        .default_protection(default_protection)
+        .build(&session, "");
+    let clean = runner.clean(keyspace).await?;
+    match output {
+        Output::Text => {
+            for object in &clean.dropped {
+                println!("Dropped {}", object);
+            }
+            for migration in &clean.forgotten {
+                println!("Removed {} from the history", migration);
+            }
+        }
+        Output::Json => print_json(&json!({
+            "keyspace": keyspace,
+            "dropped": clean.dropped,
+            "forgotten": clean.forgotten,
+        })),
+    }
+
+    Ok(())
+}
+
+async fn show_history(
+    args: ConnectArgs,
+    limit: Option<usize>,
+    since: Option<OffsetDateTime>,
+    output: Output,
+) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, Path::new(""));
+    let mut history = runner.history().await?;
+    if let Some(since) = since {
+        history
+            .entries
+            .retain(|entry| entry.applied_at.is_some_and(|at| at >= since));
+    }
+    if let Some(limit) = limit {
+        let skip = history.entries.len().saturating_sub(limit);
+        history.entries.drain(..skip);
+    }
+
+    match output {
+        Output::Text => print!("{}", history),
+        Output::Json => print_json(&history_json(&history)),
+    }
+
+    Ok(())
+}
+
+async fn show_audit_log(
+    args: ConnectArgs,
+    limit: Option<usize>,
+    since: Option<OffsetDateTime>,
+    output: Output,
+) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, Path::new(""));
+    let mut log = runner.audit_log().await?;
+    if let Some(since) = since {
+        log.entries.retain(|entry| entry.at >= since);
+    }
+    if let Some(limit) = limit {
+        let skip = log.entries.len().saturating_sub(limit);
+        log.entries.drain(..skip);
+    }
+
+    match output {
+        Output::Text => print!("{}", log),
+        Output::Json => print_json(&audit_json(&log)),
+    }
+
+    Ok(())
+}
+
+async fn show_cql(args: ConnectArgs, version: i64) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, Path::new(""));
+    match runner.applied_cql(version).await? {
+        Some(cql) => print!("{}", cql),
+        None => bail!("No CQL recorded for an applied migration {}", version),
+    }
+
+    Ok(())
+}
+
+async fn show_status(args: ConnectArgs, migrations_path: &Path, output: Output) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, migrations_path);
+    let status = runner.status().await?;
+    match output {
+        Output::Text => print!("{}", status),
+        Output::Json => print_json(&status_json(&status)),
+    }
+
+    Ok(())
+}
+
+async fn validate(
+    args: ConnectArgs,
+    migrations_path: &Path,
+    missing_policy: MissingPolicy,
+    output: Output,
+) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = builder(&args)
+        .on_missing_migration(missing_policy)
+        .build(&session, migrations_path.to_str().unwrap());
+    let validation = runner.validate().await?;
+    if output == Output::Json {
+        print_json(&validation_json(&validation));
+        if !validation.is_valid() {
+            return Err(Reported.into());
+        }
+        return Ok(());
+    }
+    print!("{}", validation);
+    if !validation.is_valid() {
+        bail!(
+            "Validation failed with {} discrepancies",
+            validation.issues.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Exit status of `check` for applied migrations that don't match the local
+/// files, also used when the check itself fails
+const CHECK_INVALID: i32 = 2;
+
+/// Returns the exit status of `check`: 0 when up to date, 1 when migrations
+/// are pending and [`CHECK_INVALID`] on discrepancies
+async fn check(
+    args: ConnectArgs,
+    migrations_path: &Path,
+    missing_policy: MissingPolicy,
+    output: Output,
+) -> Result<i32> {
+    let session = connect(&args).await?;
+
+    let runner = builder(&args)
+        .on_missing_migration(missing_policy)
+        .build(&session, migrations_path.to_str().unwrap());
+    let validation = runner.validate().await?;
+    let status = runner.status().await?;
+    // Edited repeatable migrations are applied again by the next run
+    let pending: Vec<&MigrationStatus> = status
+        .migrations
+        .iter()
+        .filter(|m| match m.state {
+            MigrationState::Pending | MigrationState::Failed => true,
+            MigrationState::Changed => m.kind == MigrationKind::Repeatable,
+            MigrationState::Applied | MigrationState::Missing => false,
+        })
+        .collect();
+
+    let code = if !validation.is_valid() {
+        CHECK_INVALID
+    } else if !pending.is_empty() {
+        1
+    } else {
+        0
+    };
+
+    match output {
+        Output::Json => {
+            let mut result = validation_json(&validation);
+            result["up_to_date"] = json!(code == 0);
+            result["pending"] = pending
+                .iter()
+                .map(|m| {
+                    json!({
+                        "kind": kind_json(m.kind),
+                        "version": m.version,
+                        "description": m.description,
+                        "state": m.state.to_string(),
+                    })
+                })
+                .collect();
+            print_json(&result);
+        }
+        Output::Text => {
+            if !validation.is_valid() {
+                print!("{}", validation);
+            }
+            for migration in &pending {
+                match migration.kind {
+                    MigrationKind::Versioned => println!(
+                        "{}/migrate {}: {}",
+                        migration.version, migration.description, migration.state
+                    ),
+                    MigrationKind::Repeatable => {
+                        println!("R/{}: {}", migration.description, migration.state)
+                    }
+                }
+            }
+            if code == 0 {
+                println!("The database is up to date");
+            } else if !pending.is_empty() {
+                println!("{} migrations are pending", pending.len());
+            }
+        }
+    }
+
+    Ok(code)
+}
+
+async fn drift(args: ConnectArgs, migrations_path: &Path, output: Output) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, migrations_path);
+    let drift = runner.detect_drift().await?;
+    if output == Output::Json {
+        let differences: Vec<Value> = drift.differences.iter().map(difference_json).collect();
+        print_json(&json!({ "drifted": !drift.is_empty(), "differences": differences }));
+        if !drift.is_empty() {
+            return Err(Reported.into());
+        }
+        return Ok(());
+    }
+    print!("{}", drift);
+    if !drift.is_empty() {
+        bail!(
+            "The schema drifted from the applied migrations in {} places",
+            drift.differences.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Where `diff` finds the cluster to compare with
+struct OtherCluster {
+    uri: String,
+    user: Option<String>,
+    password: Option<String>,
+}
+
+async fn diff(
+    mut args: ConnectArgs,
+    other: OtherCluster,
+    keyspaces: &[String],
+    output: Output,
+) -> Result<()> {
+    // Named keyspaces missing on one side are differences, not errors
+    let read = |session: Session| async move {
+        let mut schema = Schema::read(&session, &[]).await?;
+        if !keyspaces.is_empty() {
+            schema.keyspaces.retain(|name, _| keyspaces.contains(name));
+        }
+        anyhow::Ok(schema)
+    };
+
+    let from = read(connect(&args).await?).await?;
+    let from_uri = args.uri.replace(other.uri.clone()).unwrap_or_default();
+    #[cfg(feature = "cloud")]
+    let from_uri = match args.cloud_config.take() {
+        Some(bundle) => bundle.display().to_string(),
+        None => from_uri,
+    };
+    if let Some(user) = other.user {
+        args.user = Some(user);
+        args.password = other.password;
+    }
+    let to = read(connect(&args).await?).await?;
+
+    let differences = from.diff(&to);
+    if output == Output::Json {
+        let differences: Vec<Value> = differences.iter().map(difference_json).collect();
+        print_json(&json!({
+            "from": from_uri,
+            "to": other.uri,
+            "identical": differences.is_empty(),
+            "differences": differences,
+        }));
+        if !differences.is_empty() {
+            return Err(Reported.into());
+        }
+        return Ok(());
+    }
+    if differences.is_empty() {
+        println!("The schemas are identical");
+        return Ok(());
+    }
+    println!("--- {}\n+++ {}", from_uri, other.uri);
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    bail!("The schemas differ in {} places", differences.len());
+}
+
+/// The schema `make-migration` converges to
+enum Target {
+    Script(String),
+    Cluster(String),
+}
+
+async fn make_migration(
+    mut args: ConnectArgs,
+    target: Target,
+    keyspaces: &[String],
+    name: &str,
+    naming: NamingArgs,
+    migrations_path: &PathBuf,
+) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let mut schema = match target {
+        Target::Script(cql) => {
+            Schema::parse(&cql, session.get_keyspace().as_deref().map(String::as_str))
+        }
+        Target::Cluster(uri) => {
+            // Only the connection changes; the runner options stay the same
+            args.uri = Some(uri);
+            #[cfg(feature = "cloud")]
+            args.cloud_config.take();
+            Schema::read(&connect(&args).await?, &[]).await?
+        }
+    };
+    if !keyspaces.is_empty() {
+        schema.keyspaces.retain(|name, _| keyspaces.contains(name));
+    }
+
+    let runner = migrator(&session, &args, migrations_path);
+    let cql = runner.migration_to(&schema).await?;
+    if cql.is_empty() {
+        println!("The schema is up to date, no migration was created");
+        return Ok(());
+    }
+    let template = Template {
+        up: format!(
+            "-- Generated by make-migration; review before applying\n\n{}",
+            cql
+        ),
+        ..Template::stub()
+    };
+    create_migration(migrations_path, name, naming, &template)
+}
+
+async fn dump_schema(
+    args: ConnectArgs,
+    keyspaces: &[String],
+    output_file: Option<&Path>,
+) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let schema = Schema::read(&session, keyspaces).await?;
+    match output_file {
+        Some(path) => fs::write(path, schema.to_string())
+            .with_context(|| format!("Failed to write {}", path.display()))?,
+        None => print!("{}", schema),
+    }
+
+    Ok(())
+}
+
+async fn repair(args: ConnectArgs, migrations_path: &Path, yes: bool) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, migrations_path);
+    let validation = runner.validate().await?;
+    if validation.is_valid() {
+        println!("Nothing to repair");
+        return Ok(());
+    }
+
+    print!("{}", validation);
+    if !yes && !confirm("Update the history to match the local migrations?")? {
+        println!("History not repaired");
+        return Ok(());
+    }
+
+    runner.repair_confirmed(&validation).await?;
+
+    Ok(())
+}
+
+async fn unlock(args: ConnectArgs, yes: bool) -> Result<()> {
+    let session = connect(&args).await?;
+
+    let runner = migrator(&session, &args, Path::new(""));
+    let Some(holder) = runner.lock_holder().await? else {
+        println!("Migration lock is not held");
+        return Ok(());
+    };
+
+    println!("Migration lock is held by {}", holder);
+    if !yes && !confirm("Release it? Only do this if that runner is no longer alive")? {
+        println!("Lock not released");
+        return Ok(());
+    }
+
+    if runner.force_unlock(&holder).await? {
+        println!("Migration lock released");
+    } else {
+        println!("Migration lock changed hands in the meantime, not released");
+    }
+
+    Ok(())
+}
+
+fn print_json(value: &Value) {
+    println!("{}", value);
+}
+
+/// Formats an error with its sources, like `{:#}` does for `anyhow::Error`
+fn error_chain(err: &scylla_migrate::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        message.push_str(&format!(": {}", err));
+        source = err.source();
+    }
+    message
+}
+
+fn kind_json(kind: MigrationKind) -> &'static str {
+    match kind {
+        MigrationKind::Versioned => "versioned",
+        MigrationKind::Repeatable => "repeatable",
+    }
+}
+
+fn skipped_json(skipped: &[SkippedMigration]) -> Value {
+    skipped
+        .iter()
+        .map(|skipped| {
+            json!({
+                "version": skipped.version,
+                "description": skipped.description,
+                "reason": skipped.reason.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn report_json(report: &MigrationReport, error: Option<&scylla_migrate::Error>) -> Value {
+    let applied: Vec<Value> = report
+        .applied
+        .iter()
+        .map(|applied| {
+            json!({
+                "kind": kind_json(applied.kind),
+                "version": applied.version,
+                "description": applied.description,
+                "reapplied": applied.reapplied,
+                "duration_ms": applied.duration.as_millis() as u64,
+            })
+        })
+        .collect();
+    let failed = report.failed.as_ref().map(|failed| {
+        json!({
+            "kind": kind_json(failed.kind),
+            "version": failed.version,
+            "description": failed.description,
+            "duration_ms": failed.duration.as_millis() as u64,
+            "error": failed.error,
+        })
+    });
+
+    json!({
+        "applied": applied,
+        "skipped": skipped_json(&report.skipped),
+        "failed": failed,
+        "error": error.map(error_chain),
+    })
+}
+
+fn plan_json(plan: &Plan) -> Value {
+    let pending: Vec<Value> = plan
+        .migrations
+        .iter()
+        .map(|planned| {
+            json!({
+                "kind": kind_json(planned.kind),
+                "version": planned.version,
+                "description": planned.description,
+                "changed": planned.changed,
+                "statements": planned.statements,
+            })
+        })
+        .collect();
+
+    json!({ "pending": pending, "skipped": skipped_json(&plan.skipped) })
+}
+
+fn status_json(status: &Status) -> Value {
+    let migrations: Vec<Value> = status
+        .migrations
+        .iter()
+        .map(|migration| {
+            json!({
+                "kind": kind_json(migration.kind),
+                "version": migration.version,
+                "description": migration.description,
+                "state": migration.state.to_string(),
+                "checksum": hex(&migration.checksum),
+                "applied_at": migration.applied_at.and_then(|at| at.format(&Rfc3339).ok()),
+                "execution_time_ms": migration.execution_time_ms,
+                "applied_by": migration.applied_by,
+                "host": migration.host,
+                "error": migration.error,
+            })
+        })
+        .collect();
+
+    json!({ "migrations": migrations })
+}
+
+fn history_json(history: &History) -> Value {
+    let entries: Vec<Value> = history
+        .entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "kind": kind_json(entry.kind),
+                "version": entry.version,
+                "description": entry.description,
+                "checksum": hex(&entry.checksum),
+                "applied_at": entry.applied_at.and_then(|at| at.format(&Rfc3339).ok()),
+                "execution_time_ms": entry.execution_time_ms,
+                "applied_by": entry.applied_by,
+                "host": entry.host,
+                "error": entry.error,
+            })
+        })
+        .collect();
+
+    json!({ "entries": entries })
+}
+
+fn audit_json(log: &AuditLog) -> Value {
+    let entries: Vec<Value> = log
+        .entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "at": entry.at.format(&Rfc3339).ok(),
+                "action": entry.action.as_str(),
+                "version": entry.version,
+                "description": entry.description,
+                "actor": entry.actor,
+                "host": entry.host,
+                "detail": entry.detail,
+            })
+        })
+        .collect();
+
+    json!({ "entries": entries })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn validation_json(validation: &Validation) -> Value {
+    let issues: Vec<Value> = validation
+        .issues
+        .iter()
+        .map(|issue| {
+            json!({
+                "version": issue.version,
+                "description": issue.description,
+                "discrepancy": match issue.discrepancy {
+                    Discrepancy::ChecksumMismatch => "checksum_mismatch",
+                    Discrepancy::MissingLocally => "missing_locally",
+                },
+            })
+        })
+        .collect();
+
+    json!({ "valid": validation.is_valid(), "issues": issues })
+}
+
+fn difference_json(difference: &SchemaDifference) -> Value {
+    let mut value = match &difference.object {
+        SchemaObject::Keyspace(keyspace) => json!({ "kind": "keyspace", "keyspace": keyspace }),
+        SchemaObject::Type { keyspace, name } => {
+            json!({ "kind": "type", "keyspace": keyspace, "name": name })
+        }
+        SchemaObject::Table { keyspace, name } => {
+            json!({ "kind": "table", "keyspace": keyspace, "name": name })
+        }
+        SchemaObject::Column {
+            keyspace,
+            table,
+            name,
+        } => json!({ "kind": "column", "keyspace": keyspace, "table": table, "name": name }),
+        SchemaObject::Index {
+            keyspace,
+            table,
+            name,
+        } => json!({ "kind": "index", "keyspace": keyspace, "table": table, "name": name }),
+        SchemaObject::View { keyspace, name } => {
+            json!({ "kind": "view", "keyspace": keyspace, "name": name })
+        }
+    };
+    let (change, from, to) = match &difference.difference {
+        Difference::Added => ("added", None, None),
+        Difference::Removed => ("removed", None, None),
+        Difference::Altered { from, to } => ("altered", Some(from), Some(to)),
+    };
+    value["difference"] = json!(change);
+    value["from"] = json!(from);
+    value["to"] = json!(to);
+    value
+}
+
+/// Asks a yes/no question on the terminal, defaulting to no
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "Yes"))
+}
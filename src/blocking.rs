@@ -0,0 +1,111 @@
+//! A blocking Migrator, for build scripts and tools that aren't async
+//!
+//! [`Migrator`] connects and runs its migrations on a runtime of its own, so
+//! none is needed to use it. It must not be used from within an async runtime,
+//! which panics; async code uses [`crate::Migrator`] instead.
+//!
+//! ```no_run
+//! use scylla_migrate::blocking::Migrator;
+//! use scylla_migrate::ConnectOptions;
+//!
+//! # fn main() -> scylla_migrate::Result<()> {
+//! let runner = Migrator::connect(&ConnectOptions::new("localhost:9042"), "migrations")?;
+//! if runner.validate()?.is_valid() {
+//!     runner.run()?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::{Error, Result};
+use crate::{
+    ConnectOptions, MigrationReport, MigratorBuilder, Plan, RunOptions, Status, Validation,
+};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::runtime::{self, Runtime};
+
+/// Runs migrations like [`crate::Migrator`], blocking until they are done
+pub struct Migrator {
+    // Declared first so the session is dropped before its runtime
+    inner: crate::Migrator<'static>,
+    runtime: Runtime,
+}
+
+impl Migrator {
+    /// Connects with `options` and creates a Migrator with default settings
+    /// reading migrations from the `migrations_src` directory
+    pub fn connect(options: &ConnectOptions, migrations_src: impl AsRef<Path>) -> Result<Self> {
+        Self::connect_with(crate::Migrator::builder(), options, migrations_src)
+    }
+
+    /// Like [`connect`](Self::connect), with the settings of `builder`
+    pub fn connect_with(
+        builder: MigratorBuilder,
+        options: &ConnectOptions,
+        migrations_src: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(Error::Runtime)?;
+        let session = runtime.block_on(options.connect())?;
+        Ok(Self {
+            inner: builder.build_arc(Arc::new(session), migrations_src),
+            runtime,
+        })
+    }
+
+    /// See [`crate::Migrator::run`]
+    pub fn run(&self) -> Result<MigrationReport> {
+        self.runtime.block_on(self.inner.run())
+    }
+
+    /// See [`crate::Migrator::run_to`]
+    pub fn run_to(&self, version: i64) -> Result<MigrationReport> {
+        self.runtime.block_on(self.inner.run_to(version))
+    }
+
+    /// See [`crate::Migrator::run_with`]
+    pub fn run_with(&self, options: &RunOptions) -> Result<MigrationReport> {
+        self.runtime.block_on(self.inner.run_with(options))
+    }
+
+    /// See [`crate::Migrator::plan`]
+    pub fn plan(&self) -> Result<Plan> {
+        self.runtime.block_on(self.inner.plan())
+    }
+
+    /// See [`crate::Migrator::status`]
+    pub fn status(&self) -> Result<Status> {
+        self.runtime.block_on(self.inner.status())
+    }
+
+    /// See [`crate::Migrator::validate`]
+    pub fn validate(&self) -> Result<Validation> {
+        self.runtime.block_on(self.inner.validate())
+    }
+
+    /// See [`crate::Migrator::pending_count`]
+    pub fn pending_count(&self) -> Result<usize> {
+        self.runtime.block_on(self.inner.pending_count())
+    }
+
+    /// See [`crate::Migrator::is_up_to_date`]
+    pub fn is_up_to_date(&self) -> Result<bool> {
+        self.runtime.block_on(self.inner.is_up_to_date())
+    }
+
+    /// See [`crate::Migrator::current_version`]
+    pub fn current_version(&self) -> Result<Option<i64>> {
+        self.runtime.block_on(self.inner.current_version())
+    }
+}
+
+impl std::fmt::Debug for Migrator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Migrator")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,139 @@
+//! Checks for migration files in build scripts
+//!
+//! Broken migration files fail the build instead of the deploy:
+//!
+//! ```no_run
+//! // In the main function of build.rs
+//! scylla_migrate::build::validate("migrations").unwrap();
+//! ```
+//!
+//! With `scylla-migrate` in the `[build-dependencies]` of the crate, cargo
+//! runs the check again whenever a migration file is added, changed or removed.
+
+use crate::error::{Error, Result};
+use crate::migration::{Directives, Migration};
+use crate::source::{from_files, read_files};
+use crate::token::tokenize;
+use std::borrow::Cow;
+use std::path::Path;
+
+/// Checks the migration files of the `migrations_src` directory without a cluster
+///
+/// Fails like loading them does, on misnamed files, duplicate versions, down
+/// migrations without an up migration and invalid directives, and also on
+/// scripts whose string literals, quoted identifiers, comments or function
+/// bodies are never closed. `.cql.tera` templates are paired with the other
+/// files, but aren't rendered, so their content isn't checked.
+///
+/// Prints the `cargo:rerun-if-changed` lines of the directory and its files,
+/// so it is only meant to be called from build scripts. Relative paths are
+/// relative to the manifest of the crate being built.
+pub fn validate(migrations_src: impl AsRef<Path>) -> Result<Vec<Migration>> {
+    let path = migrations_src.as_ref();
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(Error::Runtime)?;
+    let mut files = runtime.block_on(read_files(path, true))?;
+    files.sort();
+
+    let mut migrations = Vec::new();
+    for (filename, content) in files {
+        println!(
+            "cargo:rerun-if-changed={}",
+            path.join(filename.as_ref()).display()
+        );
+        if let Some(name) = filename.strip_suffix(".tera") {
+            // Other templates are only included by migrations
+            if name.ends_with(".cql") {
+                migrations.push((Cow::Owned(name.to_string()), content));
+            }
+            continue;
+        }
+
+        let invalid = |reason| Error::Parse {
+            name: filename.to_string(),
+            reason,
+        };
+        if !filename.ends_with(".down.cql") {
+            Directives::parse(&content).map_err(invalid)?;
+        }
+        if let Some(reason) = tokenize(&content).1 {
+            return Err(invalid(reason));
+        }
+        migrations.push((filename, content));
+    }
+    from_files(migrations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn dir(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, content) in files {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn accepts_valid_migrations() {
+        let dir = dir(&[
+            (
+                "1_users.up.cql",
+                "CREATE TABLE users (id int PRIMARY KEY, note text); -- it's",
+            ),
+            ("1_users.down.cql", "DROP TABLE users;"),
+            (
+                "2_seed.cql",
+                "INSERT INTO users (id, note) VALUES (1, 'it''s');",
+            ),
+        ]);
+        assert_eq!(validate(dir.path()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn rejects_unclosed_literals() {
+        let dir = dir(&[(
+            "1_seed.cql",
+            "INSERT INTO users (id, note) VALUES (1, 'oops);",
+        )]);
+        let err = validate(dir.path()).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Parse {
+                reason: "string literal is never closed",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn rejects_duplicate_versions() {
+        let dir = dir(&[("1_a.cql", "SELECT 1"), ("1_b.cql", "SELECT 2")]);
+        assert!(matches!(
+            validate(dir.path()).unwrap_err(),
+            Error::DuplicateVersion { version: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn pairs_templates_without_rendering_them() {
+        let dir = dir(&[
+            (
+                "1_users.up.cql.tera",
+                "CREATE TABLE {{ keyspace }}.users (note text PRIMARY KEY)",
+            ),
+            ("1_users.down.cql", "DROP TABLE users;"),
+            ("macros.tera", "{% macro x() %}'{% endmacro %}"),
+        ]);
+        let migrations = validate(dir.path()).unwrap();
+        assert_eq!(migrations.len(), 1);
+        assert!(migrations[0].down.is_some());
+    }
+}
@@ -0,0 +1,511 @@
+use crate::clean::PROTECTED;
+use crate::destructive::Destructive;
+use crate::event::{EventHook, MigrationEvent};
+use crate::history::{HistoryTable, Replication};
+use crate::hook::{MigrationHook, RunHook};
+use crate::lint::Linter;
+use crate::lock::MIN_LOCK_LEASE;
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::migration::Migration;
+use crate::options::{
+    ChecksumPolicy, DestructivePolicy, MigratorOptions, MissingPolicy, OutOfOrderPolicy,
+    RequirementPolicy, SchemaAgreement,
+};
+use crate::retry::RetryPolicy;
+use crate::session::{Execute, SessionRef};
+use crate::source::{EmbeddedMigrations, FileSource, MigrationSource};
+use crate::Migrator;
+use scylla::statement::Consistency;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builder for a [`Migrator`] with non-default settings
+///
+/// # Example
+/// ```no_run
+/// use scylla_migrate::Migrator;
+/// use std::time::Duration;
+///
+/// # async fn migrate(session: &scylla::Session) -> scylla_migrate::Result<()> {
+/// let runner = Migrator::builder()
+///     .history_table("ops.schema_migrations")
+///     .lock_wait(Duration::from_secs(300))
+///     .build(session, "migrations");
+/// runner.run().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MigratorBuilder {
+    options: MigratorOptions,
+    migrations: Vec<Migration>,
+}
+
+impl MigratorBuilder {
+    /// Sets the table recording applied migrations, `public.migrations` by default
+    ///
+    /// Accepts `keyspace.table`, or a bare table name in the `public` keyspace.
+    /// The keyspace is created if it doesn't exist, and also holds the
+    /// migration lock. The name is used in CQL verbatim, so it must be a valid
+    /// unquoted identifier.
+    pub fn history_table(mut self, name: &str) -> Self {
+        self.options.history = HistoryTable::parse(name);
+        self
+    }
+
+    /// Sets the replication of the history keyspace, a replication factor of 1 by default
+    ///
+    /// Used when the keyspace is created. An existing keyspace replicated
+    /// differently is reported with a warning, or altered with
+    /// [`alter_history_replication`](Self::alter_history_replication).
+    pub fn history_replication(mut self, replication: Replication) -> Self {
+        self.options.history_replication = Some(replication);
+        self
+    }
+
+    /// Alters an existing history keyspace to the configured
+    /// [`history_replication`](Self::history_replication) if it differs
+    ///
+    /// Run a repair afterwards when increasing the replication factor.
+    pub fn alter_history_replication(mut self, alter: bool) -> Self {
+        self.options.alter_history_replication = alter;
+        self
+    }
+
+    /// Sets whether the runner creates and upgrades the history keyspace and
+    /// tables, true by default
+    ///
+    /// Turn this off when the migration user may not create keyspaces or
+    /// tables; the history schema then has to be created beforehand, and runs
+    /// fail with [`Error::MissingHistorySchema`](crate::Error::MissingHistorySchema)
+    /// if any part of it is missing.
+    pub fn manage_history_schema(mut self, manage: bool) -> Self {
+        self.options.manage_history_schema = manage;
+        self
+    }
+
+    /// Sets how long to wait for another runner to release the migration lock, 60s by default
+    pub fn lock_wait(mut self, wait: Duration) -> Self {
+        self.options.lock_wait = wait;
+        self
+    }
+
+    /// Sets how long the migration lock stays valid without being extended, 60s by default
+    ///
+    /// A running migrator extends its lease every third of it; the lease only
+    /// matters when a runner dies while holding the lock. Leases shorter than
+    /// [`MIN_LOCK_LEASE`], the resolution of the lock's TTL, are raised to it.
+    pub fn lock_lease(mut self, lease: Duration) -> Self {
+        self.options.lock_lease = lease.max(MIN_LOCK_LEASE);
+        self
+    }
+
+    /// Sets how many tenant keyspaces [`Migrator::run_tenants`] migrates at
+    /// once, 1 by default
+    ///
+    /// Each tenant has its own lock and history, so they can be migrated in
+    /// parallel. Raise it for large fleets of tenants, keeping in mind that
+    /// every tenant in flight issues schema changes to the same cluster.
+    pub fn tenant_concurrency(mut self, concurrency: usize) -> Self {
+        self.options.tenant_concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets how applied migrations whose checksum changed are handled,
+    /// [`ChecksumPolicy::Error`] by default
+    pub fn on_checksum_mismatch(mut self, policy: ChecksumPolicy) -> Self {
+        self.options.checksum_policy = policy;
+        self
+    }
+
+    /// Sets how pending migrations older than the newest applied one are
+    /// handled, [`OutOfOrderPolicy::Error`] by default
+    pub fn out_of_order(mut self, policy: OutOfOrderPolicy) -> Self {
+        self.options.out_of_order = policy;
+        self
+    }
+
+    /// Sets how applied migrations missing locally are handled by runs and
+    /// [`Migrator::validate`](crate::Migrator::validate), [`MissingPolicy::Error`] by default
+    pub fn on_missing_migration(mut self, policy: MissingPolicy) -> Self {
+        self.options.missing_policy = policy;
+        self
+    }
+
+    /// Sets how pending migrations requiring another database or version than
+    /// the cluster runs are handled, [`RequirementPolicy::Error`] by default
+    ///
+    /// Skipping them lets one migrations directory serve clusters of mixed
+    /// versions: they stay pending and are applied once the cluster is upgraded.
+    /// Migrations with a `requires` directive are exempt from the
+    /// [`OutOfOrderPolicy`], so newer migrations applied meanwhile don't make
+    /// them fail the run.
+    pub fn on_unmet_requirement(mut self, policy: RequirementPolicy) -> Self {
+        self.options.requirement_policy = policy;
+        self
+    }
+
+    /// Sets how pending statements of a [`Destructive`] kind are handled,
+    /// [`DestructivePolicy::Allow`] by default
+    ///
+    /// A production setup can deny e.g. `DROP KEYSPACE` and `TRUNCATE`
+    /// outright; a run then fails with [`Error::Destructive`](crate::Error::Destructive)
+    /// before applying anything.
+    ///
+    /// ```ignore
+    /// Migrator::builder()
+    ///     .on_destructive(Destructive::DropKeyspace, DestructivePolicy::Deny)
+    ///     .on_destructive(Destructive::Truncate, DestructivePolicy::Deny)
+    ///     .build(&session, "migrations");
+    /// ```
+    pub fn on_destructive(mut self, kind: Destructive, policy: DestructivePolicy) -> Self {
+        self.options.destructive.insert(kind, policy);
+        self
+    }
+
+    /// Lints the pending migrations before a run applies any of them
+    ///
+    /// Any finding fails the run, and [`Migrator::plan`](crate::Migrator::plan),
+    /// with [`Error::Lint`](crate::Error::Lint). Placeholders are already
+    /// replaced at that point, so [`BuiltinRule::LiteralReplicationFactor`](crate::BuiltinRule::LiteralReplicationFactor)
+    /// should be allowed if replication factors are placeholders.
+    pub fn lint(mut self, linter: Linter) -> Self {
+        self.options.linter = Some(linter);
+        self
+    }
+
+    /// Sets the consistency level migration statements are executed with,
+    /// instead of the session's default
+    pub fn statement_consistency(mut self, consistency: Consistency) -> Self {
+        self.options.statement_consistency = Some(consistency);
+        self
+    }
+
+    /// Sets the consistency level the history is read and written with,
+    /// instead of the session's default
+    ///
+    /// With several datacenters, use e.g. `LocalQuorum` or `Quorum` so a runner
+    /// never reads a stale history and applies a migration twice.
+    pub fn history_consistency(mut self, consistency: Consistency) -> Self {
+        self.options.history_consistency = Some(consistency);
+        self
+    }
+
+    /// Sets whether to wait for schema agreement after every DDL statement,
+    /// [`SchemaAgreement::Auto`] by default
+    pub fn schema_agreement(mut self, schema_agreement: SchemaAgreement) -> Self {
+        self.options.schema_agreement = schema_agreement;
+        self
+    }
+
+    /// Waits for up to `timeout` for the materialized view or secondary index
+    /// a `CREATE MATERIALIZED VIEW` or `CREATE INDEX` statement creates to be
+    /// built on every node before running the next statement
+    ///
+    /// Queries of a view or index still being built miss rows, so later
+    /// statements and the application can rely on it once a migration is
+    /// applied. Runs fail with [`Error::BuildTimeout`](crate::Error::BuildTimeout)
+    /// when it takes longer.
+    pub fn wait_for_builds(mut self, timeout: Duration) -> Self {
+        self.options.wait_for_builds = Some(timeout);
+        self
+    }
+
+    /// Sets how migration statements and history queries failing with
+    /// transient errors are retried, [`RetryPolicy::none`] by default
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.options.retry = retry;
+        self
+    }
+
+    /// Sets the value of the `${name}` placeholders in migration scripts
+    ///
+    /// Placeholders are replaced before a script is split into statements;
+    /// a run fails with [`Error::UndefinedVariable`](crate::Error::UndefinedVariable)
+    /// if one has no value. Write `$${name}` for a literal `${name}`. The
+    /// checksum is computed from the script as written, so changing a value
+    /// does not mark applied migrations as modified.
+    ///
+    /// ```ignore
+    /// Migrator::builder()
+    ///     .variable("keyspace", "app_staging")
+    ///     .build(&session, "migrations");
+    /// ```
+    pub fn variable(mut self, name: &str, value: &str) -> Self {
+        self.options
+            .variables
+            .values
+            .insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Rewrites the references to the keyspace `from` in migration scripts to `to`
+    ///
+    /// For applying migrations that name their keyspace to a copy of it, such
+    /// as a [`ScratchKeyspace`](crate::test::ScratchKeyspace). Qualified names
+    /// like `from.users` and the keyspace of `CREATE`, `ALTER` and `DROP
+    /// KEYSPACE` and `USE` are rewritten after placeholders are replaced. Like
+    /// variables, renames don't change checksums.
+    pub fn rename_keyspace(mut self, from: &str, to: &str) -> Self {
+        self.options
+            .keyspace_renames
+            .push((from.to_string(), to.to_string()));
+        self
+    }
+
+    /// Sets whether placeholders without a [`variable`](Self::variable) are
+    /// taken from the environment variable of the same name, false by default
+    pub fn variables_from_env(mut self, from_env: bool) -> Self {
+        self.options.variables.from_env = from_env;
+        self
+    }
+
+    /// Sets the environment being migrated, e.g. `prod`
+    ///
+    /// Migrations limited to environments with an `env=dev|staging` directive
+    /// only run in those environments; without an environment set, they never
+    /// run. Migrations without the directive run everywhere.
+    pub fn environment(mut self, environment: &str) -> Self {
+        self.options.environment = Some(environment.to_string());
+        self
+    }
+
+    /// Sets who is recorded as having applied migrations, the OS user by default
+    ///
+    /// Pass the cluster user to audit who changed the schema rather than
+    /// where the runner was started.
+    pub fn applied_by(mut self, name: &str) -> Self {
+        self.options.applied_by = Some(name.to_string());
+        self
+    }
+
+    /// Sets whether to record every action of the runner in the audit log, off by default
+    ///
+    /// Runs, lock acquisitions and releases, applied, failed, skipped and
+    /// reverted migrations, repairs, baselines and faked migrations are
+    /// recorded with who did them and from where in the `migration_audit`
+    /// table of the history keyspace. Read it with
+    /// [`Migrator::audit_log`](crate::Migrator::audit_log).
+    pub fn audit(mut self, enabled: bool) -> Self {
+        self.options.audit = enabled;
+        self
+    }
+
+    /// Adds patterns of clusters, keyspaces and environments
+    /// [`Migrator::clean`](crate::Migrator::clean) refuses to clean, besides
+    /// the default `*prod*`
+    ///
+    /// Patterns match names ignoring case, `*` standing for any number of
+    /// characters and `?` for a single one. Empty patterns are ignored.
+    pub fn protect(mut self, patterns: &[&str]) -> Self {
+        self.options.protected.extend(
+            patterns
+                .iter()
+                .filter(|pattern| !pattern.trim().is_empty())
+                .map(|pattern| pattern.to_string()),
+        );
+        self
+    }
+
+    /// Sets whether [`Migrator::clean`](crate::Migrator::clean) refuses to
+    /// clean names matching `*prod*`, on by default
+    ///
+    /// Turning it off only drops the default pattern, those of
+    /// [`MigratorBuilder::protect`] still apply.
+    pub fn default_protection(mut self, enabled: bool) -> Self {
+        self.options
+            .protected
+            .retain(|pattern| pattern != PROTECTED);
+        if enabled {
+            self.options.protected.insert(0, PROTECTED.to_string());
+        }
+        self
+    }
+
+    /// Records applied, failed and pending migrations in `metrics`
+    ///
+    /// Pass the same [`Metrics`] to every migrator whose runs it should
+    /// count, and serve [`Metrics::render`] to Prometheus.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.options.metrics = Some(metrics);
+        self
+    }
+
+    /// Registers a hook called with every [`MigrationEvent`] of a run
+    ///
+    /// Hooks run inline between statements, so they should return quickly;
+    /// hand the event off to a channel for anything slow.
+    ///
+    /// ```ignore
+    /// Migrator::builder()
+    ///     .on_event(|event| {
+    ///         if let MigrationEvent::MigrationApplied { migration, .. } = event {
+    ///             progress.inc(1);
+    ///         }
+    ///     })
+    ///     .build(&session, "migrations");
+    /// ```
+    pub fn on_event(mut self, hook: impl Fn(&MigrationEvent<'_>) + Send + Sync + 'static) -> Self {
+        self.options.event_hooks.push(EventHook::new(hook));
+        self
+    }
+
+    /// Registers a hook called once before a run applies anything
+    ///
+    /// It receives the migrations about to be applied and is skipped if
+    /// there are none. An error stops the run before the first migration.
+    pub fn before_all(mut self, hook: impl RunHook + 'static) -> Self {
+        self.options.hooks.before_all.push(Arc::new(hook));
+        self
+    }
+
+    /// Registers a hook called before every migration a run applies
+    ///
+    /// An error stops the run without executing the migration.
+    pub fn before_each(mut self, hook: impl MigrationHook + 'static) -> Self {
+        self.options.hooks.before_each.push(Arc::new(hook));
+        self
+    }
+
+    /// Registers a hook called after every migration a run applied and recorded
+    ///
+    /// An error stops the run; the migration stays applied.
+    pub fn after_each(mut self, hook: impl MigrationHook + 'static) -> Self {
+        self.options.hooks.after_each.push(Arc::new(hook));
+        self
+    }
+
+    /// Registers a hook called once after a run applied all of its migrations
+    ///
+    /// It receives the applied migrations and is neither called for a run
+    /// that applied nothing nor for one that failed.
+    pub fn after_all(mut self, hook: impl RunHook + 'static) -> Self {
+        self.options.hooks.after_all.push(Arc::new(hook));
+        self
+    }
+
+    /// Registers a migration alongside those of the source
+    ///
+    /// Mainly meant for [`CodeMigration`](crate::CodeMigration)s, which have
+    /// no file to live in:
+    ///
+    /// ```ignore
+    /// Migrator::builder()
+    ///     .migration(Migration::code(20240201000000, "backfill_emails".into(), BackfillEmails))
+    ///     .build(&session, "migrations");
+    /// ```
+    pub fn migration(mut self, migration: Migration) -> Self {
+        self.migrations.push(migration);
+        self
+    }
+
+    /// Creates a Migrator reading migrations from the `migrations_src` directory
+    ///
+    /// With the `templates` feature, `.cql.tera` files are rendered with the
+    /// [`variable`](Self::variable)s of this builder.
+    pub fn build<'a>(self, session: &'a impl Execute, migrations_src: &'a str) -> Migrator<'a> {
+        let source = self.file_source(migrations_src);
+        self.build_with_source(session, source)
+    }
+
+    /// Like [`build`](Self::build), but the Migrator shares ownership of
+    /// `session`, so it can be stored in application state or moved into a
+    /// spawned task
+    pub fn build_arc(
+        self,
+        session: Arc<impl Execute + 'static>,
+        migrations_src: impl AsRef<Path>,
+    ) -> Migrator<'static> {
+        let source = self.file_source(migrations_src);
+        self.build_arc_with_source(session, source)
+    }
+
+    fn file_source(&self, migrations_src: impl AsRef<Path>) -> FileSource {
+        #[allow(unused_mut)]
+        let mut source = FileSource::new(migrations_src);
+        #[cfg(feature = "templates")]
+        {
+            let mut context = tera::Context::new();
+            for (name, value) in &self.options.variables.values {
+                context.insert(name, value);
+            }
+            source = source.template_context(context);
+        }
+        source
+    }
+
+    /// Creates a Migrator for migrations compiled into the binary
+    pub fn build_embedded<'a>(
+        self,
+        session: &'a impl Execute,
+        migrations: &'a EmbeddedMigrations,
+    ) -> Migrator<'a> {
+        self.build_with_source(session, *migrations)
+    }
+
+    /// Creates a Migrator taking its migrations from `source`
+    pub fn build_with_source<'a>(
+        self,
+        session: &'a impl Execute,
+        source: impl MigrationSource + 'a,
+    ) -> Migrator<'a> {
+        self.build_from(SessionRef::Borrowed(session), source)
+    }
+
+    /// Like [`build_with_source`](Self::build_with_source), sharing ownership
+    /// of `session` like [`build_arc`](Self::build_arc)
+    ///
+    /// Embedded migrations are a `'static` source too:
+    /// `build_arc_with_source(session, MIGRATIONS)`.
+    pub fn build_arc_with_source(
+        self,
+        session: Arc<impl Execute + 'static>,
+        source: impl MigrationSource + 'static,
+    ) -> Migrator<'static> {
+        self.build_from(SessionRef::Shared(session), source)
+    }
+
+    fn build_from<'a>(
+        self,
+        session: SessionRef<'a>,
+        source: impl MigrationSource + 'a,
+    ) -> Migrator<'a> {
+        Migrator {
+            session,
+            source: Arc::new(source),
+            registered: self.migrations,
+            options: self.options,
+            prepared: Default::default(),
+            server: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_protected_patterns_to_the_default() {
+        let builder = MigratorBuilder::default().protect(&["*live*", " "]);
+        assert_eq!(builder.options.protected, ["*prod*", "*live*"]);
+
+        let builder = builder.default_protection(false);
+        assert_eq!(builder.options.protected, ["*live*"]);
+        let builder = builder.default_protection(true);
+        assert_eq!(builder.options.protected, ["*prod*", "*live*"]);
+    }
+
+    #[test]
+    fn raises_short_lock_leases_to_the_minimum() {
+        let builder = MigratorBuilder::default().lock_lease(Duration::ZERO);
+        assert_eq!(builder.options.lock_lease, MIN_LOCK_LEASE);
+        let builder = builder.lock_lease(Duration::from_millis(300));
+        assert_eq!(builder.options.lock_lease, MIN_LOCK_LEASE);
+        let builder = builder.lock_lease(Duration::from_secs(30));
+        assert_eq!(builder.options.lock_lease, Duration::from_secs(30));
+    }
+}
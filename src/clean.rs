@@ -0,0 +1,169 @@
+use crate::error::{Error, Result};
+use crate::schema::{ident, types_in_dependency_order, Schema};
+use crate::tenant::matches;
+use scylla::Session;
+
+/// The outcome of [`Migrator::clean`](crate::Migrator::clean)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Clean {
+    /// The dropped objects in the order they were dropped, e.g. `table app.users`
+    pub dropped: Vec<String>,
+    /// The migrations removed from the history, to be applied again, e.g.
+    /// `1/migrate create users`
+    pub forgotten: Vec<String>,
+}
+
+/// The history record of an applied migration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Record {
+    Versioned(i64),
+    Repeatable(String),
+}
+
+/// Patterns of the clusters, keyspaces and environments that are never
+/// cleaned, unless configured otherwise
+pub(crate) const PROTECTED: &str = "*prod*";
+
+/// Fails with [`Error::Protected`] if one of `names`, e.g. the cluster name,
+/// matches a protected pattern, ignoring case
+pub(crate) fn check_protected(
+    keyspace: &str,
+    patterns: &[String],
+    names: &[(&str, &str)],
+) -> Result<()> {
+    for (kind, name) in names {
+        if let Some(pattern) = patterns
+            .iter()
+            .find(|pattern| matches(&pattern.to_lowercase(), &name.to_lowercase()))
+        {
+            return Err(Error::Protected {
+                keyspace: keyspace.to_string(),
+                name: format!("{} {}", kind, name),
+                pattern: pattern.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn cluster_name(session: &Session) -> Result<Option<String>> {
+    let (name,): (Option<String>,) = session
+        .query_unpaged("SELECT cluster_name FROM system.local", &[])
+        .await?
+        .into_rows_result()?
+        .first_row()?;
+    Ok(name)
+}
+
+/// Returns the objects of `keyspace` with the statements dropping them, in an
+/// order they can be dropped in
+///
+/// Views go before the tables they select from, which take their indexes
+/// along, and functions before the types of their arguments. Tables named in
+/// `keep` are left out.
+pub(crate) async fn drop_statements(
+    session: &Session,
+    keyspace: &str,
+    keep: &[String],
+) -> Result<Vec<(String, String)>> {
+    let schema = Schema::read(session, &[keyspace.to_string()]).await?;
+    let objects = &schema.keyspaces[keyspace];
+    let qualified = |name: &str| format!("{}.{}", ident(keyspace), ident(name));
+
+    let mut drops = Vec::new();
+    for name in objects.views.keys() {
+        drops.push((
+            format!("materialized view {}.{}", keyspace, name),
+            format!("DROP MATERIALIZED VIEW IF EXISTS {}", qualified(name)),
+        ));
+    }
+    for name in objects.tables.keys() {
+        if keep.contains(name) {
+            continue;
+        }
+        drops.push((
+            format!("table {}.{}", keyspace, name),
+            format!("DROP TABLE IF EXISTS {}", qualified(name)),
+        ));
+    }
+    for (kind, table, column) in [
+        ("AGGREGATE", "aggregates", "aggregate_name"),
+        ("FUNCTION", "functions", "function_name"),
+    ] {
+        let query_rows = session
+            .query_unpaged(
+                format!(
+                    "SELECT {}, argument_types FROM system_schema.{} WHERE keyspace_name = ?",
+                    column, table
+                ),
+                (keyspace,),
+            )
+            .await?
+            .into_rows_result()?;
+        for row in query_rows.rows()? {
+            let (name, arguments): (String, Option<Vec<String>>) = row?;
+            let arguments = arguments.unwrap_or_default().join(", ");
+            drops.push((
+                format!(
+                    "{} {}.{}({})",
+                    kind.to_lowercase(),
+                    keyspace,
+                    name,
+                    arguments
+                ),
+                format!(
+                    "DROP {} IF EXISTS {}({})",
+                    kind,
+                    qualified(&name),
+                    arguments
+                ),
+            ));
+        }
+    }
+    let types = types_in_dependency_order(&objects.types);
+    for (name, _) in types.into_iter().rev() {
+        drops.push((
+            format!("type {}.{}", keyspace, name),
+            format!("DROP TYPE IF EXISTS {}", qualified(name)),
+        ));
+    }
+    Ok(drops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protected(patterns: &[&str], names: &[(&str, &str)]) -> Option<String> {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        match check_protected("app", &patterns, names) {
+            Ok(()) => None,
+            Err(Error::Protected { name, pattern, .. }) => Some(format!("{} by {}", name, pattern)),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    #[test]
+    fn refuses_protected_names_ignoring_case() {
+        assert_eq!(
+            protected(
+                &[PROTECTED],
+                &[("keyspace", "app"), ("cluster", "Production-EU")]
+            ),
+            Some("cluster Production-EU by *prod*".to_string())
+        );
+        assert_eq!(
+            protected(&["*prod*", "LIVE_?"], &[("environment", "live_1")]),
+            Some("environment live_1 by LIVE_?".to_string())
+        );
+    }
+
+    #[test]
+    fn allows_unprotected_names() {
+        assert_eq!(
+            protected(&[PROTECTED], &[("keyspace", "app"), ("cluster", "staging")]),
+            None
+        );
+        assert_eq!(protected(&[], &[("cluster", "production")]), None);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::error::{Error, Result};
+#[cfg(feature = "cloud")]
+use scylla::authentication::PlainTextAuthenticator;
+use scylla::transport::errors::{DbError, NewSessionError};
+#[cfg(feature = "cloud")]
+use scylla::CloudSessionBuilder;
+use scylla::{Session, SessionBuilder};
+#[cfg(feature = "cloud")]
+use std::collections::HashMap;
+#[cfg(feature = "ssl")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "cloud")]
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tracing::info;
+
+/// How to reach a cluster, as used by the command line tool
+///
+/// Applications usually build their own [`Session`]; this covers programs that
+/// take connection settings from their users. Anything else the driver supports
+/// can be set on a [`SessionBuilder`] passed to
+/// [`ConnectOptions::from_session_builder`].
+///
+/// # Example
+/// ```no_run
+/// use scylla_migrate::{ConnectOptions, Migrator};
+///
+/// async fn migrate() -> scylla_migrate::Result<()> {
+///     let session = ConnectOptions::new("localhost:9042")
+///         .credentials("cassandra", "cassandra")
+///         .connect()
+///         .await?;
+///
+///     Migrator::new(&session, "migrations").run().await?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ConnectOptions {
+    builder: Builder,
+    #[cfg(feature = "ssl")]
+    tls: Option<TlsOptions>,
+    wait: Option<Duration>,
+}
+
+#[derive(Clone)]
+enum Builder {
+    Nodes(SessionBuilder),
+    #[cfg(feature = "cloud")]
+    Cloud(CloudSessionBuilder),
+}
+
+impl ConnectOptions {
+    /// Connects through the node at `uri`
+    pub fn new(uri: &str) -> Self {
+        Self::from_session_builder(SessionBuilder::new().known_node(uri))
+    }
+
+    /// Starts from a pre-configured builder, e.g. with its own load balancing
+    /// policy or TLS context
+    pub fn from_session_builder(builder: SessionBuilder) -> Self {
+        Self {
+            builder: Builder::Nodes(builder),
+            #[cfg(feature = "ssl")]
+            tls: None,
+            wait: None,
+        }
+    }
+
+    /// Connects to a ScyllaDB Cloud cluster described by a connection bundle
+    ///
+    /// The bundle provides the node addresses, the TLS material and, if it
+    /// contains them, the credentials of its current context.
+    #[cfg(feature = "cloud")]
+    pub fn cloud(bundle: impl AsRef<Path>) -> Result<Self> {
+        let bundle = bundle.as_ref();
+        let builder = CloudSessionBuilder::new(bundle).map_err(|source| Error::CloudBundle {
+            path: bundle.to_path_buf(),
+            source: Box::new(source),
+        })?;
+
+        let options = Self {
+            builder: Builder::Cloud(builder),
+            tls: None,
+            wait: None,
+        };
+        Ok(match bundle_credentials(bundle) {
+            Some((user, password)) => options.credentials(&user, &password),
+            None => options,
+        })
+    }
+
+    /// Authenticates with a username and password
+    pub fn credentials(mut self, user: &str, password: &str) -> Self {
+        self.builder = match self.builder {
+            Builder::Nodes(builder) => Builder::Nodes(builder.user(user, password)),
+            #[cfg(feature = "cloud")]
+            Builder::Cloud(mut builder) => {
+                // Cloud builders lack `user()`, the authenticator is set directly
+                builder.config.authenticator = Some(Arc::new(PlainTextAuthenticator::new(
+                    user.to_string(),
+                    password.to_string(),
+                )));
+                Builder::Cloud(builder)
+            }
+        };
+        self
+    }
+
+    /// Encrypts the connections, replacing any TLS context of the session builder
+    ///
+    /// Ignored for cloud bundles, which bring their own TLS material.
+    #[cfg(feature = "ssl")]
+    pub fn tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Keeps trying to connect for up to `timeout` while the cluster is unreachable
+    ///
+    /// Meant for starting migrations alongside a cluster that is still booting,
+    /// e.g. in docker-compose or Kubernetes. Failed authentication is not retried.
+    pub fn wait_for(mut self, timeout: Duration) -> Self {
+        self.wait = Some(timeout);
+        self
+    }
+
+    /// Opens a session to the cluster
+    pub async fn connect(&self) -> Result<Session> {
+        let Some(timeout) = self.wait else {
+            return self.connect_once().await;
+        };
+
+        let started = Instant::now();
+        loop {
+            match self.connect_once().await {
+                Err(Error::Connect(err)) if is_unreachable(&err) && started.elapsed() < timeout => {
+                    info!("Waiting for the cluster to accept connections: {}", err);
+                    sleep(WAIT_INTERVAL.min(timeout.saturating_sub(started.elapsed()))).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn connect_once(&self) -> Result<Session> {
+        let session = match &self.builder {
+            Builder::Nodes(builder) => {
+                #[allow(unused_mut)]
+                let mut builder = builder.clone();
+                #[cfg(feature = "ssl")]
+                if let Some(tls) = &self.tls {
+                    builder = builder.ssl_context(Some(tls.ssl_context()?));
+                }
+                builder.build().await
+            }
+            #[cfg(feature = "cloud")]
+            Builder::Cloud(builder) => builder.build().await,
+        };
+
+        session.map_err(|err| Error::Connect(Box::new(err)))
+    }
+}
+
+/// How long to wait between two connection attempts of [`ConnectOptions::wait_for`]
+const WAIT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Returns false for errors that waiting won't fix, such as bad credentials
+fn is_unreachable(err: &NewSessionError) -> bool {
+    !matches!(
+        err,
+        NewSessionError::EmptyKnownNodesList
+            | NewSessionError::BadQuery(_)
+            | NewSessionError::DbError(DbError::AuthenticationError | DbError::Unauthorized, _)
+    )
+}
+
+/// The parts of a connection bundle needed to find its credentials
+#[cfg(feature = "cloud")]
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Bundle {
+    auth_infos: HashMap<String, BundleAuthInfo>,
+    contexts: HashMap<String, BundleContext>,
+    current_context: String,
+}
+
+#[cfg(feature = "cloud")]
+#[derive(serde::Deserialize)]
+struct BundleAuthInfo {
+    username: Option<String>,
+    password: Option<String>,
+}
+
+#[cfg(feature = "cloud")]
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BundleContext {
+    auth_info_name: String,
+}
+
+/// Returns the username and password of the bundle's current context, if it has them
+///
+/// The driver only takes the TLS material from a bundle, so the credentials are
+/// read separately. The bundle has been validated by the driver already.
+#[cfg(feature = "cloud")]
+fn bundle_credentials(path: &Path) -> Option<(String, String)> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut bundle: Bundle = serde_yaml::from_reader(file).ok()?;
+    let context = bundle.contexts.get(&bundle.current_context)?;
+    let auth_info = bundle.auth_infos.remove(&context.auth_info_name)?;
+    auth_info.username.zip(auth_info.password)
+}
+
+/// TLS settings for encrypted client connections
+///
+/// Without a CA file, the server certificate is verified against the system's
+/// trusted certificates.
+#[cfg(feature = "ssl")]
+#[derive(Debug, Clone)]
+pub struct TlsOptions {
+    ca: Option<PathBuf>,
+    client_cert: Option<(PathBuf, PathBuf)>,
+    verify: bool,
+}
+
+#[cfg(feature = "ssl")]
+impl Default for TlsOptions {
+    fn default() -> Self {
+        Self {
+            ca: None,
+            client_cert: None,
+            verify: true,
+        }
+    }
+}
+
+#[cfg(feature = "ssl")]
+impl TlsOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Verifies the server certificate against the PEM encoded CA certificates in `path`
+    pub fn ca_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.ca = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Authenticates with a PEM encoded client certificate and private key, for mTLS
+    pub fn client_cert(mut self, cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Self {
+        self.client_cert = Some((cert.as_ref().to_path_buf(), key.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Sets whether the server certificate is verified, true by default
+    ///
+    /// Only turn this off against test clusters with self-signed certificates.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    fn ssl_context(&self) -> Result<openssl::ssl::SslContext> {
+        use openssl::ssl::{SslContextBuilder, SslFiletype, SslMethod, SslVerifyMode};
+
+        let tls_error = |path: Option<&Path>| {
+            let path = path.map(Path::to_path_buf);
+            move |source| Error::Tls { path, source }
+        };
+
+        let mut context = SslContextBuilder::new(SslMethod::tls()).map_err(tls_error(None))?;
+        match &self.ca {
+            Some(ca) => context.set_ca_file(ca).map_err(tls_error(Some(ca)))?,
+            None => context
+                .set_default_verify_paths()
+                .map_err(tls_error(None))?,
+        }
+        if let Some((cert, key)) = &self.client_cert {
+            context
+                .set_certificate_chain_file(cert)
+                .map_err(tls_error(Some(cert)))?;
+            context
+                .set_private_key_file(key, SslFiletype::PEM)
+                .map_err(tls_error(Some(key)))?;
+            context.check_private_key().map_err(tls_error(Some(key)))?;
+        }
+        context.set_verify(if self.verify {
+            SslVerifyMode::PEER
+        } else {
+            SslVerifyMode::NONE
+        });
+
+        Ok(context.build())
+    }
+}
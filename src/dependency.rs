@@ -0,0 +1,86 @@
+use crate::error::{Error, Result};
+use crate::migration::{AppliedHistory, Migration, MigrationKind};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Orders migrations so every one comes after the versions it depends on
+///
+/// Migrations keep their order otherwise, so without `depends-on` directives
+/// nothing moves. Dependencies on versions that aren't local don't affect the
+/// order, [`check`] fails on them if they aren't applied either.
+pub(crate) fn order(migrations: Vec<Migration>) -> Result<Vec<Migration>> {
+    let dependencies = migrations
+        .iter()
+        .map(|migration| Ok(migration.directives()?.depends_on))
+        .collect::<Result<Vec<_>>>()?;
+    if dependencies.iter().all(Vec::is_empty) {
+        return Ok(migrations);
+    }
+
+    let positions: HashMap<i64, usize> = migrations
+        .iter()
+        .enumerate()
+        .filter(|(_, migration)| migration.kind == MigrationKind::Versioned)
+        .map(|(position, migration)| (migration.version, position))
+        .collect();
+    let mut waiting = vec![0; migrations.len()];
+    let mut dependents = vec![Vec::new(); migrations.len()];
+    for (position, versions) in dependencies.iter().enumerate() {
+        for version in versions {
+            if let Some(&dependency) = positions.get(version) {
+                waiting[position] += 1;
+                dependents[dependency].push(position);
+            }
+        }
+    }
+
+    // Of the migrations whose dependencies are placed, the earliest goes next
+    let mut ready: BinaryHeap<_> = (0..migrations.len())
+        .filter(|&position| waiting[position] == 0)
+        .map(Reverse)
+        .collect();
+    let mut order = Vec::with_capacity(migrations.len());
+    while let Some(Reverse(position)) = ready.pop() {
+        order.push(position);
+        for &dependent in &dependents[position] {
+            waiting[dependent] -= 1;
+            if waiting[dependent] == 0 {
+                ready.push(Reverse(dependent));
+            }
+        }
+    }
+
+    if order.len() < migrations.len() {
+        let versions = (0..migrations.len())
+            .filter(|&position| waiting[position] > 0)
+            .map(|position| migrations[position].version)
+            .collect();
+        return Err(Error::DependencyCycle { versions });
+    }
+
+    let mut migrations: Vec<_> = migrations.into_iter().map(Some).collect();
+    Ok(order
+        .into_iter()
+        .filter_map(|position| migrations[position].take())
+        .collect())
+}
+
+/// Fails if a migration about to run depends on a version that is neither
+/// applied nor run before it
+pub(crate) fn check(pending: &[&Migration], applied: &AppliedHistory) -> Result<()> {
+    let mut run = HashSet::new();
+    for migration in pending {
+        for dependency in migration.directives()?.depends_on {
+            if !applied.versioned.contains_key(&dependency) && !run.contains(&dependency) {
+                return Err(Error::MissingDependency {
+                    migration: migration.to_string(),
+                    dependency,
+                });
+            }
+        }
+        if migration.kind == MigrationKind::Versioned {
+            run.insert(migration.version);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use crate::token::{tokens, Token};
+use std::fmt;
+
+/// A statement that destroys schema or data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Destructive {
+    /// `DROP KEYSPACE`
+    DropKeyspace,
+    /// `DROP TABLE`
+    DropTable,
+    /// Any other `DROP`, such as of an index, view or type
+    Drop,
+    /// `TRUNCATE`
+    Truncate,
+    /// `ALTER TABLE ... DROP`, dropping columns
+    DropColumn,
+}
+
+impl Destructive {
+    /// Classifies a single CQL statement, returning `None` for harmless ones
+    ///
+    /// Only the keywords of the statement are looked at; string literals,
+    /// quoted identifiers and comments are skipped.
+    pub fn of(statement: &str) -> Option<Self> {
+        let words: Vec<&str> = tokens(statement)
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word),
+                _ => None,
+            })
+            .collect();
+        let word = |i: usize| words.get(i).map(|w| w.to_ascii_uppercase());
+
+        match word(0)?.as_str() {
+            "TRUNCATE" => Some(Destructive::Truncate),
+            "DROP" => match word(1).as_deref() {
+                Some("KEYSPACE" | "SCHEMA") => Some(Destructive::DropKeyspace),
+                Some("TABLE" | "COLUMNFAMILY") => Some(Destructive::DropTable),
+                _ => Some(Destructive::Drop),
+            },
+            "ALTER" if matches!(word(1).as_deref(), Some("TABLE" | "COLUMNFAMILY")) => words[2..]
+                .iter()
+                .any(|w| w.eq_ignore_ascii_case("DROP"))
+                .then_some(Destructive::DropColumn),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Destructive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Destructive::DropKeyspace => "DROP KEYSPACE",
+            Destructive::DropTable => "DROP TABLE",
+            Destructive::Drop => "DROP",
+            Destructive::Truncate => "TRUNCATE",
+            Destructive::DropColumn => "ALTER TABLE ... DROP",
+        })
+    }
+}
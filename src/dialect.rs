@@ -0,0 +1,123 @@
+use crate::error::{Error, Result};
+use crate::migration::Migration;
+use crate::server::Product;
+use crate::token::{tokens, Token};
+use std::borrow::Cow;
+
+/// Returns true if `cql` has blocks limited to one database
+pub(crate) fn has_blocks(cql: &str) -> bool {
+    cql.lines().any(|line| marker(line).is_some())
+}
+
+/// Resolves the blocks of the up and down scripts of `migration` for `product`
+///
+/// The checksum stays that of the whole script, so one applied on ScyllaDB and
+/// Cassandra is the same migration.
+pub(crate) fn resolve(migration: &mut Migration, product: Product) -> Result<()> {
+    let invalid = |reason| Error::Parse {
+        name: migration.description.to_string(),
+        reason,
+    };
+    if has_blocks(&migration.cql) {
+        let cql = select(&migration.cql, product).map_err(invalid)?;
+        migration.cql = Cow::Owned(cql);
+    }
+    if let Some(down) = migration.down.as_deref().filter(|down| has_blocks(down)) {
+        let down = select(down, product).map_err(invalid)?;
+        migration.down = Some(Cow::Owned(down));
+    }
+    Ok(())
+}
+
+/// Keeps the blocks of `cql` for `product` and drops those of other databases
+///
+/// A block starts at a `-- scylla-migrate: only=scylla` or `only=cassandra`
+/// line and ends at `-- scylla-migrate: end`, the next `only=` or the end of
+/// the script. The marker lines are dropped as well.
+fn select(cql: &str, product: Product) -> Result<String, &'static str> {
+    let mut selected = String::with_capacity(cql.len());
+    let mut block = None;
+    for line in cql.split_inclusive('\n') {
+        match marker(line) {
+            Some(only) => block = only?,
+            None if block.is_none_or(|only| only == product) => selected.push_str(line),
+            None => {}
+        }
+    }
+    Ok(selected)
+}
+
+/// Parses a block marker line into the database of the block it starts,
+/// `None` for `end`
+fn marker(line: &str) -> Option<Result<Option<Product>, &'static str>> {
+    let line = line.trim();
+    let directive = line
+        .strip_prefix("--")
+        .or_else(|| line.strip_prefix("//"))?
+        .trim()
+        .strip_prefix("scylla-migrate:")?
+        .trim();
+    if directive == "end" {
+        return Some(Ok(None));
+    }
+    let product = directive.strip_prefix("only")?.trim().strip_prefix('=')?;
+    Some(match product.trim().to_ascii_lowercase().as_str() {
+        "scylla" | "scylladb" => Ok(Some(Product::Scylla)),
+        "cassandra" => Ok(Some(Product::Cassandra)),
+        _ => Err("invalid only, expected scylla or cassandra"),
+    })
+}
+
+/// Returns a feature of `statement` that only one database supports, and that database
+///
+/// Only features that fail or are silently ignored on the other database are
+/// looked for, such as ScyllaDB's `cdc` table option or Cassandra's SASI indexes.
+pub(crate) fn exclusive_feature(statement: &str) -> Option<(&'static str, Product)> {
+    let tokens = tokens(statement);
+    let option = |name: &str| {
+        tokens
+            .windows(2)
+            .any(|pair| pair[0].is(name) && pair[1] == Token::Symbol('='))
+    };
+    let words = |first: &str, second: &str| {
+        tokens
+            .windows(2)
+            .any(|pair| pair[0].is(first) && pair[1].is(second))
+    };
+    // The class of a custom index, e.g. org.apache.cassandra.index.sasi.SASIIndex
+    let custom_index = |is_class: fn(&str) -> bool| {
+        words("CUSTOM", "INDEX")
+            && tokens.iter().any(|token| {
+                matches!(token, Token::Literal(class) if is_class(&class.to_ascii_lowercase()))
+            })
+    };
+
+    // ScyllaDB configures CDC with a map, Cassandra with a flag
+    let cdc = tokens.windows(3).find_map(|window| {
+        (window[0].is("cdc") && window[1] == Token::Symbol('=')).then_some(window[2])
+    });
+
+    if cdc == Some(Token::Symbol('{')) {
+        Some(("the cdc option as a map", Product::Scylla))
+    } else if cdc.is_some_and(|value| value.is("true") || value.is("false")) {
+        Some(("the cdc option as a boolean", Product::Cassandra))
+    } else if option("tablets") {
+        Some(("tablets", Product::Scylla))
+    } else if option("per_partition_rate_limit") {
+        Some(("the per_partition_rate_limit option", Product::Scylla))
+    } else if words("USING", "TIMEOUT") {
+        Some(("USING TIMEOUT", Product::Scylla))
+    } else if words("BYPASS", "CACHE") {
+        Some(("BYPASS CACHE", Product::Scylla))
+    } else if custom_index(|class| class.ends_with("sasiindex")) {
+        Some(("a SASI index", Product::Cassandra))
+    } else if custom_index(|class| class == "sai" || class.ends_with("storageattachedindex")) {
+        Some(("a storage-attached index", Product::Cassandra))
+    } else if option("read_repair") {
+        Some(("the read_repair option", Product::Cassandra))
+    } else if option("additional_write_policy") {
+        Some(("the additional_write_policy option", Product::Cassandra))
+    } else {
+        None
+    }
+}
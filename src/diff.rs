@@ -0,0 +1,364 @@
+use crate::schema::{Column, ColumnKind, Index, KeyspaceSchema, Schema, TableSchema, ViewSchema};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A keyspace or an object within one
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SchemaObject {
+    Keyspace(String),
+    Type {
+        keyspace: String,
+        name: String,
+    },
+    Table {
+        keyspace: String,
+        name: String,
+    },
+    Column {
+        keyspace: String,
+        table: String,
+        name: String,
+    },
+    Index {
+        keyspace: String,
+        table: String,
+        name: String,
+    },
+    View {
+        keyspace: String,
+        name: String,
+    },
+}
+
+/// How an object differs between two schemas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// Only the second schema has it
+    Added,
+    /// Only the first schema has it
+    Removed,
+    /// Both have it, with different definitions
+    Altered { from: String, to: String },
+}
+
+/// An object that differs between two schemas, see [`Schema::diff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaDifference {
+    pub object: SchemaObject,
+    pub difference: Difference,
+}
+
+impl Schema {
+    /// Returns the objects that differ in `other`, keyspace by keyspace
+    ///
+    /// Objects of an added or removed keyspace or table are not listed on their
+    /// own. Replication, table options and view definitions are only compared
+    /// where both schemas have them, so a schema that leaves them empty, such as
+    /// one derived from migrations, only compares the objects themselves.
+    pub fn diff(&self, other: &Schema) -> Vec<SchemaDifference> {
+        let mut differences = Vec::new();
+        compare(
+            &self.keyspaces,
+            &other.keyspaces,
+            |name| SchemaObject::Keyspace(name.to_string()),
+            diff_keyspace,
+            &mut differences,
+        );
+        differences
+    }
+}
+
+/// Reports the keys of only one of the maps to `out` and calls `both` for
+/// the others, in key order
+fn compare<V>(
+    from: &BTreeMap<String, V>,
+    to: &BTreeMap<String, V>,
+    object: impl Fn(&str) -> SchemaObject,
+    mut both: impl FnMut(&str, &V, &V, &mut Vec<SchemaDifference>),
+    out: &mut Vec<SchemaDifference>,
+) {
+    let mut names: Vec<_> = from.keys().chain(to.keys()).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let difference = match (from.get(name), to.get(name)) {
+            (Some(from), Some(to)) => {
+                both(name, from, to, out);
+                continue;
+            }
+            (Some(_), None) => Difference::Removed,
+            _ => Difference::Added,
+        };
+        out.push(SchemaDifference {
+            object: object(name),
+            difference,
+        });
+    }
+}
+
+fn altered(object: SchemaObject, from: String, to: String) -> SchemaDifference {
+    SchemaDifference {
+        object,
+        difference: Difference::Altered { from, to },
+    }
+}
+
+fn diff_keyspace(
+    keyspace: &str,
+    from: &KeyspaceSchema,
+    to: &KeyspaceSchema,
+    out: &mut Vec<SchemaDifference>,
+) {
+    let definition = |ks: &KeyspaceSchema| {
+        format!(
+            "replication = {:?}, durable_writes = {}",
+            ks.replication, ks.durable_writes
+        )
+    };
+    if !from.replication.is_empty()
+        && !to.replication.is_empty()
+        && (from.replication != to.replication || from.durable_writes != to.durable_writes)
+    {
+        out.push(altered(
+            SchemaObject::Keyspace(keyspace.to_string()),
+            definition(from),
+            definition(to),
+        ));
+    }
+
+    compare(
+        &from.types,
+        &to.types,
+        |name| SchemaObject::Type {
+            keyspace: keyspace.to_string(),
+            name: name.to_string(),
+        },
+        |name, from, to, out| {
+            let fields = |fields: &[(String, String)]| {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(field, cql_type)| format!("{} {}", field, cql_type))
+                    .collect();
+                format!("({})", fields.join(", "))
+            };
+            if from.fields != to.fields {
+                out.push(altered(
+                    SchemaObject::Type {
+                        keyspace: keyspace.to_string(),
+                        name: name.to_string(),
+                    },
+                    fields(&from.fields),
+                    fields(&to.fields),
+                ));
+            }
+        },
+        out,
+    );
+
+    compare(
+        &from.tables,
+        &to.tables,
+        |name| SchemaObject::Table {
+            keyspace: keyspace.to_string(),
+            name: name.to_string(),
+        },
+        |name, from, to, out| diff_table(keyspace, name, from, to, out),
+        out,
+    );
+
+    compare(
+        &from.views,
+        &to.views,
+        |name| SchemaObject::View {
+            keyspace: keyspace.to_string(),
+            name: name.to_string(),
+        },
+        |name, from, to, out| {
+            let object = || SchemaObject::View {
+                keyspace: keyspace.to_string(),
+                name: name.to_string(),
+            };
+            let definitions_known = !from.where_clause.is_empty() && !to.where_clause.is_empty();
+            if from.base_table != to.base_table
+                || definitions_known && view_definition(from) != view_definition(to)
+            {
+                out.push(altered(
+                    object(),
+                    view_definition(from),
+                    view_definition(to),
+                ));
+            }
+            if !from.table.options.is_empty() && !to.table.options.is_empty() {
+                diff_options(object, &from.table, &to.table, out);
+            }
+        },
+        out,
+    );
+}
+
+fn diff_table(
+    keyspace: &str,
+    table: &str,
+    from: &TableSchema,
+    to: &TableSchema,
+    out: &mut Vec<SchemaDifference>,
+) {
+    let object = || SchemaObject::Table {
+        keyspace: keyspace.to_string(),
+        name: table.to_string(),
+    };
+    if !from.options.is_empty() && !to.options.is_empty() {
+        diff_options(object, from, to, out);
+    }
+
+    let columns = |table: &TableSchema| -> BTreeMap<String, Column> {
+        table
+            .columns
+            .iter()
+            .map(|column| (column.name.clone(), column.clone()))
+            .collect()
+    };
+    compare(
+        &columns(from),
+        &columns(to),
+        |name| SchemaObject::Column {
+            keyspace: keyspace.to_string(),
+            table: table.to_string(),
+            name: name.to_string(),
+        },
+        |name, from, to, out| {
+            if from != to {
+                out.push(altered(
+                    SchemaObject::Column {
+                        keyspace: keyspace.to_string(),
+                        table: table.to_string(),
+                        name: name.to_string(),
+                    },
+                    column_definition(from),
+                    column_definition(to),
+                ));
+            }
+        },
+        out,
+    );
+
+    compare(
+        &from.indexes,
+        &to.indexes,
+        |name| SchemaObject::Index {
+            keyspace: keyspace.to_string(),
+            table: table.to_string(),
+            name: name.to_string(),
+        },
+        |name, from, to, out| {
+            if from != to {
+                out.push(altered(
+                    SchemaObject::Index {
+                        keyspace: keyspace.to_string(),
+                        table: table.to_string(),
+                        name: name.to_string(),
+                    },
+                    index_definition(from),
+                    index_definition(to),
+                ));
+            }
+        },
+        out,
+    );
+}
+
+/// Reports each option that differs as an alteration of `object`
+fn diff_options(
+    object: impl Fn() -> SchemaObject,
+    from: &TableSchema,
+    to: &TableSchema,
+    out: &mut Vec<SchemaDifference>,
+) {
+    let value = |table: &TableSchema, option: &str| match table.options.get(option) {
+        Some(value) => format!("{} = {}", option, value),
+        None => format!("{} unset", option),
+    };
+    let mut options: Vec<_> = from.options.keys().chain(to.options.keys()).collect();
+    options.sort();
+    options.dedup();
+    for option in options {
+        if from.options.get(option) != to.options.get(option) {
+            out.push(altered(object(), value(from, option), value(to, option)));
+        }
+    }
+}
+
+fn column_definition(column: &Column) -> String {
+    let kind = match column.kind {
+        ColumnKind::PartitionKey => " partition key",
+        ColumnKind::Clustering if column.descending => " clustering key DESC",
+        ColumnKind::Clustering => " clustering key",
+        ColumnKind::Static => " static",
+        ColumnKind::Regular => "",
+    };
+    format!("{}{}", column.cql_type, kind)
+}
+
+fn index_definition(index: &Index) -> String {
+    match &index.class {
+        Some(class) => format!("({}) USING '{}'", index.target, class),
+        None => format!("({})", index.target),
+    }
+}
+
+fn view_definition(view: &ViewSchema) -> String {
+    let mut definition = format!("FROM {}", view.base_table);
+    if !view.where_clause.is_empty() {
+        definition.push_str(&format!(" WHERE {}", view.where_clause));
+    }
+    definition
+}
+
+impl SchemaObject {
+    /// Returns the keyspace of the object, or the keyspace itself
+    pub fn keyspace(&self) -> &str {
+        match self {
+            SchemaObject::Keyspace(keyspace)
+            | SchemaObject::Type { keyspace, .. }
+            | SchemaObject::Table { keyspace, .. }
+            | SchemaObject::Column { keyspace, .. }
+            | SchemaObject::Index { keyspace, .. }
+            | SchemaObject::View { keyspace, .. } => keyspace,
+        }
+    }
+}
+
+impl fmt::Display for SchemaObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaObject::Keyspace(keyspace) => write!(f, "keyspace {}", keyspace),
+            SchemaObject::Type { keyspace, name } => write!(f, "type {}.{}", keyspace, name),
+            SchemaObject::Table { keyspace, name } => write!(f, "table {}.{}", keyspace, name),
+            SchemaObject::Column {
+                keyspace,
+                table,
+                name,
+            } => write!(f, "column {}.{}.{}", keyspace, table, name),
+            SchemaObject::Index {
+                keyspace,
+                table,
+                name,
+            } => write!(f, "index {}.{} on {}", keyspace, name, table),
+            SchemaObject::View { keyspace, name } => {
+                write!(f, "materialized view {}.{}", keyspace, name)
+            }
+        }
+    }
+}
+
+impl fmt::Display for SchemaDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.difference {
+            Difference::Added => write!(f, "+ {}", self.object),
+            Difference::Removed => write!(f, "- {}", self.object),
+            Difference::Altered { from, to } => {
+                write!(f, "~ {}: {} -> {}", self.object, from, to)
+            }
+        }
+    }
+}
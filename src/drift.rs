@@ -0,0 +1,903 @@
+use crate::diff::{Difference, SchemaDifference};
+use crate::schema::{
+    ident, Column, ColumnKind, Index, KeyspaceSchema, Schema, TableSchema, UserType, ViewSchema,
+};
+use crate::token::{tokens, Token};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Differences between the live schema and the one the applied migrations
+/// create, see [`Migrator::detect_drift`](crate::Migrator::detect_drift)
+#[derive(Debug, Default)]
+pub struct Drift {
+    /// From the schema of the migrations to the live one: added objects were
+    /// created outside of migrations, removed ones dropped
+    pub differences: Vec<SchemaDifference>,
+}
+
+impl Drift {
+    /// Returns true when the live schema matches the applied migrations
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "The schema matches the applied migrations");
+        }
+
+        for difference in &self.differences {
+            let object = &difference.object;
+            match &difference.difference {
+                Difference::Added => writeln!(f, "{} was created outside of migrations", object)?,
+                Difference::Removed => writeln!(f, "{} was dropped outside of migrations", object)?,
+                Difference::Altered { from, to } => writeln!(
+                    f,
+                    "{} was altered outside of migrations: {} -> {}",
+                    object, from, to
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replays the DDL statements of migrations on a [`Schema`]
+///
+/// Only the objects and columns are tracked unless definitions are asked for;
+/// otherwise replication, options and view definitions are left empty.
+/// Anything else, such as DML, functions or statements on unknown objects, is
+/// skipped.
+#[derive(Debug, Default)]
+pub(crate) struct ImpliedSchema {
+    pub schema: Schema,
+    /// Keyspace of unqualified names, set by `USE`
+    keyspace: Option<String>,
+    /// Whether to track replication, options and view definitions
+    definitions: bool,
+}
+
+impl ImpliedSchema {
+    pub fn new(keyspace: Option<String>) -> Self {
+        Self {
+            schema: Schema::default(),
+            keyspace,
+            definitions: false,
+        }
+    }
+
+    /// Also tracks replication, table options and view definitions
+    pub fn with_definitions(mut self) -> Self {
+        self.definitions = true;
+        self
+    }
+
+    pub fn apply(&mut self, statement: &str) {
+        let mut parser = Parser {
+            tokens: tokens(statement),
+            pos: 0,
+        };
+        // Statements that don't parse as expected leave the schema as it is
+        let _ = self.apply_tokens(&mut parser);
+    }
+
+    fn apply_tokens(&mut self, p: &mut Parser) -> Option<()> {
+        if p.eat("USE") {
+            self.keyspace = Some(p.name()?);
+        } else if p.eat("CREATE") {
+            p.eat("CUSTOM");
+            if p.eat("KEYSPACE") || p.eat("SCHEMA") {
+                let if_not_exists = p.if_not_exists();
+                let name = p.name()?;
+                let exists = self.schema.keyspaces.contains_key(&name);
+                let keyspace = self.schema.keyspaces.entry(name).or_default();
+                if self.definitions && !(if_not_exists && exists) {
+                    keyspace.durable_writes = true;
+                    if p.eat("WITH") {
+                        let _ = p.keyspace_options(keyspace);
+                    }
+                }
+            } else if p.eat("TYPE") {
+                let if_not_exists = p.if_not_exists();
+                let (keyspace, name) = self.qualified(p)?;
+                let fields = p.fields()?;
+                let keyspace = self.keyspace_mut(keyspace);
+                if !(if_not_exists && keyspace.types.contains_key(&name)) {
+                    keyspace.types.insert(name, UserType { fields });
+                }
+            } else if p.eat("TABLE") || p.eat("COLUMNFAMILY") {
+                let if_not_exists = p.if_not_exists();
+                let (keyspace, name) = self.qualified(p)?;
+                let mut table = p.table()?;
+                if !self.definitions {
+                    table.options.clear();
+                }
+                let keyspace = self.keyspace_mut(keyspace);
+                if !(if_not_exists && keyspace.tables.contains_key(&name)) {
+                    keyspace.tables.insert(name, table);
+                }
+            } else if p.eat("INDEX") {
+                let if_not_exists = p.if_not_exists();
+                let name = if p.peek_is("ON") {
+                    None
+                } else {
+                    Some(p.name()?)
+                };
+                p.expect("ON")?;
+                let (keyspace, table) = self.qualified(p)?;
+                p.symbol('(')?;
+                let target = p.render_until(&[')'], &[]);
+                p.symbol(')')?;
+                let class = if p.eat("USING") {
+                    match p.next()? {
+                        Token::Literal(class) => Some(class.to_string()),
+                        _ => None,
+                    }
+                } else {
+                    None
+                };
+                // The name the cluster gives unnamed indexes
+                let name = name.unwrap_or_else(|| {
+                    let column = target
+                        .rsplit(|c: char| !(c.is_alphanumeric() || c == '_' || c == '"'))
+                        .find(|word| !word.is_empty())
+                        .unwrap_or_default()
+                        .trim_matches('"');
+                    format!("{}_{}_idx", table, column)
+                });
+                let table = self.keyspace_mut(keyspace).tables.get_mut(&table)?;
+                if !(if_not_exists && table.indexes.contains_key(&name)) {
+                    table.indexes.insert(name, Index { target, class });
+                }
+            } else if p.eat("MATERIALIZED") {
+                p.expect("VIEW")?;
+                let if_not_exists = p.if_not_exists();
+                let (keyspace, name) = self.qualified(p)?;
+                p.expect("AS")?;
+                p.expect("SELECT")?;
+                let all_columns = p.eat_symbol('*');
+                let selected = if all_columns {
+                    Vec::new()
+                } else {
+                    p.names().unwrap_or_default()
+                };
+                p.skip_to("FROM")?;
+                let (_, base_table) = self.qualified(p)?;
+                let definitions = self.definitions;
+                let keyspace = self.keyspace_mut(keyspace);
+                let mut view = ViewSchema {
+                    base_table,
+                    include_all_columns: all_columns,
+                    ..ViewSchema::default()
+                };
+                if definitions {
+                    let base = keyspace.tables.get(&view.base_table);
+                    let _ = p.view(&mut view, base, selected);
+                }
+                if !(if_not_exists && keyspace.views.contains_key(&name)) {
+                    keyspace.views.insert(name, view);
+                }
+            }
+        } else if p.eat("ALTER") {
+            if p.eat("KEYSPACE") || p.eat("SCHEMA") {
+                let name = p.name()?;
+                let keyspace = self.schema.keyspaces.get_mut(&name)?;
+                if self.definitions && p.eat("WITH") {
+                    p.keyspace_options(keyspace)?;
+                }
+            } else if p.eat("TABLE") || p.eat("COLUMNFAMILY") {
+                let (keyspace, name) = self.qualified(p)?;
+                let table = self
+                    .schema
+                    .keyspaces
+                    .get_mut(&keyspace)?
+                    .tables
+                    .get_mut(&name)?;
+                p.alter_table(table)?;
+                if !self.definitions {
+                    table.options.clear();
+                }
+            } else if p.eat("TYPE") {
+                let (keyspace, name) = self.qualified(p)?;
+                let user_type = self
+                    .schema
+                    .keyspaces
+                    .get_mut(&keyspace)?
+                    .types
+                    .get_mut(&name)?;
+                p.alter_type(user_type)?;
+            }
+        } else if p.eat("DROP") {
+            if p.eat("KEYSPACE") || p.eat("SCHEMA") {
+                p.if_exists();
+                let name = p.name()?;
+                self.schema.keyspaces.remove(&name);
+            } else if p.eat("TYPE") {
+                p.if_exists();
+                let (keyspace, name) = self.qualified(p)?;
+                self.schema
+                    .keyspaces
+                    .get_mut(&keyspace)?
+                    .types
+                    .remove(&name);
+            } else if p.eat("TABLE") || p.eat("COLUMNFAMILY") {
+                p.if_exists();
+                let (keyspace, name) = self.qualified(p)?;
+                self.schema
+                    .keyspaces
+                    .get_mut(&keyspace)?
+                    .tables
+                    .remove(&name);
+            } else if p.eat("INDEX") {
+                p.if_exists();
+                let (keyspace, name) = self.qualified(p)?;
+                for table in self
+                    .schema
+                    .keyspaces
+                    .get_mut(&keyspace)?
+                    .tables
+                    .values_mut()
+                {
+                    table.indexes.remove(&name);
+                }
+            } else if p.eat("MATERIALIZED") {
+                p.expect("VIEW")?;
+                p.if_exists();
+                let (keyspace, name) = self.qualified(p)?;
+                self.schema
+                    .keyspaces
+                    .get_mut(&keyspace)?
+                    .views
+                    .remove(&name);
+            }
+        }
+        Some(())
+    }
+
+    /// Reads `keyspace.name` or `name`, qualified with the current keyspace
+    fn qualified(&self, p: &mut Parser) -> Option<(String, String)> {
+        let first = p.name()?;
+        if p.eat_symbol('.') {
+            return Some((first, p.name()?));
+        }
+        Some((self.keyspace.clone()?, first))
+    }
+
+    fn keyspace_mut(&mut self, keyspace: String) -> &mut KeyspaceSchema {
+        self.schema.keyspaces.entry(keyspace).or_default()
+    }
+}
+
+/// Tells the keyspaces statements change, qualifying names with the keyspace
+/// of the last `USE` statement, like [`ImpliedSchema`]
+#[derive(Debug, Default)]
+pub(crate) struct ChangedKeyspaces {
+    /// Keyspace of unqualified names, set by `USE`
+    keyspace: Option<String>,
+}
+
+impl ChangedKeyspaces {
+    pub fn new(keyspace: Option<String>) -> Self {
+        Self { keyspace }
+    }
+
+    /// Adds the keyspaces of the objects `statement` creates, alters, drops or
+    /// writes to, returning false if it names one without a keyspace while
+    /// none is in use
+    ///
+    /// Reads and statements on roles, permissions and the like change none.
+    pub fn add(&mut self, statement: &str, keyspaces: &mut BTreeSet<String>) -> bool {
+        let mut parser = Parser {
+            tokens: tokens(statement),
+            pos: 0,
+        };
+        self.add_tokens(&mut parser, keyspaces).is_some()
+    }
+
+    fn add_tokens(&mut self, p: &mut Parser, keyspaces: &mut BTreeSet<String>) -> Option<()> {
+        if p.eat("USE") {
+            self.keyspace = Some(p.name()?);
+        } else if p.eat("CREATE") || p.eat("ALTER") || p.eat("DROP") {
+            let creates = p.tokens[0].is("CREATE");
+            // Skips `IF NOT EXISTS` or `IF EXISTS`
+            let guard = |p: &mut Parser| match creates {
+                true => p.if_not_exists(),
+                false => p.if_exists(),
+            };
+            if p.eat("OR") {
+                p.expect("REPLACE")?;
+            }
+            p.eat("CUSTOM");
+            p.eat("MATERIALIZED");
+            if p.eat("KEYSPACE") || p.eat("SCHEMA") {
+                let _ = guard(p);
+                keyspaces.insert(p.name()?);
+            } else if p.eat("INDEX") {
+                // Indexes are named in the keyspace of their table
+                if creates {
+                    p.skip_to("ON")?;
+                } else {
+                    let _ = guard(p);
+                }
+                keyspaces.insert(self.keyspace_of(p)?);
+            } else if [
+                "TABLE",
+                "COLUMNFAMILY",
+                "TYPE",
+                "VIEW",
+                "FUNCTION",
+                "AGGREGATE",
+            ]
+            .iter()
+            .any(|kind| p.eat(kind))
+            {
+                let _ = guard(p);
+                keyspaces.insert(self.keyspace_of(p)?);
+            }
+        } else if p.eat("TRUNCATE") {
+            let _ = p.eat("TABLE") || p.eat("COLUMNFAMILY");
+            keyspaces.insert(self.keyspace_of(p)?);
+        } else if p.peek_is("INSERT")
+            || p.peek_is("UPDATE")
+            || p.peek_is("DELETE")
+            || p.eat("BEGIN")
+        {
+            // Every statement of a batch
+            while let Some(token) = p.next() {
+                if token.is("INTO") || token.is("UPDATE") || token.is("FROM") {
+                    keyspaces.insert(self.keyspace_of(p)?);
+                }
+            }
+        }
+        Some(())
+    }
+
+    /// Reads `keyspace.name` or `name`, returning the keyspace
+    fn keyspace_of(&self, p: &mut Parser) -> Option<String> {
+        let first = p.name()?;
+        if p.eat_symbol('.') {
+            return Some(first);
+        }
+        self.keyspace.clone()
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn peek_is(&self, keyword: &str) -> bool {
+        self.peek().is_some_and(|token| token.is(keyword))
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.peek()?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    /// Consumes the next token if it is the word `keyword`
+    fn eat(&mut self, keyword: &str) -> bool {
+        let found = self.peek_is(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, keyword: &str) -> Option<()> {
+        self.eat(keyword).then_some(())
+    }
+
+    fn eat_symbol(&mut self, symbol: char) -> bool {
+        let found = self.peek() == Some(Token::Symbol(symbol));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn symbol(&mut self, symbol: char) -> Option<()> {
+        self.eat_symbol(symbol).then_some(())
+    }
+
+    fn if_not_exists(&mut self) -> bool {
+        self.eat("IF") && self.eat("NOT") && self.eat("EXISTS")
+    }
+
+    fn if_exists(&mut self) -> bool {
+        self.eat("IF") && self.eat("EXISTS")
+    }
+
+    /// Reads an identifier, lowercased unless quoted
+    fn name(&mut self) -> Option<String> {
+        match self.next()? {
+            Token::Word(word) => Some(word.to_ascii_lowercase()),
+            Token::Identifier(name) => Some(name.replace("\"\"", "\"")),
+            _ => None,
+        }
+    }
+
+    /// Skips past the word `keyword` outside of parentheses
+    fn skip_to(&mut self, keyword: &str) -> Option<()> {
+        let mut depth = 0;
+        loop {
+            match self.next()? {
+                Token::Symbol('(') => depth += 1,
+                Token::Symbol(')') => depth -= 1,
+                token if depth == 0 && token.is(keyword) => return Some(()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Renders the tokens up to one of `symbols` or `keywords` outside of
+    /// brackets the way `system_schema` writes them, e.g. `map<text, int>`
+    fn render_until(&mut self, symbols: &[char], keywords: &[&str]) -> String {
+        let mut rendered = String::new();
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            if depth == 0
+                && (matches!(token, Token::Symbol(c) if symbols.contains(&c))
+                    || keywords.iter().any(|keyword| token.is(keyword)))
+            {
+                break;
+            }
+            self.pos += 1;
+
+            let text = match token {
+                Token::Word(word) if word.eq_ignore_ascii_case("varchar") => "text".to_string(),
+                Token::Word(word) => word.to_ascii_lowercase(),
+                Token::Identifier(name) => ident(name),
+                Token::Literal(literal) => format!("'{}'", literal),
+                Token::Symbol(c) => {
+                    match c {
+                        '<' | '(' => depth += 1,
+                        '>' | ')' => depth -= 1,
+                        _ => {}
+                    }
+                    c.to_string()
+                }
+            };
+            let joins_words = rendered.ends_with(|c: char| c.is_alphanumeric() || c == '_')
+                && text.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '"');
+            if joins_words {
+                rendered.push(' ');
+            }
+            rendered.push_str(&text);
+            if text == "," || text == ":" {
+                rendered.push(' ');
+            }
+        }
+        rendered
+    }
+
+    /// Reads a column type, ending before `,`, `)`, `STATIC` or `PRIMARY`
+    fn cql_type(&mut self) -> String {
+        self.render_until(&[',', ')'], &["STATIC", "PRIMARY"])
+    }
+
+    /// Reads `(name type, ...)`
+    fn fields(&mut self) -> Option<Vec<(String, String)>> {
+        self.symbol('(')?;
+        let mut fields = Vec::new();
+        loop {
+            let name = self.name()?;
+            fields.push((name, self.cql_type()));
+            if !self.eat_symbol(',') {
+                break;
+            }
+        }
+        self.symbol(')')?;
+        Some(fields)
+    }
+
+    /// Reads the column list and options of `CREATE TABLE`
+    fn table(&mut self) -> Option<TableSchema> {
+        self.symbol('(')?;
+        let mut columns = Vec::new();
+        let mut partition_key = Vec::new();
+        let mut clustering = Vec::new();
+        loop {
+            if self.eat("PRIMARY") {
+                self.expect("KEY")?;
+                (partition_key, clustering) = self.primary_key()?;
+            } else {
+                let name = self.name()?;
+                let cql_type = self.cql_type();
+                let kind = if self.eat("STATIC") {
+                    ColumnKind::Static
+                } else {
+                    ColumnKind::Regular
+                };
+                if self.eat("PRIMARY") {
+                    self.expect("KEY")?;
+                    partition_key = vec![name.clone()];
+                }
+                columns.push(Column {
+                    name,
+                    cql_type,
+                    kind,
+                    descending: false,
+                });
+            }
+            if !self.eat_symbol(',') {
+                break;
+            }
+            // A trailing comma before the closing parenthesis
+            if self.peek() == Some(Token::Symbol(')')) {
+                break;
+            }
+        }
+        self.symbol(')')?;
+
+        let mut table = TableSchema {
+            columns,
+            ..TableSchema::default()
+        };
+        let mut descending = Vec::new();
+        if self.eat("WITH") {
+            let _ = self.table_options(&mut descending, &mut table.options);
+        }
+        set_keys(&mut table, &partition_key, &clustering, &descending);
+        Some(table)
+    }
+
+    /// Reads `(key, clustering, ...)` after `PRIMARY KEY` as the partition key
+    /// and clustering columns
+    fn primary_key(&mut self) -> Option<(Vec<String>, Vec<String>)> {
+        self.symbol('(')?;
+        let partition_key = if self.eat_symbol('(') {
+            let names = self.names()?;
+            self.symbol(')')?;
+            names
+        } else {
+            vec![self.name()?]
+        };
+        let clustering = if self.eat_symbol(',') {
+            self.names()?
+        } else {
+            Vec::new()
+        };
+        self.symbol(')')?;
+        Some((partition_key, clustering))
+    }
+
+    /// Reads the `WITH` clauses of a table or view up to the first one it
+    /// doesn't understand, collecting descending clustering columns and options
+    fn table_options(
+        &mut self,
+        descending: &mut Vec<String>,
+        options: &mut BTreeMap<String, String>,
+    ) -> Option<()> {
+        loop {
+            if self.eat("CLUSTERING") {
+                self.expect("ORDER")?;
+                self.expect("BY")?;
+                self.symbol('(')?;
+                loop {
+                    let name = self.name()?;
+                    if self.eat("DESC") {
+                        descending.push(name);
+                    } else {
+                        self.eat("ASC");
+                    }
+                    if !self.eat_symbol(',') {
+                        break;
+                    }
+                }
+                self.symbol(')')?;
+            } else if self.eat("COMPACT") {
+                self.expect("STORAGE")?;
+            } else {
+                let option = self.name()?;
+                self.symbol('=')?;
+                let value = self.render_until(&[';'], &["AND"]);
+                options.insert(option, value);
+            }
+            if !self.eat("AND") {
+                return Some(());
+            }
+        }
+    }
+
+    /// Reads the `WITH` clauses of a keyspace
+    fn keyspace_options(&mut self, keyspace: &mut KeyspaceSchema) -> Option<()> {
+        loop {
+            let option = self.name()?;
+            self.symbol('=')?;
+            match option.as_str() {
+                "replication" => keyspace.replication = self.map()?,
+                "durable_writes" => keyspace.durable_writes = self.eat("TRUE"),
+                _ => {
+                    self.render_until(&[';'], &["AND"]);
+                }
+            }
+            if !self.eat("AND") {
+                return Some(());
+            }
+        }
+    }
+
+    /// Reads a map literal of strings, e.g. `{'class': 'NetworkTopologyStrategy'}`
+    fn map(&mut self) -> Option<BTreeMap<String, String>> {
+        self.symbol('{')?;
+        let mut map = BTreeMap::new();
+        while !self.eat_symbol('}') {
+            let key = match self.next()? {
+                Token::Literal(key) => key.replace("''", "'"),
+                _ => return None,
+            };
+            self.symbol(':')?;
+            let value = match self.next()? {
+                Token::Literal(value) => value.replace("''", "'"),
+                Token::Word(value) => value.to_string(),
+                _ => return None,
+            };
+            map.insert(key, value);
+            self.eat_symbol(',');
+        }
+        Some(map)
+    }
+
+    /// Reads what follows the base table of `CREATE MATERIALIZED VIEW`
+    fn view(
+        &mut self,
+        view: &mut ViewSchema,
+        base: Option<&TableSchema>,
+        selected: Vec<String>,
+    ) -> Option<()> {
+        self.expect("WHERE")?;
+        view.where_clause = self.where_clause();
+        self.expect("PRIMARY")?;
+        self.expect("KEY")?;
+        let (partition_key, clustering) = self.primary_key()?;
+
+        let names: Vec<String> = match base {
+            Some(base) if view.include_all_columns => {
+                base.columns.iter().map(|c| c.name.clone()).collect()
+            }
+            _ => selected,
+        };
+        let mut columns: Vec<Column> = Vec::new();
+        for name in partition_key.iter().chain(&clustering).chain(&names) {
+            if columns.iter().any(|c| c.name == *name) {
+                continue;
+            }
+            let cql_type = base
+                .and_then(|base| base.columns.iter().find(|c| c.name == *name))
+                .map(|c| c.cql_type.clone())
+                .unwrap_or_default();
+            columns.push(Column {
+                name: name.clone(),
+                cql_type,
+                kind: ColumnKind::Regular,
+                descending: false,
+            });
+        }
+        view.table.columns = columns;
+
+        let mut descending = Vec::new();
+        if self.eat("WITH") {
+            let _ = self.table_options(&mut descending, &mut view.table.options);
+        }
+        set_keys(&mut view.table, &partition_key, &clustering, &descending);
+        Some(())
+    }
+
+    /// Renders a `WHERE` clause up to `PRIMARY KEY`, with its keywords in
+    /// upper case as the cluster stores them
+    fn where_clause(&mut self) -> String {
+        let rendered = self.render_until(&[], &["PRIMARY"]);
+        rendered
+            .split(' ')
+            .map(|word| match word {
+                "and" | "is" | "not" | "null" | "in" | "contains" => word.to_ascii_uppercase(),
+                _ => word.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Reads `name, name, ...` up to the closing parenthesis
+    fn names(&mut self) -> Option<Vec<String>> {
+        let mut names = vec![self.name()?];
+        while self.eat_symbol(',') {
+            names.push(self.name()?);
+        }
+        Some(names)
+    }
+
+    fn alter_table(&mut self, table: &mut TableSchema) -> Option<()> {
+        if self.eat("ADD") {
+            let parenthesized = self.eat_symbol('(');
+            loop {
+                let name = self.name()?;
+                let cql_type = self.cql_type();
+                let kind = if self.eat("STATIC") {
+                    ColumnKind::Static
+                } else {
+                    ColumnKind::Regular
+                };
+                table.columns.retain(|column| column.name != name);
+                table.columns.push(Column {
+                    name,
+                    cql_type,
+                    kind,
+                    descending: false,
+                });
+                if !(parenthesized && self.eat_symbol(',')) {
+                    break;
+                }
+            }
+            sort_columns(table);
+        } else if self.eat("DROP") {
+            let names = if self.eat_symbol('(') {
+                self.names()?
+            } else {
+                vec![self.name()?]
+            };
+            table.columns.retain(|column| !names.contains(&column.name));
+        } else if self.eat("WITH") {
+            self.table_options(&mut Vec::new(), &mut table.options)?;
+        } else if self.eat("RENAME") {
+            loop {
+                let from = self.name()?;
+                self.expect("TO")?;
+                let to = self.name()?;
+                if let Some(column) = table.columns.iter_mut().find(|c| c.name == from) {
+                    column.name = to;
+                }
+                if !self.eat("AND") {
+                    break;
+                }
+            }
+            sort_columns(table);
+        } else if self.eat("ALTER") {
+            let name = self.name()?;
+            self.expect("TYPE")?;
+            let cql_type = self.cql_type();
+            let column = table.columns.iter_mut().find(|c| c.name == name)?;
+            column.cql_type = cql_type;
+        }
+        Some(())
+    }
+
+    fn alter_type(&mut self, user_type: &mut UserType) -> Option<()> {
+        if self.eat("ADD") {
+            let name = self.name()?;
+            let cql_type = self.cql_type();
+            user_type.fields.push((name, cql_type));
+        } else if self.eat("RENAME") {
+            loop {
+                let from = self.name()?;
+                self.expect("TO")?;
+                let to = self.name()?;
+                if let Some(field) = user_type.fields.iter_mut().find(|(name, _)| *name == from) {
+                    field.0 = to;
+                }
+                if !self.eat("AND") {
+                    break;
+                }
+            }
+        } else if self.eat("ALTER") {
+            let name = self.name()?;
+            self.expect("TYPE")?;
+            let cql_type = self.cql_type();
+            let field = user_type
+                .fields
+                .iter_mut()
+                .find(|(field, _)| *field == name)?;
+            field.1 = cql_type;
+        }
+        Some(())
+    }
+}
+
+/// Marks the key columns of a table and sorts its columns: partition key and
+/// clustering columns in key order, then the others by name
+fn set_keys(
+    table: &mut TableSchema,
+    partition_key: &[String],
+    clustering: &[String],
+    descending: &[String],
+) {
+    for column in &mut table.columns {
+        if partition_key.contains(&column.name) {
+            column.kind = ColumnKind::PartitionKey;
+        } else if clustering.contains(&column.name) {
+            column.kind = ColumnKind::Clustering;
+            column.descending = descending.contains(&column.name);
+        }
+    }
+    let position = |column: &Column| match column.kind {
+        ColumnKind::PartitionKey => partition_key.iter().position(|n| *n == column.name),
+        ColumnKind::Clustering => clustering.iter().position(|n| *n == column.name),
+        ColumnKind::Static | ColumnKind::Regular => None,
+    };
+    table.columns.sort_by(|a, b| {
+        (a.kind.min(ColumnKind::Static), position(a), &a.name).cmp(&(
+            b.kind.min(ColumnKind::Static),
+            position(b),
+            &b.name,
+        ))
+    });
+}
+
+/// Puts the non-key columns of a table back in name order
+fn sort_columns(table: &mut TableSchema) {
+    let keys = table
+        .columns
+        .iter()
+        .take_while(|c| matches!(c.kind, ColumnKind::PartitionKey | ColumnKind::Clustering))
+        .count();
+    table.columns[keys..].sort_by(|a, b| a.name.cmp(&b.name));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed(statements: &[&str]) -> Option<Vec<String>> {
+        let mut changed = ChangedKeyspaces::new(None);
+        let mut keyspaces = BTreeSet::new();
+        for statement in statements {
+            if !changed.add(statement, &mut keyspaces) {
+                return None;
+            }
+        }
+        Some(keyspaces.into_iter().collect())
+    }
+
+    #[test]
+    fn tells_the_keyspaces_statements_change() {
+        assert_eq!(
+            changed(&[
+                "CREATE KEYSPACE IF NOT EXISTS app WITH replication = {'class': 'NetworkTopologyStrategy'}",
+                "CREATE TABLE IF NOT EXISTS app.users (id uuid PRIMARY KEY, email text)",
+                "CREATE INDEX ON app.users (email)",
+                "CREATE OR REPLACE FUNCTION util.twice(x int) RETURNS NULL ON NULL INPUT RETURNS int LANGUAGE lua AS 'return x * 2'",
+                r#"DROP MATERIALIZED VIEW IF EXISTS "Reports".by_day"#,
+            ]),
+            Some(vec![
+                "Reports".to_string(),
+                "app".to_string(),
+                "util".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn qualifies_names_with_the_keyspace_in_use() {
+        assert_eq!(
+            changed(&[
+                "USE app",
+                "ALTER TABLE users ADD nickname text",
+                "BEGIN BATCH INSERT INTO users (id) VALUES (uuid()); UPDATE audit.log SET seen = true WHERE id = 1; APPLY BATCH",
+                "TRUNCATE TABLE sessions",
+            ]),
+            Some(vec!["app".to_string(), "audit".to_string()])
+        );
+        assert_eq!(changed(&["DELETE FROM users WHERE id = 1"]), None);
+    }
+
+    #[test]
+    fn ignores_statements_changing_no_keyspace() {
+        assert_eq!(
+            changed(&[
+                "SELECT * FROM users",
+                "CREATE ROLE reader",
+                "GRANT SELECT ON KEYSPACE app TO reader",
+            ]),
+            Some(Vec::new())
+        );
+    }
+}
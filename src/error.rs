@@ -0,0 +1,332 @@
+use crate::assertion::Assertion;
+use crate::destructive::Destructive;
+use crate::lint::LintFinding;
+use crate::lock::LockHolder;
+use crate::report::RunFailed;
+use crate::server::{Requirement, Server};
+use scylla::deserialize::{DeserializationError, TypeCheckError};
+use scylla::transport::errors::{NewSessionError, QueryError};
+use scylla::transport::query_result::{
+    FirstRowError, IntoRowsResultError, MaybeFirstRowError, RowsError,
+};
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Error type of user-provided code, such as [`CodeMigration`](crate::CodeMigration)s
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can go wrong while migrating
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// A migrations directory or file could not be read
+    #[error("Failed to read {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// A migration file is misnamed or lacks its counterpart
+    #[error("Invalid migration {name}: {reason}")]
+    Parse { name: String, reason: &'static str },
+    /// A migration uses a `${NAME}` placeholder that has no value, see
+    /// [`MigratorBuilder::variable`](crate::MigratorBuilder::variable)
+    #[error("Migration {migration} uses ${{{name}}}, which has no value")]
+    UndefinedVariable { migration: String, name: String },
+    /// Several migrations share a version, which would make their order arbitrary
+    #[error(
+        "Migration version {version} is used more than once: {}",
+        names.join(", ")
+    )]
+    DuplicateVersion { version: i64, names: Vec<String> },
+    /// No session to the cluster could be opened
+    #[error("Failed to connect to the cluster")]
+    Connect(#[source] Box<NewSessionError>),
+    /// The runtime of a [`blocking::Migrator`](crate::blocking::Migrator) could not be started
+    #[error("Failed to start the async runtime")]
+    Runtime(#[source] io::Error),
+    /// A ScyllaDB Cloud connection bundle could not be read or is invalid
+    #[cfg(feature = "cloud")]
+    #[error("Invalid cloud connection bundle {}", path.display())]
+    CloudBundle {
+        path: PathBuf,
+        #[source]
+        source: Box<scylla::cloud::CloudConfigError>,
+    },
+    /// The TLS configuration or a certificate or key file is invalid
+    #[cfg(feature = "ssl")]
+    #[error("Failed to set up TLS{}", with_path(path))]
+    Tls {
+        path: Option<PathBuf>,
+        #[source]
+        source: openssl::error::ErrorStack,
+    },
+    /// A `.tera` migration template is invalid or failed to render
+    #[cfg(feature = "templates")]
+    #[error("Failed to render migration templates")]
+    Template(#[source] Box<tera::Error>),
+    /// A custom [`MigrationSource`](crate::MigrationSource) failed to load migrations
+    #[error("Failed to load migrations")]
+    Source(#[source] BoxError),
+    /// A [`RemoteSource`](crate::RemoteSource) failed to fetch its manifest or a file
+    #[cfg(feature = "remote")]
+    #[error("Failed to fetch {url}")]
+    Fetch {
+        url: String,
+        #[source]
+        source: BoxError,
+    },
+    /// The manifest of a [`RemoteSource`](crate::RemoteSource) is invalid
+    #[cfg(feature = "remote")]
+    #[error("Invalid migration manifest {url}: {reason}")]
+    RemoteManifest { url: String, reason: String },
+    /// A file fetched by a [`RemoteSource`](crate::RemoteSource) doesn't have
+    /// the checksum its manifest lists
+    #[cfg(feature = "remote")]
+    #[error("{url} has the SHA-256 checksum {actual}, but the manifest lists {expected}")]
+    RemoteChecksum {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    /// Applied migrations were modified afterwards, refused by [`ChecksumPolicy::Error`](crate::ChecksumPolicy::Error)
+    #[error("Applied migrations were modified afterwards: {}", join(versions))]
+    ChecksumMismatch { versions: Vec<i64> },
+    /// Applied migrations are not present locally, refused by
+    /// [`MissingPolicy::Error`](crate::MissingPolicy::Error)
+    #[error(
+        "Migrations {} were applied but are not present locally",
+        join(versions)
+    )]
+    MissingMigrations { versions: Vec<i64> },
+    /// Pending migrations are older than the newest applied one, refused by
+    /// [`OutOfOrderPolicy::Error`](crate::OutOfOrderPolicy::Error)
+    #[error(
+        "Migrations {} are older than the applied version {newest}",
+        join(versions)
+    )]
+    OutOfOrder { versions: Vec<i64>, newest: i64 },
+    /// A pending migration depends on a version that is neither applied nor
+    /// run before it, see [`Directives::depends_on`](crate::Directives::depends_on)
+    #[error("Migration {migration} depends on version {dependency}, which is neither applied nor part of this run")]
+    MissingDependency { migration: String, dependency: i64 },
+    /// The cluster doesn't meet the `requires` directive of a pending
+    /// migration, refused by [`RequirementPolicy::Error`](crate::RequirementPolicy::Error)
+    #[error("Migration {migration} requires {requirement}, but the cluster runs {server}")]
+    UnmetRequirement {
+        migration: String,
+        requirement: Requirement,
+        server: Server,
+    },
+    /// A statement of a pending migration uses a feature the database of the
+    /// cluster doesn't have, e.g. ScyllaDB's `cdc` option on Cassandra
+    #[error(
+        "Statement {index} of migration {migration} uses {feature}, which {server} doesn't support"
+    )]
+    Dialect {
+        migration: String,
+        index: usize,
+        feature: String,
+        server: Server,
+    },
+    /// The `depends-on` directives of migrations form a cycle
+    #[error("Migrations {} depend on each other in a cycle", join(versions))]
+    DependencyCycle { versions: Vec<i64> },
+    /// A pending migration contains a statement denied by its
+    /// [`DestructivePolicy`](crate::DestructivePolicy)
+    #[error("Statement {index} of migration {version} ({description}) is a denied {kind} statement: {statement}")]
+    Destructive {
+        version: i64,
+        description: String,
+        /// Position of the statement in its migration, starting at 1
+        index: usize,
+        statement: String,
+        kind: Destructive,
+    },
+    /// The pre-flight linter of [`MigratorBuilder::lint`](crate::MigratorBuilder::lint)
+    /// found problems in a pending migration
+    #[error(
+        "Migration {migration} failed linting: {}",
+        findings.iter().map(LintFinding::to_string).collect::<Vec<_>>().join("; ")
+    )]
+    Lint {
+        migration: String,
+        findings: Vec<LintFinding>,
+    },
+    /// A statement of a migration was rejected by the cluster
+    #[error("Statement {index} of migration {version} ({description}) failed: {statement}")]
+    Execution {
+        version: i64,
+        description: String,
+        /// Position of the statement in its migration, starting at 1
+        index: usize,
+        statement: String,
+        #[source]
+        source: Box<QueryError>,
+    },
+    /// A [`CodeMigration`](crate::CodeMigration) returned an error
+    #[error("Code migration {version} ({description}) failed")]
+    Code {
+        version: i64,
+        description: String,
+        #[source]
+        source: BoxError,
+    },
+    /// A hook registered with the [`MigratorBuilder`](crate::MigratorBuilder) returned an error
+    #[error("Hook {hook} failed")]
+    Hook {
+        /// Which hook, e.g. `before_each of 1/migrate init`
+        hook: String,
+        #[source]
+        source: BoxError,
+    },
+    /// No local migration has the requested version
+    #[error("No migration with version {0}")]
+    UnknownVersion(i64),
+    /// A migration can't be reverted
+    #[error("Cannot revert {version}: {reason}")]
+    Irreversible { version: i64, reason: &'static str },
+    /// Migrations can't be squashed into the one with this version, see
+    /// [`squash`](crate::squash)
+    #[error("Cannot squash migrations into {version}: {reason}")]
+    Squash { version: i64, reason: &'static str },
+    /// Part of the history schema is missing while it is not managed by the runner,
+    /// see [`MigratorBuilder::manage_history_schema`](crate::MigratorBuilder::manage_history_schema)
+    #[error("{0} does not exist; create it or let the runner manage the history schema")]
+    MissingHistorySchema(String),
+    /// A listed tenant keyspace does not exist
+    #[error("Tenant keyspace {0} does not exist")]
+    UnknownTenant(String),
+    /// A keyspace whose schema was asked for does not exist
+    #[error("Keyspace {0} does not exist")]
+    UnknownKeyspace(String),
+    /// A table to import the history of or to seed does not exist
+    #[error("Table {0} does not exist")]
+    UnknownTable(String),
+    /// Seed data could not be read or converted to the types of its table, see
+    /// [`Migrator::seed_csv`](crate::Migrator::seed_csv)
+    #[error("Invalid seed data on line {line}: {reason}")]
+    Seed { line: usize, reason: String },
+    /// A keyspace was not cleaned because a protected pattern matches it, its
+    /// cluster or the environment, see [`MigratorBuilder::protect`](crate::MigratorBuilder::protect)
+    #[error("Refusing to clean {keyspace}: {name} matches the protected pattern {pattern}")]
+    Protected {
+        keyspace: String,
+        /// What matched, e.g. `cluster production-eu`
+        name: String,
+        pattern: String,
+    },
+    /// [`Migrator::clean`](crate::Migrator::clean) can't tell whether an applied
+    /// migration only changed the cleaned keyspace, so its record can neither be
+    /// kept nor removed
+    #[error("Refusing to clean {keyspace}: {migration} {reason}")]
+    MixedHistory {
+        keyspace: String,
+        migration: String,
+        reason: String,
+    },
+    /// A [`Backfill`](crate::Backfill) failed to read, transform or write a row
+    #[error("Backfill of {table} failed")]
+    Backfill {
+        table: String,
+        #[source]
+        source: BoxError,
+    },
+    /// A fixture file is malformed or doesn't match its tables, see
+    /// [`Migrator::load_fixtures`](crate::Migrator::load_fixtures)
+    #[error("Invalid fixture file {}: {reason}", path.display())]
+    Fixture { path: PathBuf, reason: String },
+    /// The docker container of a [`ScyllaContainer`](crate::test::ScyllaContainer)
+    /// could not be started
+    #[cfg(feature = "testcontainers")]
+    #[error("Failed to start the ScyllaDB container: {0}")]
+    Container(String),
+    /// Migrating one of several tenant keyspaces failed
+    #[error("Failed to migrate tenant {keyspace}")]
+    Tenant {
+        keyspace: String,
+        #[source]
+        source: Box<Error>,
+    },
+    /// Another runner held the migration lock for longer than the lock wait
+    #[error("Migration lock is held by {0}")]
+    LockHeld(LockHolder),
+    /// The lock holder vanished while waiting, but the lock could not be taken
+    #[error("Timed out waiting for the migration lock")]
+    LockTimeout,
+    /// The rows of an `-- assert:` query didn't match after the migration
+    #[error("Assertion of {migration} failed, expected {assertion} but found {found}")]
+    AssertionFailed {
+        migration: String,
+        assertion: Assertion,
+        found: String,
+    },
+    /// A materialized view or index was not built within
+    /// [`MigratorBuilder::wait_for_builds`](crate::MigratorBuilder::wait_for_builds)
+    #[error("Timed out after {timeout:?} waiting for {build} to be built")]
+    BuildTimeout { build: String, timeout: Duration },
+    /// The run was cancelled by [`Migrator::run_with_cancel`](crate::Migrator::run_with_cancel)
+    #[error("Run cancelled")]
+    Cancelled,
+    /// The migration lock could not be extended and may be held by another runner
+    #[error("Migration lock was lost to another runner")]
+    LockLost,
+    /// A run stopped at a failing migration
+    #[error(transparent)]
+    RunFailed(Box<RunFailed>),
+    /// Something needs the driver's session, which the [`Execute`](crate::Execute)
+    /// of the migrator doesn't have, e.g. a [`MockSession`](crate::test::MockSession)
+    #[error("{0} needs a scylla Session, which the session of the migrator doesn't have")]
+    SessionRequired(&'static str),
+    /// A query outside of a migration statement failed
+    #[error("Query failed")]
+    Query(#[source] Box<QueryError>),
+    /// A query returned something other than the expected rows
+    #[error("Unexpected query result")]
+    Result(#[source] BoxError),
+}
+
+fn join(versions: &[i64]) -> String {
+    versions
+        .iter()
+        .map(i64::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(feature = "ssl")]
+fn with_path(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| format!(" with {}", path.display()))
+        .unwrap_or_default()
+}
+
+impl From<QueryError> for Error {
+    fn from(err: QueryError) -> Self {
+        Error::Query(Box::new(err))
+    }
+}
+
+macro_rules! result_error {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Error {
+                fn from(err: $ty) -> Self {
+                    Error::Result(Box::new(err))
+                }
+            }
+        )*
+    };
+}
+
+result_error!(
+    IntoRowsResultError,
+    RowsError,
+    MaybeFirstRowError,
+    FirstRowError,
+    DeserializationError,
+    TypeCheckError
+);
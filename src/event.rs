@@ -0,0 +1,51 @@
+use crate::error::Error;
+use crate::migration::Migration;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Progress of a run, passed to the hooks registered with
+/// [`MigratorBuilder::on_event`](crate::MigratorBuilder::on_event)
+#[derive(Debug)]
+pub enum MigrationEvent<'a> {
+    /// A migration is about to be executed
+    MigrationStarted { migration: &'a Migration },
+    /// A statement of a CQL migration completed; `index` starts at 1
+    StatementExecuted {
+        migration: &'a Migration,
+        index: usize,
+        statement: &'a str,
+        duration: Duration,
+    },
+    /// A migration was executed and recorded in the history
+    MigrationApplied {
+        migration: &'a Migration,
+        duration: Duration,
+    },
+    /// A migration failed, which stops the run
+    MigrationFailed {
+        migration: &'a Migration,
+        duration: Duration,
+        error: &'a Error,
+    },
+}
+
+/// Shared callback invoked for every [`MigrationEvent`]
+#[derive(Clone)]
+pub(crate) struct EventHook(Arc<dyn Fn(&MigrationEvent<'_>) + Send + Sync>);
+
+impl EventHook {
+    pub fn new(hook: impl Fn(&MigrationEvent<'_>) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    pub fn call(&self, event: &MigrationEvent<'_>) {
+        (self.0)(event)
+    }
+}
+
+impl fmt::Debug for EventHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventHook")
+    }
+}
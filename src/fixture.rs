@@ -0,0 +1,379 @@
+use crate::error::{Error, Result};
+use crate::seed::{self, type_name};
+use scylla::frame::response::result::{ColumnType, CqlValue};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Rows inserted per batch, unless they are inserted with `if_not_exists`
+pub(crate) const BATCH_SIZE: usize = 50;
+
+/// A fixture loaded by [`Migrator::load_fixtures`](crate::Migrator::load_fixtures)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedFixture {
+    /// The file defining the fixture
+    pub file: PathBuf,
+    /// The table, as `keyspace.table`
+    pub table: String,
+    /// Whether the table was truncated first
+    pub truncated: bool,
+    /// Rows written
+    pub rows: usize,
+    /// Rows of an `if_not_exists` fixture left alone because they existed
+    pub existing: usize,
+}
+
+/// The rows of one table in a fixture file
+pub(crate) struct Fixture {
+    pub keyspace: String,
+    pub table: String,
+    pub truncate: bool,
+    pub if_not_exists: bool,
+    pub rows: Vec<Map<String, Value>>,
+}
+
+/// Reads the fixture file at `path`, or those of the directory by file name
+pub(crate) fn read_fixtures(path: &Path) -> Result<Vec<(PathBuf, Vec<Fixture>)>> {
+    let io_error = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let files = if path.is_dir() {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(path).map_err(io_error)? {
+            let file = entry.map_err(io_error)?.path();
+            let fixture = file
+                .extension()
+                .is_some_and(|ext| ext == "json" || ext == "yaml" || ext == "yml");
+            if file.is_file() && fixture {
+                files.push(file);
+            }
+        }
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut fixtures = Vec::new();
+    for file in files {
+        let content = fs::read_to_string(&file).map_err(|source| Error::Io {
+            path: file.clone(),
+            source,
+        })?;
+        let parsed = parse(&file, &content)?;
+        fixtures.push((file, parsed));
+    }
+    Ok(fixtures)
+}
+
+fn parse(file: &Path, content: &str) -> Result<Vec<Fixture>> {
+    let invalid = |reason: String| Error::Fixture {
+        path: file.to_path_buf(),
+        reason,
+    };
+    let document: Value = match file.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(content).map_err(|err| invalid(err.to_string()))?,
+        #[cfg(feature = "yaml")]
+        Some("yaml" | "yml") => {
+            serde_yaml::from_str(content).map_err(|err| invalid(err.to_string()))?
+        }
+        #[cfg(not(feature = "yaml"))]
+        Some("yaml" | "yml") => {
+            return Err(invalid(
+                "YAML fixtures require the yaml feature".to_string(),
+            ))
+        }
+        _ => return Err(invalid("expected a .json, .yaml or .yml file".to_string())),
+    };
+    let Value::Object(tables) = document else {
+        return Err(invalid(
+            "expected an object mapping tables to their rows".to_string(),
+        ));
+    };
+
+    let mut fixtures = Vec::new();
+    for (name, entry) in tables {
+        let Some((keyspace, table)) = name.split_once('.') else {
+            return Err(invalid(format!(
+                "table {} is not qualified with its keyspace",
+                name
+            )));
+        };
+        let (rows, truncate, if_not_exists) = match entry {
+            Value::Array(rows) => (rows, false, false),
+            Value::Object(mut options) => {
+                let mut flag = |option: &str| match options.remove(option) {
+                    None => Ok(false),
+                    Some(Value::Bool(set)) => Ok(set),
+                    Some(_) => Err(invalid(format!("{} of {} must be a boolean", option, name))),
+                };
+                let truncate = flag("truncate")?;
+                let if_not_exists = flag("if_not_exists")?;
+                let rows = match options.remove("rows") {
+                    Some(Value::Array(rows)) => rows,
+                    None => Vec::new(),
+                    Some(_) => return Err(invalid(format!("rows of {} must be an array", name))),
+                };
+                if let Some(option) = options.keys().next() {
+                    return Err(invalid(format!("unknown option {} of {}", option, name)));
+                }
+                (rows, truncate, if_not_exists)
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "{} must be an array of rows or an object with rows",
+                    name
+                )))
+            }
+        };
+        let rows = rows
+            .into_iter()
+            .map(|row| match row {
+                Value::Object(row) => Ok(row),
+                _ => Err(invalid(format!("rows of {} must be objects", name))),
+            })
+            .collect::<Result<_>>()?;
+        fixtures.push(Fixture {
+            keyspace: keyspace.to_string(),
+            table: table.to_string(),
+            truncate,
+            if_not_exists,
+            rows,
+        });
+    }
+    Ok(fixtures)
+}
+
+/// Converts a JSON value to a value of `typ`
+///
+/// Scalars are converted as CSV fields are, strings holding the literals of
+/// collections, tuples and user-defined types included; arrays become lists,
+/// sets and tuples, and objects maps and user-defined types.
+pub(crate) fn json_value(typ: &ColumnType<'_>, value: &Value) -> Result<Option<CqlValue>, String> {
+    let element = |typ: &ColumnType<'_>, value: &Value| {
+        json_value(typ, value)?.ok_or_else(|| "collections can't contain null".to_string())
+    };
+    match (typ, value) {
+        (_, Value::Null) => Ok(None),
+        (_, Value::String(value)) => seed::value(typ, Some(value)),
+        (
+            ColumnType::List(_)
+            | ColumnType::Set(_)
+            | ColumnType::Map(_, _)
+            | ColumnType::Tuple(_)
+            | ColumnType::UserDefinedType { .. },
+            Value::Bool(_) | Value::Number(_),
+        ) => Err(format!("{} is not a valid {}", value, type_name(typ))),
+        (_, Value::Bool(_) | Value::Number(_)) => seed::value(typ, Some(&value.to_string())),
+        (ColumnType::List(typ), Value::Array(items)) => Ok(Some(CqlValue::List(
+            items
+                .iter()
+                .map(|value| element(typ, value))
+                .collect::<Result<_, _>>()?,
+        ))),
+        (ColumnType::Set(typ), Value::Array(items)) => Ok(Some(CqlValue::Set(
+            items
+                .iter()
+                .map(|value| element(typ, value))
+                .collect::<Result<_, _>>()?,
+        ))),
+        (ColumnType::Tuple(types), Value::Array(items)) if types.len() == items.len() => {
+            Ok(Some(CqlValue::Tuple(
+                types
+                    .iter()
+                    .zip(items)
+                    .map(|(typ, value)| json_value(typ, value))
+                    .collect::<Result<_, _>>()?,
+            )))
+        }
+        (ColumnType::Tuple(types), Value::Array(items)) => Err(format!(
+            "expected a tuple of {} elements, found {}",
+            types.len(),
+            items.len()
+        )),
+        (ColumnType::Map(key, typ), Value::Object(entries)) => {
+            let mut pairs = Vec::new();
+            for (k, v) in entries {
+                let k = seed::value(key, Some(k))?
+                    .ok_or_else(|| "collections can't contain null".to_string())?;
+                pairs.push((k, element(typ, v)?));
+            }
+            Ok(Some(CqlValue::Map(pairs)))
+        }
+        (
+            ColumnType::UserDefinedType {
+                type_name,
+                keyspace,
+                field_types,
+            },
+            Value::Object(given),
+        ) => {
+            if let Some(name) = given
+                .keys()
+                .find(|name| !field_types.iter().any(|(field, _)| field == name.as_str()))
+            {
+                return Err(format!("{} has no field {}", type_name, name));
+            }
+            let mut fields = Vec::new();
+            for (name, typ) in field_types {
+                let value = match given.get(name.as_ref()) {
+                    Some(value) => json_value(typ, value)?,
+                    None => None,
+                };
+                fields.push((name.to_string(), value));
+            }
+            Ok(Some(CqlValue::UserDefinedType {
+                keyspace: keyspace.to_string(),
+                type_name: type_name.to_string(),
+                fields,
+            }))
+        }
+        _ => Err(format!("{} is not a valid {}", value, type_name(typ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::borrow::Cow;
+
+    #[test]
+    fn converts_scalars_as_csv_fields() {
+        assert_eq!(
+            json_value(&ColumnType::Int, &json!(1)).unwrap(),
+            Some(CqlValue::Int(1))
+        );
+        assert_eq!(
+            json_value(&ColumnType::Boolean, &json!(true)).unwrap(),
+            Some(CqlValue::Boolean(true))
+        );
+        assert_eq!(
+            json_value(&ColumnType::Text, &json!("it's")).unwrap(),
+            Some(CqlValue::Text("it's".into()))
+        );
+        assert_eq!(
+            json_value(&ColumnType::BigInt, &json!("42")).unwrap(),
+            Some(CqlValue::BigInt(42))
+        );
+        assert_eq!(json_value(&ColumnType::Int, &json!(null)).unwrap(), None);
+        assert_eq!(
+            json_value(&ColumnType::Int, &json!(1.5)).unwrap_err(),
+            "1.5 is not a valid int"
+        );
+        assert_eq!(
+            json_value(&ColumnType::Int, &json!([1])).unwrap_err(),
+            "[1] is not a valid int"
+        );
+    }
+
+    #[test]
+    fn converts_arrays_and_objects_to_collections() {
+        let list = ColumnType::List(Box::new(ColumnType::Int));
+        assert_eq!(
+            json_value(&list, &json!([1, 2])).unwrap(),
+            Some(CqlValue::List(vec![CqlValue::Int(1), CqlValue::Int(2)]))
+        );
+        assert_eq!(
+            json_value(&list, &json!("[1, 2]")).unwrap(),
+            Some(CqlValue::List(vec![CqlValue::Int(1), CqlValue::Int(2)]))
+        );
+        assert_eq!(
+            json_value(&list, &json!([1, null])).unwrap_err(),
+            "collections can't contain null"
+        );
+        assert_eq!(
+            json_value(&list, &json!(1)).unwrap_err(),
+            "1 is not a valid list"
+        );
+        let set = ColumnType::Set(Box::new(ColumnType::Text));
+        assert_eq!(
+            json_value(&set, &json!(["admin"])).unwrap(),
+            Some(CqlValue::Set(vec![CqlValue::Text("admin".into())]))
+        );
+        let map = ColumnType::Map(
+            Box::new(ColumnType::Int),
+            Box::new(ColumnType::List(Box::new(ColumnType::Text))),
+        );
+        assert_eq!(
+            json_value(&map, &json!({ "1": ["a"] })).unwrap(),
+            Some(CqlValue::Map(vec![(
+                CqlValue::Int(1),
+                CqlValue::List(vec![CqlValue::Text("a".into())])
+            )]))
+        );
+        let tuple = ColumnType::Tuple(vec![ColumnType::Int, ColumnType::Text]);
+        assert_eq!(
+            json_value(&tuple, &json!([1, null])).unwrap(),
+            Some(CqlValue::Tuple(vec![Some(CqlValue::Int(1)), None]))
+        );
+        assert_eq!(
+            json_value(&tuple, &json!([1])).unwrap_err(),
+            "expected a tuple of 2 elements, found 1"
+        );
+    }
+
+    #[test]
+    fn converts_objects_to_user_defined_types_by_field_type() {
+        let address = ColumnType::UserDefinedType {
+            type_name: Cow::Borrowed("address"),
+            keyspace: Cow::Borrowed("app"),
+            field_types: vec![
+                (Cow::Borrowed("street"), ColumnType::Text),
+                (Cow::Borrowed("zip"), ColumnType::Int),
+                (Cow::Borrowed("city"), ColumnType::Text),
+            ],
+        };
+        assert_eq!(
+            json_value(&address, &json!({ "zip": 1000, "street": "Main" })).unwrap(),
+            Some(CqlValue::UserDefinedType {
+                keyspace: "app".into(),
+                type_name: "address".into(),
+                fields: vec![
+                    ("street".into(), Some(CqlValue::Text("Main".into()))),
+                    ("zip".into(), Some(CqlValue::Int(1000))),
+                    ("city".into(), None),
+                ],
+            })
+        );
+        assert_eq!(
+            json_value(&address, &json!({ "zip": "x" })).unwrap_err(),
+            "x is not a valid int"
+        );
+        assert_eq!(
+            json_value(&address, &json!({ "country": "KE" })).unwrap_err(),
+            "address has no field country"
+        );
+    }
+
+    #[test]
+    fn parses_tables_with_options() {
+        let fixtures = parse(
+            Path::new("users.json"),
+            r#"{
+                "app.roles": [{ "name": "admin" }],
+                "app.users": { "truncate": true, "rows": [{ "id": 1 }] }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(fixtures.len(), 2);
+        assert_eq!(
+            (fixtures[1].table.as_str(), fixtures[1].truncate),
+            ("users", true)
+        );
+        assert_eq!(fixtures[1].rows[0]["id"], json!(1));
+        let reason = |content| match parse(Path::new("users.json"), content) {
+            Err(Error::Fixture { reason, .. }) => reason,
+            _ => panic!("the fixture should be rejected"),
+        };
+        assert_eq!(
+            reason(r#"{ "users": [] }"#),
+            "table users is not qualified with its keyspace"
+        );
+        assert_eq!(
+            reason(r#"{ "app.users": { "truncate": 1 } }"#),
+            "truncate of app.users must be a boolean"
+        );
+    }
+}
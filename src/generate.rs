@@ -0,0 +1,422 @@
+use crate::diff::{Difference, SchemaDifference, SchemaObject};
+use crate::schema::{
+    create_index, create_keyspace, create_table, create_type, create_view, ident, map_literal,
+    types_in_dependency_order, ColumnKind, KeyspaceSchema, Schema,
+};
+use std::collections::HashSet;
+
+impl Schema {
+    /// Returns the CQL script turning this schema into `target`, or an empty
+    /// string if they don't differ
+    ///
+    /// The script is a best effort to review before applying. Objects are
+    /// created, altered and dropped with `IF [NOT] EXISTS` guards, drops
+    /// last. Changes CQL can't make in place, such as a new column type or a
+    /// different view, are left as comments. Options of existing tables are
+    /// only changed where both schemas have them, see [`Schema::diff`].
+    pub fn migration_to(&self, target: &Schema) -> String {
+        let mut generator = Generator {
+            from: self,
+            to: target,
+            statements: Vec::new(),
+            drops: Vec::new(),
+            typed_keyspaces: HashSet::new(),
+            altered_tables: HashSet::new(),
+        };
+        for difference in self.diff(target) {
+            generator.converge(&difference);
+        }
+
+        // Views before the indexes, tables and types they depend on
+        generator.drops.sort_by_key(|(rank, _)| *rank);
+        let mut statements = generator.statements;
+        statements.extend(generator.drops.into_iter().map(|(_, drop)| drop));
+        if statements.is_empty() {
+            return String::new();
+        }
+        statements.join("\n\n") + "\n"
+    }
+}
+
+struct Generator<'a> {
+    from: &'a Schema,
+    to: &'a Schema,
+    statements: Vec<String>,
+    /// Drop statements, ranked by the order they have to run in
+    drops: Vec<(u8, String)>,
+    /// Keyspaces whose added types were created
+    typed_keyspaces: HashSet<String>,
+    /// Tables whose options were altered
+    altered_tables: HashSet<(String, String)>,
+}
+
+impl Generator<'_> {
+    fn converge(&mut self, difference: &SchemaDifference) {
+        let manual = |reason: &str| format!("-- Not generated, {}: {}", reason, difference);
+
+        match (&difference.object, &difference.difference) {
+            (SchemaObject::Keyspace(name), Difference::Added) => {
+                let keyspace = &self.to.keyspaces[name];
+                if keyspace.replication.is_empty() {
+                    self.statements
+                        .push(manual("the replication of the keyspace is unknown"));
+                } else {
+                    self.statements.push(create_keyspace(name, keyspace, true));
+                }
+                self.create_objects(name, keyspace);
+            }
+            (SchemaObject::Keyspace(name), Difference::Removed) => {
+                self.drops
+                    .push((4, format!("DROP KEYSPACE IF EXISTS {};", ident(name))));
+            }
+            (SchemaObject::Keyspace(name), Difference::Altered { .. }) => {
+                let keyspace = &self.to.keyspaces[name];
+                self.statements.push(format!(
+                    "ALTER KEYSPACE {} WITH replication = {} AND durable_writes = {};",
+                    ident(name),
+                    map_literal(keyspace.replication.clone()),
+                    keyspace.durable_writes
+                ));
+            }
+            (SchemaObject::Type { keyspace, .. }, Difference::Added) => {
+                // All at once, so types come after the ones they use
+                if self.typed_keyspaces.insert(keyspace.clone()) {
+                    let existing = &self.from.keyspaces[keyspace].types;
+                    for (name, user_type) in
+                        types_in_dependency_order(&self.to.keyspaces[keyspace].types)
+                    {
+                        if !existing.contains_key(name) {
+                            self.statements
+                                .push(create_type(keyspace, name, user_type, true));
+                        }
+                    }
+                }
+            }
+            (SchemaObject::Type { keyspace, name }, Difference::Removed) => {
+                self.drops.push((
+                    3,
+                    format!("DROP TYPE IF EXISTS {}.{};", ident(keyspace), ident(name)),
+                ));
+            }
+            (SchemaObject::Type { keyspace, name }, Difference::Altered { .. }) => {
+                let from = &self.from.keyspaces[keyspace].types[name].fields;
+                let to = &self.to.keyspaces[keyspace].types[name].fields;
+                if to.starts_with(from) {
+                    for (field, cql_type) in &to[from.len()..] {
+                        self.statements.push(format!(
+                            "ALTER TYPE {}.{} ADD {} {};",
+                            ident(keyspace),
+                            ident(name),
+                            ident(field),
+                            cql_type
+                        ));
+                    }
+                } else {
+                    self.statements
+                        .push(manual("fields can only be added to types"));
+                }
+            }
+            (SchemaObject::Table { keyspace, name }, Difference::Added) => {
+                let table = &self.to.keyspaces[keyspace].tables[name];
+                self.statements
+                    .push(create_table(keyspace, name, table, true));
+                for (index, definition) in &table.indexes {
+                    self.statements
+                        .push(create_index(keyspace, name, index, definition, true));
+                }
+            }
+            (SchemaObject::Table { keyspace, name }, Difference::Removed) => {
+                self.drops.push((
+                    2,
+                    format!("DROP TABLE IF EXISTS {}.{};", ident(keyspace), ident(name)),
+                ));
+            }
+            (SchemaObject::Table { keyspace, name }, Difference::Altered { .. }) => {
+                // One statement for all the options of the table
+                if !self.altered_tables.insert((keyspace.clone(), name.clone())) {
+                    return;
+                }
+                let from = &self.from.keyspaces[keyspace].tables[name].options;
+                let to = &self.to.keyspaces[keyspace].tables[name].options;
+                let options: Vec<_> = to
+                    .iter()
+                    .filter(|(option, value)| from.get(*option) != Some(value))
+                    .map(|(option, value)| format!("{} = {}", option, value))
+                    .collect();
+                if !options.is_empty() {
+                    self.statements.push(format!(
+                        "ALTER TABLE {}.{} WITH {};",
+                        ident(keyspace),
+                        ident(name),
+                        options.join("\n    AND ")
+                    ));
+                }
+            }
+            (
+                SchemaObject::Column {
+                    keyspace,
+                    table,
+                    name,
+                },
+                Difference::Added,
+            ) => {
+                let columns = &self.to.keyspaces[keyspace].tables[table].columns;
+                let Some(column) = columns.iter().find(|c| c.name == *name) else {
+                    return;
+                };
+                let is_static = match column.kind {
+                    ColumnKind::Static => " static",
+                    ColumnKind::Regular => "",
+                    ColumnKind::PartitionKey | ColumnKind::Clustering => {
+                        self.statements
+                            .push(manual("primary key columns can't be added"));
+                        return;
+                    }
+                };
+                self.statements.push(format!(
+                    "ALTER TABLE {}.{} ADD {} {}{};",
+                    ident(keyspace),
+                    ident(table),
+                    ident(name),
+                    column.cql_type,
+                    is_static
+                ));
+            }
+            (
+                SchemaObject::Column {
+                    keyspace,
+                    table,
+                    name,
+                },
+                Difference::Removed,
+            ) => {
+                let columns = &self.from.keyspaces[keyspace].tables[table].columns;
+                let is_key = columns.iter().any(|c| {
+                    c.name == *name
+                        && matches!(c.kind, ColumnKind::PartitionKey | ColumnKind::Clustering)
+                });
+                if is_key {
+                    self.statements
+                        .push(manual("primary key columns can't be dropped"));
+                } else {
+                    self.statements.push(format!(
+                        "ALTER TABLE {}.{} DROP {};",
+                        ident(keyspace),
+                        ident(table),
+                        ident(name)
+                    ));
+                }
+            }
+            (SchemaObject::Column { .. }, Difference::Altered { .. }) => {
+                self.statements
+                    .push(manual("the type or kind of a column can't be changed"));
+            }
+            (
+                SchemaObject::Index {
+                    keyspace,
+                    table,
+                    name,
+                },
+                difference,
+            ) => {
+                let drop = format!("DROP INDEX IF EXISTS {}.{};", ident(keyspace), ident(name));
+                match difference {
+                    Difference::Removed => self.drops.push((1, drop)),
+                    Difference::Added | Difference::Altered { .. } => {
+                        if matches!(difference, Difference::Altered { .. }) {
+                            self.statements.push(drop);
+                        }
+                        let index = &self.to.keyspaces[keyspace].tables[table].indexes[name];
+                        self.statements
+                            .push(create_index(keyspace, table, name, index, true));
+                    }
+                }
+            }
+            (SchemaObject::View { keyspace, name }, Difference::Added) => {
+                let view = &self.to.keyspaces[keyspace].views[name];
+                if view.where_clause.is_empty() {
+                    self.statements
+                        .push(manual("the definition of the view is unknown"));
+                } else {
+                    self.statements
+                        .push(create_view(keyspace, name, view, true));
+                }
+            }
+            (SchemaObject::View { keyspace, name }, Difference::Removed) => {
+                self.drops.push((
+                    0,
+                    format!(
+                        "DROP MATERIALIZED VIEW IF EXISTS {}.{};",
+                        ident(keyspace),
+                        ident(name)
+                    ),
+                ));
+            }
+            (SchemaObject::View { .. }, Difference::Altered { .. }) => {
+                self.statements.push(manual("the view has to be recreated"));
+            }
+        }
+    }
+
+    /// Creates the types, tables, indexes and views of an added keyspace
+    fn create_objects(&mut self, name: &str, keyspace: &KeyspaceSchema) {
+        for (type_name, user_type) in types_in_dependency_order(&keyspace.types) {
+            self.statements
+                .push(create_type(name, type_name, user_type, true));
+        }
+        for (table_name, table) in &keyspace.tables {
+            self.statements
+                .push(create_table(name, table_name, table, true));
+            for (index, definition) in &table.indexes {
+                self.statements
+                    .push(create_index(name, table_name, index, definition, true));
+            }
+        }
+        for (view_name, view) in &keyspace.views {
+            if view.where_clause.is_empty() {
+                self.statements.push(format!(
+                    "-- Not generated, the definition of the view is unknown: + {}",
+                    SchemaObject::View {
+                        keyspace: name.to_string(),
+                        name: view_name.to_string(),
+                    }
+                ));
+            } else {
+                self.statements
+                    .push(create_view(name, view_name, view, true));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP: &str = "
+        CREATE KEYSPACE app WITH replication = {'class': 'NetworkTopologyStrategy', 'dc1': '3'};
+        CREATE TYPE app.address (street text, city text);
+        CREATE TABLE app.users (id uuid, org int, name text, home frozen<address>, PRIMARY KEY (org, id));
+        CREATE INDEX users_name ON app.users (name);
+        CREATE MATERIALIZED VIEW app.users_by_name AS SELECT org, id, name FROM app.users
+            WHERE org IS NOT NULL AND id IS NOT NULL AND name IS NOT NULL
+            PRIMARY KEY (name, org, id);
+    ";
+
+    const KEYSPACE: &str =
+        "CREATE KEYSPACE app WITH replication = {'class': 'NetworkTopologyStrategy', 'dc1': '3'};";
+
+    #[test]
+    fn drops_views_indexes_tables_types_then_keyspaces() {
+        let from = Schema::parse(
+            &format!(
+                "{}CREATE TYPE app.phone (number text);
+                CREATE TABLE app.events (id timeuuid PRIMARY KEY);
+                CREATE KEYSPACE old WITH replication = {{'class': 'SimpleStrategy', 'replication_factor': '1'}};",
+                APP
+            ),
+            None,
+        );
+        // The users table and the type it uses stay
+        let to = Schema::parse(
+            &format!(
+                "{}CREATE TYPE app.address (street text, city text);
+                CREATE TABLE app.users (id uuid, org int, name text, home frozen<address>, PRIMARY KEY (org, id));",
+                KEYSPACE
+            ),
+            None,
+        );
+        let script = from.migration_to(&to);
+        let drops: Vec<_> = script.lines().filter(|line| !line.is_empty()).collect();
+        assert_eq!(
+            drops,
+            [
+                "DROP MATERIALIZED VIEW IF EXISTS app.users_by_name;",
+                "DROP INDEX IF EXISTS app.users_name;",
+                "DROP TABLE IF EXISTS app.events;",
+                "DROP TYPE IF EXISTS app.phone;",
+                "DROP KEYSPACE IF EXISTS old;",
+            ]
+        );
+        assert_eq!(from.migration_to(&from), "");
+    }
+
+    #[test]
+    fn adds_only_appended_type_fields() {
+        let from = Schema::parse(
+            &format!(
+                "{}CREATE TYPE app.address (street text, city text);",
+                KEYSPACE
+            ),
+            None,
+        );
+        let appended = Schema::parse(
+            &format!(
+                "{}CREATE TYPE app.address (street text, city text, zip int, country text);",
+                KEYSPACE
+            ),
+            None,
+        );
+        assert_eq!(
+            from.migration_to(&appended),
+            "ALTER TYPE app.address ADD zip int;\n\nALTER TYPE app.address ADD country text;\n"
+        );
+
+        let inserted = Schema::parse(
+            &format!(
+                "{}CREATE TYPE app.address (street text, zip int, city text);",
+                KEYSPACE
+            ),
+            None,
+        );
+        let script = from.migration_to(&inserted);
+        assert!(
+            script.starts_with("-- Not generated, fields can only be added to types:"),
+            "{}",
+            script
+        );
+        assert!(!script.contains("ALTER TYPE"));
+    }
+
+    #[test]
+    fn leaves_primary_key_changes_as_comments() {
+        let from = Schema::parse(
+            &format!(
+                "{}CREATE TABLE app.users (org int, id uuid, name text, PRIMARY KEY (org, id));",
+                KEYSPACE
+            ),
+            None,
+        );
+        let to = Schema::parse(
+            &format!(
+                "{}CREATE TABLE app.users (id uuid, name text, email text, PRIMARY KEY (id));",
+                KEYSPACE
+            ),
+            None,
+        );
+        let script = from.migration_to(&to);
+        assert!(script.contains("ALTER TABLE app.users ADD email text;"));
+        assert!(script.contains(
+            "-- Not generated, primary key columns can't be dropped: - column app.users.org"
+        ));
+        assert!(script.contains(
+            "-- Not generated, the type or kind of a column can't be changed: ~ column app.users.id"
+        ));
+        assert!(!script.contains("DROP org"));
+
+        let keyed = Schema::parse(
+            &format!(
+                "{}CREATE TABLE app.users (org int, id uuid, name text, region text, PRIMARY KEY (org, id, region));",
+                KEYSPACE
+            ),
+            None,
+        );
+        let script = from.migration_to(&keyed);
+        assert!(
+            script.starts_with("-- Not generated, primary key columns can't be added:"),
+            "{}",
+            script
+        );
+        assert!(!script.contains("ADD region"));
+    }
+}
@@ -0,0 +1,270 @@
+use crate::migration::MigrationKind;
+use crate::session::{Execute, Prepared};
+use crate::status::short_checksum;
+use scylla::transport::errors::QueryError;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Location of the table recording applied migrations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HistoryTable {
+    pub keyspace: String,
+    pub table: String,
+}
+
+impl HistoryTable {
+    /// Parses `keyspace.table`, or a bare `table` in the `public` keyspace
+    pub fn parse(name: &str) -> Self {
+        let (keyspace, table) = name.split_once('.').unwrap_or(("public", name));
+        Self {
+            keyspace: keyspace.to_string(),
+            table: table.to_string(),
+        }
+    }
+
+    /// Returns the table recording applied repeatable migrations
+    pub fn repeatable(&self) -> String {
+        format!("{}.{}_repeatable", self.keyspace, self.table)
+    }
+
+    /// Returns the table recording the progress of interrupted migrations
+    pub fn progress(&self) -> String {
+        format!("{}.{}_progress", self.keyspace, self.table)
+    }
+}
+
+/// Prepared statements of the history tables by their CQL, prepared on first use
+///
+/// Shared by a [`Migrator`](crate::Migrator) and the runners of its tenants, so
+/// every statement is prepared once, however often it is executed.
+#[derive(Debug, Default)]
+pub(crate) struct PreparedStatements(Mutex<HashMap<String, Prepared>>);
+
+impl PreparedStatements {
+    pub async fn get(&self, session: &dyn Execute, cql: &str) -> Result<Prepared, QueryError> {
+        let cached = self.0.lock().unwrap().get(cql).cloned();
+        if let Some(prepared) = cached {
+            return Ok(prepared);
+        }
+
+        let prepared = session.prepare(cql).await?;
+        self.0
+            .lock()
+            .unwrap()
+            .insert(cql.to_string(), prepared.clone());
+        Ok(prepared)
+    }
+}
+
+impl Default for HistoryTable {
+    fn default() -> Self {
+        Self::parse("public.migrations")
+    }
+}
+
+impl fmt::Display for HistoryTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.keyspace, self.table)
+    }
+}
+
+/// Replication of the history keyspace, using `NetworkTopologyStrategy`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Replication {
+    /// The same replication factor in every datacenter
+    Factor(u32),
+    /// A replication factor per datacenter
+    Datacenters(Vec<(String, u32)>),
+}
+
+impl Replication {
+    /// Parses a replication factor such as `3`, or datacenters such as `dc1:3,dc2:3`
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Ok(factor) = value.trim().parse() {
+            return Some(Replication::Factor(factor));
+        }
+
+        value
+            .split(',')
+            .map(|dc| {
+                let (name, factor) = dc.split_once(':')?;
+                let name = name.trim();
+                if name.is_empty() {
+                    return None;
+                }
+                Some((name.to_string(), factor.trim().parse().ok()?))
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(Replication::Datacenters)
+    }
+
+    /// Returns true if `replication`, as read from `system_schema.keyspaces`, matches
+    pub(crate) fn matches(&self, replication: &HashMap<String, String>) -> bool {
+        let class = replication.get("class").map(String::as_str);
+        if !class.is_some_and(|class| class.ends_with("NetworkTopologyStrategy")) {
+            return false;
+        }
+
+        let factors: HashMap<&str, &str> = replication
+            .iter()
+            .filter(|(key, _)| *key != "class")
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        match self {
+            Replication::Factor(factor) => {
+                let factor = factor.to_string();
+                !factors.is_empty() && factors.values().all(|value| *value == factor)
+            }
+            Replication::Datacenters(datacenters) => {
+                factors.len() == datacenters.len()
+                    && datacenters.iter().all(|(name, factor)| {
+                        factors.get(name.as_str()) == Some(&factor.to_string().as_str())
+                    })
+            }
+        }
+    }
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Replication::Factor(1)
+    }
+}
+
+/// Formats the replication as a CQL map literal
+impl fmt::Display for Replication {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{'class': 'NetworkTopologyStrategy'")?;
+        match self {
+            Replication::Factor(factor) => write!(f, ", 'replication_factor': {}", factor)?,
+            Replication::Datacenters(datacenters) => {
+                for (name, factor) in datacenters {
+                    write!(f, ", '{}': {}", name.replace('\'', "''"), factor)?;
+                }
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Everything recorded in the history tables, oldest first
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct History {
+    pub entries: Vec<HistoryEntry>,
+}
+
+/// A recorded application, or failed attempt, of a migration
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HistoryEntry {
+    pub kind: MigrationKind,
+    /// Always 0 for repeatable migrations
+    pub version: i64,
+    pub description: String,
+    pub checksum: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "time::serde::rfc3339::option"))]
+    pub applied_at: Option<OffsetDateTime>,
+    /// How long the migration took to apply, for migrations recorded with it
+    pub execution_time_ms: Option<i64>,
+    /// Who applied the migration, the cluster user or else the OS user
+    pub applied_by: Option<String>,
+    /// Host of the runner that applied the migration
+    pub host: Option<String>,
+    /// Why the attempt failed, for failed attempts
+    pub error: Option<String>,
+}
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.entries.is_empty() {
+            return writeln!(f, "No migrations applied");
+        }
+
+        writeln!(
+            f,
+            "{:<16} {:<16} {:<25} {:>10} DESCRIPTION",
+            "VERSION", "CHECKSUM", "APPLIED AT", "DURATION"
+        )?;
+
+        for entry in &self.entries {
+            let applied_at = entry
+                .applied_at
+                .and_then(|at| at.format(&Rfc3339).ok())
+                .unwrap_or_else(|| "-".to_string());
+
+            let version = match entry.kind {
+                MigrationKind::Versioned => entry.version.to_string(),
+                MigrationKind::Repeatable => "R".to_string(),
+            };
+
+            let duration = entry
+                .execution_time_ms
+                .map(|ms| format!("{}ms", ms))
+                .unwrap_or_else(|| "-".to_string());
+
+            writeln!(
+                f,
+                "{:<16} {:<16} {:<25} {:>10} {}",
+                version,
+                short_checksum(&entry.checksum),
+                applied_at,
+                duration,
+                entry.description
+            )?;
+            if let Some(error) = &entry.error {
+                writeln!(f, "{:<16} failed: {}", "", error)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replication(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_factors_and_datacenters() {
+        assert_eq!(Replication::parse(" 3 "), Some(Replication::Factor(3)));
+        assert_eq!(
+            Replication::parse("dc1:3, dc2 : 2"),
+            Some(Replication::Datacenters(vec![
+                ("dc1".to_string(), 3),
+                ("dc2".to_string(), 2)
+            ]))
+        );
+        for value in ["", "-1", "dc1", "dc1:", ":3", "dc1:3,", "dc1:three"] {
+            assert_eq!(Replication::parse(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn matches_network_topology_replication() {
+        let class = (
+            "class",
+            "org.apache.cassandra.locator.NetworkTopologyStrategy",
+        );
+        let two_dcs = replication(&[class, ("dc1", "3"), ("dc2", "3")]);
+        assert!(Replication::Factor(3).matches(&two_dcs));
+        assert!(!Replication::Factor(1).matches(&two_dcs));
+        assert!(Replication::parse("dc2:3,dc1:3").unwrap().matches(&two_dcs));
+        assert!(!Replication::parse("dc1:3").unwrap().matches(&two_dcs));
+        assert!(!Replication::Factor(3).matches(&replication(&[class])));
+        assert!(!Replication::Factor(1).matches(&replication(&[
+            ("class", "org.apache.cassandra.locator.SimpleStrategy"),
+            ("replication_factor", "1"),
+        ])));
+    }
+}
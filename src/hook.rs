@@ -0,0 +1,122 @@
+use crate::error::{BoxError, Error, Result};
+use crate::migration::Migration;
+use crate::session::SessionRef;
+use async_trait::async_trait;
+use scylla::Session;
+use std::fmt;
+use std::sync::Arc;
+
+/// Code run before or after every migration of a run, registered with
+/// [`MigratorBuilder::before_each`](crate::MigratorBuilder::before_each) and
+/// [`MigratorBuilder::after_each`](crate::MigratorBuilder::after_each)
+///
+/// # Example
+/// ```no_run
+/// use async_trait::async_trait;
+/// use scylla::Session;
+/// use scylla_migrate::{BoxError, Migration, MigrationHook};
+///
+/// struct Notify;
+///
+/// #[async_trait]
+/// impl MigrationHook for Notify {
+///     async fn call(&self, _session: &Session, migration: &Migration) -> Result<(), BoxError> {
+///         println!("Applied {}", migration);
+///         Ok(())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait MigrationHook: Send + Sync {
+    async fn call(&self, session: &Session, migration: &Migration) -> Result<(), BoxError>;
+}
+
+/// Code run once before or after the migrations of a run, registered with
+/// [`MigratorBuilder::before_all`](crate::MigratorBuilder::before_all) and
+/// [`MigratorBuilder::after_all`](crate::MigratorBuilder::after_all)
+///
+/// `migrations` are the ones about to be applied, or those that were.
+#[async_trait]
+pub trait RunHook: Send + Sync {
+    async fn call(&self, session: &Session, migrations: &[&Migration]) -> Result<(), BoxError>;
+}
+
+/// Hooks of a [`Migrator`](crate::Migrator), called in registration order
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub before_all: Vec<Arc<dyn RunHook>>,
+    pub before_each: Vec<Arc<dyn MigrationHook>>,
+    pub after_each: Vec<Arc<dyn MigrationHook>>,
+    pub after_all: Vec<Arc<dyn RunHook>>,
+}
+
+impl Hooks {
+    pub async fn before_all(
+        &self,
+        session: &SessionRef<'_>,
+        migrations: &[&Migration],
+    ) -> Result<()> {
+        run_all(&self.before_all, "before_all", session, migrations).await
+    }
+
+    pub async fn before_each(&self, session: &SessionRef<'_>, migration: &Migration) -> Result<()> {
+        run_each(&self.before_each, "before_each", session, migration).await
+    }
+
+    pub async fn after_each(&self, session: &SessionRef<'_>, migration: &Migration) -> Result<()> {
+        run_each(&self.after_each, "after_each", session, migration).await
+    }
+
+    pub async fn after_all(
+        &self,
+        session: &SessionRef<'_>,
+        migrations: &[&Migration],
+    ) -> Result<()> {
+        run_all(&self.after_all, "after_all", session, migrations).await
+    }
+}
+
+async fn run_all(
+    hooks: &[Arc<dyn RunHook>],
+    name: &str,
+    session: &SessionRef<'_>,
+    migrations: &[&Migration],
+) -> Result<()> {
+    for hook in hooks {
+        hook.call(session.require("hooks")?, migrations)
+            .await
+            .map_err(|source| Error::Hook {
+                hook: name.to_string(),
+                source,
+            })?;
+    }
+    Ok(())
+}
+
+async fn run_each(
+    hooks: &[Arc<dyn MigrationHook>],
+    name: &str,
+    session: &SessionRef<'_>,
+    migration: &Migration,
+) -> Result<()> {
+    for hook in hooks {
+        hook.call(session.require("hooks")?, migration)
+            .await
+            .map_err(|source| Error::Hook {
+                hook: format!("{} of {}", name, migration),
+                source,
+            })?;
+    }
+    Ok(())
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("before_all", &self.before_all.len())
+            .field("before_each", &self.before_each.len())
+            .field("after_each", &self.after_each.len())
+            .field("after_all", &self.after_all.len())
+            .finish()
+    }
+}
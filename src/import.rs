@@ -0,0 +1,132 @@
+use crate::error::{Error, Result};
+use scylla::Session;
+use time::OffsetDateTime;
+
+/// Another migration tool whose history can be imported, see
+/// [`Migrator::import_history`](crate::Migrator::import_history)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryFormat {
+    /// The `flyway_schema_history` table of Flyway
+    Flyway,
+    /// The `database_migrations` table of cassandra-migrate
+    CassandraMigrate,
+}
+
+impl HistoryFormat {
+    /// Returns the table the tool records its history in by default
+    pub fn default_table(&self) -> &'static str {
+        match self {
+            HistoryFormat::Flyway => "flyway_schema_history",
+            HistoryFormat::CassandraMigrate => "database_migrations",
+        }
+    }
+}
+
+/// The outcome of [`Migrator::import_history`](crate::Migrator::import_history)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Import {
+    /// Versions recorded as applied
+    pub imported: Vec<i64>,
+    /// Versions that were already recorded
+    pub existing: Vec<i64>,
+    /// Versions the other tool applied that have no local versioned migration,
+    /// as it recorded them
+    pub unmatched: Vec<String>,
+}
+
+/// A versioned migration the other tool applied successfully
+pub(crate) struct ForeignMigration {
+    pub version: String,
+    pub applied_at: Option<OffsetDateTime>,
+    pub applied_by: Option<String>,
+    pub execution_time_ms: Option<i64>,
+}
+
+type FlywayRow = (
+    Option<String>,
+    Option<bool>,
+    Option<String>,
+    Option<OffsetDateTime>,
+    Option<i32>,
+);
+
+/// Reads the successfully applied versioned migrations of `keyspace.table`
+pub(crate) async fn read_history(
+    session: &Session,
+    format: HistoryFormat,
+    keyspace: &str,
+    table: &str,
+) -> Result<Vec<ForeignMigration>> {
+    let exists = session
+        .query_unpaged(
+            "SELECT table_name FROM system_schema.tables WHERE keyspace_name = ? AND table_name = ?",
+            (keyspace, table),
+        )
+        .await?
+        .into_rows_result()?
+        .rows_num()
+        > 0;
+    if !exists {
+        return Err(Error::UnknownTable(format!("{}.{}", keyspace, table)));
+    }
+
+    let mut migrations = Vec::new();
+    match format {
+        HistoryFormat::Flyway => {
+            let query_rows = session
+                .query_unpaged(
+                    format!(
+                        "SELECT version, success, installed_by, installed_on, execution_time FROM {}.{}",
+                        keyspace, table
+                    ),
+                    &[],
+                )
+                .await?
+                .into_rows_result()?;
+            for row in query_rows.rows()? {
+                let (version, success, installed_by, installed_on, execution_time): FlywayRow =
+                    row?;
+                // Repeatable migrations have no version
+                let Some(version) = version.filter(|_| success == Some(true)) else {
+                    continue;
+                };
+                migrations.push(ForeignMigration {
+                    version,
+                    applied_at: installed_on,
+                    applied_by: installed_by,
+                    execution_time_ms: execution_time.map(i64::from),
+                });
+            }
+        }
+        HistoryFormat::CassandraMigrate => {
+            let query_rows = session
+                .query_unpaged(
+                    format!(
+                        "SELECT version, state, applied_at FROM {}.{}",
+                        keyspace, table
+                    ),
+                    &[],
+                )
+                .await?
+                .into_rows_result()?;
+            for row in query_rows.rows()? {
+                let (version, state, applied_at): (
+                    Option<i32>,
+                    Option<String>,
+                    Option<OffsetDateTime>,
+                ) = row?;
+                let Some(version) = version.filter(|_| state.as_deref() == Some("SUCCEEDED"))
+                else {
+                    continue;
+                };
+                migrations.push(ForeignMigration {
+                    version: version.to_string(),
+                    applied_at,
+                    applied_by: None,
+                    execution_time_ms: None,
+                });
+            }
+        }
+    }
+    Ok(migrations)
+}
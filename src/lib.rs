@@ -2,14 +2,15 @@
 //!
 //! This library provides functionality for managing database migrations in ScyllaDB.
 //! It supports reading .cql files from a specified directory and executing them in order,
-//! while tracking which migrations have been applied.
+//! while tracking which migrations have been applied. Migrations shipped with a down
+//! script can be reverted again.
 //!
 //! # Example
 //! ```no_run
 //! use scylla_migrate::Migrator;
 //! use scylla::SessionBuilder;
 //!
-//! async fn migrate() -> anyhow::Result<()> {
+//! async fn migrate() -> Result<(), Box<dyn std::error::Error>> {
 //!     let session = SessionBuilder::new()
 //!         .known_node("localhost:9042")
 //!         .build()
@@ -21,56 +22,353 @@
 //! }
 //! ```
 
+mod assertion;
+mod audit;
+mod backfill;
+pub mod blocking;
+pub mod build;
+mod builder;
+mod clean;
+mod connect;
+mod dependency;
+mod destructive;
+mod dialect;
+mod diff;
+mod drift;
+mod error;
+mod event;
+mod fixture;
+mod generate;
+mod history;
+mod hook;
+mod import;
+mod lint;
+mod lock;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod migration;
+mod mock;
+mod options;
+mod plan;
+#[cfg(feature = "remote")]
+mod remote;
+mod report;
+mod retry;
+mod schema;
+mod seed;
+mod server;
+mod session;
+mod source;
+mod squash;
+mod status;
+mod telemetry;
+mod tenant;
+pub mod test;
+mod token;
+mod validate;
+mod variables;
+mod view_build;
 
-use crate::migration::{AppliedMigration, Migration};
-use anyhow::{Context, Result};
+pub use crate::assertion::Assertion;
+pub use crate::audit::{AuditAction, AuditEntry, AuditLog};
+pub use crate::backfill::{Backfill, BackfillReport};
+pub use crate::builder::MigratorBuilder;
+pub use crate::clean::Clean;
+pub use crate::connect::ConnectOptions;
+#[cfg(feature = "ssl")]
+pub use crate::connect::TlsOptions;
+pub use crate::destructive::Destructive;
+pub use crate::diff::{Difference, SchemaDifference, SchemaObject};
+pub use crate::drift::Drift;
+pub use crate::error::{BoxError, Error, Result};
+pub use crate::event::MigrationEvent;
+pub use crate::fixture::LoadedFixture;
+pub use crate::history::{History, HistoryEntry, Replication};
+pub use crate::hook::{MigrationHook, RunHook};
+pub use crate::import::{HistoryFormat, Import};
+pub use crate::lint::{lint, BuiltinRule, LintFinding, LintRule, Linter};
+pub use crate::lock::{LockHolder, MIN_LOCK_LEASE};
+#[cfg(feature = "metrics")]
+pub use crate::metrics::Metrics;
+pub use crate::migration::{parse_duration, CodeMigration, Directives, Migration, MigrationKind};
+pub use crate::options::{
+    ChecksumPolicy, DestructivePolicy, MissingPolicy, OutOfOrderPolicy, RequirementPolicy,
+    RunOptions, SchemaAgreement,
+};
+pub use crate::plan::{Plan, PlannedMigration, SkipReason, SkippedMigration};
+#[cfg(feature = "remote")]
+pub use crate::remote::RemoteSource;
+pub use crate::report::{ExecutedMigration, FailedMigration, MigrationReport, RunFailed};
+pub use crate::retry::RetryPolicy;
+pub use crate::schema::{
+    Column, ColumnKind, Index, KeyspaceSchema, Schema, TableSchema, UserType, ViewSchema,
+};
+pub use crate::seed::Seed;
+pub use crate::server::{Comparison, Product, ReleaseVersion, Requirement, Server};
+pub use crate::session::{Execute, Pages, Prepared, Rows};
+pub use crate::source::{
+    AssetSource, EmbeddedMigrations, FileSource, MigrationSource, StaticSource,
+};
+pub use crate::squash::{squash, Squash};
+pub use crate::status::{MigrationState, MigrationStatus, Status};
+pub use crate::tenant::Tenants;
+pub use crate::validate::{Discrepancy, Validation, ValidationIssue};
+
+use crate::clean::Record;
+use crate::drift::{ChangedKeyspaces, ImpliedSchema};
+use crate::fixture::{json_value, read_fixtures};
+use crate::history::{HistoryTable, PreparedStatements};
+use crate::import::read_history;
+use crate::lock::MigrationLock;
+use crate::migration::{
+    rename_keyspace, statements, statements_checksum, AppliedHistory, AppliedMigration,
+    StatementOptions, VersionedHistory,
+};
+use crate::options::MigratorOptions;
+use crate::report::error_chain;
+use crate::schema::ident;
+use crate::seed::{column_types, insert_statement};
+use crate::session::SessionRef;
+use crate::tenant::existing_keyspaces;
+use futures::{future, stream, StreamExt, TryStreamExt};
+use scylla::batch::{Batch, BatchType};
+use scylla::frame::response::result::{CqlValue, Row};
+use scylla::frame::value::MaybeUnset;
+use scylla::prepared_statement::PreparedStatement;
+use scylla::query::Query;
+use scylla::serialize::row::SerializeRow;
 use scylla::Session;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 use time::OffsetDateTime;
-use tokio::fs;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use uuid::Uuid;
+
+/// Embeds the .cql files of a migrations directory into the binary
+///
+/// The directory is resolved relative to the crate's `Cargo.toml` and defaults
+/// to `migrations`. Files are read at compile time, so the resulting binary
+/// doesn't need the directory at runtime. Cargo rebuilds the crate when an
+/// embedded file changes, but not when a file is added; add
+/// `println!("cargo:rerun-if-changed=migrations");` to a build script to cover that.
+///
+/// # Example
+/// ```ignore
+/// use scylla_migrate::{embed_migrations, EmbeddedMigrations, Migrator};
+///
+/// static MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+///
+/// # async fn migrate(session: &scylla::Session) -> scylla_migrate::Result<()> {
+/// Migrator::embedded(session, &MIGRATIONS).run().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "embed")]
+pub use scylla_migrate_macros::embed_migrations;
+
+/// A row of the migrations table, as selected by `get_applied_versioned`
+type HistoryRow = (
+    i64,
+    Vec<u8>,
+    Option<String>,
+    Option<OffsetDateTime>,
+    Option<bool>,
+    Option<i64>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+/// Columns of the audit table read by [`Migrator::audit_log`]
+type AuditRow = (
+    OffsetDateTime,
+    String,
+    Option<i64>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+/// Columns added to the migrations table after its initial release
+///
+/// Tracking tables created by older versions are upgraded in place.
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("has_down", "boolean"),
+    ("execution_time_ms", "bigint"),
+    ("applied_by", "text"),
+    ("host", "text"),
+    ("status", "text"),
+    ("error", "text"),
+    ("cql", "text"),
+];
 
 /// Main runner for executing database migrations
-#[derive(Debug)]
+///
+/// Applied migrations are recorded in `public.migrations` unless configured
+/// otherwise with [`MigratorBuilder::history_table`]. Runs that change the
+/// schema hold an advisory lock in the `migration_lock` table of the same
+/// keyspace for their whole duration, so concurrent runners never interleave.
 pub struct Migrator<'a> {
-    session: &'a Session,
-    migrations_src: &'a str,
+    session: SessionRef<'a>,
+    source: Arc<dyn MigrationSource + 'a>,
+    registered: Vec<Migration>,
+    options: MigratorOptions,
+    prepared: Arc<PreparedStatements>,
+    /// What the cluster runs, detected when first needed
+    server: Arc<OnceLock<Server>>,
+}
+
+impl fmt::Debug for Migrator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Migrator")
+            .field("session", &self.session)
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Migrator<'static> {
+    /// Creates a Migrator with default settings owning a share of `session`
+    ///
+    /// Unlike [`Migrator::new`], it borrows nothing, so services can keep it
+    /// in their state or run migrations from a background task at startup:
+    ///
+    /// ```no_run
+    /// # async fn start(session: std::sync::Arc<scylla::Session>) {
+    /// let runner = scylla_migrate::Migrator::new_arc(session, "migrations");
+    /// let migrations = tokio::spawn(async move { runner.run().await });
+    /// # }
+    /// ```
+    ///
+    /// Shorthand for `Migrator::builder().build_arc(session, migrations_src)`.
+    pub fn new_arc(session: Arc<impl Execute + 'static>, migrations_src: impl AsRef<Path>) -> Self {
+        Self::builder().build_arc(session, migrations_src)
+    }
 }
 
 impl<'a> Migrator<'a> {
-    /// Creates a new Migrator instance
-    pub fn new(session: &'a Session, migrations_src: &'a str) -> Self {
-        Self {
-            session,
-            migrations_src,
+    /// Creates a new Migrator instance with default settings
+    ///
+    /// Shorthand for `Migrator::builder().build(session, migrations_src)`.
+    pub fn new(session: &'a impl Execute, migrations_src: &'a str) -> Self {
+        Self::builder().build(session, migrations_src)
+    }
+
+    /// Creates a Migrator with default settings for migrations compiled into the binary
+    ///
+    /// Shorthand for `Migrator::builder().build_embedded(session, migrations)`.
+    pub fn embedded(session: &'a impl Execute, migrations: &'a EmbeddedMigrations) -> Self {
+        Self::builder().build_embedded(session, migrations)
+    }
+
+    /// Returns a builder for configuring a Migrator
+    pub fn builder() -> MigratorBuilder {
+        MigratorBuilder::default()
+    }
+
+    /// Runs `work` while holding the migration lock
+    ///
+    /// Creates the history keyspace and lock table first, since the lock has to
+    /// live somewhere before anything else can be set up safely.
+    async fn locked<T>(&self, work: impl Future<Output = Result<T>>) -> Result<T> {
+        if self.options.manage_history_schema {
+            self.create_history_keyspace().await?;
+            MigrationLock::create_table(&self.session, &self.options.history).await?;
+            if self.options.audit {
+                audit::create_table(&self.session, &self.options.history).await?;
+            }
+        } else {
+            self.require_table("migration_lock").await?;
+            if self.options.audit {
+                self.require_table("migration_audit").await?;
+            }
+        }
+
+        let lock = MigrationLock::acquire(
+            &self.session,
+            &self.options.history,
+            self.options.lock_wait,
+            self.options.lock_lease,
+        )
+        .await?;
+        self.audit(AuditEntry::new(AuditAction::LockAcquired)).await;
+
+        let result = tokio::select! {
+            result = work => result,
+            err = lock.keep_alive() => Err(err),
+        };
+
+        let released = lock.release().await;
+        if released.is_ok() {
+            self.audit(AuditEntry::new(AuditAction::LockReleased)).await;
         }
+        let value = result?;
+        released?;
+        Ok(value)
     }
 
-    async fn create_public_keyspace(&self) -> Result<()> {
+    async fn create_history_keyspace(&self) -> Result<()> {
+        let replication = self.options.history_replication.clone().unwrap_or_default();
         self.session
-            .query_unpaged(
-                r#"
-                CREATE KEYSPACE IF NOT EXISTS public
-                WITH REPLICATION = {'class' : 'NetworkTopologyStrategy', 'replication_factor' : 1}
-                "#,
+            .query(
+                Query::new(format!(
+                    "CREATE KEYSPACE IF NOT EXISTS {} WITH REPLICATION = {}",
+                    self.options.history.keyspace, replication
+                )),
                 &[],
             )
             .await?;
         self.session.await_schema_agreement().await?;
+
+        if let Some(replication) = &self.options.history_replication {
+            self.check_history_replication(replication).await?;
+        }
         Ok(())
     }
 
-    async fn create_migration_table(&self) -> Result<()> {
+    /// Compares the replication of an existing history keyspace with the configured one
+    async fn check_history_replication(&self, replication: &Replication) -> Result<()> {
+        let (current,): (HashMap<String, String>,) = self
+            .session
+            .query(
+                Query::new(
+                    "SELECT replication FROM system_schema.keyspaces WHERE keyspace_name = ?",
+                ),
+                &(self.options.history.keyspace.as_str(),),
+            )
+            .await?
+            .first_row()?;
+        if replication.matches(&current) {
+            return Ok(());
+        }
+
+        if !self.options.alter_history_replication {
+            warn!(
+                "History keyspace {} is replicated with {:?} instead of {}",
+                self.options.history.keyspace, current, replication
+            );
+            return Ok(());
+        }
+
+        info!(
+            "Altering replication of history keyspace {} to {}",
+            self.options.history.keyspace, replication
+        );
         self.session
-            .query_unpaged(
-                r#"CREATE TABLE IF NOT EXISTS public.migrations (
-                    version bigint,
-                    checksum blob,
-                    description text,
-                    applied_at timestamp,
-                    PRIMARY KEY (version, checksum)
-                )"#,
+            .query(
+                Query::new(format!(
+                    "ALTER KEYSPACE {} WITH REPLICATION = {}",
+                    self.options.history.keyspace, replication
+                )),
                 &[],
             )
             .await?;
@@ -78,126 +376,3114 @@ impl<'a> Migrator<'a> {
         Ok(())
     }
 
-    async fn record_migration(&self, migration: &Migration) -> Result<()> {
+    async fn create_migration_table(&self) -> Result<()> {
+        if !self.options.manage_history_schema {
+            return self.require_history_tables().await;
+        }
+
         self.session
-            .query_unpaged(
-                r#"
-                    INSERT INTO public.migrations
-                        (version, description, checksum, applied_at)
-                        VALUES (?, ?, ?, ?)
+            .query(
+                Query::new(format!(
+                    r#"CREATE TABLE IF NOT EXISTS {} (
+                        version bigint,
+                        checksum blob,
+                        description text,
+                        applied_at timestamp,
+                        has_down boolean,
+                        execution_time_ms bigint,
+                        applied_by text,
+                        host text,
+                        status text,
+                        error text,
+                        cql text,
+                        PRIMARY KEY (version, checksum)
+                    )"#,
+                    self.options.history
+                )),
+                &[],
+            )
+            .await?;
+        self.session
+            .query(
+                Query::new(format!(
+                    r#"CREATE TABLE IF NOT EXISTS {} (
+                        description text,
+                        checksum blob,
+                        applied_at timestamp,
+                        PRIMARY KEY (description)
+                    )"#,
+                    self.options.history.repeatable()
+                )),
+                &[],
+            )
+            .await?;
+        self.session
+            .query(
+                Query::new(format!(
+                    r#"CREATE TABLE IF NOT EXISTS {} (
+                        version bigint,
+                        statements_done int,
+                        checksum blob,
+                        updated_at timestamp,
+                        PRIMARY KEY (version)
+                    )"#,
+                    self.options.history.progress()
+                )),
+                &[],
+            )
+            .await?;
+        self.session.await_schema_agreement().await?;
+        self.upgrade_migration_table().await
+    }
+
+    async fn table_exists(&self, table: &str) -> Result<bool> {
+        let query_rows = self
+            .session
+            .query(
+                Query::new(
+                    r#"
+                    SELECT table_name FROM system_schema.tables
+                        WHERE keyspace_name = ? AND table_name = ?
                 "#,
-                (
-                    migration.version,
-                    migration.description.as_ref(),
-                    migration.checksum.as_ref(),
-                    OffsetDateTime::now_utc(),
                 ),
+                &(self.options.history.keyspace.as_str(), table),
             )
             .await?;
+        Ok(!query_rows.is_empty())
+    }
+
+    /// Fails unless `table` exists in the history keyspace
+    async fn require_table(&self, table: &str) -> Result<()> {
+        if self.table_exists(table).await? {
+            return Ok(());
+        }
+        Err(Error::MissingHistorySchema(format!(
+            "Table {}.{}",
+            self.options.history.keyspace, table
+        )))
+    }
+
+    /// Checks that the history tables exist with all columns, for unmanaged history schemas
+    async fn require_history_tables(&self) -> Result<()> {
+        let table = &self.options.history.table;
+        self.require_table(table).await?;
+        self.require_table(&format!("{}_repeatable", table)).await?;
+        self.require_table(&format!("{}_progress", table)).await?;
+
+        let columns = self.migration_table_columns().await?;
+        if let Some((name, _)) = ADDED_COLUMNS
+            .iter()
+            .find(|(name, _)| !columns.contains(*name))
+        {
+            return Err(Error::MissingHistorySchema(format!(
+                "Column {} of {}",
+                name, self.options.history
+            )));
+        }
         Ok(())
     }
 
-    async fn get_applied_migrations(&self) -> Result<HashMap<i64, AppliedMigration>> {
+    /// Returns the columns of the tracking table, empty if it does not exist yet
+    async fn migration_table_columns(&self) -> Result<HashSet<String>> {
         let query_rows = self
             .session
-            .query_unpaged("SELECT version, checksum FROM public.migrations", ())
+            .query(
+                Query::new(
+                    r#"
+                    SELECT column_name FROM system_schema.columns
+                        WHERE keyspace_name = ? AND table_name = ?
+                "#,
+                ),
+                &(
+                    self.options.history.keyspace.as_str(),
+                    self.options.history.table.as_str(),
+                ),
+            )
+            .await?;
+
+        let columns = query_rows
+            .rows::<(String,)>()?
+            .into_iter()
+            .map(|(name,)| name)
+            .collect();
+        Ok(columns)
+    }
+
+    async fn upgrade_migration_table(&self) -> Result<()> {
+        let columns = self.migration_table_columns().await?;
+
+        let mut altered = false;
+        for (name, cql_type) in ADDED_COLUMNS {
+            if columns.contains(*name) {
+                continue;
+            }
+
+            self.session
+                .query(
+                    Query::new(format!(
+                        "ALTER TABLE {} ADD {} {}",
+                        self.options.history, name, cql_type
+                    )),
+                    &[],
+                )
+                .await?;
+            altered = true;
+        }
+
+        if altered {
+            self.session.await_schema_agreement().await?;
+        }
+        Ok(())
+    }
+
+    async fn record_migration(&self, migration: &Migration, duration: Duration) -> Result<()> {
+        if migration.kind == MigrationKind::Repeatable {
+            return self.record_repeatable(migration).await;
+        }
+
+        let applied = AppliedMigration {
+            checksum: migration.checksum.clone(),
+            description: migration.description.clone(),
+            applied_at: Some(OffsetDateTime::now_utc()),
+            has_down: migration.down.is_some(),
+            execution_time_ms: Some(duration.as_millis() as i64),
+            applied_by: self.options.applied_by.clone(),
+            host: Some(gethostname::gethostname().to_string_lossy().into_owned()),
+            error: None,
+        };
+        self.insert_versioned(migration.version, &applied, executed_cql(migration))
+            .await
+    }
+
+    /// Leaves a record of a versioned migration that failed
+    ///
+    /// The record doesn't count as applied; it shows up as failed in the
+    /// status until a later attempt succeeds.
+    async fn record_failure(&self, migration: &Migration, duration: Duration, error: &Error) {
+        if migration.kind == MigrationKind::Repeatable {
+            return;
+        }
+
+        let failed = AppliedMigration {
+            checksum: migration.checksum.clone(),
+            description: migration.description.clone(),
+            applied_at: Some(OffsetDateTime::now_utc()),
+            has_down: migration.down.is_some(),
+            execution_time_ms: Some(duration.as_millis() as i64),
+            applied_by: self.options.applied_by.clone(),
+            host: Some(gethostname::gethostname().to_string_lossy().into_owned()),
+            error: Some(error_chain(error)),
+        };
+        // The original error matters more than failing to record it
+        if let Err(err) = self
+            .insert_versioned(migration.version, &failed, executed_cql(migration))
+            .await
+        {
+            warn!("Failed to record failure of {}: {}", migration, err);
+        }
+    }
+
+    /// Writes a record of a versioned migration, with the CQL it executed if
+    /// it was executed
+    async fn insert_versioned(
+        &self,
+        version: i64,
+        applied: &AppliedMigration,
+        cql: Option<&str>,
+    ) -> Result<()> {
+        self.write_history(
+            format!(
+                r#"
+                        INSERT INTO {}
+                            (version, description, checksum, applied_at, has_down,
+                             execution_time_ms, applied_by, host, status, error, cql)
+                            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                self.options.history
+            ),
+            (
+                version,
+                applied.description.as_ref(),
+                applied.checksum.as_ref(),
+                applied.applied_at.unwrap_or_else(OffsetDateTime::now_utc),
+                applied.has_down,
+                applied.execution_time_ms,
+                applied.applied_by.as_deref(),
+                applied.host.as_deref(),
+                if applied.error.is_some() {
+                    "failed"
+                } else {
+                    "success"
+                },
+                applied.error.as_deref(),
+                cql,
+            ),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the CQL recorded with the history row of `version` and `checksum`
+    async fn recorded_cql(&self, version: i64, checksum: &[u8]) -> Result<Option<String>> {
+        let row = self
+            .query_history(
+                format!(
+                    "SELECT cql FROM {} WHERE version = ? AND checksum = ?",
+                    self.options.history
+                ),
+                (version, checksum),
+            )
             .await?
-            .into_rows_result()
-            .context("Failed to get rows from migrations table")?;
+            .maybe_first_row::<(Option<String>,)>()?;
+        Ok(row.and_then(|(cql,)| cql))
+    }
+
+    async fn record_repeatable(&self, migration: &Migration) -> Result<()> {
+        self.write_history(
+            format!(
+                r#"
+                        INSERT INTO {} (description, checksum, applied_at)
+                            VALUES (?, ?, ?)
+                    "#,
+                self.options.history.repeatable()
+            ),
+            (
+                migration.description.as_ref(),
+                migration.checksum.as_ref(),
+                OffsetDateTime::now_utc(),
+            ),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_migration(&self, version: i64) -> Result<()> {
+        self.write_history(
+            format!("DELETE FROM {} WHERE version = ?", self.options.history),
+            (version,),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Replaces the recorded checksum of an applied migration with its local one
+    ///
+    /// The checksum is part of the primary key, so the row is rewritten,
+    /// keeping the time it was applied.
+    async fn update_checksum(
+        &self,
+        migration: &Migration,
+        applied: &AppliedMigration,
+    ) -> Result<()> {
+        // What was executed stays recorded, not the edited file
+        let cql = self
+            .recorded_cql(migration.version, &applied.checksum)
+            .await?;
+        self.remove_migration(migration.version).await?;
+        self.insert_versioned(
+            migration.version,
+            &AppliedMigration {
+                checksum: migration.checksum.clone(),
+                description: migration.description.clone(),
+                ..applied.clone()
+            },
+            cql.as_deref(),
+        )
+        .await
+    }
+
+    async fn get_applied_migrations(&self) -> Result<AppliedHistory> {
+        let (versioned, failed) = self.get_applied_versioned().await?;
+        Ok(AppliedHistory {
+            versioned,
+            failed,
+            repeatable: self.get_applied_repeatables().await?,
+        })
+    }
+
+    /// Passes every row of the migrations table to `read`, reapplications and
+    /// failed attempts included
+    ///
+    /// The rows are read page by page, so large histories are never held in
+    /// memory as a whole.
+    async fn read_versioned_rows(&self, mut read: impl FnMut(i64, AppliedMigration)) -> Result<()> {
+        let mut pages = self
+            .query_history_paged(
+                format!(
+                    r#"
+                    SELECT version, checksum, description, applied_at, has_down,
+                           execution_time_ms, applied_by, host, status, error
+                        FROM {}
+                "#,
+                    self.options.history
+                ),
+                (),
+            )
+            .await?;
+
+        while let Some(page) = pages.try_next().await? {
+            for (v, c, desc, at, d, ms, by, host, status, error) in page.rows::<HistoryRow>()? {
+                read(
+                    v,
+                    AppliedMigration {
+                        checksum: Cow::Owned(c),
+                        description: Cow::Owned(desc.unwrap_or_default()),
+                        applied_at: at,
+                        has_down: d.unwrap_or(false),
+                        execution_time_ms: ms,
+                        applied_by: by,
+                        host,
+                        // Rows written before the status column existed are successes
+                        error: match status.as_deref() {
+                            Some("failed") => Some(error.unwrap_or_default()),
+                            _ => None,
+                        },
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the applied versioned migrations and the failed attempts since
+    async fn get_applied_versioned(&self) -> Result<(VersionedHistory, VersionedHistory)> {
+        let mut map = VersionedHistory::new();
+        let mut failed = VersionedHistory::new();
+
+        self.read_versioned_rows(|v, applied| {
+            let map = match applied.error {
+                Some(_) => &mut failed,
+                None => &mut map,
+            };
+
+            // A migration reapplied after a change has one row per checksum,
+            // the most recent one describes what is in the schema now
+            if map
+                .get(&v)
+                .is_some_and(|recorded| recorded.applied_at >= applied.applied_at)
+            {
+                return;
+            }
+
+            map.insert(v, applied);
+        })
+        .await?;
+
+        // Failures fixed by a later successful attempt are history
+        failed.retain(|v, attempt| {
+            map.get(v)
+                .is_none_or(|applied| applied.applied_at < attempt.applied_at)
+        });
+
+        Ok((map, failed))
+    }
+
+    async fn get_applied_repeatables(&self) -> Result<HashMap<String, AppliedMigration>> {
+        let mut pages = self
+            .query_history_paged(
+                format!(
+                    "SELECT description, checksum, applied_at FROM {}",
+                    self.options.history.repeatable()
+                ),
+                (),
+            )
+            .await?;
 
         let mut map = HashMap::new();
 
-        for row in query_rows.rows()? {
-            let (v, c): (i64, Vec<u8>) = row?;
-            map.insert(
-                v,
-                AppliedMigration {
-                    checksum: Cow::Owned(c),
-                },
-            );
+        while let Some(page) = pages.try_next().await? {
+            for (desc, c, at) in page.rows::<(String, Vec<u8>, Option<OffsetDateTime>)>()? {
+                map.insert(
+                    desc.clone(),
+                    AppliedMigration {
+                        checksum: Cow::Owned(c),
+                        description: Cow::Owned(desc),
+                        applied_at: at,
+                        has_down: false,
+                        execution_time_ms: None,
+                        applied_by: None,
+                        host: None,
+                        error: None,
+                    },
+                );
+            }
         }
 
         Ok(map)
     }
 
+    /// Like `get_applied_migrations`, but without requiring the tracking tables to exist
+    async fn get_existing_applied_migrations(&self) -> Result<AppliedHistory> {
+        let mut history = AppliedHistory::default();
+        if !self.migration_table_columns().await?.is_empty() {
+            (history.versioned, history.failed) = self.get_applied_versioned().await?;
+        }
+        if self
+            .table_exists(&format!("{}_repeatable", self.options.history.table))
+            .await?
+        {
+            history.repeatable = self.get_applied_repeatables().await?;
+        }
+        Ok(history)
+    }
+
     async fn load_migrations(&self) -> Result<Vec<Migration>> {
-        let mut entries = fs::read_dir(&self.migrations_src)
-            .await
-            .context("Could not find migrations directory")?;
+        let mut migrations = self.source.migrations().await?;
+        migrations.extend(self.registered.iter().cloned());
 
-        let mut migrations = Vec::new();
+        let blocks = migrations.iter().any(|migration| {
+            dialect::has_blocks(&migration.cql)
+                || migration.down.as_deref().is_some_and(dialect::has_blocks)
+        });
+        let product = match blocks {
+            true => Some(self.server().await?.product),
+            false => None,
+        };
 
-        while let Some(entry) = entries.next_entry().await? {
-            if let Ok(meta) = entry.metadata().await {
-                if !meta.is_file() {
-                    continue;
-                }
+        for migration in &mut migrations {
+            if let Some(product) = product {
+                dialect::resolve(migration, product)?;
+            }
+            self.substitute_variables(migration)?;
+            self.rename_keyspaces(migration);
+            if let Some(description) = migration.directives()?.description {
+                migration.description = Cow::Owned(description);
+            }
+        }
 
-                let path = entry.path();
-                if path.extension().and_then(|ext| ext.to_str()) != Some("cql") {
-                    continue;
-                }
+        // Versioned migrations in version order, then repeatable ones, as they
+        // may depend on any versioned one
+        migrations.sort_by_key(|m| (m.kind == MigrationKind::Repeatable, m.version));
 
-                let filename = entry.file_name().to_string_lossy().into_owned();
+        // Registered migrations may collide with each other or with the source
+        if let Some(duplicate) = migrations.windows(2).find(|pair| {
+            pair.iter().all(|m| m.kind == MigrationKind::Versioned)
+                && pair[0].version == pair[1].version
+        }) {
+            let version = duplicate[0].version;
+            let names = migrations
+                .iter()
+                .filter(|m| m.kind == MigrationKind::Versioned && m.version == version)
+                .map(|m| m.description.to_string())
+                .collect();
+            return Err(Error::DuplicateVersion { version, names });
+        }
+        Ok(migrations)
+    }
 
-                let version = filename
-                    .split('_')
-                    .next()
-                    .and_then(|v| v.parse::<i64>().ok())
-                    .ok_or_else(|| {
-                        anyhow::anyhow!("Invalid migration filename format: {}", filename)
-                    })?;
+    /// Resolves the placeholders of the up and down scripts
+    ///
+    /// The checksum stays that of the script as written, so changing a
+    /// variable does not mark applied migrations as modified.
+    fn substitute_variables(&self, migration: &mut Migration) -> Result<()> {
+        let undefined = |name| Error::UndefinedVariable {
+            migration: migration.description.to_string(),
+            name,
+        };
+        let cql = match self.options.variables.substitute(&migration.cql) {
+            Ok(Cow::Owned(cql)) => Some(cql),
+            Ok(Cow::Borrowed(_)) => None,
+            Err(name) => return Err(undefined(name)),
+        };
+        let down = match migration
+            .down
+            .as_deref()
+            .map(|down| self.options.variables.substitute(down))
+        {
+            Some(Ok(Cow::Owned(down))) => Some(down),
+            Some(Ok(Cow::Borrowed(_))) | None => None,
+            Some(Err(name)) => return Err(undefined(name)),
+        };
 
-                let cql = fs::read_to_string(path).await?;
+        if let Some(cql) = cql {
+            migration.cql = Cow::Owned(cql);
+        }
+        if let Some(down) = down {
+            migration.down = Some(Cow::Owned(down));
+        }
+        Ok(())
+    }
 
-                migrations.push(Migration::new(
-                    version,
-                    Cow::Owned(entry.file_name().to_string_lossy().to_string()),
-                    Cow::Owned(cql),
-                ));
+    /// Rewrites the keyspace references of the up and down scripts, see
+    /// [`MigratorBuilder::rename_keyspace`]
+    fn rename_keyspaces(&self, migration: &mut Migration) {
+        for (from, to) in &self.options.keyspace_renames {
+            if let Some(cql) = rename_keyspace(&migration.cql, from, to) {
+                migration.cql = Cow::Owned(cql);
+            }
+            if let Some(down) = migration
+                .down
+                .as_deref()
+                .and_then(|down| rename_keyspace(down, from, to))
+            {
+                migration.down = Some(Cow::Owned(down));
             }
         }
+    }
 
-        // Sort migrations by version
-        migrations.sort_by(|a, b| a.version.cmp(&b.version));
-        Ok(migrations)
+    /// Returns the runner currently holding the migration lock, if any
+    pub async fn lock_holder(&self) -> Result<Option<LockHolder>> {
+        if !self.table_exists("migration_lock").await? {
+            return Ok(None);
+        }
+        MigrationLock::holder(&self.session, &self.options.history).await
     }
 
-    /// Runs all pending migrations
+    /// Releases a migration lock left behind by `holder`
     ///
-    /// This will:
-    /// 1. Create the public keyspace and migrations table if they don't exist
-    /// 2. Load all migrations from the migrations directory
-    /// 3. Check each migration and execute it if it hasn't been applied
-    pub async fn run(&self) -> Result<()> {
-        self.create_public_keyspace().await?;
-        self.create_migration_table().await?;
+    /// Meant for recovering from a runner that crashed while holding the lock;
+    /// releasing the lock of a live runner lets others migrate concurrently.
+    /// Returns false if `holder` no longer holds the lock.
+    pub async fn force_unlock(&self, holder: &LockHolder) -> Result<bool> {
+        let released =
+            MigrationLock::force_release(&self.session, &self.options.history, holder).await?;
+        if released {
+            self.audit(
+                AuditEntry::new(AuditAction::LockForceReleased)
+                    .detail(format!("held by {}", holder)),
+            )
+            .await;
+        }
+        Ok(released)
+    }
+
+    /// Computes which migrations a run would apply, without applying them
+    ///
+    /// Unlike [`Migrator::run`], this neither creates the history keyspace and
+    /// table nor executes any migration; it only reads the history.
+    pub async fn plan(&self) -> Result<Plan> {
+        self.plan_with(&RunOptions::default()).await
+    }
 
+    /// Computes which migrations [`Migrator::run_with`] would apply for the given options
+    pub async fn plan_with(&self, options: &RunOptions) -> Result<Plan> {
         let migrations = self.load_migrations().await?;
-        let applied_migrations = self.get_applied_migrations().await?;
-        for migration in migrations {
-            if let Some(applied) = applied_migrations.get(&migration.version) {
-                if applied.checksum.as_ref() == migration.checksum.as_ref() {
-                    println!("Migration {} already applied", migration.description);
-                    continue;
-                } else {
-                    // Checksum different - run the migration again as it might have new statements
-                    println!(
-                        "Migration {} has changes, applying updates",
-                        migration.description
-                    );
+        let applied_migrations = self.get_existing_applied_migrations().await?;
+        let migrations = self.in_environment(migrations, &applied_migrations)?;
+        let (migrations, unmet) = self
+            .meeting_requirements(migrations, &applied_migrations)
+            .await?;
+        let migrations = dependency::order(migrations)?;
+
+        self.check_checksums(&migrations, &applied_migrations)?;
+        self.check_order(&migrations, &applied_migrations)?;
+        self.check_missing(&migrations, &applied_migrations)?;
+
+        let mut plan = Plan {
+            skipped: unmet,
+            ..Plan::default()
+        };
+        let mut pending = Vec::new();
+        for migration in &migrations {
+            let changed = match applied_migrations.get(migration) {
+                Some(applied) if applied.checksum.as_ref() == migration.checksum.as_ref() => {
+                    continue
                 }
+                Some(_) => true,
+                None => false,
+            };
+
+            if changed && self.ignores_change(migration) {
+                plan.skipped.push(SkippedMigration {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    reason: SkipReason::ChecksumMismatch,
+                });
+                continue;
             }
 
-            // Either migration hasn't been applied or has changes
-            migration.up(self.session).await?;
-            self.record_migration(&migration).await?;
-            println!(
-                "Applied {}/migrate {}",
-                migration.version, migration.description
+            if let Some(newest) = self.skipped_out_of_order(migration, &applied_migrations) {
+                plan.skipped.push(SkippedMigration {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    reason: SkipReason::OutOfOrder(newest),
+                });
+                continue;
+            }
+
+            let versioned = plan
+                .migrations
+                .iter()
+                .filter(|m| m.kind == MigrationKind::Versioned)
+                .count();
+            if let Some(reason) = options.skip_reason(migration, versioned) {
+                plan.skipped.push(SkippedMigration {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    reason,
+                });
+                continue;
+            }
+
+            pending.push(migration);
+            plan.migrations.push(PlannedMigration {
+                kind: migration.kind,
+                version: migration.version,
+                description: migration.description.to_string(),
+                changed,
+                statements: migration
+                    .statements()
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            });
+        }
+
+        dependency::check(&pending, &applied_migrations)?;
+        self.check_dialect(&pending).await?;
+        self.check_destructive(&pending)?;
+        self.check_lint(&pending)?;
+        Ok(plan)
+    }
+
+    /// Reports every known migration as applied, pending, changed or missing
+    ///
+    /// Local migrations are merged with the recorded history.
+    /// Like [`Migrator::plan`], this only reads from the cluster.
+    pub async fn status(&self) -> Result<Status> {
+        let migrations = self.load_migrations().await?;
+        let mut applied_migrations = self.get_existing_applied_migrations().await?;
+        let migrations = self.in_environment(migrations, &applied_migrations)?;
+
+        let mut status = Status::default();
+        for migration in migrations {
+            let applied = match migration.kind {
+                MigrationKind::Versioned => applied_migrations.versioned.remove(&migration.version),
+                MigrationKind::Repeatable => applied_migrations
+                    .repeatable
+                    .remove(migration.description.as_ref()),
+            };
+
+            let entry = match applied {
+                Some(applied) => MigrationStatus {
+                    kind: migration.kind,
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    state: if applied.checksum.as_ref() == migration.checksum.as_ref() {
+                        MigrationState::Applied
+                    } else {
+                        MigrationState::Changed
+                    },
+                    checksum: applied.checksum.into_owned(),
+                    applied_at: applied.applied_at,
+                    execution_time_ms: applied.execution_time_ms,
+                    applied_by: applied.applied_by,
+                    host: applied.host,
+                    error: None,
+                },
+                None => match applied_migrations.failed.remove(&migration.version) {
+                    Some(attempt) if migration.kind == MigrationKind::Versioned => {
+                        MigrationStatus {
+                            kind: migration.kind,
+                            version: migration.version,
+                            description: migration.description.to_string(),
+                            state: MigrationState::Failed,
+                            checksum: attempt.checksum.into_owned(),
+                            applied_at: attempt.applied_at,
+                            execution_time_ms: attempt.execution_time_ms,
+                            applied_by: attempt.applied_by,
+                            host: attempt.host,
+                            error: attempt.error,
+                        }
+                    }
+                    _ => MigrationStatus {
+                        kind: migration.kind,
+                        version: migration.version,
+                        description: migration.description.to_string(),
+                        state: MigrationState::Pending,
+                        checksum: migration.checksum.into_owned(),
+                        applied_at: None,
+                        execution_time_ms: None,
+                        applied_by: None,
+                        host: None,
+                        error: None,
+                    },
+                },
+            };
+            status.migrations.push(entry);
+        }
+
+        // Whatever is left was applied from files that are no longer present
+        let missing = applied_migrations
+            .versioned
+            .into_iter()
+            .map(|(version, applied)| (MigrationKind::Versioned, version, applied))
+            .chain(
+                applied_migrations
+                    .repeatable
+                    .into_values()
+                    .map(|applied| (MigrationKind::Repeatable, 0, applied)),
             );
+        for (kind, version, applied) in missing {
+            status.migrations.push(MigrationStatus {
+                kind,
+                version,
+                description: applied.description.into_owned(),
+                state: MigrationState::Missing,
+                checksum: applied.checksum.into_owned(),
+                applied_at: applied.applied_at,
+                execution_time_ms: applied.execution_time_ms,
+                applied_by: applied.applied_by,
+                host: applied.host,
+                error: None,
+            });
         }
 
-        Ok(())
+        // Versioned migrations first in version order, then repeatable ones by name
+        status.migrations.sort_by(|a, b| {
+            (
+                a.kind == MigrationKind::Repeatable,
+                a.version,
+                &a.description,
+            )
+                .cmp(&(
+                    b.kind == MigrationKind::Repeatable,
+                    b.version,
+                    &b.description,
+                ))
+        });
+        Ok(status)
+    }
+
+    /// Checks that every applied versioned migration still exists locally, unchanged
+    ///
+    /// Reads only, like [`Migrator::status`]. Check [`Validation::is_valid`]
+    /// before deploying, e.g. in CI. Under [`MissingPolicy::Warn`] migrations
+    /// missing locally are logged instead of reported.
+    pub async fn validate(&self) -> Result<Validation> {
+        let mut validation = Validation::from_status(&self.status().await?);
+        if self.options.missing_policy == MissingPolicy::Warn {
+            validation.issues.retain(|issue| {
+                if issue.discrepancy != Discrepancy::MissingLocally {
+                    return true;
+                }
+                warn!(
+                    version = issue.version,
+                    "Migration {}/migrate {} was applied but is not present locally",
+                    issue.version,
+                    issue.description
+                );
+                false
+            });
+        }
+        Ok(validation)
+    }
+
+    /// Returns everything recorded in the history tables, oldest first
+    ///
+    /// Unlike [`Migrator::status`], which shows the current state of each
+    /// migration, this lists every application of a migration reapplied after a
+    /// change and every failed attempt. Repeatable migrations only keep their
+    /// latest application. Local migrations are not read.
+    pub async fn history(&self) -> Result<History> {
+        let mut history = History::default();
+        if !self.migration_table_columns().await?.is_empty() {
+            self.read_versioned_rows(|version, applied| {
+                history
+                    .entries
+                    .push(history_entry(MigrationKind::Versioned, version, applied));
+            })
+            .await?;
+        }
+        if self
+            .table_exists(&format!("{}_repeatable", self.options.history.table))
+            .await?
+        {
+            for applied in self.get_applied_repeatables().await?.into_values() {
+                history
+                    .entries
+                    .push(history_entry(MigrationKind::Repeatable, 0, applied));
+            }
+        }
+
+        history
+            .entries
+            .sort_by_key(|entry| (entry.applied_at, entry.version));
+        Ok(history)
+    }
+
+    /// Returns the CQL the applied versioned migration `version` executed, as
+    /// recorded in the history
+    ///
+    /// The script is recorded after variables were replaced and blocks for
+    /// other databases dropped. `None` if the migration isn't applied, or was
+    /// recorded without executing it, e.g. by [`Migrator::baseline`], by a
+    /// code migration or before the history recorded scripts.
+    pub async fn applied_cql(&self, version: i64) -> Result<Option<String>> {
+        let applied = self.get_existing_applied_migrations().await?;
+        match applied.versioned.get(&version) {
+            Some(applied) => self.recorded_cql(version, &applied.checksum).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the actions recorded in the audit log, oldest first
+    ///
+    /// Empty unless runners record them, see [`MigratorBuilder::audit`].
+    /// Actions recorded by newer versions of the runner are left out.
+    pub async fn audit_log(&self) -> Result<AuditLog> {
+        let mut log = AuditLog::default();
+        if !self.table_exists("migration_audit").await? {
+            return Ok(log);
+        }
+
+        let mut pages = self
+            .query_history_paged(
+                format!(
+                    r#"
+                        SELECT at, action, version, description, actor, host, detail
+                            FROM {} WHERE name = ?
+                    "#,
+                    audit::table(&self.options.history)
+                ),
+                (self.options.history.table.as_str(),),
+            )
+            .await?;
+        while let Some(page) = pages.try_next().await? {
+            for (at, action, version, description, actor, host, detail) in
+                page.rows::<AuditRow>()?
+            {
+                let Some(action) = AuditAction::parse(&action) else {
+                    continue;
+                };
+                log.entries.push(AuditEntry {
+                    at,
+                    action,
+                    version,
+                    description,
+                    actor,
+                    host,
+                    detail,
+                });
+            }
+        }
+        Ok(log)
+    }
+
+    /// Returns the applied migrations, versioned ones in version order followed
+    /// by repeatable ones by name
+    ///
+    /// Each migration is listed once, with its latest application. Local
+    /// migrations are not read, so this works without the migration files,
+    /// e.g. on an admin page.
+    pub async fn applied(&self) -> Result<Vec<HistoryEntry>> {
+        let applied = self.get_existing_applied_migrations().await?;
+
+        let mut versioned: Vec<_> = applied.versioned.into_iter().collect();
+        versioned.sort_by_key(|(version, _)| *version);
+        let mut repeatable: Vec<_> = applied.repeatable.into_values().collect();
+        repeatable.sort_by(|a, b| a.description.cmp(&b.description));
+
+        Ok(versioned
+            .into_iter()
+            .map(|(version, applied)| history_entry(MigrationKind::Versioned, version, applied))
+            .chain(
+                repeatable
+                    .into_iter()
+                    .map(|applied| history_entry(MigrationKind::Repeatable, 0, applied)),
+            )
+            .collect())
+    }
+
+    /// Returns the local migrations a run would apply, in the order they would
+    /// run, including repeatable migrations changed since their last application
+    ///
+    /// Migrations a run skips under [`RequirementPolicy::Skip`] or
+    /// [`OutOfOrderPolicy::Skip`] are left out, like those of other environments.
+    /// Unlike [`Migrator::plan`], no other policy or run option is checked.
+    pub async fn pending(&self) -> Result<Vec<Migration>> {
+        let migrations = self.load_migrations().await?;
+        let applied = self.get_existing_applied_migrations().await?;
+        let migrations = self.in_environment(migrations, &applied)?;
+        let migrations = match self.options.requirement_policy {
+            RequirementPolicy::Skip => self.meeting_requirements(migrations, &applied).await?.0,
+            // A run fails on them instead, so they are pending
+            RequirementPolicy::Error => migrations,
+        };
+        let migrations = dependency::order(migrations)?;
+
+        Ok(unapplied(migrations, &applied, self.options.out_of_order))
+    }
+
+    /// Returns how many local migrations a run would apply
+    ///
+    /// Counts what [`Migrator::pending`] returns. Besides the history tables,
+    /// the cluster is only asked what it runs if a migration has a `requires`
+    /// directive, so it is cheap enough for readiness probes.
+    pub async fn pending_count(&self) -> Result<usize> {
+        Ok(self.pending().await?.len())
+    }
+
+    /// Returns whether every local migration has been applied, but those a
+    /// run would skip, see [`Migrator::pending`]
+    ///
+    /// Meant for readiness probes: a service whose binary expects a newer
+    /// schema than the cluster has can refuse to become ready until the
+    /// migrations have run.
+    pub async fn is_up_to_date(&self) -> Result<bool> {
+        Ok(self.pending_count().await? == 0)
+    }
+
+    /// Returns the highest applied version, or `None` before the first migration
+    ///
+    /// Meant for asserting at startup that the schema is recent enough for
+    /// the application.
+    pub async fn current_version(&self) -> Result<Option<i64>> {
+        let applied = self.get_existing_applied_migrations().await?;
+        Ok(applied.versioned.into_keys().max())
+    }
+
+    /// Compares the live schema with the one the applied migrations create
+    ///
+    /// The DDL statements of the applied migrations are replayed in the order
+    /// they run, and the resulting keyspaces, types, tables, columns, indexes
+    /// and views are compared with `system_schema`, reporting objects that were
+    /// created, dropped or altered outside of migrations. Only keyspaces the
+    /// migrations use are compared, without the tables of the runner itself.
+    /// Replication and table options are not compared, and unqualified names
+    /// resolve to the keyspace of the session unless a migration switches with
+    /// `USE`.
+    pub async fn detect_drift(&self) -> Result<Drift> {
+        let migrations = self.load_migrations().await?;
+        let applied_migrations = self.get_existing_applied_migrations().await?;
+
+        let mut implied = ImpliedSchema::new(self.session_keyspace());
+        for migration in &migrations {
+            if applied_migrations.get(migration).is_none() {
+                continue;
+            }
+            for statement in migration.statements() {
+                implied.apply(statement);
+            }
+        }
+
+        let live = self.live_schema(&implied.schema).await?;
+        Ok(Drift {
+            differences: implied.schema.diff(&live),
+        })
+    }
+
+    /// Returns the CQL script turning the live schema into `target`, see
+    /// [`Schema::migration_to`]
+    ///
+    /// Only the keyspaces of `target` are compared, so other keyspaces are
+    /// never dropped, and the tables of the runner itself are left alone on
+    /// both sides. Running the script, e.g. as a new migration, is up to the
+    /// caller.
+    pub async fn migration_to(&self, target: &Schema) -> Result<String> {
+        let live = self.live_schema(target).await?;
+        let mut target = target.clone();
+        self.remove_history_tables(&mut target);
+        Ok(live.migration_to(&target))
+    }
+
+    /// Reads the live schema of the keyspaces of `schema` that exist, without
+    /// the tables of the runner
+    async fn live_schema(&self, schema: &Schema) -> Result<Schema> {
+        let existing = existing_keyspaces(&self.session).await?;
+        let keyspaces: Vec<String> = schema
+            .keyspaces
+            .keys()
+            .filter(|ks| existing.contains(*ks))
+            .cloned()
+            .collect();
+        let mut live = if keyspaces.is_empty() {
+            Schema::default()
+        } else {
+            Schema::read(self.session.require("drift detection")?, &keyspaces).await?
+        };
+        self.remove_history_tables(&mut live);
+        Ok(live)
+    }
+
+    fn remove_history_tables(&self, schema: &mut Schema) {
+        remove_runner_tables(schema, &self.options.history);
+    }
+
+    /// Updates the history to match the local migrations
+    ///
+    /// Stores the local checksum for applied migrations that were modified and
+    /// removes the history of migrations deleted locally, fixing what
+    /// [`Migrator::validate`] reports. No migration is executed. Meant for
+    /// intentional edits to applied migrations, such as reformatting; returns
+    /// the discrepancies that were repaired.
+    pub async fn repair(&self) -> Result<Validation> {
+        self.locked(self.repair_history(None)).await
+    }
+
+    /// Repairs the discrepancies of `confirmed`, such as a [`Validation`]
+    /// shown to and confirmed by a user
+    ///
+    /// Discrepancies that no longer exist once the lock is held are skipped,
+    /// and those found since are left for another validation; returns the
+    /// discrepancies that were repaired.
+    pub async fn repair_confirmed(&self, confirmed: &Validation) -> Result<Validation> {
+        self.locked(self.repair_history(Some(confirmed))).await
+    }
+
+    async fn repair_history(&self, confirmed: Option<&Validation>) -> Result<Validation> {
+        self.create_migration_table().await?;
+
+        let mut validation = Validation::from_status(&self.status().await?);
+        if let Some(confirmed) = confirmed {
+            validation.issues.retain(|issue| {
+                confirmed
+                    .issues
+                    .iter()
+                    .any(|c| c.version == issue.version && c.discrepancy == issue.discrepancy)
+            });
+        }
+        let migrations = self.load_migrations().await?;
+        let applied_migrations = self.get_applied_migrations().await?;
+        for issue in &validation.issues {
+            match issue.discrepancy {
+                Discrepancy::ChecksumMismatch => {
+                    let Some(migration) = migrations
+                        .iter()
+                        .find(|m| m.kind == MigrationKind::Versioned && m.version == issue.version)
+                    else {
+                        continue;
+                    };
+                    let Some(applied) = applied_migrations.versioned.get(&issue.version) else {
+                        continue;
+                    };
+                    self.update_checksum(migration, applied).await?;
+                    self.audit(
+                        AuditEntry::new(AuditAction::Repaired)
+                            .migration(migration)
+                            .detail("updated the checksum"),
+                    )
+                    .await;
+                    info!(
+                        version = migration.version,
+                        description = %migration.description,
+                        "Updated checksum of {}",
+                        migration
+                    );
+                }
+                Discrepancy::MissingLocally => {
+                    self.remove_migration(issue.version).await?;
+                    self.audit(
+                        AuditEntry::new(AuditAction::Repaired)
+                            .version(issue.version, &issue.description)
+                            .detail("removed from the history"),
+                    )
+                    .await;
+                    info!(
+                        version = issue.version,
+                        description = %issue.description,
+                        "Removed {}/migrate {} from the history",
+                        issue.version,
+                        issue.description
+                    );
+                }
+            }
+        }
+
+        Ok(validation)
+    }
+
+    /// Runs all pending migrations
+    ///
+    /// This will:
+    /// 1. Create the history keyspace and migrations table if they don't exist
+    /// 2. Load all migrations from the migrations directory
+    /// 3. Check each migration and execute it if it hasn't been applied
+    ///
+    /// The run stops at the first failing migration with a [`RunFailed`]
+    /// error, which holds the report up to that point.
+    pub async fn run(&self) -> Result<MigrationReport> {
+        self.run_with(&RunOptions::default()).await
+    }
+
+    /// Runs pending migrations up to and including `version`
+    ///
+    /// Pending migrations newer than `version` are reported as skipped.
+    pub async fn run_to(&self, version: i64) -> Result<MigrationReport> {
+        self.run_with(&RunOptions::new().to_version(version)).await
+    }
+
+    /// Runs the pending migrations selected by `options`
+    pub async fn run_with(&self, options: &RunOptions) -> Result<MigrationReport> {
+        self.run_with_cancel(options, future::pending()).await
+    }
+
+    /// Runs the pending migrations selected by `options` until `cancel` completes
+    ///
+    /// Once it does, e.g. on a shutdown signal, the statement being executed
+    /// is let finish and the run stops with [`Error::Cancelled`], reporting the
+    /// migrations applied so far and releasing the lock. A versioned migration
+    /// stopped halfway resumes at its next statement on the next run, while
+    /// repeatable and code migrations are always finished first.
+    pub async fn run_with_cancel(
+        &self,
+        options: &RunOptions,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<MigrationReport> {
+        let cancelled = AtomicBool::new(false);
+        until_cancelled(self.run_until(options, &cancelled), cancel, &cancelled).await
+    }
+
+    /// Runs the pending migrations selected by `options`, stopping once
+    /// `cancelled` is set
+    async fn run_until(
+        &self,
+        options: &RunOptions,
+        cancelled: &AtomicBool,
+    ) -> Result<MigrationReport> {
+        let span = telemetry::span!("run", history = %self.options.history);
+        telemetry::describe(&span, "migrate", Some(&self.options.history.keyspace));
+        let result = self
+            .locked(self.audited_run(options, cancelled))
+            .instrument(span.clone())
+            .await;
+        telemetry::record_result(&span, &result);
+        result
+    }
+
+    /// Applies the pending migrations, recording the run in the audit log
+    async fn audited_run(
+        &self,
+        options: &RunOptions,
+        cancelled: &AtomicBool,
+    ) -> Result<MigrationReport> {
+        self.audit(AuditEntry::new(AuditAction::RunStarted)).await;
+        let result = self.apply_pending(options, cancelled).await;
+
+        let report = match &result {
+            Ok(report) => Some(report),
+            Err(Error::RunFailed(failed)) => Some(&failed.report),
+            Err(_) => None,
+        };
+        for skipped in report.iter().flat_map(|report| &report.skipped) {
+            self.audit(
+                AuditEntry::new(AuditAction::MigrationSkipped)
+                    .version(skipped.version, &skipped.description)
+                    .detail(skipped.reason.to_string()),
+            )
+            .await;
+        }
+        let finished = match &result {
+            Ok(report) => AuditEntry::new(AuditAction::RunFinished).detail(format!(
+                "{} applied, {} skipped",
+                report.applied.len(),
+                report.skipped.len()
+            )),
+            Err(err) => AuditEntry::new(AuditAction::RunFailed).detail(error_chain(err)),
+        };
+        self.audit(finished).await;
+        result
+    }
+
+    /// Returns a runner migrating the tenant keyspace `keyspace`
+    ///
+    /// The runner records its history in a table of the tenant keyspace,
+    /// named like the configured history table, e.g. `customer_1.migrations`,
+    /// and holds the lock of that keyspace. `${tenant}` placeholders are
+    /// replaced by `keyspace`. Unlike other history keyspaces, the replication
+    /// of a tenant keyspace is never checked or altered.
+    pub fn for_tenant(&self, keyspace: &str) -> Migrator<'a> {
+        let mut options = self.options.clone();
+        options.history.keyspace = keyspace.to_string();
+        options.history_replication = None;
+        options
+            .variables
+            .values
+            .insert("tenant".to_string(), keyspace.to_string());
+
+        Migrator {
+            session: self.session.clone(),
+            source: Arc::clone(&self.source),
+            registered: self.registered.clone(),
+            options,
+            prepared: Arc::clone(&self.prepared),
+            server: Arc::clone(&self.server),
+        }
+    }
+
+    /// Runs the pending migrations selected by `options` in every tenant keyspace
+    ///
+    /// Tenants are migrated each as by [`Migrator::for_tenant`], one after the
+    /// other or as many at once as
+    /// [`MigratorBuilder::tenant_concurrency`] allows. The first failing
+    /// tenant stops the run with an [`Error::Tenant`]; the tenants before it
+    /// stay migrated.
+    pub async fn run_tenants(
+        &self,
+        tenants: &Tenants,
+        options: &RunOptions,
+    ) -> Result<Vec<(String, MigrationReport)>> {
+        self.run_tenants_with_cancel(tenants, options, future::pending())
+            .await
+    }
+
+    /// Like [`Migrator::run_tenants`], but stops like
+    /// [`Migrator::run_with_cancel`] once `cancel` completes
+    ///
+    /// Tenants being migrated stop after their current statement and no
+    /// further tenants are started.
+    pub async fn run_tenants_with_cancel(
+        &self,
+        tenants: &Tenants,
+        options: &RunOptions,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<Vec<(String, MigrationReport)>> {
+        let mut reports = Vec::new();
+        for (keyspace, report) in self
+            .run_each_tenant_with_cancel(tenants, options, cancel)
+            .await?
+        {
+            match report {
+                Ok(report) => reports.push((keyspace, report)),
+                Err(source) => {
+                    return Err(Error::Tenant {
+                        keyspace,
+                        source: Box::new(source),
+                    })
+                }
+            }
+        }
+        Ok(reports)
+    }
+
+    /// Like [`Migrator::run_tenants`], but returns the outcome of every tenant
+    /// that was migrated, in tenant order
+    ///
+    /// Once a tenant fails, no further tenants are started. Those already being
+    /// migrated concurrently are still finished, so the outcomes may include
+    /// tenants after the failing one, and more than one failure.
+    pub async fn run_each_tenant(
+        &self,
+        tenants: &Tenants,
+        options: &RunOptions,
+    ) -> Result<Vec<(String, Result<MigrationReport>)>> {
+        self.run_each_tenant_with_cancel(tenants, options, future::pending())
+            .await
+    }
+
+    /// Like [`Migrator::run_each_tenant`], but stops like
+    /// [`Migrator::run_tenants_with_cancel`] once `cancel` completes
+    pub async fn run_each_tenant_with_cancel(
+        &self,
+        tenants: &Tenants,
+        options: &RunOptions,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<Vec<(String, Result<MigrationReport>)>> {
+        let keyspaces = tenants.keyspaces(&self.session).await?;
+        info!(
+            "Migrating {} tenant keyspaces, {} at a time",
+            keyspaces.len(),
+            self.options.tenant_concurrency
+        );
+
+        let failed = AtomicBool::new(false);
+        let cancelled = AtomicBool::new(false);
+        let outcomes = stream::iter(keyspaces)
+            .map(|keyspace| {
+                let failed = &failed;
+                let cancelled = &cancelled;
+                async move {
+                    if failed.load(Ordering::SeqCst) {
+                        return None;
+                    }
+                    let report = self
+                        .for_tenant(&keyspace)
+                        .run_until(options, cancelled)
+                        .instrument(info_span!("tenant", keyspace = %keyspace))
+                        .await;
+                    if report.is_err() {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                    Some((keyspace, report))
+                }
+            })
+            .buffered(self.options.tenant_concurrency)
+            .filter_map(future::ready)
+            .collect();
+        Ok(until_cancelled(outcomes, cancel, &cancelled).await)
+    }
+
+    async fn apply_pending(
+        &self,
+        options: &RunOptions,
+        cancelled: &AtomicBool,
+    ) -> Result<MigrationReport> {
+        self.create_migration_table().await?;
+
+        let migrations = self.load_migrations().await?;
+        let applied_migrations = self.get_applied_migrations().await?;
+        let migrations = self.in_environment(migrations, &applied_migrations)?;
+        let (migrations, unmet) = self
+            .meeting_requirements(migrations, &applied_migrations)
+            .await?;
+        let migrations = dependency::order(migrations)?;
+        self.check_checksums(&migrations, &applied_migrations)?;
+        self.check_order(&migrations, &applied_migrations)?;
+        self.check_missing(&migrations, &applied_migrations)?;
+
+        let mut report = MigrationReport {
+            skipped: unmet,
+            ..MigrationReport::default()
+        };
+        let mut pending: Vec<&Migration> = Vec::new();
+        for migration in &migrations {
+            let applied = applied_migrations.get(migration);
+            if applied.is_some_and(|a| a.checksum.as_ref() == migration.checksum.as_ref()) {
+                info!(
+                    version = migration.version,
+                    description = %migration.description,
+                    "Migration {} already applied",
+                    migration.description
+                );
+                continue;
+            }
+
+            if applied.is_some() && self.ignores_change(migration) {
+                warn!(
+                    version = migration.version,
+                    description = %migration.description,
+                    "Migration {} was changed after it was applied, not reapplying",
+                    migration
+                );
+                report.skipped.push(SkippedMigration {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    reason: SkipReason::ChecksumMismatch,
+                });
+                continue;
+            }
+
+            if let Some(newest) = self.skipped_out_of_order(migration, &applied_migrations) {
+                warn!(
+                    version = migration.version,
+                    description = %migration.description,
+                    "Migration {} is older than the applied version {}, not applying",
+                    migration,
+                    newest
+                );
+                report.skipped.push(SkippedMigration {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    reason: SkipReason::OutOfOrder(newest),
+                });
+                continue;
+            }
+
+            let versioned = pending
+                .iter()
+                .filter(|m| m.kind == MigrationKind::Versioned)
+                .count();
+            if let Some(reason) = options.skip_reason(migration, versioned) {
+                info!(
+                    version = migration.version,
+                    description = %migration.description,
+                    "Skipped {}: {}",
+                    migration,
+                    reason
+                );
+                report.skipped.push(SkippedMigration {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    reason,
+                });
+                continue;
+            }
+
+            pending.push(migration);
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.options.metrics {
+            metrics.set_pending(&self.options.history.keyspace, pending.len());
+        }
+
+        // Nothing is executed while a denied statement is pending or a dependency missing
+        dependency::check(&pending, &applied_migrations)?;
+        self.check_dialect(&pending).await?;
+        self.check_destructive(&pending)?;
+        self.check_lint(&pending)?;
+
+        let hooks = &self.options.hooks;
+        if !pending.is_empty() {
+            hooks.before_all(&self.session, &pending).await?;
+        }
+
+        for (i, &migration) in pending.iter().enumerate() {
+            if i > 0 {
+                if let Some(delay) = options.migration_delay {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(report.abort(Error::Cancelled));
+            }
+            let applied = applied_migrations.get(migration);
+            let span = telemetry::span!(
+                "migration",
+                version = migration.version,
+                description = %migration.description
+            );
+            telemetry::describe(
+                &span,
+                &format!("migrate {}", migration),
+                Some(&self.options.history.keyspace),
+            );
+            if applied.is_some() {
+                // Checksum different - run the migration again as it might have new statements
+                span.in_scope(|| {
+                    info!(
+                        "Migration {} has changes, applying updates",
+                        migration.description
+                    )
+                });
+            }
+
+            if let Err(err) = hooks.before_each(&self.session, migration).await {
+                return Err(report.abort(err));
+            }
+
+            // Either migration hasn't been applied or has changes
+            self.emit(&MigrationEvent::MigrationStarted { migration });
+            let started = Instant::now();
+            let result = self
+                .apply_migration(migration, options, cancelled)
+                .instrument(span.clone())
+                .await;
+            telemetry::record_result(&span, &result);
+            let duration = started.elapsed();
+            if let Err(err @ Error::Cancelled) = result {
+                // Recorded as failed, so the history shows it partially applied
+                self.record_failure(migration, duration, &err).await;
+                span.in_scope(|| info!("Cancelled {}, it resumes on the next run", migration));
+                return Err(report.abort(err));
+            }
+            if let Err(err) = result {
+                self.record_failure(migration, duration, &err).await;
+                #[cfg(feature = "metrics")]
+                if let Some(metrics) = &self.options.metrics {
+                    metrics.record_failed(&self.options.history.keyspace);
+                }
+                self.audit(
+                    AuditEntry::new(AuditAction::MigrationFailed)
+                        .migration(migration)
+                        .detail(error_chain(&err)),
+                )
+                .await;
+                self.emit(&MigrationEvent::MigrationFailed {
+                    migration,
+                    duration,
+                    error: &err,
+                });
+                span.in_scope(|| {
+                    error!(
+                        duration_ms = duration.as_millis() as u64,
+                        "Failed {}: {}",
+                        migration,
+                        error_chain(&err)
+                    )
+                });
+                return Err(report.fail(migration, duration, err));
+            }
+
+            self.emit(&MigrationEvent::MigrationApplied {
+                migration,
+                duration,
+            });
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = &self.options.metrics {
+                metrics.record_applied(&self.options.history.keyspace, duration);
+            }
+            self.audit(
+                AuditEntry::new(AuditAction::MigrationApplied)
+                    .migration(migration)
+                    .detail(format!("in {}ms", duration.as_millis())),
+            )
+            .await;
+            report.push_applied(migration, applied.is_some(), duration);
+            span.in_scope(|| {
+                info!(
+                    duration_ms = duration.as_millis() as u64,
+                    "Applied {}", migration
+                )
+            });
+
+            if let Err(err) = hooks.after_each(&self.session, migration).await {
+                return Err(report.abort(err));
+            }
+        }
+
+        if !pending.is_empty() {
+            if let Err(err) = hooks.after_all(&self.session, &pending).await {
+                return Err(report.abort(err));
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Executes `migration` and records it as applied
+    ///
+    /// Versioned CQL migrations are checkpointed after every statement, so a
+    /// migration interrupted by a failing statement resumes at that statement
+    /// instead of replaying the ones before it, which may not be idempotent.
+    /// Once `cancelled` is set, they also stop at the next checkpoint with
+    /// [`Error::Cancelled`].
+    async fn apply_migration(
+        &self,
+        migration: &Migration,
+        run: &RunOptions,
+        cancelled: &AtomicBool,
+    ) -> Result<()> {
+        let started = Instant::now();
+        if migration.code.is_some() {
+            migration
+                .up(self.session.require("code migrations")?)
+                .await?;
+            return self.record_migration(migration, started.elapsed()).await;
+        }
+
+        let directives = migration.directives()?;
+        let options = StatementOptions {
+            delay: run.statement_delay,
+            ..self.statement_options()
+        }
+        .with_directives(&directives);
+
+        let checkpointed = migration.kind == MigrationKind::Versioned;
+        let statements = migration.statements();
+        let resume_at = if checkpointed {
+            self.resume_point(migration, &statements).await?
+        } else {
+            0
+        };
+
+        for (i, statement) in statements.iter().enumerate().skip(resume_at) {
+            if i > resume_at {
+                if let Some(delay) = options.delay {
+                    tokio::time::sleep(delay).await;
+                }
+                if checkpointed && cancelled.load(Ordering::Relaxed) {
+                    return Err(Error::Cancelled);
+                }
+            }
+            let duration = migration
+                .execute_statement(&self.session, i + 1, statement, &options)
+                .await?;
+            self.emit(&MigrationEvent::StatementExecuted {
+                migration,
+                index: i + 1,
+                statement,
+                duration,
+            });
+            if checkpointed {
+                self.save_progress(migration, &statements[..=i]).await?;
+            }
+        }
+        // A rerun after a failed assertion resumes here, checking them again
+        for assertion in &directives.asserts {
+            assertion.check(&self.session, migration, &options).await?;
+        }
+
+        self.record_migration(migration, started.elapsed()).await?;
+        if checkpointed {
+            self.clear_progress(migration.version).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns how many statements of `migration` an earlier, interrupted run executed
+    ///
+    /// Those statements are only skipped if they are still the same; if they
+    /// were edited since, the migration starts over.
+    async fn resume_point(&self, migration: &Migration, statements: &[&str]) -> Result<usize> {
+        let progress = self
+            .query_history(
+                format!(
+                    "SELECT statements_done, checksum FROM {} WHERE version = ?",
+                    self.options.history.progress()
+                ),
+                (migration.version,),
+            )
+            .await?
+            .maybe_first_row::<(i32, Vec<u8>)>()?;
+
+        let Some((done, checksum)) = progress else {
+            return Ok(0);
+        };
+
+        let done = done as usize;
+        if done <= statements.len() && statements_checksum(&statements[..done]) == checksum {
+            info!(
+                "Resuming {} at statement {} of {}",
+                migration,
+                done + 1,
+                statements.len()
+            );
+            Ok(done)
+        } else {
+            warn!(
+                "Statements of {} executed by an interrupted run have changed, starting over",
+                migration
+            );
+            Ok(0)
+        }
+    }
+
+    /// Records that the statements `done` of `migration` have been executed
+    async fn save_progress(&self, migration: &Migration, done: &[&str]) -> Result<()> {
+        self.write_history(
+            format!(
+                r#"
+                        INSERT INTO {} (version, statements_done, checksum, updated_at)
+                            VALUES (?, ?, ?, ?)
+                    "#,
+                self.options.history.progress()
+            ),
+            (
+                migration.version,
+                done.len() as i32,
+                statements_checksum(done),
+                OffsetDateTime::now_utc(),
+            ),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn clear_progress(&self, version: i64) -> Result<()> {
+        self.write_history(
+            format!(
+                "DELETE FROM {} WHERE version = ?",
+                self.options.history.progress()
+            ),
+            (version,),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the keyspace the session uses, which unqualified names resolve to
+    fn session_keyspace(&self) -> Option<String> {
+        let session = self.session.session()?;
+        session.get_keyspace().map(|keyspace| keyspace.to_string())
+    }
+
+    /// Returns how migration statements are executed, before directives
+    fn statement_options(&self) -> StatementOptions {
+        let schema_agreement = match self.options.schema_agreement {
+            SchemaAgreement::Auto => self
+                .session
+                .session()
+                .is_some_and(|session| session.get_cluster_data().get_nodes_info().len() > 1),
+            SchemaAgreement::Always => true,
+            SchemaAgreement::Never => false,
+        };
+
+        StatementOptions {
+            consistency: self.options.statement_consistency,
+            timeout: None,
+            schema_agreement,
+            wait_for_builds: self.options.wait_for_builds,
+            delay: None,
+            retry: self.options.retry,
+        }
+    }
+
+    /// Queries the history tables with a prepared statement, with the
+    /// configured consistency and retries
+    async fn query_history(&self, cql: String, values: impl SerializeRow + Sync) -> Result<Rows> {
+        let prepared = self.prepare_history(&cql).await?;
+        let rows = self
+            .options
+            .retry
+            .run(|| self.session.execute(&prepared, &values))
+            .await?;
+        Ok(rows)
+    }
+
+    /// Writes to the history tables with a prepared statement, with the
+    /// configured consistency and retries
+    async fn write_history(&self, cql: String, values: impl SerializeRow + Sync) -> Result<()> {
+        self.query_history(cql, values).await?;
+        Ok(())
+    }
+
+    /// Pages through the rows of a history query, with the configured
+    /// consistency, retrying the request of the first page
+    async fn query_history_paged(
+        &self,
+        cql: String,
+        values: impl SerializeRow + Sync,
+    ) -> Result<Pages> {
+        let prepared = self.prepare_history(&cql).await?;
+        let rows = self
+            .options
+            .retry
+            .run(|| self.session.execute_paged(&prepared, &values))
+            .await?;
+        Ok(rows)
+    }
+
+    /// Returns the prepared statement of a history query, with the configured
+    /// consistency
+    async fn prepare_history(&self, cql: &str) -> Result<Prepared> {
+        let mut prepared = self
+            .options
+            .retry
+            .run(|| self.prepared.get(&self.session, cql))
+            .await?;
+        if let Some(consistency) = self.options.history_consistency {
+            prepared.set_consistency(consistency);
+        }
+        Ok(prepared)
+    }
+
+    /// Records `entry` in the audit log, if enabled
+    ///
+    /// Failing to record it only warns, as the action it records already happened.
+    async fn audit(&self, entry: AuditEntry) {
+        if !self.options.audit {
+            return;
+        }
+
+        let recorded = self
+            .write_history(
+                format!(
+                    r#"
+                        INSERT INTO {}
+                            (name, at, id, action, version, description, actor, host, detail)
+                            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                    audit::table(&self.options.history)
+                ),
+                (
+                    self.options.history.table.as_str(),
+                    entry.at,
+                    Uuid::new_v4(),
+                    entry.action.as_str(),
+                    entry.version,
+                    entry.description.as_deref(),
+                    self.options.applied_by.as_deref(),
+                    gethostname::gethostname().to_string_lossy().as_ref(),
+                    entry.detail.as_deref(),
+                ),
+            )
+            .await;
+        if let Err(err) = recorded {
+            warn!(
+                "Failed to record {} in the audit log: {}",
+                entry.action, err
+            );
+        }
+    }
+
+    fn emit(&self, event: &MigrationEvent<'_>) {
+        for hook in &self.options.event_hooks {
+            hook.call(event);
+        }
+    }
+
+    /// Fails if the checksum policy forbids running with modified applied migrations
+    fn check_checksums(&self, migrations: &[Migration], applied: &AppliedHistory) -> Result<()> {
+        if self.options.checksum_policy != ChecksumPolicy::Error {
+            return Ok(());
+        }
+
+        let versions: Vec<i64> = migrations
+            .iter()
+            .filter(|m| m.kind == MigrationKind::Versioned)
+            .filter(|m| {
+                applied
+                    .get(m)
+                    .is_some_and(|a| a.checksum.as_ref() != m.checksum.as_ref())
+            })
+            .map(|m| m.version)
+            .collect();
+        if !versions.is_empty() {
+            return Err(Error::ChecksumMismatch { versions });
+        }
+        Ok(())
+    }
+
+    /// Fails if a pending migration contains a statement denied by its
+    /// [`DestructivePolicy`], warning about those to warn about
+    fn check_destructive(&self, pending: &[&Migration]) -> Result<()> {
+        if self.options.destructive.is_empty() {
+            return Ok(());
+        }
+
+        for migration in pending {
+            for (i, statement) in migration.statements().into_iter().enumerate() {
+                let Some(kind) = Destructive::of(statement) else {
+                    continue;
+                };
+                match self.options.destructive.get(&kind) {
+                    Some(DestructivePolicy::Deny) => {
+                        return Err(Error::Destructive {
+                            version: migration.version,
+                            description: migration.description.to_string(),
+                            index: i + 1,
+                            statement: statement.to_string(),
+                            kind,
+                        })
+                    }
+                    Some(DestructivePolicy::Warn) => warn!(
+                        version = migration.version,
+                        description = %migration.description,
+                        "Migration {} contains a {} statement: {}",
+                        migration,
+                        kind,
+                        statement
+                    ),
+                    Some(DestructivePolicy::Allow) | None => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if the pre-flight linter finds anything in a pending migration
+    fn check_lint(&self, pending: &[&Migration]) -> Result<()> {
+        let Some(linter) = &self.options.linter else {
+            return Ok(());
+        };
+
+        for migration in pending {
+            let findings = linter.lint(&migration.cql);
+            if !findings.is_empty() {
+                return Err(Error::Lint {
+                    migration: migration.to_string(),
+                    findings,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if applied versioned migrations are missing locally, unless they are only to be warned about
+    fn check_missing(&self, migrations: &[Migration], applied: &AppliedHistory) -> Result<()> {
+        let local: HashSet<i64> = migrations
+            .iter()
+            .filter(|m| m.kind == MigrationKind::Versioned)
+            .map(|m| m.version)
+            .collect();
+        let mut versions: Vec<i64> = applied
+            .versioned
+            .keys()
+            .filter(|version| !local.contains(version))
+            .copied()
+            .collect();
+        if versions.is_empty() {
+            return Ok(());
+        }
+        versions.sort_unstable();
+
+        match self.options.missing_policy {
+            MissingPolicy::Error => Err(Error::MissingMigrations { versions }),
+            MissingPolicy::Warn => {
+                for version in versions {
+                    warn!(
+                        version,
+                        "Migration {} was applied but is not present locally", version
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Fails if the out-of-order policy forbids pending migrations older than applied ones
+    fn check_order(&self, migrations: &[Migration], applied: &AppliedHistory) -> Result<()> {
+        if self.options.out_of_order != OutOfOrderPolicy::Error {
+            return Ok(());
+        }
+
+        let versions: Vec<i64> = migrations
+            .iter()
+            .filter(|m| out_of_order(m, applied).is_some())
+            .map(|m| m.version)
+            .collect();
+        if let Some(newest) = applied
+            .versioned
+            .keys()
+            .max()
+            .filter(|_| !versions.is_empty())
+        {
+            return Err(Error::OutOfOrder {
+                versions,
+                newest: *newest,
+            });
+        }
+        Ok(())
+    }
+
+    /// Drops migrations limited to other environments, except applied ones
+    ///
+    /// Applied migrations are kept so they are still validated, rather than
+    /// reported as missing when the environment is changed or left unset.
+    fn in_environment(
+        &self,
+        migrations: Vec<Migration>,
+        applied: &AppliedHistory,
+    ) -> Result<Vec<Migration>> {
+        let mut kept = Vec::with_capacity(migrations.len());
+        for migration in migrations {
+            let environments = migration.directives()?.environments;
+            let included = environments.is_empty()
+                || self
+                    .options
+                    .environment
+                    .as_ref()
+                    .is_some_and(|env| environments.contains(env));
+            if included || applied.get(&migration).is_some() {
+                kept.push(migration);
+            } else {
+                debug!(
+                    "Leaving out {}, which is limited to {}",
+                    migration,
+                    environments.join(", ")
+                );
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Leaves out the pending migrations whose `requires` directive the
+    /// cluster doesn't meet, returning them as skipped, or fails on them under
+    /// [`RequirementPolicy::Error`]
+    ///
+    /// Like migrations of other environments, applied versioned migrations are
+    /// kept, and left-out ones aren't out of order once the cluster meets them.
+    async fn meeting_requirements(
+        &self,
+        migrations: Vec<Migration>,
+        applied: &AppliedHistory,
+    ) -> Result<(Vec<Migration>, Vec<SkippedMigration>)> {
+        let mut kept = Vec::with_capacity(migrations.len());
+        let mut skipped = Vec::new();
+        for migration in migrations {
+            let requirement = match migration.directives()?.requires {
+                Some(requirement)
+                    if migration.kind == MigrationKind::Repeatable
+                        || applied.get(&migration).is_none() =>
+                {
+                    requirement
+                }
+                _ => {
+                    kept.push(migration);
+                    continue;
+                }
+            };
+            let server = self.server().await?;
+            if requirement.is_met_by(&server) {
+                kept.push(migration);
+                continue;
+            }
+
+            if self.options.requirement_policy == RequirementPolicy::Error {
+                return Err(Error::UnmetRequirement {
+                    migration: migration.to_string(),
+                    requirement,
+                    server,
+                });
+            }
+            info!(
+                version = migration.version,
+                description = %migration.description,
+                "Skipped {}: requires {}, the cluster runs {}",
+                migration,
+                requirement,
+                server
+            );
+            skipped.push(SkippedMigration {
+                version: migration.version,
+                description: migration.description.to_string(),
+                reason: SkipReason::UnmetRequirement(requirement),
+            });
+        }
+        Ok((kept, skipped))
+    }
+
+    /// Returns what the cluster runs, asking it the first time
+    async fn server(&self) -> Result<Server> {
+        if let Some(server) = self.server.get() {
+            return Ok(*server);
+        }
+        let server = Server::detect(&self.session).await?;
+        Ok(*self.server.get_or_init(|| server))
+    }
+
+    /// Fails if a pending migration uses a feature only the other database has
+    ///
+    /// The cluster is only asked what it runs if such a feature is used.
+    async fn check_dialect(&self, pending: &[&Migration]) -> Result<()> {
+        for migration in pending {
+            for (index, statement) in migration.statements().into_iter().enumerate() {
+                let Some((feature, product)) = dialect::exclusive_feature(statement) else {
+                    continue;
+                };
+                let server = self.server().await?;
+                if server.product != product {
+                    return Err(Error::Dialect {
+                        migration: migration.to_string(),
+                        index: index + 1,
+                        feature: feature.to_string(),
+                        server,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the newest applied version if `migration` is to be skipped for being older
+    fn skipped_out_of_order(&self, migration: &Migration, applied: &AppliedHistory) -> Option<i64> {
+        skipped_out_of_order(self.options.out_of_order, migration, applied)
+    }
+
+    /// Returns true if a change to the applied `migration` is to be left alone
+    fn ignores_change(&self, migration: &Migration) -> bool {
+        migration.kind == MigrationKind::Versioned
+            && self.options.checksum_policy == ChecksumPolicy::Warn
+    }
+
+    /// Records all versioned migrations up to and including `version` as
+    /// applied, without executing them
+    ///
+    /// Meant for adopting the tool on a database whose schema was managed by
+    /// hand so far. Migrations already recorded are left alone. Returns the
+    /// number of migrations recorded.
+    pub async fn baseline(&self, version: i64) -> Result<usize> {
+        self.locked(self.record_baseline(version)).await
+    }
+
+    async fn record_baseline(&self, version: i64) -> Result<usize> {
+        self.create_migration_table().await?;
+
+        let migrations = self.load_migrations().await?;
+        let applied_migrations = self.get_applied_migrations().await?;
+        let migrations = self.in_environment(migrations, &applied_migrations)?;
+
+        let mut recorded = 0;
+        for migration in &migrations {
+            if migration.kind != MigrationKind::Versioned
+                || migration.version > version
+                || applied_migrations
+                    .versioned
+                    .contains_key(&migration.version)
+            {
+                continue;
+            }
+
+            self.mark_applied(migration).await?;
+            self.audit(AuditEntry::new(AuditAction::Baselined).migration(migration))
+                .await;
+            info!(
+                version = migration.version,
+                description = %migration.description,
+                "Baselined {}",
+                migration
+            );
+            recorded += 1;
+        }
+
+        Ok(recorded)
+    }
+
+    /// Records the migration with `version` as applied without executing it
+    ///
+    /// For reconciling environments where a change was applied by hand, e.g.
+    /// during an incident. Returns false if the migration was already applied.
+    pub async fn fake(&self, version: i64) -> Result<bool> {
+        self.locked(self.record_fake(version)).await
+    }
+
+    async fn record_fake(&self, version: i64) -> Result<bool> {
+        self.create_migration_table().await?;
+
+        let migrations = self.load_migrations().await?;
+        let migration = migrations
+            .iter()
+            .find(|m| m.kind == MigrationKind::Versioned && m.version == version)
+            .ok_or(Error::UnknownVersion(version))?;
+
+        let applied_migrations = self.get_applied_migrations().await?;
+        if applied_migrations.versioned.contains_key(&version) {
+            return Ok(false);
+        }
+
+        self.mark_applied(migration).await?;
+        self.clear_progress(version).await?;
+        self.audit(AuditEntry::new(AuditAction::Faked).migration(migration))
+            .await;
+        info!(
+            version,
+            description = %migration.description,
+            "Recorded {} as applied",
+            migration
+        );
+        Ok(true)
+    }
+
+    /// Removes the history of the migration with `version` without reverting it
+    ///
+    /// The opposite of [`Migrator::fake`]; the next run applies the migration
+    /// again. Returns false if it was not recorded.
+    pub async fn unfake(&self, version: i64) -> Result<bool> {
+        self.locked(self.remove_fake(version)).await
+    }
+
+    async fn remove_fake(&self, version: i64) -> Result<bool> {
+        self.create_migration_table().await?;
+
+        let applied_migrations = self.get_applied_migrations().await?;
+        let Some(applied) = applied_migrations.versioned.get(&version) else {
+            return Ok(false);
+        };
+
+        self.remove_migration(version).await?;
+        self.audit(AuditEntry::new(AuditAction::Unfaked).version(version, &applied.description))
+            .await;
+        info!(
+            version,
+            description = %applied.description,
+            "Removed {}/migrate {} from the history",
+            version,
+            applied.description
+        );
+        Ok(true)
+    }
+
+    /// Drops every table, view, function and type of `keyspace` and removes
+    /// the migrations that changed it from the history, so a development
+    /// database can be migrated from scratch
+    ///
+    /// The keyspace itself is kept. Refuses with [`Error::Protected`] when a
+    /// pattern of [`MigratorBuilder::protect`] matches the cluster name,
+    /// `keyspace` or the environment, by default anything containing `prod`.
+    /// Records of migrations changing other keyspaces only are kept, and
+    /// nothing is dropped with [`Error::MixedHistory`] if a migration changed
+    /// `keyspace` and another one, or its keyspaces can't be told. The history
+    /// tables and the audit log are kept, also when they are in `keyspace`.
+    pub async fn clean(&self, keyspace: &str) -> Result<Clean> {
+        let session = self.session.require("clean")?;
+        let cluster = clean::cluster_name(session).await?;
+        let mut names = vec![("keyspace", keyspace)];
+        if let Some(cluster) = &cluster {
+            names.push(("cluster", cluster));
+        }
+        if let Some(environment) = &self.options.environment {
+            names.push(("environment", environment));
+        }
+        clean::check_protected(keyspace, &self.options.protected, &names)?;
+
+        self.locked(self.drop_all(keyspace)).await
+    }
+
+    async fn drop_all(&self, keyspace: &str) -> Result<Clean> {
+        let history = &self.options.history;
+        let records = self.cleaned_history(keyspace).await?;
+        let keep = if keyspace == history.keyspace {
+            runner_tables(history)
+        } else {
+            Vec::new()
+        };
+
+        let mut clean = Clean::default();
+        for (object, drop) in
+            clean::drop_statements(self.session.require("clean")?, keyspace, &keep).await?
+        {
+            self.session.query(Query::new(drop), &[]).await?;
+            info!("Dropped {}", object);
+            clean.dropped.push(object);
+        }
+        if !clean.dropped.is_empty() {
+            self.session.await_schema_agreement().await?;
+        }
+
+        let progress = self
+            .table_exists(&format!("{}_progress", history.table))
+            .await?;
+        for (migration, record) in records {
+            match record {
+                Record::Versioned(version) => {
+                    self.remove_migration(version).await?;
+                    if progress {
+                        self.clear_progress(version).await?;
+                    }
+                }
+                Record::Repeatable(description) => self.remove_repeatable(&description).await?,
+            }
+            info!("Removed {} from the history", migration);
+            clean.forgotten.push(migration);
+        }
+        self.audit(AuditEntry::new(AuditAction::Cleaned).detail(format!(
+            "{}, {} objects dropped, {} migrations removed from the history",
+            keyspace,
+            clean.dropped.len(),
+            clean.forgotten.len()
+        )))
+        .await;
+        Ok(clean)
+    }
+
+    /// Returns the applied migrations that changed `keyspace` and no other,
+    /// whose records [`Migrator::clean`] removes
+    ///
+    /// The CQL recorded with a versioned migration is read, as it is what was
+    /// executed, and the local file otherwise.
+    async fn cleaned_history(&self, keyspace: &str) -> Result<Vec<(String, Record)>> {
+        let migrations = self.load_migrations().await?;
+        let applied = self.get_existing_applied_migrations().await?;
+        let refuse = |migration: &str, reason: String| Error::MixedHistory {
+            keyspace: keyspace.to_string(),
+            migration: migration.to_string(),
+            reason,
+        };
+
+        let mut versions: Vec<_> = applied.versioned.iter().collect();
+        versions.sort_by_key(|(version, _)| **version);
+        let mut repeatables: Vec<_> = applied.repeatable.keys().collect();
+        repeatables.sort();
+
+        let mut changed = ChangedKeyspaces::new(self.session_keyspace());
+        let mut records = Vec::new();
+        let versioned = versions.into_iter().map(|(version, recorded)| {
+            let local = migrations
+                .iter()
+                .find(|m| m.kind == MigrationKind::Versioned && m.version == *version);
+            (
+                format!("{}/migrate {}", version, recorded.description),
+                Record::Versioned(*version),
+                local,
+                Some((*version, recorded)),
+            )
+        });
+        let repeatable = repeatables.into_iter().map(|description| {
+            let local = migrations
+                .iter()
+                .find(|m| m.kind == MigrationKind::Repeatable && m.description == *description);
+            (
+                format!("repeatable {}", description),
+                Record::Repeatable(description.clone()),
+                local,
+                None,
+            )
+        });
+        for (name, record, local, recorded) in versioned.chain(repeatable).collect::<Vec<_>>() {
+            let recorded_cql = match recorded {
+                Some((version, recorded)) => self.recorded_cql(version, &recorded.checksum).await?,
+                None => None,
+            };
+            let cql = match (recorded_cql, local) {
+                (Some(cql), _) => Cow::Owned(cql),
+                (None, Some(migration)) if migration.code.is_some() => {
+                    return Err(refuse(
+                        &name,
+                        "is a code migration, which may change any keyspace".to_string(),
+                    ));
+                }
+                (None, Some(migration)) => migration.cql.clone(),
+                (None, None) => {
+                    return Err(refuse(
+                        &name,
+                        "is no longer present locally, nor recorded with its CQL".to_string(),
+                    ));
+                }
+            };
+
+            let mut keyspaces = BTreeSet::new();
+            for statement in statements(&cql) {
+                if !changed.add(statement, &mut keyspaces) {
+                    return Err(refuse(
+                        &name,
+                        format!(
+                            "changes an object without naming its keyspace: {}",
+                            statement
+                        ),
+                    ));
+                }
+            }
+            if !keyspaces.contains(keyspace) {
+                continue;
+            }
+            if let Some(other) = keyspaces.iter().find(|other| *other != keyspace) {
+                return Err(refuse(
+                    &name,
+                    format!("also changes the keyspace {}", other),
+                ));
+            }
+            records.push((name, record));
+        }
+        Ok(records)
+    }
+
+    async fn remove_repeatable(&self, description: &str) -> Result<()> {
+        self.write_history(
+            format!(
+                "DELETE FROM {} WHERE description = ?",
+                self.options.history.repeatable()
+            ),
+            (description,),
+        )
+        .await
+    }
+
+    /// Rewrites the history after the migrations up to `version` were merged
+    /// into one with [`squash`]
+    ///
+    /// Removes the history of the squashed migrations and records the squashed
+    /// one in their place, keeping the time `version` was applied. Databases
+    /// that applied none of them are left alone, as the next run applies the
+    /// squashed migration like any other; returns false for them. Databases
+    /// that applied only some of them can't be rewritten, they have to be
+    /// migrated up to `version` with the unsquashed files first.
+    pub async fn squash_history(&self, version: i64) -> Result<bool> {
+        self.locked(self.record_squash(version)).await
+    }
+
+    async fn record_squash(&self, version: i64) -> Result<bool> {
+        self.create_migration_table().await?;
+
+        let migrations = self.load_migrations().await?;
+        let migration = migrations
+            .iter()
+            .find(|m| m.kind == MigrationKind::Versioned && m.version == version)
+            .ok_or(Error::UnknownVersion(version))?;
+        if migrations
+            .iter()
+            .any(|m| m.kind == MigrationKind::Versioned && m.version < version)
+        {
+            return Err(Error::Squash {
+                version,
+                reason: "older migrations are still present locally",
+            });
+        }
+
+        let applied_migrations = self.get_applied_migrations().await?;
+        let mut squashed: Vec<i64> = applied_migrations
+            .versioned
+            .keys()
+            .chain(applied_migrations.failed.keys())
+            .copied()
+            .filter(|&v| v < version)
+            .collect();
+        squashed.sort();
+        squashed.dedup();
+
+        let Some(applied) = applied_migrations.versioned.get(&version) else {
+            if squashed.is_empty() {
+                return Ok(false);
+            }
+            return Err(Error::Squash {
+                version,
+                reason: "only some of the squashed migrations are applied",
+            });
+        };
+
+        if applied.checksum != migration.checksum {
+            self.remove_migration(version).await?;
+            self.insert_versioned(
+                version,
+                &AppliedMigration {
+                    checksum: migration.checksum.clone(),
+                    description: migration.description.clone(),
+                    has_down: false,
+                    ..applied.clone()
+                },
+                None,
+            )
+            .await?;
+        }
+        for &squashed_version in &squashed {
+            self.remove_migration(squashed_version).await?;
+        }
+        self.audit(
+            AuditEntry::new(AuditAction::Squashed)
+                .migration(migration)
+                .detail(format!(
+                    "replaced {}",
+                    squashed
+                        .iter()
+                        .map(i64::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+        )
+        .await;
+        info!(
+            version,
+            description = %migration.description,
+            "Recorded {} in place of {} squashed migrations",
+            migration,
+            squashed.len()
+        );
+        Ok(true)
+    }
+
+    /// Records the versioned migrations another tool applied as applied,
+    /// without executing them
+    ///
+    /// For switching tools without losing the applied history. The history
+    /// table of the other tool, `table` or the default one of `format`, is
+    /// looked up in the keyspace of the tracking table unless qualified. Each
+    /// successfully applied version is matched with the local migration of
+    /// that version, which is recorded with its own checksum and the time and
+    /// user the other tool recorded. Versions without a local migration, such
+    /// as Flyway's dotted ones, are returned as unmatched; failed and
+    /// repeatable migrations are skipped.
+    pub async fn import_history(
+        &self,
+        format: HistoryFormat,
+        table: Option<&str>,
+    ) -> Result<Import> {
+        self.locked(self.record_import(format, table)).await
+    }
+
+    async fn record_import(&self, format: HistoryFormat, table: Option<&str>) -> Result<Import> {
+        self.create_migration_table().await?;
+
+        let table = table.unwrap_or(format.default_table());
+        let (keyspace, table) = table
+            .split_once('.')
+            .unwrap_or((&self.options.history.keyspace, table));
+        let foreign = read_history(
+            self.session.require("importing history")?,
+            format,
+            keyspace,
+            table,
+        )
+        .await?;
+
+        let migrations = self.load_migrations().await?;
+        let applied_migrations = self.get_applied_migrations().await?;
+        let mut import = Import::default();
+        for entry in foreign {
+            let migration = entry.version.parse::<i64>().ok().and_then(|version| {
+                migrations
+                    .iter()
+                    .find(|m| m.kind == MigrationKind::Versioned && m.version == version)
+            });
+            let Some(migration) = migration else {
+                import.unmatched.push(entry.version);
+                continue;
+            };
+            // Tools may record a version again after a failed attempt
+            if import.imported.contains(&migration.version)
+                || import.existing.contains(&migration.version)
+            {
+                continue;
+            }
+            if applied_migrations
+                .versioned
+                .contains_key(&migration.version)
+            {
+                import.existing.push(migration.version);
+                continue;
+            }
+
+            let applied = AppliedMigration {
+                checksum: migration.checksum.clone(),
+                description: migration.description.clone(),
+                applied_at: entry.applied_at,
+                has_down: migration.down.is_some(),
+                execution_time_ms: entry.execution_time_ms,
+                applied_by: entry.applied_by.or_else(|| self.options.applied_by.clone()),
+                host: None,
+                error: None,
+            };
+            self.insert_versioned(migration.version, &applied, None)
+                .await?;
+            info!(
+                version = migration.version,
+                description = %migration.description,
+                "Imported {} as applied",
+                migration
+            );
+            import.imported.push(migration.version);
+        }
+
+        import.imported.sort();
+        import.existing.sort();
+        import.unmatched.sort();
+        import.unmatched.dedup();
+        Ok(import)
+    }
+
+    /// Inserts the rows of the CSV document `csv` into `keyspace.table`
+    ///
+    /// Seed data is not a migration: it is neither locked nor recorded, and
+    /// loading it again overwrites the rows with the same primary key. The
+    /// first record names the columns. The INSERT statement is prepared once
+    /// and fields are bound as values of the types of the table's columns:
+    /// collections, tuples and user-defined types are written as their CQL
+    /// literal, e.g. `{'admin', 'ops'}`, and empty unquoted fields are inserted
+    /// as null. Rows are sent in unlogged batches of `batch_size`, logging the
+    /// progress after each.
+    pub async fn seed_csv(
+        &self,
+        keyspace: &str,
+        table: &str,
+        csv: &str,
+        batch_size: usize,
+    ) -> Result<Seed> {
+        let session = self.session.require("seeding")?;
+        let types = column_types(session, keyspace, table).await?;
+        let mut records = seed::records(csv)?.into_iter();
+        let Some(header) = records.next() else {
+            return Ok(Seed::default());
+        };
+        let mut columns = Vec::new();
+        for name in &header.fields {
+            let name = name.as_deref().unwrap_or_default();
+            if !types.contains_key(name) {
+                return Err(Error::Seed {
+                    line: header.line,
+                    reason: format!("{}.{} has no column {:?}", keyspace, table, name),
+                });
+            }
+            columns.push(name);
+        }
+        let insert = session
+            .prepare(insert_statement(keyspace, table, &columns, false))
+            .await?;
+
+        let records: Vec<_> = records.collect();
+        let mut seed = Seed::default();
+        for chunk in records.chunks(batch_size.max(1)) {
+            let values = chunk
+                .iter()
+                .map(|record| seed::values(insert.get_variable_col_specs(), record))
+                .collect::<Result<Vec<_>>>()?;
+            self.execute_batch(session, &insert, &values).await?;
+
+            seed.rows += chunk.len();
+            seed.batches += 1;
+            info!(
+                "Inserted {} of {} rows into {}.{}",
+                seed.rows,
+                records.len(),
+                keyspace,
+                table
+            );
+        }
+        Ok(seed)
+    }
+
+    /// Loads the fixture file at `path`, or every .json, .yaml and .yml file
+    /// of the directory, in file name order
+    ///
+    /// A fixture file maps tables to the rows to insert into them, or to an
+    /// object with the rows and options:
+    ///
+    /// ```json
+    /// {
+    ///     "app.roles": [{ "name": "admin" }],
+    ///     "app.users": {
+    ///         "truncate": true,
+    ///         "rows": [{ "id": 1, "email": "ada@example.com", "roles": ["admin"] }]
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// The INSERT of a table is prepared once and values are bound as the
+    /// types of its columns, like those of [`Migrator::seed_csv`]; columns a
+    /// row leaves out are unset. `truncate` empties the table before loading;
+    /// `if_not_exists` inserts the rows with `IF NOT EXISTS`, leaving rows
+    /// that already exist as they are. Otherwise loading again overwrites the
+    /// rows with the same primary key. All files are checked before anything
+    /// is written. YAML files require the `yaml` feature.
+    pub async fn load_fixtures(&self, path: impl AsRef<Path>) -> Result<Vec<LoadedFixture>> {
+        let session = self.session.require("fixtures")?;
+        let mut pending = Vec::new();
+        for (file, fixtures) in read_fixtures(path.as_ref())? {
+            for fixture in fixtures {
+                let (keyspace, table) = (&fixture.keyspace, &fixture.table);
+                let types = column_types(session, keyspace, table).await?;
+                let invalid = |i: usize, column: &str, reason: String| Error::Fixture {
+                    path: file.clone(),
+                    reason: format!(
+                        "row {} of {}.{}, column {}: {}",
+                        i + 1,
+                        keyspace,
+                        table,
+                        column,
+                        reason
+                    ),
+                };
+                let mut columns: Vec<&str> = Vec::new();
+                for (i, row) in fixture.rows.iter().enumerate() {
+                    for column in row.keys() {
+                        if !types.contains_key(column) {
+                            return Err(invalid(
+                                i,
+                                column,
+                                format!("{}.{} has no column", keyspace, table),
+                            ));
+                        }
+                        if !columns.contains(&column.as_str()) {
+                            columns.push(column);
+                        }
+                    }
+                }
+                if columns.is_empty() {
+                    pending.push((file.clone(), fixture, None, Vec::new()));
+                    continue;
+                }
+
+                let insert = session
+                    .prepare(insert_statement(
+                        keyspace,
+                        table,
+                        &columns,
+                        fixture.if_not_exists,
+                    ))
+                    .await?;
+                let mut rows = Vec::new();
+                for (i, row) in fixture.rows.iter().enumerate() {
+                    // Columns a row leaves out are unset rather than overwritten with null
+                    let values = insert
+                        .get_variable_col_specs()
+                        .iter()
+                        .map(|column| match row.get(column.name()) {
+                            Some(value) => json_value(column.typ(), value)
+                                .map(MaybeUnset::Set)
+                                .map_err(|reason| invalid(i, column.name(), reason)),
+                            None => Ok(MaybeUnset::Unset),
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    rows.push(values);
+                }
+                pending.push((file.clone(), fixture, Some(insert), rows));
+            }
+        }
+
+        let mut loaded = Vec::new();
+        for (file, fixture, insert, values) in pending {
+            let name = format!("{}.{}", ident(&fixture.keyspace), ident(&fixture.table));
+            if fixture.truncate {
+                self.execute_data(format!("TRUNCATE {}", name)).await?;
+            }
+            let (mut rows, mut existing) = (0, 0);
+            match insert {
+                // Conditional statements can only be batched within a partition
+                Some(insert) if fixture.if_not_exists => {
+                    let mut insert = Prepared::from(insert);
+                    if let Some(consistency) = self.options.statement_consistency {
+                        insert.set_consistency(consistency);
+                    }
+                    for values in &values {
+                        let applied = self
+                            .options
+                            .retry
+                            .run(|| self.session.execute(&insert, values))
+                            .await?
+                            .first_row::<Row>()?
+                            .columns
+                            .first()
+                            .is_some_and(|applied| applied == &Some(CqlValue::Boolean(true)));
+                        if applied {
+                            rows += 1;
+                        } else {
+                            existing += 1;
+                        }
+                    }
+                }
+                Some(insert) => {
+                    for chunk in values.chunks(fixture::BATCH_SIZE) {
+                        self.execute_batch(session, &insert, chunk).await?;
+                    }
+                    rows = values.len();
+                }
+                None => {}
+            }
+            info!("Loaded {} rows into {} from {}", rows, name, file.display());
+            loaded.push(LoadedFixture {
+                file,
+                table: format!("{}.{}", fixture.keyspace, fixture.table),
+                truncated: fixture.truncate,
+                rows,
+                existing,
+            });
+        }
+        Ok(loaded)
+    }
+
+    /// Executes an unlogged batch of `prepared` for each row of `values`,
+    /// with the consistency of migration statements and retries
+    async fn execute_batch<V: SerializeRow>(
+        &self,
+        session: &Session,
+        prepared: &PreparedStatement,
+        values: &[V],
+    ) -> Result<()> {
+        let mut batch = Batch::new(BatchType::Unlogged);
+        for _ in values {
+            batch.append_statement(prepared.clone());
+        }
+        if let Some(consistency) = self.options.statement_consistency {
+            batch.set_consistency(consistency);
+        }
+        self.options
+            .retry
+            .run(|| session.batch(&batch, values))
+            .await?;
+        Ok(())
+    }
+
+    /// Executes a statement writing seed or fixture data, with the consistency
+    /// of migration statements and retries
+    async fn execute_data(&self, cql: String) -> Result<Rows> {
+        let mut query = Query::new(cql);
+        if let Some(consistency) = self.options.statement_consistency {
+            query.set_consistency(consistency);
+        }
+        let result = self
+            .options
+            .retry
+            .run(|| self.session.query(query.clone(), &[]))
+            .await?;
+        Ok(result)
+    }
+
+    /// Records a versioned migration as applied without executing it
+    async fn mark_applied(&self, migration: &Migration) -> Result<()> {
+        let applied = AppliedMigration {
+            checksum: migration.checksum.clone(),
+            description: migration.description.clone(),
+            applied_at: Some(OffsetDateTime::now_utc()),
+            has_down: migration.down.is_some(),
+            execution_time_ms: None,
+            applied_by: self.options.applied_by.clone(),
+            host: Some(gethostname::gethostname().to_string_lossy().into_owned()),
+            error: None,
+        };
+        self.insert_versioned(migration.version, &applied, None)
+            .await
+    }
+
+    /// Reverts the last `n` applied migrations
+    ///
+    /// Migrations are reverted newest first by executing their down scripts.
+    /// Nothing is reverted unless every one of them was applied with a down
+    /// script and that script is still present in the migrations directory.
+    pub async fn revert(&self, n: usize) -> Result<()> {
+        self.locked(self.revert_applied(n)).await
+    }
+
+    async fn revert_applied(&self, n: usize) -> Result<()> {
+        self.create_migration_table().await?;
+
+        let migrations = self.load_migrations().await?;
+        let applied_migrations = self.get_applied_migrations().await?;
+
+        let mut versions: Vec<i64> = applied_migrations.versioned.keys().copied().collect();
+        versions.sort_unstable_by(|a, b| b.cmp(a));
+        versions.truncate(n);
+
+        let mut to_revert = Vec::with_capacity(versions.len());
+        for version in versions {
+            let migration = migrations
+                .iter()
+                .find(|m| m.kind == MigrationKind::Versioned && m.version == version)
+                .ok_or(Error::Irreversible {
+                    version,
+                    reason: "migration file not found",
+                })?;
+
+            if !applied_migrations.versioned[&version].has_down {
+                return Err(Error::Irreversible {
+                    version,
+                    reason: "it was applied without a down migration",
+                });
+            }
+            if migration.down.is_none() {
+                return Err(Error::Irreversible {
+                    version,
+                    reason: "down migration file not found",
+                });
+            }
+
+            to_revert.push(migration);
+        }
+
+        for migration in to_revert {
+            let span = info_span!(
+                "revert",
+                version = migration.version,
+                description = %migration.description
+            );
+            let started = Instant::now();
+            async {
+                migration
+                    .execute_down(&self.session, self.statement_options())
+                    .await?;
+                self.remove_migration(migration.version).await
+            }
+            .instrument(span.clone())
+            .await?;
+            self.audit(AuditEntry::new(AuditAction::MigrationReverted).migration(migration))
+                .await;
+            span.in_scope(|| {
+                info!(
+                    duration_ms = started.elapsed().as_millis() as u64,
+                    "Reverted {}", migration
+                )
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Leaves out the applied migrations and those skipped for being out of order
+/// under `policy`, keeping repeatable migrations changed since they were applied
+fn unapplied(
+    migrations: Vec<Migration>,
+    applied: &AppliedHistory,
+    policy: OutOfOrderPolicy,
+) -> Vec<Migration> {
+    migrations
+        .into_iter()
+        .filter(|migration| match applied.get(migration) {
+            Some(recorded) => {
+                migration.kind == MigrationKind::Repeatable
+                    && recorded.checksum.as_ref() != migration.checksum.as_ref()
+            }
+            None => skipped_out_of_order(policy, migration, applied).is_none(),
+        })
+        .collect()
+}
+
+/// Returns the newest applied version if `migration` is to be skipped under
+/// `policy` for being older
+fn skipped_out_of_order(
+    policy: OutOfOrderPolicy,
+    migration: &Migration,
+    applied: &AppliedHistory,
+) -> Option<i64> {
+    match policy {
+        OutOfOrderPolicy::Skip => out_of_order(migration, applied),
+        OutOfOrderPolicy::Error | OutOfOrderPolicy::Apply => None,
+    }
+}
+
+/// Returns the newest applied version if the pending `migration` is older than it
+///
+/// Migrations with a `requires` directive are never out of order: they may
+/// have been left out under [`RequirementPolicy::Skip`] until the cluster was
+/// upgraded, while newer migrations were applied.
+/// Returns the tables of the runner in the history keyspace: the history,
+/// repeatable and progress tables, and the lock and audit tables they share
+fn runner_tables(history: &HistoryTable) -> Vec<String> {
+    vec![
+        history.table.clone(),
+        format!("{}_repeatable", history.table),
+        format!("{}_progress", history.table),
+        "migration_lock".to_string(),
+        "migration_audit".to_string(),
+    ]
+}
+
+/// Removes the tables of the runner from `schema`, so they are neither drift
+/// nor dropped by a generated migration
+fn remove_runner_tables(schema: &mut Schema, history: &HistoryTable) {
+    if let Some(keyspace) = schema.keyspaces.get_mut(&history.keyspace) {
+        for table in runner_tables(history) {
+            keyspace.tables.remove(&table);
+        }
+    }
+}
+
+fn out_of_order(migration: &Migration, applied: &AppliedHistory) -> Option<i64> {
+    if migration.kind != MigrationKind::Versioned
+        || applied.versioned.contains_key(&migration.version)
+        || migration
+            .directives()
+            .is_ok_and(|directives| directives.requires.is_some())
+    {
+        return None;
+    }
+
+    applied
+        .versioned
+        .keys()
+        .max()
+        .copied()
+        .filter(|newest| migration.version < *newest)
+}
+
+fn history_entry(kind: MigrationKind, version: i64, applied: AppliedMigration) -> HistoryEntry {
+    HistoryEntry {
+        kind,
+        version,
+        description: applied.description.into_owned(),
+        checksum: applied.checksum.into_owned(),
+        applied_at: applied.applied_at,
+        execution_time_ms: applied.execution_time_ms,
+        applied_by: applied.applied_by,
+        host: applied.host,
+        error: applied.error,
+    }
+}
+
+/// Returns the script `migration` executes, `None` for code migrations
+fn executed_cql(migration: &Migration) -> Option<&str> {
+    migration.code.is_none().then_some(migration.cql.as_ref())
+}
+
+/// Runs `work`, setting `cancelled` once `cancel` completes, for `work` to
+/// stop at its next checkpoint
+async fn until_cancelled<T>(
+    work: impl Future<Output = T>,
+    cancel: impl Future<Output = ()>,
+    cancelled: &AtomicBool,
+) -> T {
+    let watch = async {
+        cancel.await;
+        info!("Cancelling the run after the current statement");
+        cancelled.store(true, Ordering::Relaxed);
+        future::pending::<Infallible>().await
+    };
+    tokio::select! {
+        result = work => result,
+        never = watch => match never {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(version: i64) -> AppliedHistory {
+        let record = AppliedMigration {
+            checksum: Cow::Borrowed(&[]),
+            description: Cow::Borrowed("applied"),
+            applied_at: None,
+            has_down: false,
+            execution_time_ms: None,
+            applied_by: None,
+            host: None,
+            error: None,
+        };
+        AppliedHistory {
+            versioned: HashMap::from([(version, record)]),
+            ..AppliedHistory::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_migrations_without_a_cluster() {
+        let session = test::MockSession::new();
+        let migrations = EmbeddedMigrations::new(&[(
+            "20240101000000_users.cql",
+            "CREATE TABLE app.users (id uuid PRIMARY KEY);",
+        )]);
+        let report = Migrator::embedded(&session, &migrations)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(report.applied.len(), 1);
+        assert_eq!(report.applied[0].version, 20240101000000);
+
+        let statements = session.statements();
+        let position = |prefix: &str| {
+            statements
+                .iter()
+                .position(|statement| statement.trim_start().starts_with(prefix))
+                .unwrap_or_else(|| panic!("no {} in {:#?}", prefix, statements))
+        };
+        let locked = position("INSERT INTO public.migration_lock");
+        let created = position("CREATE TABLE app.users");
+        let recorded = position("INSERT INTO public.migrations");
+        let released = position("DELETE FROM public.migration_lock");
+        assert!(locked < created && created < recorded && recorded < released);
+    }
+
+    #[tokio::test]
+    async fn skips_confirmed_repairs_that_no_longer_apply() {
+        let session = test::MockSession::new();
+        let migrations = EmbeddedMigrations::new(&[(
+            "20240101000000_users.cql",
+            "CREATE TABLE app.users (id uuid PRIMARY KEY);",
+        )]);
+        let confirmed = Validation {
+            issues: vec![ValidationIssue {
+                version: 20240101000000,
+                description: "users".to_string(),
+                discrepancy: Discrepancy::ChecksumMismatch,
+            }],
+        };
+        let repaired = Migrator::embedded(&session, &migrations)
+            .repair_confirmed(&confirmed)
+            .await
+            .unwrap();
+        assert!(repaired.is_valid());
+        assert!(!session
+            .statements()
+            .iter()
+            .any(|statement| statement.starts_with("DELETE FROM public.migrations")));
+    }
+
+    #[test]
+    fn leaves_the_runner_tables_out_of_drift_and_generated_migrations() {
+        let history = HistoryTable::parse("app.migrations");
+        let migrated = "CREATE TABLE app.users (id uuid PRIMARY KEY);";
+        let mut live = Schema::parse(
+            &format!(
+                "{}\n\
+                 CREATE TABLE app.migrations (version bigint PRIMARY KEY);\n\
+                 CREATE TABLE app.migrations_repeatable (description text PRIMARY KEY);\n\
+                 CREATE TABLE app.migrations_progress (version bigint PRIMARY KEY);\n\
+                 CREATE TABLE app.migration_lock (name text PRIMARY KEY);\n\
+                 CREATE TABLE app.migration_audit (name text, id timeuuid, PRIMARY KEY (name, id));",
+                migrated
+            ),
+            None,
+        );
+        remove_runner_tables(&mut live, &history);
+
+        let mut target = Schema::parse(migrated, None);
+        remove_runner_tables(&mut target, &history);
+        assert_eq!(target.diff(&live), []);
+        assert_eq!(live.migration_to(&target), "");
+    }
+
+    #[test]
+    fn flags_older_pending_migrations() {
+        let migration = Migration::new(1, "older".into(), "SELECT now() FROM system.local;".into());
+        assert_eq!(out_of_order(&migration, &applied(2)), Some(2));
+        assert_eq!(out_of_order(&migration, &applied(1)), None);
+        assert_eq!(out_of_order(&migration, &applied(0)), None);
+    }
+
+    #[test]
+    fn leaves_out_what_a_run_skips_from_pending() {
+        let migrations = || {
+            vec![
+                Migration::new(1, "older".into(), "SELECT now() FROM system.local;".into()),
+                Migration::new(3, "newer".into(), "SELECT now() FROM system.local;".into()),
+            ]
+        };
+        let versions = |migrations: Vec<Migration>| {
+            migrations
+                .iter()
+                .map(|migration| migration.version)
+                .collect::<Vec<_>>()
+        };
+
+        let pending = unapplied(migrations(), &applied(2), OutOfOrderPolicy::Skip);
+        assert_eq!(versions(pending), [3]);
+        let pending = unapplied(migrations(), &applied(2), OutOfOrderPolicy::Error);
+        assert_eq!(versions(pending), [1, 3]);
+        let pending = unapplied(migrations(), &applied(1), OutOfOrderPolicy::Skip);
+        assert_eq!(versions(pending), [3]);
+    }
+
+    #[test]
+    fn exempts_migrations_with_requirements() {
+        let cql = "-- scylla-migrate: requires=scylla >= 5.4\nSELECT now() FROM system.local;";
+        let migration = Migration::new(1, "tablets".into(), cql.into());
+        assert_eq!(out_of_order(&migration, &applied(2)), None);
     }
 }
@@ -0,0 +1,467 @@
+use crate::migration::statements;
+use crate::token::{tokens, Token};
+use std::fmt;
+use std::sync::Arc;
+
+/// A check [`Linter`] runs on every statement of a migration script
+///
+/// Implement it for organization rules, such as naming conventions, banned
+/// column types or required table options, and register it with
+/// [`Linter::rule`].
+///
+/// # Example
+/// ```
+/// use scylla_migrate::{LintRule, Linter};
+///
+/// struct NoCounters;
+///
+/// impl LintRule for NoCounters {
+///     fn name(&self) -> &str {
+///         "no-counters"
+///     }
+///
+///     fn check(&self, statement: &str) -> Option<String> {
+///         statement
+///             .to_ascii_lowercase()
+///             .contains(" counter")
+///             .then(|| "counter columns are not allowed".to_string())
+///     }
+/// }
+///
+/// let findings = Linter::new()
+///     .rule(NoCounters)
+///     .lint("CREATE TABLE IF NOT EXISTS hits (page text PRIMARY KEY, n counter);");
+/// assert_eq!(findings[0].rule, "no-counters");
+/// ```
+pub trait LintRule: Send + Sync {
+    /// Name of the rule, shown with its findings and used to [`allow`](Linter::allow) it
+    fn name(&self) -> &str;
+
+    /// Checks a single statement, returning what is wrong with it, if anything
+    ///
+    /// Statements come without their trailing semicolon and leading comments.
+    fn check(&self, statement: &str) -> Option<String>;
+}
+
+/// A problem a [`Linter`] found in a migration script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    /// Line the offending statement starts on, starting at 1
+    pub line: usize,
+    /// Name of the rule that found it
+    pub rule: String,
+    pub message: String,
+}
+
+/// The rules a [`Linter`] runs by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuiltinRule {
+    /// `CREATE` without `IF NOT EXISTS`, failing when an interrupted migration is run again
+    MissingIfNotExists,
+    /// `ALLOW FILTERING`, which scans whole tables
+    AllowFiltering,
+    /// `SELECT *` in a materialized view, which silently changes with its base table
+    SelectStarInView,
+    /// A table or view with clustering columns but no `CLUSTERING ORDER BY`
+    MissingClusteringOrder,
+    /// A keyspace replication factor written as a number instead of a `${placeholder}`
+    LiteralReplicationFactor,
+}
+
+impl BuiltinRule {
+    pub const ALL: [BuiltinRule; 5] = [
+        BuiltinRule::MissingIfNotExists,
+        BuiltinRule::AllowFiltering,
+        BuiltinRule::SelectStarInView,
+        BuiltinRule::MissingClusteringOrder,
+        BuiltinRule::LiteralReplicationFactor,
+    ];
+
+    /// Returns the name of the rule, e.g. `missing-if-not-exists`
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinRule::MissingIfNotExists => "missing-if-not-exists",
+            BuiltinRule::AllowFiltering => "allow-filtering",
+            BuiltinRule::SelectStarInView => "select-star-in-view",
+            BuiltinRule::MissingClusteringOrder => "missing-clustering-order",
+            BuiltinRule::LiteralReplicationFactor => "literal-replication-factor",
+        }
+    }
+}
+
+impl LintRule for BuiltinRule {
+    fn name(&self) -> &str {
+        BuiltinRule::name(self)
+    }
+
+    fn check(&self, statement: &str) -> Option<String> {
+        let tokens = tokens(statement);
+        let found = match self {
+            BuiltinRule::MissingIfNotExists => missing_if_not_exists(&tokens),
+            BuiltinRule::AllowFiltering => allow_filtering(&tokens),
+            BuiltinRule::SelectStarInView => select_star_in_view(&tokens),
+            BuiltinRule::MissingClusteringOrder => missing_clustering_order(&tokens),
+            BuiltinRule::LiteralReplicationFactor => literal_replication_factor(&tokens),
+        };
+        found.then(|| self.to_string())
+    }
+}
+
+impl fmt::Display for BuiltinRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BuiltinRule::MissingIfNotExists => {
+                "CREATE without IF NOT EXISTS fails when the migration is run again"
+            }
+            BuiltinRule::AllowFiltering => "ALLOW FILTERING scans the whole table",
+            BuiltinRule::SelectStarInView => {
+                "SELECT * in a materialized view changes whenever its base table does"
+            }
+            BuiltinRule::MissingClusteringOrder => {
+                "clustering columns without an explicit CLUSTERING ORDER BY"
+            }
+            BuiltinRule::LiteralReplicationFactor => {
+                "literal replication factor, use a ${placeholder} to set it per environment"
+            }
+        })
+    }
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {} ({})", self.line, self.message, self.rule)
+    }
+}
+
+/// Statically checks migration scripts against a set of [`LintRule`]s, without a cluster
+///
+/// Pass it to [`MigratorBuilder::lint`](crate::MigratorBuilder::lint) to also
+/// check pending migrations before a run applies them.
+#[derive(Clone)]
+pub struct Linter {
+    rules: Vec<Arc<dyn LintRule>>,
+}
+
+impl Linter {
+    /// Creates a linter running the [`BuiltinRule`]s
+    pub fn new() -> Self {
+        Self::empty().rules(BuiltinRule::ALL)
+    }
+
+    /// Creates a linter without any rules
+    pub fn empty() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Adds a rule, run after the ones added before
+    pub fn rule(mut self, rule: impl LintRule + 'static) -> Self {
+        self.rules.push(Arc::new(rule));
+        self
+    }
+
+    fn rules(self, rules: impl IntoIterator<Item = impl LintRule + 'static>) -> Self {
+        rules.into_iter().fold(self, Self::rule)
+    }
+
+    /// Removes the rules named `name`
+    pub fn allow(mut self, name: &str) -> Self {
+        self.rules.retain(|rule| rule.name() != name);
+        self
+    }
+
+    /// Checks every statement of a script, returning the findings in order
+    ///
+    /// Placeholders are left in place, so `${rf}` counts as a parameterized
+    /// replication factor.
+    pub fn lint(&self, cql: &str) -> Vec<LintFinding> {
+        let mut findings = Vec::new();
+        for statement in statements(cql) {
+            // Statements are slices of the script, which tells their position
+            let offset = statement.as_ptr() as usize - cql.as_ptr() as usize;
+            let line = cql[..offset].matches('\n').count() + 1;
+
+            for rule in &self.rules {
+                if let Some(message) = rule.check(statement) {
+                    findings.push(LintFinding {
+                        line,
+                        rule: rule.name().to_string(),
+                        message,
+                    });
+                }
+            }
+        }
+        findings
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Linter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.rules.iter().map(|rule| rule.name()))
+            .finish()
+    }
+}
+
+/// Checks a migration script with the [`BuiltinRule`]s, see [`Linter::lint`]
+pub fn lint(cql: &str) -> Vec<LintFinding> {
+    Linter::new().lint(cql)
+}
+
+/// Returns true if the words `keywords` appear in a row somewhere in `tokens`
+fn contains(tokens: &[Token<'_>], keywords: &[&str]) -> bool {
+    tokens
+        .windows(keywords.len())
+        .any(|window| window.iter().zip(keywords).all(|(t, k)| t.is(k)))
+}
+
+fn creates_view(tokens: &[Token<'_>]) -> bool {
+    tokens.len() > 2 && tokens[0].is("CREATE") && tokens[1].is("MATERIALIZED")
+}
+
+fn is_word(tokens: &[Token<'_>], i: usize, keyword: &str) -> bool {
+    tokens.get(i).is_some_and(|token| token.is(keyword))
+}
+
+fn missing_if_not_exists(tokens: &[Token<'_>]) -> bool {
+    // CREATE OR REPLACE FUNCTION is idempotent as well
+    is_word(tokens, 0, "CREATE")
+        && !is_word(tokens, 1, "OR")
+        && !contains(tokens, &["IF", "NOT", "EXISTS"])
+}
+
+fn allow_filtering(tokens: &[Token<'_>]) -> bool {
+    contains(tokens, &["ALLOW", "FILTERING"])
+}
+
+fn select_star_in_view(tokens: &[Token<'_>]) -> bool {
+    creates_view(tokens)
+        && tokens
+            .windows(2)
+            .any(|pair| pair[0].is("SELECT") && pair[1] == Token::Symbol('*'))
+}
+
+fn missing_clustering_order(tokens: &[Token<'_>]) -> bool {
+    let table = is_word(tokens, 0, "CREATE")
+        && (is_word(tokens, 1, "TABLE") || is_word(tokens, 1, "COLUMNFAMILY"));
+    if !(table || creates_view(tokens)) || contains(tokens, &["CLUSTERING", "ORDER"]) {
+        return false;
+    }
+
+    // PRIMARY KEY (partition, clustering...) has clustering columns when it
+    // lists more than one element; an inline PRIMARY KEY has none
+    let Some(start) = tokens.windows(3).position(|window| {
+        window[0].is("PRIMARY") && window[1].is("KEY") && window[2] == Token::Symbol('(')
+    }) else {
+        return false;
+    };
+    let mut depth = 0;
+    for token in &tokens[start + 3..] {
+        match token {
+            Token::Symbol('(') => depth += 1,
+            Token::Symbol(')') if depth == 0 => return false,
+            Token::Symbol(')') => depth -= 1,
+            Token::Symbol(',') if depth == 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn literal_replication_factor(tokens: &[Token<'_>]) -> bool {
+    let keyspace = (is_word(tokens, 0, "CREATE") || is_word(tokens, 0, "ALTER"))
+        && (is_word(tokens, 1, "KEYSPACE") || is_word(tokens, 1, "SCHEMA"));
+
+    // Values of the replication map other than its class, i.e. the factors
+    let open = tokens
+        .iter()
+        .position(|token| token.is("REPLICATION"))
+        .and_then(|start| {
+            let open = tokens[start..]
+                .iter()
+                .position(|t| *t == Token::Symbol('{'))?;
+            Some(start + open)
+        });
+    let (true, Some(open)) = (keyspace, open) else {
+        return false;
+    };
+
+    let mut depth = 0;
+    let mut key = None;
+    for pair in tokens[open + 1..].windows(2) {
+        match pair[0] {
+            Token::Symbol('{') => depth += 1,
+            Token::Symbol('}') if depth == 0 => break,
+            Token::Symbol('}') => depth -= 1,
+            Token::Literal(literal) if depth == 0 && pair[1] == Token::Symbol(':') => {
+                key = Some(literal);
+            }
+            Token::Symbol(':') if depth == 0 && key != Some("class") => {
+                let number = match pair[1] {
+                    Token::Word(value) | Token::Literal(value) => {
+                        !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+                    }
+                    _ => false,
+                };
+                if number {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finds(rule: BuiltinRule, statement: &str) -> bool {
+        rule.check(statement).is_some()
+    }
+
+    #[test]
+    fn requires_if_not_exists_on_create() {
+        let rule = BuiltinRule::MissingIfNotExists;
+        assert!(finds(rule, "CREATE TABLE ks.t (id int PRIMARY KEY)"));
+        assert!(finds(rule, "create index on ks.t (v)"));
+        assert!(!finds(
+            rule,
+            "CREATE TABLE IF NOT EXISTS ks.t (id int PRIMARY KEY)"
+        ));
+        assert!(!finds(
+            rule,
+            "CREATE OR REPLACE FUNCTION ks.f (x int) RETURNS NULL ON NULL INPUT RETURNS int LANGUAGE lua AS 'return x'"
+        ));
+        assert!(!finds(rule, "ALTER TABLE ks.t ADD v int"));
+    }
+
+    #[test]
+    fn flags_allow_filtering_and_select_star_in_views() {
+        assert!(finds(
+            BuiltinRule::AllowFiltering,
+            "SELECT * FROM ks.t WHERE v = 1 ALLOW FILTERING"
+        ));
+        assert!(!finds(
+            BuiltinRule::AllowFiltering,
+            "SELECT * FROM ks.t WHERE id = 1"
+        ));
+        // Words in strings are not keywords
+        assert!(!finds(
+            BuiltinRule::AllowFiltering,
+            "INSERT INTO ks.t (id, note) VALUES (1, 'ALLOW FILTERING')"
+        ));
+
+        let rule = BuiltinRule::SelectStarInView;
+        assert!(finds(
+            rule,
+            "CREATE MATERIALIZED VIEW ks.v AS SELECT * FROM ks.t WHERE v IS NOT NULL AND id IS NOT NULL PRIMARY KEY (v, id)"
+        ));
+        assert!(!finds(
+            rule,
+            "CREATE MATERIALIZED VIEW ks.v AS SELECT v, id FROM ks.t WHERE v IS NOT NULL AND id IS NOT NULL PRIMARY KEY (v, id)"
+        ));
+        assert!(!finds(rule, "SELECT * FROM ks.t"));
+    }
+
+    #[test]
+    fn tracks_the_depth_of_composite_partition_keys() {
+        let rule = BuiltinRule::MissingClusteringOrder;
+        assert!(finds(
+            rule,
+            "CREATE TABLE ks.t (a int, b int, c int, PRIMARY KEY ((a, b), c))"
+        ));
+        assert!(!finds(
+            rule,
+            "CREATE TABLE ks.t (a int, b int, v int, PRIMARY KEY ((a, b)))"
+        ));
+        assert!(!finds(
+            rule,
+            "CREATE TABLE ks.t (a int, b int, PRIMARY KEY (a))"
+        ));
+        assert!(!finds(rule, "CREATE TABLE ks.t (a int PRIMARY KEY, b int)"));
+        assert!(!finds(
+            rule,
+            "CREATE TABLE ks.t (a int, c int, PRIMARY KEY (a, c)) WITH CLUSTERING ORDER BY (c DESC)"
+        ));
+        assert!(finds(
+            rule,
+            "CREATE MATERIALIZED VIEW ks.v AS SELECT a, c FROM ks.t WHERE a IS NOT NULL AND c IS NOT NULL PRIMARY KEY (c, a)"
+        ));
+        assert!(!finds(rule, "CREATE TYPE ks.pair (a int, b int)"));
+    }
+
+    #[test]
+    fn walks_the_replication_map_for_factors() {
+        let rule = BuiltinRule::LiteralReplicationFactor;
+        assert!(finds(
+            rule,
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 3}"
+        ));
+        assert!(finds(
+            rule,
+            "ALTER KEYSPACE ks WITH replication = {'class': 'NetworkTopologyStrategy', 'dc1': '3'}"
+        ));
+        assert!(!finds(
+            rule,
+            "CREATE KEYSPACE ks WITH replication = {'class': 'NetworkTopologyStrategy', 'dc1': ${rf}}"
+        ));
+        assert!(!finds(
+            rule,
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': ${rf}} AND durable_writes = true"
+        ));
+        // Only the replication map holds factors
+        assert!(!finds(
+            rule,
+            "CREATE KEYSPACE ks WITH replication = {'class': '${strategy}', 'rf': ${rf}} AND tablets = {'initial': 8}"
+        ));
+        assert!(!finds(
+            rule,
+            "CREATE TABLE ks.t (id int PRIMARY KEY) WITH compaction = {'class': 'LeveledCompactionStrategy', 'sstable_size_in_mb': 160}"
+        ));
+    }
+
+    struct NoDrops;
+
+    impl LintRule for NoDrops {
+        fn name(&self) -> &str {
+            "no-drops"
+        }
+
+        fn check(&self, statement: &str) -> Option<String> {
+            statement
+                .trim_start()
+                .starts_with("DROP")
+                .then(|| "drops data".to_string())
+        }
+    }
+
+    #[test]
+    fn reports_the_line_of_each_finding() {
+        let cql = "CREATE TABLE IF NOT EXISTS ks.t (id int PRIMARY KEY, v int);\n\nSELECT * FROM ks.t\n    WHERE v = 1 ALLOW FILTERING;\nDROP TABLE ks.t;\n";
+        let findings = Linter::new()
+            .allow("allow-filtering")
+            .rule(NoDrops)
+            .lint(cql);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            (findings[0].line, findings[0].rule.as_str()),
+            (5, "no-drops")
+        );
+
+        let findings = lint(cql);
+        assert_eq!(
+            findings
+                .iter()
+                .map(|finding| (finding.line, finding.rule.as_str()))
+                .collect::<Vec<_>>(),
+            [(3, "allow-filtering")]
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use crate::error::{Error, Result};
+use crate::history::HistoryTable;
+use crate::session::{Execute, Rows};
+use scylla::frame::response::result::{CqlValue, Row};
+use scylla::query::Query;
+use std::fmt;
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio::time::{sleep, Instant};
+use uuid::Uuid;
+
+/// How often a waiting runner retries to acquire a held lock
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The shortest lease, as the TTL of the lock row is in whole seconds
+pub const MIN_LOCK_LEASE: Duration = Duration::from_secs(1);
+
+/// Advisory lock serializing runners against the same migrations table
+///
+/// The lock is a row in the `migration_lock` table of the history keyspace,
+/// keyed by the history table name and inserted with a lightweight
+/// transaction and a TTL. The holder keeps extending the TTL while it works,
+/// so the lock of a crashed runner expires on its own once the lease runs out.
+pub(crate) struct MigrationLock<'a> {
+    session: &'a dyn Execute,
+    history: &'a HistoryTable,
+    owner: Uuid,
+    hostname: String,
+    acquired_at: OffsetDateTime,
+    lease: Duration,
+}
+
+/// Current holder of the migration lock
+#[derive(Debug, Clone)]
+pub struct LockHolder {
+    pub owner: Uuid,
+    pub hostname: Option<String>,
+    pub acquired_at: Option<OffsetDateTime>,
+}
+
+impl fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {} since {}",
+            self.owner,
+            self.hostname.as_deref().unwrap_or("an unknown host"),
+            self.acquired_at
+                .and_then(|at| at.format(&Rfc3339).ok())
+                .as_deref()
+                .unwrap_or("an unknown time")
+        )
+    }
+}
+
+impl<'a> MigrationLock<'a> {
+    pub async fn create_table(session: &dyn Execute, history: &HistoryTable) -> Result<()> {
+        session
+            .query(
+                Query::new(format!(
+                    r#"CREATE TABLE IF NOT EXISTS {}.migration_lock (
+                        name text,
+                        owner uuid,
+                        hostname text,
+                        acquired_at timestamp,
+                        PRIMARY KEY (name)
+                    )"#,
+                    history.keyspace
+                )),
+                &[],
+            )
+            .await?;
+        session.await_schema_agreement().await?;
+        Ok(())
+    }
+
+    /// Acquires the lock for `history`, waiting at most `wait` for a current holder
+    pub async fn acquire(
+        session: &'a dyn Execute,
+        history: &'a HistoryTable,
+        wait: Duration,
+        lease: Duration,
+    ) -> Result<Self> {
+        let lock = Self {
+            session,
+            history,
+            owner: Uuid::new_v4(),
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+            acquired_at: OffsetDateTime::now_utc(),
+            lease,
+        };
+
+        let deadline = Instant::now() + wait;
+        loop {
+            let result = session
+                .query(
+                    Query::new(format!(
+                        r#"
+                            INSERT INTO {}.migration_lock (name, owner, hostname, acquired_at)
+                                VALUES (?, ?, ?, ?)
+                                IF NOT EXISTS
+                                USING TTL ?
+                        "#,
+                        history.keyspace
+                    )),
+                    &(
+                        history.table.as_str(),
+                        lock.owner,
+                        lock.hostname.as_str(),
+                        lock.acquired_at,
+                        lock.ttl(),
+                    ),
+                )
+                .await?;
+
+            if applied(result)? {
+                return Ok(lock);
+            }
+
+            if Instant::now() >= deadline {
+                return Err(match Self::holder(session, history).await? {
+                    Some(holder) => Error::LockHeld(holder),
+                    None => Error::LockTimeout,
+                });
+            }
+
+            sleep(RETRY_INTERVAL).await;
+        }
+    }
+
+    /// Returns the current holder of the lock for `history`, if any
+    pub async fn holder(
+        session: &dyn Execute,
+        history: &HistoryTable,
+    ) -> Result<Option<LockHolder>> {
+        let holder = session
+            .query(
+                Query::new(format!(
+                    r#"
+                        SELECT owner, hostname, acquired_at
+                            FROM {}.migration_lock WHERE name = ?
+                    "#,
+                    history.keyspace
+                )),
+                &(history.table.as_str(),),
+            )
+            .await?
+            .maybe_first_row::<(Uuid, Option<String>, Option<OffsetDateTime>)>()?;
+
+        Ok(holder.map(|(owner, hostname, acquired_at)| LockHolder {
+            owner,
+            hostname,
+            acquired_at,
+        }))
+    }
+
+    /// Releases the lock for `history` on behalf of `holder`
+    ///
+    /// Returns false if the lock has meanwhile been released or taken over.
+    pub async fn force_release(
+        session: &dyn Execute,
+        history: &HistoryTable,
+        holder: &LockHolder,
+    ) -> Result<bool> {
+        let result = session
+            .query(
+                Query::new(format!(
+                    "DELETE FROM {}.migration_lock WHERE name = ? IF owner = ?",
+                    history.keyspace
+                )),
+                &(history.table.as_str(), holder.owner),
+            )
+            .await?;
+        applied(result)
+    }
+
+    /// Extends the lease until the returned future is dropped
+    ///
+    /// Only returns when the lease could not be extended, meaning the lock may
+    /// have been taken over by another runner.
+    pub async fn keep_alive(&self) -> Error {
+        loop {
+            sleep(self.lease / 3).await;
+
+            let result = self
+                .session
+                .query(
+                    Query::new(format!(
+                        r#"
+                            UPDATE {}.migration_lock USING TTL ?
+                                SET owner = ?, hostname = ?, acquired_at = ?
+                                WHERE name = ?
+                                IF owner = ?
+                        "#,
+                        self.history.keyspace
+                    )),
+                    &(
+                        self.ttl(),
+                        self.owner,
+                        self.hostname.as_str(),
+                        self.acquired_at,
+                        self.history.table.as_str(),
+                        self.owner,
+                    ),
+                )
+                .await;
+
+            match result.map_err(Error::from).and_then(applied) {
+                Ok(true) => continue,
+                Ok(false) => return Error::LockLost,
+                Err(err) => return err,
+            }
+        }
+    }
+
+    pub async fn release(self) -> Result<()> {
+        self.session
+            .query(
+                Query::new(format!(
+                    "DELETE FROM {}.migration_lock WHERE name = ? IF owner = ?",
+                    self.history.keyspace
+                )),
+                &(self.history.table.as_str(), self.owner),
+            )
+            .await?;
+        Ok(())
+    }
+
+    fn ttl(&self) -> i32 {
+        self.lease.as_secs().clamp(1, i32::MAX as u64) as i32
+    }
+}
+
+/// Reads the `[applied]` column of a lightweight transaction result
+fn applied(rows: Rows) -> Result<bool> {
+    let row: Row = rows.first_row()?;
+    Ok(matches!(
+        row.columns.first(),
+        Some(Some(CqlValue::Boolean(true)))
+    ))
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the buckets of `migration_duration_seconds`
+const DURATION_BUCKETS: [f64; 10] = [0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0];
+
+/// Metrics of the runs of the migrators it is registered with, by history
+/// keyspace
+///
+/// Register it with [`MigratorBuilder::metrics`](crate::MigratorBuilder::metrics)
+/// and serve [`Metrics::render`] from the `/metrics` endpoint of the service to
+/// alert on failed or slow migrations:
+///
+/// - `migrations_applied_total` and `migrations_failed_total`, counters
+/// - `migration_duration_seconds`, a histogram of applied migrations
+/// - `pending_migrations`, a gauge of the migrations the last run found
+///   pending and hasn't applied yet
+///
+/// Each is labelled with the `keyspace` of the history, so the tenants of
+/// [`Migrator::run_tenants`](crate::Migrator::run_tenants) are told apart.
+#[derive(Debug, Default)]
+pub struct Metrics(Mutex<BTreeMap<String, KeyspaceMetrics>>);
+
+#[derive(Debug, Default)]
+struct KeyspaceMetrics {
+    applied: u64,
+    failed: u64,
+    pending: u64,
+    /// Applied migrations per bucket of [`DURATION_BUCKETS`], not cumulative
+    buckets: [u64; DURATION_BUCKETS.len()],
+    duration_sum: f64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out)
+            .expect("writing to a String cannot fail");
+        out
+    }
+
+    fn write(&self, out: &mut String) -> fmt::Result {
+        let keyspaces = self.0.lock().unwrap();
+
+        writeln!(out, "# HELP migrations_applied_total Migrations applied")?;
+        writeln!(out, "# TYPE migrations_applied_total counter")?;
+        for (keyspace, metrics) in keyspaces.iter() {
+            writeln!(
+                out,
+                "migrations_applied_total{{keyspace=\"{}\"}} {}",
+                escape(keyspace),
+                metrics.applied
+            )?;
+        }
+
+        writeln!(out, "# HELP migrations_failed_total Migrations that failed")?;
+        writeln!(out, "# TYPE migrations_failed_total counter")?;
+        for (keyspace, metrics) in keyspaces.iter() {
+            writeln!(
+                out,
+                "migrations_failed_total{{keyspace=\"{}\"}} {}",
+                escape(keyspace),
+                metrics.failed
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP migration_duration_seconds Time taken to apply a migration"
+        )?;
+        writeln!(out, "# TYPE migration_duration_seconds histogram")?;
+        for (keyspace, metrics) in keyspaces.iter() {
+            let keyspace = escape(keyspace);
+            let mut cumulative = 0;
+            for (bound, count) in DURATION_BUCKETS.iter().zip(metrics.buckets) {
+                cumulative += count;
+                writeln!(
+                    out,
+                    "migration_duration_seconds_bucket{{keyspace=\"{}\",le=\"{}\"}} {}",
+                    keyspace, bound, cumulative
+                )?;
+            }
+            writeln!(
+                out,
+                "migration_duration_seconds_bucket{{keyspace=\"{}\",le=\"+Inf\"}} {}",
+                keyspace, metrics.applied
+            )?;
+            writeln!(
+                out,
+                "migration_duration_seconds_sum{{keyspace=\"{}\"}} {}",
+                keyspace, metrics.duration_sum
+            )?;
+            writeln!(
+                out,
+                "migration_duration_seconds_count{{keyspace=\"{}\"}} {}",
+                keyspace, metrics.applied
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP pending_migrations Migrations pending at the last run, not applied yet"
+        )?;
+        writeln!(out, "# TYPE pending_migrations gauge")?;
+        for (keyspace, metrics) in keyspaces.iter() {
+            writeln!(
+                out,
+                "pending_migrations{{keyspace=\"{}\"}} {}",
+                escape(keyspace),
+                metrics.pending
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Records that a run found `pending` migrations to apply in `keyspace`
+    pub(crate) fn set_pending(&self, keyspace: &str, pending: usize) {
+        self.update(keyspace, |metrics| metrics.pending = pending as u64);
+    }
+
+    /// Records a migration applied in `keyspace` in `duration`
+    pub(crate) fn record_applied(&self, keyspace: &str, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        self.update(keyspace, |metrics| {
+            metrics.applied += 1;
+            metrics.pending = metrics.pending.saturating_sub(1);
+            metrics.duration_sum += seconds;
+            if let Some(bucket) = DURATION_BUCKETS.iter().position(|&bound| seconds <= bound) {
+                metrics.buckets[bucket] += 1;
+            }
+        });
+    }
+
+    /// Records a migration that failed in `keyspace`
+    pub(crate) fn record_failed(&self, keyspace: &str) {
+        self.update(keyspace, |metrics| metrics.failed += 1);
+    }
+
+    fn update(&self, keyspace: &str, update: impl FnOnce(&mut KeyspaceMetrics)) {
+        let mut keyspaces = self.0.lock().unwrap();
+        update(keyspaces.entry(keyspace.to_string()).or_default());
+    }
+}
+
+/// Escapes a label value of the text exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -1,19 +1,85 @@
-use anyhow::{Context, Result};
+use crate::assertion::Assertion;
+use crate::error::{BoxError, Error, Result};
+use crate::retry::RetryPolicy;
+use crate::schema::ident;
+use crate::server::Requirement;
+use crate::session::Execute;
+use crate::telemetry;
+use async_trait::async_trait;
+use scylla::query::Query;
+use scylla::statement::Consistency;
 use scylla::Session;
 use sha2::{Digest, Sha384};
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tracing::{debug, Instrument};
 
 /// Represents a single database migration
 ///
 /// Each migration corresponds to a .cql file in the migrations directory.
 /// The file name format should be: TIMESTAMP_description.cql
 /// For example: "20240117000000_create_users.cql"
-#[derive(Debug)]
+///
+/// Reversible migrations are written as a pair of files sharing the same
+/// version: "20240117000000_create_users.up.cql" and
+/// "20240117000000_create_users.down.cql".
+///
+/// Repeatable migrations are named "R__description.cql" instead and have no
+/// version; they are identified by their description.
+///
+/// Migrations written in Rust instead of CQL are created with
+/// [`Migration::code`].
+#[derive(Clone)]
 pub struct Migration {
     pub version: i64,
+    pub kind: MigrationKind,
     pub description: Cow<'static, str>,
     pub cql: Cow<'static, str>,
     pub checksum: Cow<'static, [u8]>,
+    pub down: Option<Cow<'static, str>>,
+    pub code: Option<Arc<dyn CodeMigration>>,
+}
+
+/// A migration implemented in Rust, for changes CQL alone can't express
+///
+/// Typical uses are backfills that read, transform and write back data.
+/// Code migrations are ordered and tracked like .cql migrations of the same
+/// version.
+///
+/// # Example
+/// ```no_run
+/// use async_trait::async_trait;
+/// use scylla::Session;
+/// use scylla_migrate::{BoxError, CodeMigration};
+///
+/// struct BackfillEmails;
+///
+/// #[async_trait]
+/// impl CodeMigration for BackfillEmails {
+///     async fn up(&self, session: &Session) -> Result<(), BoxError> {
+///         session
+///             .query_unpaged("UPDATE app.users SET email = '' WHERE id = 1", &[])
+///             .await?;
+///         Ok(())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait CodeMigration: Send + Sync {
+    async fn up(&self, session: &Session) -> Result<(), BoxError>;
+
+    /// Identifies the current implementation, hashed into the checksum
+    ///
+    /// Code can't be checksummed like CQL, so by default a code migration is
+    /// never reported as changed. Return something that changes along with
+    /// the implementation, e.g. a revision number, to have it flagged.
+    fn fingerprint(&self) -> Cow<'static, str> {
+        Cow::Borrowed("")
+    }
 }
 
 impl Migration {
@@ -23,32 +89,879 @@ impl Migration {
 
         Migration {
             version,
+            kind: MigrationKind::Versioned,
             description,
             cql,
             checksum,
+            down: None,
+            code: None,
+        }
+    }
+
+    /// Creates a versioned migration running `code` instead of CQL
+    pub fn code(
+        version: i64,
+        description: Cow<'static, str>,
+        code: impl CodeMigration + 'static,
+    ) -> Self {
+        Migration {
+            checksum: Cow::Owned(Vec::from(
+                Sha384::digest(code.fingerprint().as_bytes()).as_slice(),
+            )),
+            code: Some(Arc::new(code)),
+            ..Self::new(version, description, Cow::Borrowed(""))
         }
     }
 
+    /// Creates a repeatable migration, re-run whenever its checksum changes
+    pub fn repeatable(description: Cow<'static, str>, cql: Cow<'static, str>) -> Self {
+        Migration {
+            kind: MigrationKind::Repeatable,
+            ..Self::new(0, description, cql)
+        }
+    }
+
+    /// Attaches the CQL used to revert this migration
+    ///
+    /// The down script does not contribute to the checksum, so adding one to an
+    /// already applied migration does not mark it as changed.
+    pub fn with_down(mut self, down: Cow<'static, str>) -> Self {
+        self.down = Some(down);
+        self
+    }
+
+    /// Returns the individual statements of the up script
+    ///
+    /// A script with the `no-split` directive is a single statement.
+    pub fn statements(&self) -> Vec<&str> {
+        statements(&self.cql)
+    }
+
+    /// Returns the directives in the header of the up script
+    pub fn directives(&self) -> Result<Directives> {
+        Directives::parse(&self.cql).map_err(|reason| Error::Parse {
+            name: self.description.to_string(),
+            reason,
+        })
+    }
+
     pub async fn up(&self, session: &Session) -> Result<()> {
-        // Split the content into individual statements
-        let statements: Vec<_> = self
-            .cql
-            .split(';')
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
-            .collect();
+        match &self.code {
+            Some(code) => code.up(session).await.map_err(|source| Error::Code {
+                version: self.version,
+                description: self.description.to_string(),
+                source,
+            }),
+            None => execute(session, self, &self.cql, StatementOptions::default()).await,
+        }
+    }
+
+    /// Executes a single statement of this migration, returning how long it took
+    ///
+    /// `index` is the position of the statement, starting at 1.
+    pub(crate) async fn execute_statement(
+        &self,
+        session: &dyn Execute,
+        index: usize,
+        statement: &str,
+        options: &StatementOptions,
+    ) -> Result<Duration> {
+        let span = telemetry::span!("statement", index);
+        telemetry::describe_statement(&span, statement);
+        let result = self
+            .run_statement(session, index, statement, options)
+            .instrument(span.clone())
+            .await;
+        telemetry::record_result(&span, &result);
+        result
+    }
+
+    async fn run_statement(
+        &self,
+        session: &dyn Execute,
+        index: usize,
+        statement: &str,
+        options: &StatementOptions,
+    ) -> Result<Duration> {
+        let mut query = Query::new(statement);
+        if let Some(consistency) = options.consistency {
+            query.set_consistency(consistency);
+        }
+        if options.timeout.is_some() {
+            query.set_request_timeout(options.timeout);
+        }
+
+        let started = Instant::now();
+        options
+            .retry
+            .run(|| session.query(query.clone(), &[]))
+            .await
+            .map_err(|source| Error::Execution {
+                version: self.version,
+                description: self.description.to_string(),
+                index,
+                statement: statement.to_string(),
+                source: Box::new(source),
+            })?;
+        if options.schema_agreement && is_schema_change(statement) {
+            session.await_schema_agreement().await?;
+        }
+        if let Some(timeout) = options.wait_for_builds {
+            session.wait_for_build(statement, timeout).await?;
+        }
+        let duration = started.elapsed();
+        debug!(
+            statement = index,
+            duration_ms = duration.as_millis() as u64,
+            "Executed {}",
+            statement
+        );
+        Ok(duration)
+    }
+
+    /// Executes the down script of this migration
+    pub async fn down(&self, session: &Session) -> Result<()> {
+        self.execute_down(session, StatementOptions::default())
+            .await
+    }
+
+    pub(crate) async fn execute_down(
+        &self,
+        session: &dyn Execute,
+        options: StatementOptions,
+    ) -> Result<()> {
+        let down = self.down.as_deref().ok_or(Error::Irreversible {
+            version: self.version,
+            reason: "it has no down migration",
+        })?;
+
+        execute(session, self, down, options).await
+    }
+}
+
+/// How the statements of a migration are executed
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct StatementOptions {
+    /// The session's default if unset
+    pub consistency: Option<Consistency>,
+    /// The session's default if unset
+    pub timeout: Option<Duration>,
+    /// Whether to wait for all nodes to agree on the schema after DDL statements
+    pub schema_agreement: bool,
+    /// How long to wait for created views and indexes to be built, not at all if unset
+    pub wait_for_builds: Option<Duration>,
+    /// Pause between statements, none if unset
+    pub delay: Option<Duration>,
+    pub retry: RetryPolicy,
+}
+
+impl StatementOptions {
+    /// Applies the directives of a script on top of these options
+    pub fn with_directives(self, directives: &Directives) -> Self {
+        Self {
+            consistency: directives.consistency.or(self.consistency),
+            timeout: directives.timeout.or(self.timeout),
+            delay: directives.delay.or(self.delay),
+            ..self
+        }
+    }
+}
+
+/// Settings of a single migration, overriding those of the runner
+///
+/// Directives are written in comments at the top of a script, before its first
+/// statement, as a comma separated list:
+///
+/// ```sql
+/// -- scylla-migrate: description=backfill emails, consistency=ALL, timeout=60s, no-split
+/// -- scylla-migrate: delay=200ms
+/// -- scylla-migrate: env=dev|staging
+/// -- scylla-migrate: depends-on=20240102093000|20240103110000
+/// -- scylla-migrate: requires=scylla >= 5.4
+/// -- assert: count >= 1: SELECT id FROM app.users_by_email LIMIT 1
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Directives {
+    /// Recorded in the history instead of the file name
+    pub description: Option<String>,
+    /// Consistency level of the statements, as written in CQL, e.g. `LOCAL_QUORUM`
+    pub consistency: Option<Consistency>,
+    /// Timeout of each statement, e.g. `500ms`, `60s` or `5m`
+    pub timeout: Option<Duration>,
+    /// Pause between statements, e.g. `200ms`, overriding
+    /// [`RunOptions::statement_delay`](crate::RunOptions::statement_delay)
+    pub delay: Option<Duration>,
+    /// Executes the script as one statement instead of splitting it at semicolons
+    pub no_split: bool,
+    /// Environments the migration is limited to, e.g. `env=dev|staging`; all if empty
+    ///
+    /// See [`MigratorBuilder::environment`](crate::MigratorBuilder::environment).
+    pub environments: Vec<String>,
+    /// Versions that have to be applied first, e.g. `depends-on=20240102093000|20240103110000`
+    ///
+    /// A run applies the migration after them even if its version is lower, and
+    /// fails with [`Error::MissingDependency`] if one is neither applied nor part of the run.
+    pub depends_on: Vec<i64>,
+    /// Database and version the cluster has to run, e.g. `requires=scylla >= 5.4`
+    ///
+    /// See [`MigratorBuilder::on_unmet_requirement`](crate::MigratorBuilder::on_unmet_requirement).
+    pub requires: Option<Requirement>,
+    /// Queries checked after the statements, from `-- assert:` comments
+    ///
+    /// A migration whose assertion fails is recorded as failed, like one whose
+    /// statement failed.
+    pub asserts: Vec<Assertion>,
+}
+
+impl Directives {
+    pub(crate) fn parse(cql: &str) -> std::result::Result<Self, &'static str> {
+        let mut directives = Self::default();
+
+        for line in cql[..header_len(cql)].lines() {
+            let Some(comment) = line
+                .trim()
+                .strip_prefix("--")
+                .or_else(|| line.trim().strip_prefix("//"))
+                .map(str::trim)
+            else {
+                continue;
+            };
+            if let Some(assertion) = comment.strip_prefix("assert:") {
+                directives.asserts.push(
+                    Assertion::parse(assertion)
+                        .ok_or("invalid assert, expected e.g. count >= 1: SELECT ...")?,
+                );
+                continue;
+            }
+            let Some(list) = comment.strip_prefix("scylla-migrate:") else {
+                continue;
+            };
+
+            for directive in list.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+                match directive.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                    Some(("description", description)) => {
+                        directives.description = Some(description.to_string());
+                    }
+                    Some(("consistency", level)) => {
+                        directives.consistency =
+                            Some(parse_consistency(level).ok_or("unknown consistency level")?);
+                    }
+                    Some(("timeout", timeout)) => {
+                        directives.timeout = Some(
+                            parse_duration(timeout).ok_or("invalid timeout, expected e.g. 60s")?,
+                        );
+                    }
+                    Some(("delay", delay)) => {
+                        directives.delay = Some(
+                            parse_duration(delay).ok_or("invalid delay, expected e.g. 200ms")?,
+                        );
+                    }
+                    Some(("env", environments)) => {
+                        directives.environments = environments
+                            .split('|')
+                            .map(|env| env.trim().to_string())
+                            .collect();
+                        if directives.environments.iter().any(String::is_empty) {
+                            return Err("invalid env, expected e.g. dev|staging");
+                        }
+                    }
+                    Some(("depends-on", versions)) => {
+                        directives.depends_on = versions
+                            .split('|')
+                            .map(|version| version.trim().parse())
+                            .collect::<std::result::Result<_, _>>()
+                            .map_err(|_| "invalid depends-on, expected versions like 20240102093000|20240103110000")?;
+                    }
+                    Some(("requires", requirement)) => {
+                        directives.requires = Some(
+                            Requirement::parse(requirement)
+                                .ok_or("invalid requires, expected e.g. scylla >= 5.4")?,
+                        );
+                    }
+                    None if directive == "no-split" => directives.no_split = true,
+                    // Block markers, resolved when the migrations are loaded
+                    Some(("only", _)) => {}
+                    None if directive == "end" => {}
+                    _ => return Err("unknown directive"),
+                }
+            }
+        }
+
+        Ok(directives)
+    }
+}
+
+/// Returns the length of the leading comments and blank lines of a script
+fn header_len(cql: &str) -> usize {
+    let mut len = 0;
+    for line in cql.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with("--") && !trimmed.starts_with("//") {
+            break;
+        }
+        len += line.len();
+    }
+    len
+}
+
+fn parse_consistency(level: &str) -> Option<Consistency> {
+    Some(match level.to_ascii_uppercase().as_str() {
+        "ANY" => Consistency::Any,
+        "ONE" => Consistency::One,
+        "TWO" => Consistency::Two,
+        "THREE" => Consistency::Three,
+        "QUORUM" => Consistency::Quorum,
+        "ALL" => Consistency::All,
+        "LOCAL_QUORUM" => Consistency::LocalQuorum,
+        "EACH_QUORUM" => Consistency::EachQuorum,
+        "LOCAL_ONE" => Consistency::LocalOne,
+        "SERIAL" => Consistency::Serial,
+        "LOCAL_SERIAL" => Consistency::LocalSerial,
+        _ => return None,
+    })
+}
+
+/// Parses a number followed by `ms`, `s`, `m` or `h`, as in the `timeout` and
+/// `delay` directives, or returns `None` if it is malformed or too large
+///
+/// ```
+/// use std::time::Duration;
+///
+/// assert_eq!(scylla_migrate::parse_duration("5m"), Some(Duration::from_secs(300)));
+/// assert_eq!(scylla_migrate::parse_duration("5 minutes"), None);
+/// ```
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let amount: u64 = value[..split].parse().ok()?;
+    match &value[split..] {
+        "ms" => Some(Duration::from_millis(amount)),
+        "s" => Some(Duration::from_secs(amount)),
+        "m" => amount.checked_mul(60).map(Duration::from_secs),
+        "h" => amount.checked_mul(3600).map(Duration::from_secs),
+        _ => None,
+    }
+}
+
+/// Whether a migration runs once or whenever it changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MigrationKind {
+    /// Applied once, in version order
+    Versioned,
+    /// Applied after all versioned migrations, again whenever its checksum changes
+    Repeatable,
+}
+
+/// Returns the statements of a script, honoring its `no-split` directive
+pub(crate) fn statements(cql: &str) -> Vec<&str> {
+    if !Directives::parse(cql).is_ok_and(|directives| directives.no_split) {
+        return split_statements(cql);
+    }
+
+    let body = cql[header_len(cql)..]
+        .trim()
+        .trim_end_matches(';')
+        .trim_end();
+    if body.is_empty() {
+        Vec::new()
+    } else {
+        vec![body]
+    }
+}
+
+/// Splits the content into individual statements
+///
+/// Statements are separated by semicolons, except for semicolons inside
+/// string literals, quoted identifiers, `$$`-delimited function bodies and
+/// comments. A `BEGIN BATCH ... APPLY BATCH` block is a single statement,
+/// semicolons between its inner statements included. Comments in front of a
+/// statement are dropped, as are statements consisting of comments only.
+fn split_statements(cql: &str) -> Vec<&str> {
+    let bytes = cql.as_bytes();
+    let mut statements = Vec::new();
+    // Start of the statement being scanned, once something other than
+    // whitespace and comments has been seen
+    let mut start = None;
+    // Whether the statement being scanned is a batch not yet closed by APPLY BATCH
+    let mut in_batch = false;
+    let mut prev_word = "";
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'-', Some(b'-')) | (b'/', Some(b'/')) => {
+                i = find(bytes, i + 2, b"\n").map_or(bytes.len(), |end| end + 1);
+            }
+            (b'/', Some(b'*')) => {
+                i = find(bytes, i + 2, b"*/").map_or(bytes.len(), |end| end + 2);
+            }
+            (b'$', Some(b'$')) => {
+                start.get_or_insert(i);
+                i = find(bytes, i + 2, b"$$").map_or(bytes.len(), |end| end + 2);
+            }
+            (quote @ (b'\'' | b'"'), _) => {
+                start.get_or_insert(i);
+                i = skip_quoted(bytes, i, quote);
+            }
+            (b';', _) if in_batch => i += 1,
+            (b';', _) => {
+                if let Some(s) = start.take() {
+                    statements.push(cql[s..i].trim());
+                }
+                i += 1;
+            }
+            (c, _) if c.is_ascii_alphabetic() => {
+                let end = bytes[i..]
+                    .iter()
+                    .position(|b| !(b.is_ascii_alphanumeric() || *b == b'_'))
+                    .map_or(bytes.len(), |len| i + len);
+                let word = &cql[i..end];
+
+                if start.is_none() {
+                    start = Some(i);
+                    in_batch = word.eq_ignore_ascii_case("BEGIN");
+                } else if in_batch
+                    && word.eq_ignore_ascii_case("BATCH")
+                    && prev_word.eq_ignore_ascii_case("APPLY")
+                {
+                    in_batch = false;
+                }
+
+                prev_word = word;
+                i = end;
+            }
+            (c, _) => {
+                if !c.is_ascii_whitespace() {
+                    start.get_or_insert(i);
+                }
+                i += 1;
+            }
+        }
+    }
+
+    if let Some(s) = start {
+        statements.push(cql[s..].trim());
+    }
+
+    statements
+}
 
-        for stmt in statements {
-            session
-                .query_unpaged(stmt, &[])
-                .await
-                .with_context(|| format!("Failed to execute migration statement: {}", stmt))?;
+/// Rewrites the references to the keyspace `from` in a script to `to`, or
+/// returns `None` if there are none
+///
+/// References are qualified names such as `from.users` and the keyspace names
+/// of `CREATE`, `ALTER` and `DROP KEYSPACE` and `USE`. Unquoted names match
+/// case-insensitively, so `from` is the name as stored. String literals, such
+/// as class names in `WITH replication`, and comments are left alone.
+pub(crate) fn rename_keyspace(cql: &str, from: &str, to: &str) -> Option<String> {
+    let bytes = cql.as_bytes();
+    let mut renamed = String::new();
+    let mut copied = 0;
+    // Whether the previous words were KEYSPACE or USE, with IF [NOT] EXISTS
+    let mut keyspace_next = false;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'-', Some(b'-')) | (b'/', Some(b'/')) => {
+                i = find(bytes, i + 2, b"\n").map_or(bytes.len(), |end| end + 1);
+            }
+            (b'/', Some(b'*')) => {
+                i = find(bytes, i + 2, b"*/").map_or(bytes.len(), |end| end + 2);
+            }
+            (b'$', Some(b'$')) => {
+                i = find(bytes, i + 2, b"$$").map_or(bytes.len(), |end| end + 2);
+                keyspace_next = false;
+            }
+            (b'\'', _) => {
+                i = skip_quoted(bytes, i, b'\'');
+                keyspace_next = false;
+            }
+            (c, _) if c == b'"' || c.is_ascii_alphabetic() => {
+                let (end, name) = if c == b'"' {
+                    let end = skip_quoted(bytes, i, b'"');
+                    let name = cql[i + 1..end.saturating_sub(1).max(i + 1)].replace("\"\"", "\"");
+                    (end, name)
+                } else {
+                    let end = bytes[i..]
+                        .iter()
+                        .position(|b| !(b.is_ascii_alphanumeric() || *b == b'_'))
+                        .map_or(bytes.len(), |len| i + len);
+                    (end, cql[i..end].to_ascii_lowercase())
+                };
+                let qualified = cql[end..].trim_start().starts_with('.');
+                if name == from && (qualified || keyspace_next) {
+                    renamed.push_str(&cql[copied..i]);
+                    renamed.push_str(&ident(to));
+                    copied = end;
+                }
+
+                keyspace_next = match name.as_str() {
+                    _ if c == b'"' => false,
+                    "keyspace" | "use" => true,
+                    "if" | "not" | "exists" => keyspace_next,
+                    _ => false,
+                };
+                i = end;
+            }
+            (c, _) => {
+                if !c.is_ascii_whitespace() {
+                    keyspace_next = false;
+                }
+                i += 1;
+            }
         }
+    }
+
+    if copied == 0 {
+        return None;
+    }
+    renamed.push_str(&cql[copied..]);
+    Some(renamed)
+}
+
+/// Returns the index right after the literal opened by the quote at `open`
+///
+/// A doubled quote inside the literal is an escaped quote, not its end.
+pub(crate) fn skip_quoted(bytes: &[u8], open: usize, quote: u8) -> usize {
+    closing_quote(bytes, open, quote).unwrap_or(bytes.len())
+}
+
+/// Like [`skip_quoted`], but returns `None` if the literal is never closed
+pub(crate) fn closing_quote(bytes: &[u8], open: usize, quote: u8) -> Option<usize> {
+    let mut i = open + 1;
+    while i < bytes.len() {
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return Some(i + 1);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Finds the first occurrence of `needle` at or after `from`
+pub(crate) fn find(bytes: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    bytes[from..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|pos| from + pos)
+}
+
+async fn execute(
+    session: &dyn Execute,
+    migration: &Migration,
+    cql: &str,
+    options: StatementOptions,
+) -> Result<()> {
+    let directives = Directives::parse(cql).map_err(|reason| Error::Parse {
+        name: migration.description.to_string(),
+        reason,
+    })?;
+    let options = options.with_directives(&directives);
+
+    for (i, stmt) in statements(cql).into_iter().enumerate() {
+        migration
+            .execute_statement(session, i + 1, stmt, &options)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Returns true for statements creating, altering or dropping schema elements
+fn is_schema_change(statement: &str) -> bool {
+    let keyword = statement.split_whitespace().next().unwrap_or_default();
+    ["CREATE", "ALTER", "DROP"]
+        .iter()
+        .any(|ddl| keyword.eq_ignore_ascii_case(ddl))
+}
+
+/// Checksums a run of statements, to tell whether already executed ones changed
+pub(crate) fn statements_checksum(statements: &[&str]) -> Vec<u8> {
+    let mut hasher = Sha384::new();
+    for stmt in statements {
+        hasher.update(stmt.as_bytes());
+        hasher.update(b";");
+    }
+    Vec::from(hasher.finalize().as_slice())
+}
+
+impl fmt::Debug for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Migration")
+            .field("version", &self.version)
+            .field("kind", &self.kind)
+            .field("description", &self.description)
+            .field("cql", &self.cql)
+            .field("checksum", &self.checksum)
+            .field("down", &self.down)
+            .field("code", &self.code.is_some())
+            .finish()
+    }
+}
 
-        Ok(())
+impl fmt::Display for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            MigrationKind::Versioned => write!(f, "{}/migrate {}", self.version, self.description),
+            MigrationKind::Repeatable => write!(f, "repeatable {}", self.description),
+        }
     }
 }
 
+#[derive(Clone)]
 pub struct AppliedMigration {
     pub checksum: Cow<'static, [u8]>,
+    pub description: Cow<'static, str>,
+    pub applied_at: Option<OffsetDateTime>,
+    pub has_down: bool,
+    pub execution_time_ms: Option<i64>,
+    pub applied_by: Option<String>,
+    pub host: Option<String>,
+    /// Set for failed attempts, which don't count as applied
+    pub error: Option<String>,
+}
+
+/// Records of versioned migrations by version
+pub type VersionedHistory = HashMap<i64, AppliedMigration>;
+
+/// Recorded history of versioned and repeatable migrations
+#[derive(Default)]
+pub struct AppliedHistory {
+    pub versioned: VersionedHistory,
+    /// Latest failed attempts of versioned migrations not applied since
+    pub failed: VersionedHistory,
+    pub repeatable: HashMap<String, AppliedMigration>,
+}
+
+impl AppliedHistory {
+    /// Returns the record of a local migration, if it was applied before
+    pub fn get(&self, migration: &Migration) -> Option<&AppliedMigration> {
+        match migration.kind {
+            MigrationKind::Versioned => self.versioned.get(&migration.version),
+            MigrationKind::Repeatable => self.repeatable.get(migration.description.as_ref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{MockRequest, MockSession};
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        for value in ["", "30", "s", "1.5s", "-1s", "10 s", "10d", "10S"] {
+            assert_eq!(parse_duration(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        assert_eq!(parse_duration("999999999999999999h"), None);
+        assert_eq!(parse_duration("999999999999999999m"), None);
+        assert_eq!(parse_duration("99999999999999999999s"), None);
+        assert_eq!(
+            parse_duration("18446744073709551615s"),
+            Some(Duration::from_secs(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn parses_directives_from_header_comments() {
+        let directives = Directives::parse(
+            "-- a migration\n\
+             -- scylla-migrate: description=backfill emails, consistency=local_quorum, timeout=60s\n\
+             \n\
+             // scylla-migrate: delay=200ms, no-split, env=dev | staging\n\
+             -- scylla-migrate: depends-on=20240102093000|20240103110000\n\
+             CREATE TABLE t (id int PRIMARY KEY);\n\
+             -- scylla-migrate: timeout=5m\n",
+        )
+        .unwrap();
+        assert_eq!(
+            directives,
+            Directives {
+                description: Some("backfill emails".to_string()),
+                consistency: Some(Consistency::LocalQuorum),
+                timeout: Some(Duration::from_secs(60)),
+                delay: Some(Duration::from_millis(200)),
+                no_split: true,
+                environments: vec!["dev".to_string(), "staging".to_string()],
+                depends_on: vec![20240102093000, 20240103110000],
+                ..Directives::default()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_directives() {
+        let cases = [
+            ("consistency=MOST", "unknown consistency level"),
+            ("timeout=1.5s", "invalid timeout, expected e.g. 60s"),
+            ("env=dev|", "invalid env, expected e.g. dev|staging"),
+            (
+                "depends-on=next",
+                "invalid depends-on, expected versions like 20240102093000|20240103110000",
+            ),
+            (
+                "requires=postgres 16",
+                "invalid requires, expected e.g. scylla >= 5.4",
+            ),
+            ("split", "unknown directive"),
+        ];
+        for (directive, reason) in cases {
+            let cql = format!("-- scylla-migrate: {}\nSELECT 1;", directive);
+            assert_eq!(Directives::parse(&cql), Err(reason), "{}", directive);
+        }
+        assert_eq!(
+            Directives::parse("-- assert: most: SELECT 1\nSELECT 1;"),
+            Err("invalid assert, expected e.g. count >= 1: SELECT ...")
+        );
+    }
+
+    #[test]
+    fn splits_statements_at_semicolons() {
+        assert_eq!(
+            split_statements("CREATE TABLE a (id int PRIMARY KEY);\n\nDROP TABLE b;\nDROP TABLE c"),
+            [
+                "CREATE TABLE a (id int PRIMARY KEY)",
+                "DROP TABLE b",
+                "DROP TABLE c"
+            ]
+        );
+        assert!(split_statements(" ;\n; ").is_empty());
+    }
+
+    #[test]
+    fn keeps_semicolons_in_literals_identifiers_and_bodies() {
+        let cql = "INSERT INTO t (s) VALUES ('a;''b');\n\
+                   SELECT \"odd;name\" FROM t;\n\
+                   CREATE FUNCTION f() RETURNS NULL ON NULL INPUT RETURNS int \
+                   LANGUAGE lua AS $$ return 1; $$;";
+        assert_eq!(
+            split_statements(cql),
+            [
+                "INSERT INTO t (s) VALUES ('a;''b')",
+                "SELECT \"odd;name\" FROM t",
+                "CREATE FUNCTION f() RETURNS NULL ON NULL INPUT RETURNS int \
+                 LANGUAGE lua AS $$ return 1; $$",
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_comments_in_front_of_statements() {
+        let cql = "-- users; and roles\n\
+                   // another; comment\n\
+                   /* block; comment */ DROP TABLE a;\n\
+                   -- only a comment;";
+        assert_eq!(split_statements(cql), ["DROP TABLE a"]);
+    }
+
+    #[test]
+    fn keeps_batches_together() {
+        let cql = "BEGIN UNLOGGED BATCH\n\
+                   INSERT INTO t (id) VALUES (1);\n\
+                   INSERT INTO t (id) VALUES (2);\n\
+                   APPLY BATCH;\n\
+                   begin batch INSERT INTO t (id) VALUES (3); apply batch;\n\
+                   DROP TABLE u;";
+        assert_eq!(
+            split_statements(cql),
+            [
+                "BEGIN UNLOGGED BATCH\n\
+                 INSERT INTO t (id) VALUES (1);\n\
+                 INSERT INTO t (id) VALUES (2);\n\
+                 APPLY BATCH",
+                "begin batch INSERT INTO t (id) VALUES (3); apply batch",
+                "DROP TABLE u",
+            ]
+        );
+    }
+
+    fn query(statement: &str, consistency: Option<Consistency>) -> MockRequest {
+        MockRequest::Query {
+            statement: statement.to_string(),
+            consistency,
+        }
+    }
+
+    #[tokio::test]
+    async fn executes_statements_through_any_session() {
+        let migration = Migration::new(
+            1,
+            "users".into(),
+            "-- scylla-migrate: consistency=ALL\n\
+             CREATE TABLE app.users (id uuid PRIMARY KEY);\n\
+             INSERT INTO app.users (id) VALUES (uuid());"
+                .into(),
+        );
+        let session = MockSession::new();
+        let options = StatementOptions {
+            schema_agreement: true,
+            wait_for_builds: Some(Duration::from_secs(1)),
+            ..StatementOptions::default()
+        };
+        execute(&session, &migration, &migration.cql, options)
+            .await
+            .unwrap();
+
+        let create = "CREATE TABLE app.users (id uuid PRIMARY KEY)";
+        let insert = "INSERT INTO app.users (id) VALUES (uuid())";
+        assert_eq!(
+            session.requests(),
+            [
+                query(create, Some(Consistency::All)),
+                MockRequest::SchemaAgreement,
+                MockRequest::Build(create.to_string()),
+                query(insert, Some(Consistency::All)),
+                MockRequest::Build(insert.to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_first_failing_statement() {
+        let migration = Migration::new(
+            1,
+            "users".into(),
+            "CREATE TABLE a (id int PRIMARY KEY);\nCREATE TABLE b;\nCREATE TABLE c (id int PRIMARY KEY);"
+                .into(),
+        );
+        let session = MockSession::new().reject("CREATE TABLE b");
+        let error = execute(
+            &session,
+            &migration,
+            &migration.cql,
+            StatementOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            matches!(error, Error::Execution { index: 2, .. }),
+            "{}",
+            error
+        );
+        assert_eq!(
+            session.requests(),
+            [
+                query("CREATE TABLE a (id int PRIMARY KEY)", None),
+                query("CREATE TABLE b", None),
+            ]
+        );
+    }
 }
@@ -0,0 +1,244 @@
+use crate::error::Result;
+use crate::session::{Execute, Pages, Prepared, Rows};
+use crate::token::tokens;
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use scylla::query::Query;
+use scylla::serialize::row::SerializeRow;
+use scylla::statement::Consistency;
+use scylla::transport::errors::{DbError, QueryError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// An [`Execute`] standing in for a cluster in unit tests
+///
+/// Records the requests made through it and answers them without a cluster:
+/// lightweight transactions, such as those of the migration lock, are applied,
+/// statements given rows with [`MockSession::respond`] return those, and all
+/// other statements return no rows, so a migrator sees an empty history.
+/// Migrations that need the driver's session, such as
+/// [`CodeMigration`](crate::CodeMigration)s, fail with
+/// [`Error::SessionRequired`](crate::Error::SessionRequired).
+///
+/// ```
+/// use scylla_migrate::test::MockSession;
+/// use scylla_migrate::{EmbeddedMigrations, Migrator};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> scylla_migrate::Result<()> {
+/// static MIGRATIONS: EmbeddedMigrations = EmbeddedMigrations::new(&[(
+///     "20240101000000_users.cql",
+///     "CREATE TABLE app.users (id uuid PRIMARY KEY);",
+/// )]);
+///
+/// let session = MockSession::new();
+/// let report = Migrator::embedded(&session, &MIGRATIONS).run().await?;
+/// assert_eq!(report.applied.len(), 1);
+/// assert!(session
+///     .statements()
+///     .contains(&"CREATE TABLE app.users (id uuid PRIMARY KEY)".to_string()));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct MockSession {
+    responses: Vec<(String, Rows)>,
+    rejected: Vec<String>,
+    requests: Mutex<Vec<MockRequest>>,
+}
+
+/// A request made through a [`MockSession`]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum MockRequest {
+    /// An unprepared statement
+    Query {
+        statement: String,
+        consistency: Option<Consistency>,
+    },
+    /// A prepared statement
+    Execute {
+        statement: String,
+        consistency: Option<Consistency>,
+    },
+    SchemaAgreement,
+    /// A wait for the view or index a statement creates
+    Build(String),
+}
+
+impl MockSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the statements containing `pattern` with `rows`
+    ///
+    /// The first response whose pattern a statement contains is used.
+    pub fn respond(mut self, pattern: impl Into<String>, rows: Rows) -> Self {
+        self.responses.push((pattern.into(), rows));
+        self
+    }
+
+    /// Fails the statements containing `pattern` as invalid, as a cluster
+    /// rejects a malformed statement
+    pub fn reject(mut self, pattern: impl Into<String>) -> Self {
+        self.rejected.push(pattern.into());
+        self
+    }
+
+    /// Returns the requests made so far, in order
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Returns the statements executed so far, prepared or not, in order
+    pub fn statements(&self) -> Vec<String> {
+        self.requests()
+            .into_iter()
+            .filter_map(|request| match request {
+                MockRequest::Query { statement, .. } | MockRequest::Execute { statement, .. } => {
+                    Some(statement)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn answer(&self, request: MockRequest, statement: &str) -> Result<Rows, QueryError> {
+        self.requests.lock().unwrap().push(request);
+        if self
+            .rejected
+            .iter()
+            .any(|pattern| statement.contains(pattern.as_str()))
+        {
+            return Err(QueryError::DbError(
+                DbError::Invalid,
+                format!("rejected by the mock session: {}", statement),
+            ));
+        }
+        if let Some((_, rows)) = self
+            .responses
+            .iter()
+            .find(|(pattern, _)| statement.contains(pattern.as_str()))
+        {
+            return Ok(rows.clone());
+        }
+        if is_conditional(statement) {
+            return Ok(Rows::applied(true));
+        }
+        Ok(Rows::default())
+    }
+}
+
+/// Returns true for an `INSERT`, `UPDATE` or `DELETE` with an `IF` condition
+fn is_conditional(statement: &str) -> bool {
+    let tokens = tokens(statement);
+    let modifies = tokens
+        .first()
+        .is_some_and(|token| token.is("INSERT") || token.is("UPDATE") || token.is("DELETE"));
+    modifies && tokens.iter().skip(1).any(|token| token.is("IF"))
+}
+
+#[async_trait]
+impl Execute for MockSession {
+    async fn query(
+        &self,
+        statement: Query,
+        _values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError> {
+        let request = MockRequest::Query {
+            statement: statement.contents.clone(),
+            consistency: statement.get_consistency(),
+        };
+        self.answer(request, &statement.contents)
+    }
+
+    async fn prepare(&self, statement: &str) -> Result<Prepared, QueryError> {
+        Ok(Prepared::new(statement))
+    }
+
+    async fn execute(
+        &self,
+        prepared: &Prepared,
+        _values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError> {
+        let request = MockRequest::Execute {
+            statement: prepared.statement().to_string(),
+            consistency: prepared.consistency(),
+        };
+        self.answer(request, prepared.statement())
+    }
+
+    async fn execute_paged(
+        &self,
+        prepared: &Prepared,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Pages, QueryError> {
+        let rows = self.execute(prepared, values).await?;
+        Ok(stream::iter([Ok(rows)]).boxed())
+    }
+
+    async fn await_schema_agreement(&self) -> Result<(), QueryError> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push(MockRequest::SchemaAgreement);
+        Ok(())
+    }
+
+    async fn wait_for_build(&self, statement: &str, _timeout: Duration) -> Result<()> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push(MockRequest::Build(statement.to_string()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_lightweight_transactions() {
+        assert!(is_conditional(
+            "INSERT INTO ks.migration_lock (name) VALUES (?) IF NOT EXISTS"
+        ));
+        assert!(is_conditional(
+            "DELETE FROM ks.t WHERE name = ? IF owner = ?"
+        ));
+        assert!(!is_conditional(
+            "CREATE TABLE IF NOT EXISTS ks.t (id int PRIMARY KEY)"
+        ));
+        assert!(!is_conditional("INSERT INTO ks.t (id) VALUES (1)"));
+    }
+
+    #[tokio::test]
+    async fn answers_with_the_first_matching_response() {
+        let session = MockSession::new()
+            .respond("FROM ks.a", Rows::applied(false))
+            .reject("DROP");
+        let rows = session
+            .query(Query::new("SELECT x FROM ks.a"), &())
+            .await
+            .unwrap();
+        assert_eq!(rows.first_row::<(bool,)>().unwrap(), (false,));
+        assert!(session
+            .query(Query::new("SELECT x FROM ks.b"), &())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(session
+            .query(Query::new("DROP TABLE ks.a"), &())
+            .await
+            .is_err());
+        assert_eq!(
+            session.statements(),
+            [
+                "SELECT x FROM ks.a",
+                "SELECT x FROM ks.b",
+                "DROP TABLE ks.a"
+            ]
+        );
+    }
+}
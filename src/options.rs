@@ -0,0 +1,240 @@
+use crate::clean::PROTECTED;
+use crate::destructive::Destructive;
+use crate::event::EventHook;
+use crate::history::{HistoryTable, Replication};
+use crate::hook::Hooks;
+use crate::lint::Linter;
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::migration::{Migration, MigrationKind};
+use crate::plan::SkipReason;
+use crate::retry::RetryPolicy;
+use crate::variables::Variables;
+use scylla::statement::Consistency;
+use std::collections::HashMap;
+use std::env;
+#[cfg(feature = "metrics")]
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Settings of a [`Migrator`](crate::Migrator), filled in by [`MigratorBuilder`](crate::MigratorBuilder)
+#[derive(Debug, Clone)]
+pub(crate) struct MigratorOptions {
+    pub history: HistoryTable,
+    /// Replication of a newly created history keyspace; once set, existing keyspaces are checked too
+    pub history_replication: Option<Replication>,
+    pub alter_history_replication: bool,
+    pub manage_history_schema: bool,
+    pub lock_wait: Duration,
+    pub lock_lease: Duration,
+    /// Tenant keyspaces migrated at once by [`Migrator::run_tenants`](crate::Migrator::run_tenants)
+    pub tenant_concurrency: usize,
+    pub checksum_policy: ChecksumPolicy,
+    pub out_of_order: OutOfOrderPolicy,
+    pub missing_policy: MissingPolicy,
+    pub requirement_policy: RequirementPolicy,
+    /// Policies of destructive statement kinds, [`DestructivePolicy::Allow`] if absent
+    pub destructive: HashMap<Destructive, DestructivePolicy>,
+    /// Linter checking pending migrations before a run, none by default
+    pub linter: Option<Linter>,
+    /// Consistency of migration statements, the session default if unset
+    pub statement_consistency: Option<Consistency>,
+    /// Consistency of reads and writes of the history, the session default if unset
+    pub history_consistency: Option<Consistency>,
+    pub schema_agreement: SchemaAgreement,
+    /// How long to wait for created views and indexes to be built, not at all if unset
+    pub wait_for_builds: Option<Duration>,
+    pub retry: RetryPolicy,
+    pub variables: Variables,
+    /// Keyspaces whose references in migration scripts are rewritten, with their new name
+    pub keyspace_renames: Vec<(String, String)>,
+    /// Environment the runner migrates, selecting migrations limited with `env=`
+    pub environment: Option<String>,
+    pub event_hooks: Vec<EventHook>,
+    pub hooks: Hooks,
+    pub applied_by: Option<String>,
+    /// Whether runner actions are recorded in the `migration_audit` table
+    pub audit: bool,
+    /// Patterns of the clusters, keyspaces and environments [`Migrator::clean`](crate::Migrator::clean) refuses to clean
+    pub protected: Vec<String>,
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<Arc<Metrics>>,
+}
+
+impl Default for MigratorOptions {
+    fn default() -> Self {
+        Self {
+            history: HistoryTable::default(),
+            history_replication: None,
+            alter_history_replication: false,
+            manage_history_schema: true,
+            lock_wait: Duration::from_secs(60),
+            lock_lease: Duration::from_secs(60),
+            tenant_concurrency: 1,
+            checksum_policy: ChecksumPolicy::default(),
+            out_of_order: OutOfOrderPolicy::default(),
+            missing_policy: MissingPolicy::default(),
+            requirement_policy: RequirementPolicy::default(),
+            destructive: HashMap::new(),
+            linter: None,
+            statement_consistency: None,
+            history_consistency: None,
+            schema_agreement: SchemaAgreement::default(),
+            wait_for_builds: None,
+            retry: RetryPolicy::default(),
+            variables: Variables::default(),
+            keyspace_renames: Vec::new(),
+            environment: None,
+            event_hooks: Vec::new(),
+            hooks: Hooks::default(),
+            applied_by: env::var("USER").or_else(|_| env::var("USERNAME")).ok(),
+            audit: false,
+            protected: vec![PROTECTED.to_string()],
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+}
+
+/// What to do with an applied versioned migration whose file has changed since
+///
+/// Repeatable migrations are exempt; they are meant to be reapplied on change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumPolicy {
+    /// Refuse to run anything while an applied migration differs from its file
+    #[default]
+    Error,
+    /// Report the mismatch and leave the migration as applied
+    Warn,
+    /// Execute the changed migration again and record the new checksum
+    Reapply,
+}
+
+/// What to do with a pending migration older than the newest applied one
+///
+/// Such migrations typically appear when branches developed in parallel are
+/// merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutOfOrderPolicy {
+    /// Refuse to run anything while such migrations are pending
+    #[default]
+    Error,
+    /// Report and skip them, applying only newer migrations
+    Skip,
+    /// Apply them like any other pending migration
+    Apply,
+}
+
+/// What to do with an applied versioned migration that is not present locally
+///
+/// This usually means the wrong migrations directory is used, or its history
+/// was deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingPolicy {
+    /// Refuse to run anything and report it from [`Migrator::validate`](crate::Migrator::validate)
+    #[default]
+    Error,
+    /// Log a warning and carry on
+    Warn,
+}
+
+/// What to do with a pending migration whose `requires` directive the cluster
+/// doesn't meet, see [`Directives::requires`](crate::Directives::requires)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RequirementPolicy {
+    /// Refuse to run anything while such migrations are pending
+    #[default]
+    Error,
+    /// Report and leave them out, as if they were limited to another environment
+    Skip,
+}
+
+/// What to do with pending migrations containing a kind of [`Destructive`] statement
+///
+/// Checked for all migrations a run would apply before the first one is executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DestructivePolicy {
+    /// Execute them like any other statement
+    #[default]
+    Allow,
+    /// Log a warning for each of them and execute them
+    Warn,
+    /// Refuse to run anything while such statements are pending
+    Deny,
+}
+
+/// Whether to wait for schema agreement after every DDL statement of a migration
+///
+/// Until all nodes agree on the schema, a statement depending on the previous
+/// one, e.g. an index on a table just created, may reach a node that doesn't
+/// know about it yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemaAgreement {
+    /// Wait when the cluster has more than one node
+    #[default]
+    Auto,
+    /// Always wait, also on a single node
+    Always,
+    /// Never wait, e.g. when migrations wait for agreement themselves
+    Never,
+}
+
+/// Options controlling which pending migrations a run applies
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    to_version: Option<i64>,
+    steps: Option<usize>,
+    pub(crate) statement_delay: Option<Duration>,
+    pub(crate) migration_delay: Option<Duration>,
+}
+
+impl RunOptions {
+    /// Creates options applying every pending migration
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only applies versioned migrations up to and including `version`
+    pub fn to_version(mut self, version: i64) -> Self {
+        self.to_version = Some(version);
+        self
+    }
+
+    /// Applies at most `n` pending versioned migrations
+    pub fn steps(mut self, n: usize) -> Self {
+        self.steps = Some(n);
+        self
+    }
+
+    /// Waits `delay` between the statements of a migration, pacing large
+    /// backfills so they don't saturate the cluster
+    ///
+    /// A `delay` directive of the migration takes precedence.
+    pub fn statement_delay(mut self, delay: Duration) -> Self {
+        self.statement_delay = Some(delay);
+        self
+    }
+
+    /// Waits `delay` between migrations
+    pub fn migration_delay(mut self, delay: Duration) -> Self {
+        self.migration_delay = Some(delay);
+        self
+    }
+
+    /// Returns why a pending migration should not be applied, if it shouldn't
+    ///
+    /// `applied` is the number of versioned migrations applied so far in this
+    /// run. Repeatable migrations are never skipped.
+    pub(crate) fn skip_reason(&self, migration: &Migration, applied: usize) -> Option<SkipReason> {
+        if migration.kind == MigrationKind::Repeatable {
+            return None;
+        }
+
+        let version = migration.version;
+        match (self.to_version, self.steps) {
+            (Some(target), _) if version > target => Some(SkipReason::AboveTarget(target)),
+            (_, Some(steps)) if applied >= steps => Some(SkipReason::StepLimit(steps)),
+            _ => None,
+        }
+    }
+}
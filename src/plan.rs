@@ -0,0 +1,132 @@
+use crate::destructive::Destructive;
+use crate::migration::MigrationKind;
+use crate::server::Requirement;
+use std::fmt;
+
+/// Migrations a run would apply, computed without changing the cluster
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Plan {
+    pub migrations: Vec<PlannedMigration>,
+    /// Pending migrations the run options excluded
+    pub skipped: Vec<SkippedMigration>,
+}
+
+/// A pending migration together with the statements it would execute
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlannedMigration {
+    pub kind: MigrationKind,
+    /// Always 0 for repeatable migrations
+    pub version: i64,
+    pub description: String,
+    /// Set when the migration was applied before with a different checksum
+    pub changed: bool,
+    pub statements: Vec<String>,
+}
+
+/// A pending migration that is intentionally not applied
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SkippedMigration {
+    pub version: i64,
+    pub description: String,
+    pub reason: SkipReason,
+}
+
+/// Why a pending migration was not applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SkipReason {
+    /// The migration is newer than the requested target version
+    AboveTarget(i64),
+    /// The run already applied the requested number of migrations
+    StepLimit(usize),
+    /// The migration was applied with a different checksum and is not reapplied
+    ChecksumMismatch,
+    /// The migration is older than the newest applied version
+    OutOfOrder(i64),
+    /// The cluster doesn't run the database or version the migration requires
+    UnmetRequirement(Requirement),
+}
+
+impl Plan {
+    /// Returns true when there is nothing to apply
+    pub fn is_empty(&self) -> bool {
+        self.migrations.is_empty()
+    }
+}
+
+impl PlannedMigration {
+    /// Returns the statements that drop or truncate something, with their
+    /// position starting at 1
+    pub fn destructive_statements(&self) -> impl Iterator<Item = (usize, &str, Destructive)> {
+        self.statements
+            .iter()
+            .enumerate()
+            .filter_map(|(i, stmt)| Some((i + 1, stmt.as_str(), Destructive::of(stmt)?)))
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            writeln!(f, "No pending migrations")?;
+        }
+
+        for migration in &self.migrations {
+            let action = if migration.changed {
+                "Reapply"
+            } else {
+                "Apply"
+            };
+            match migration.kind {
+                MigrationKind::Versioned => writeln!(
+                    f,
+                    "{} {}/migrate {}",
+                    action, migration.version, migration.description
+                )?,
+                MigrationKind::Repeatable => {
+                    writeln!(f, "{} repeatable {}", action, migration.description)?
+                }
+            }
+
+            for (i, stmt) in migration.statements.iter().enumerate() {
+                writeln!(f, "  [{}] {};", i + 1, stmt)?;
+            }
+        }
+
+        for migration in &self.skipped {
+            writeln!(f, "{}", migration)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SkippedMigration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Skipped {}/migrate {}: {}",
+            self.version, self.description, self.reason
+        )
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::AboveTarget(target) => {
+                write!(f, "newer than target version {}", target)
+            }
+            SkipReason::StepLimit(steps) => write!(f, "step limit of {} reached", steps),
+            SkipReason::ChecksumMismatch => write!(f, "changed after it was applied"),
+            SkipReason::OutOfOrder(newest) => {
+                write!(f, "older than the applied version {}", newest)
+            }
+            SkipReason::UnmetRequirement(requirement) => write!(f, "requires {}", requirement),
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use crate::error::{BoxError, Error, Result};
+use crate::migration::Migration;
+use crate::source::{load_files, File, MigrationSource};
+use async_trait::async_trait;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+use ureq::native_tls::TlsConnector;
+use ureq::Agent;
+use url::Url;
+
+/// Files a [`RemoteSource`] fetches at once
+const FETCH_CONCURRENCY: usize = 8;
+
+/// Redirects followed before a fetch fails
+const MAX_REDIRECTS: usize = 5;
+
+/// Size of the largest response body read
+const MAX_RESPONSE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Fetches migration files listed in a manifest from an HTTP(S) server
+///
+/// Lets many clusters be pointed at a central artifact server instead of
+/// baking the migration files into every image. The manifest is a JSON
+/// document listing the files with their SHA-256 checksums:
+///
+/// ```json
+/// {
+///   "migrations": [
+///     { "file": "20240117000000_create_users.up.cql", "sha256": "9f86d081884c7d65..." },
+///     { "file": "20240117000000_create_users.down.cql", "sha256": "60303ae22b998861..." }
+///   ]
+/// }
+/// ```
+///
+/// Files are resolved relative to the manifest, unless they are URLs of their
+/// own, and named after the last component of their path, without any query
+/// string, e.g. the signature of a signed URL. A file whose content
+/// doesn't match its checksum fails the load with [`Error::RemoteChecksum`],
+/// before any migration is applied. The manifest and files are fetched again
+/// every time migrations are loaded.
+///
+/// # Example
+/// ```no_run
+/// # async fn migrate(session: &scylla::Session) -> scylla_migrate::Result<()> {
+/// use scylla_migrate::{Migrator, RemoteSource};
+///
+/// let source = RemoteSource::new("https://artifacts.example.com/app/v42/manifest.json")
+///     .header("Authorization", "Bearer secret");
+/// Migrator::builder()
+///     .build_with_source(session, source)
+///     .run()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RemoteSource {
+    manifest: String,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+}
+
+impl RemoteSource {
+    /// Fetches the manifest at the `http://` or `https://` URL `manifest`
+    ///
+    /// HTTPS servers are verified against the system's certificate
+    /// authorities. Requests time out after 30 seconds of inactivity, and
+    /// responses larger than 64 MiB are refused.
+    pub fn new(manifest: impl Into<String>) -> Self {
+        Self {
+            manifest: manifest.into(),
+            headers: Vec::new(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Sends `name: value` with every request, e.g. to authenticate
+    ///
+    /// Redirects to another host don't get the headers, so credentials for
+    /// the artifact server aren't handed on to wherever it redirects to.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets how long to wait for the server to connect, accept or send data
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the client the manifest and its files are fetched with
+    fn agent(&self) -> Result<Agent> {
+        let tls = TlsConnector::new().map_err(|err| Error::Fetch {
+            url: self.manifest.clone(),
+            source: err.into(),
+        })?;
+        Ok(ureq::AgentBuilder::new()
+            .tls_connector(Arc::new(tls))
+            .timeout_connect(self.timeout)
+            .timeout_read(self.timeout)
+            .timeout_write(self.timeout)
+            .redirects(0)
+            .user_agent(concat!("scylla-migrate/", env!("CARGO_PKG_VERSION")))
+            .build())
+    }
+
+    /// Fetches `url` on a blocking thread
+    async fn fetch(&self, agent: &Agent, url: String) -> Result<Vec<u8>> {
+        let agent = agent.clone();
+        let headers = self.headers.clone();
+        let fetched = tokio::task::spawn_blocking({
+            let url = url.clone();
+            move || get(&agent, &url, &headers)
+        })
+        .await
+        .map_err(BoxError::from)
+        .and_then(|result| result);
+        fetched.map_err(|source| Error::Fetch { url, source })
+    }
+
+    /// Returns the URLs of the files the manifest lists with their checksums
+    fn manifest_files(&self, manifest: &[u8]) -> Result<Vec<(String, String)>> {
+        let invalid = |reason: &str| Error::RemoteManifest {
+            url: self.manifest.clone(),
+            reason: reason.to_string(),
+        };
+        let manifest: Value =
+            serde_json::from_slice(manifest).map_err(|err| invalid(&err.to_string()))?;
+        let entries = manifest
+            .get("migrations")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("no \"migrations\" array"))?;
+
+        entries
+            .iter()
+            .map(|entry| {
+                let field = |name| entry.get(name).and_then(Value::as_str);
+                let file = field("file").ok_or_else(|| invalid("a migration has no \"file\""))?;
+                let checksum = field("sha256")
+                    .ok_or_else(|| invalid(&format!("{} has no \"sha256\"", file)))?;
+                let url = resolve(&self.manifest, file)
+                    .map_err(|err| invalid(&format!("{}: {}", file, err)))?;
+                Ok((url, checksum.to_ascii_lowercase()))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl MigrationSource for RemoteSource {
+    async fn migrations(&self) -> Result<Vec<Migration>> {
+        let agent = self.agent()?;
+        let manifest = self.fetch(&agent, self.manifest.clone()).await?;
+        let files = self.manifest_files(&manifest)?;
+        debug!(
+            "Fetching {} migration files from {}",
+            files.len(),
+            self.manifest
+        );
+
+        let files: Vec<File> = stream::iter(files)
+            .map(|(url, expected)| async {
+                let content = self.fetch(&agent, url.clone()).await?;
+                let actual = hex(&Sha256::digest(&content));
+                if actual != expected {
+                    return Err(Error::RemoteChecksum {
+                        url,
+                        expected,
+                        actual,
+                    });
+                }
+                let filename = filename(&url).to_string();
+                let cql = String::from_utf8(content).map_err(|_| Error::Parse {
+                    name: filename.clone(),
+                    reason: "file is not valid UTF-8",
+                })?;
+                Ok((Cow::Owned(filename), Cow::Owned(cql)))
+            })
+            .buffer_unordered(FETCH_CONCURRENCY)
+            .try_collect()
+            .await?;
+        load_files(files).await
+    }
+}
+
+/// Resolves `file` against the URL of the document listing it
+fn resolve(base: &str, file: &str) -> std::result::Result<String, url::ParseError> {
+    Ok(Url::parse(base)?.join(file)?.into())
+}
+
+/// Returns the last segment of the path of `url`, without its query or fragment
+fn filename(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit('/').next().unwrap_or_default()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Fetches `url` with a GET request, following redirects
+fn get(
+    agent: &Agent,
+    url: &str,
+    headers: &[(String, String)],
+) -> std::result::Result<Vec<u8>, BoxError> {
+    let mut url = Url::parse(url)?;
+    let mut headers = headers;
+    for _ in 0..=MAX_REDIRECTS {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err("only http:// and https:// URLs are supported".into());
+        }
+        let mut request = agent.request_url("GET", &url);
+        for (name, value) in headers {
+            check_header(name, value)?;
+            request = request.set(name, value);
+        }
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status, _)) => {
+                return Err(format!("the server responded with status {}", status).into())
+            }
+            Err(err) => return Err(err.into()),
+        };
+        match response.status() {
+            200 => return read_body(response.into_reader(), MAX_RESPONSE_SIZE),
+            301 | 302 | 303 | 307 | 308 => {
+                let location = response
+                    .header("location")
+                    .ok_or("the server redirected without a Location")?;
+                let next = url.join(location)?;
+                if !redirect_keeps_headers(&url, &next)? {
+                    headers = &[];
+                }
+                url = next;
+            }
+            status => return Err(format!("the server responded with status {}", status).into()),
+        }
+    }
+    Err(format!("more than {} redirects", MAX_REDIRECTS).into())
+}
+
+/// Returns whether a redirect from `from` to `to` may carry the headers of the
+/// source, e.g. its credentials, which only the same host and port may receive
+///
+/// Downgrades from HTTPS to plain HTTP are refused.
+fn redirect_keeps_headers(from: &Url, to: &Url) -> std::result::Result<bool, BoxError> {
+    let (from_tls, to_tls) = (from.scheme() == "https", to.scheme() == "https");
+    if from_tls && !to_tls {
+        return Err("refusing to follow a redirect from https:// to http://".into());
+    }
+    // Upgrading to HTTPS moves to its port
+    let upgraded = !from_tls && to_tls;
+    Ok(from.host() == to.host()
+        && (from.port_or_known_default() == to.port_or_known_default() || upgraded))
+}
+
+/// Fails on headers that would end the line they are written on, or that
+/// have no valid name, without repeating their value
+fn check_header(name: &str, value: &str) -> std::result::Result<(), BoxError> {
+    let valid_name = !name.is_empty() && name.bytes().all(|b| b.is_ascii_graphic() && b != b':');
+    if !valid_name || value.bytes().any(|b| b == b'\r' || b == b'\n' || b == 0) {
+        return Err(format!("invalid header {:?}", name).into());
+    }
+    Ok(())
+}
+
+/// Reads a body of up to `limit` bytes
+fn read_body(body: impl Read, limit: u64) -> std::result::Result<Vec<u8>, BoxError> {
+    let mut content = Vec::new();
+    body.take(limit + 1).read_to_end(&mut content)?;
+    if content.len() as u64 > limit {
+        return Err(format!("the response is larger than {} bytes", limit).into());
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn keeps_headers(from: &str, to: &str) -> std::result::Result<bool, BoxError> {
+        redirect_keeps_headers(&Url::parse(from)?, &Url::parse(to)?)
+    }
+
+    #[test]
+    fn redirects_keep_headers_on_the_same_host_only() {
+        assert!(keeps_headers("https://a.example/m.json", "https://A.example/v2/m.json").unwrap());
+        assert!(keeps_headers("http://a.example/m.json", "https://a.example/m.json").unwrap());
+        assert!(keeps_headers("https://a.example/m.json", "https://a.example:443/m.json").unwrap());
+        assert!(!keeps_headers("https://a.example/m.json", "https://b.example/m.json").unwrap());
+        assert!(
+            !keeps_headers("https://a.example/m.json", "https://a.example:8443/m.json").unwrap()
+        );
+    }
+
+    #[test]
+    fn redirects_from_https_to_http_are_refused() {
+        assert!(keeps_headers("https://a.example/m.json", "http://a.example/m.json").is_err());
+    }
+
+    #[test]
+    fn resolves_files_against_the_manifest() {
+        let manifest = "https://a.example/app/v1/manifest.json";
+        let resolve = |base, file| resolve(base, file).unwrap();
+        assert_eq!(
+            resolve(manifest, "1_users.cql"),
+            "https://a.example/app/v1/1_users.cql"
+        );
+        assert_eq!(
+            resolve(manifest, "sql/1_users.cql"),
+            "https://a.example/app/v1/sql/1_users.cql"
+        );
+        assert_eq!(
+            resolve(manifest, "/shared/1_users.cql"),
+            "https://a.example/shared/1_users.cql"
+        );
+        assert_eq!(
+            resolve(manifest, "https://b.example/1_users.cql"),
+            "https://b.example/1_users.cql"
+        );
+        assert_eq!(
+            resolve(manifest, "//b.example/1_users.cql"),
+            "https://b.example/1_users.cql"
+        );
+        assert_eq!(
+            resolve("https://a.example/v1/m.json?sig=a/b", "1_users.cql"),
+            "https://a.example/v1/1_users.cql"
+        );
+        assert_eq!(
+            resolve("https://a.example", "1_users.cql"),
+            "https://a.example/1_users.cql"
+        );
+        assert!(super::resolve("manifest.json", "1_users.cql").is_err());
+    }
+
+    #[test]
+    fn names_files_without_query_or_fragment() {
+        assert_eq!(filename("https://a.example/v1/1_users.cql"), "1_users.cql");
+        assert_eq!(
+            filename("https://a.example/v1/1_users.up.cql?sig=abc&expires=1"),
+            "1_users.up.cql"
+        );
+        assert_eq!(filename("https://a.example/1_users.cql#top"), "1_users.cql");
+        assert_eq!(
+            filename("https://a.example/1_users.cql?next=/a/b"),
+            "1_users.cql"
+        );
+    }
+
+    #[test]
+    fn rejects_headers_breaking_the_request() {
+        assert!(check_header("Authorization", "Bearer secret").is_ok());
+        let error = check_header("Authorization", "Bearer secret\r\nX-Injected: 1").unwrap_err();
+        assert!(!error.to_string().contains("secret"));
+        assert!(check_header("X-Injected\n", "1").is_err());
+        assert!(check_header("Bad Name", "1").is_err());
+        assert!(check_header("", "1").is_err());
+    }
+
+    #[test]
+    fn limits_the_response_size() {
+        assert_eq!(read_body(&b"hello"[..], 5).unwrap(), b"hello");
+        assert!(read_body(&b"hello"[..], 4).is_err());
+        assert!(read_body(io::repeat(b'a'), 1024).is_err());
+    }
+
+    /// Serves `response` to one request on a local port, returning the port
+    /// and the request it got
+    fn serve(response: String) -> (u16, std::thread::JoinHandle<String>) {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            while reader.read_line(&mut request).unwrap() > 2 {}
+            stream.write_all(response.as_bytes()).unwrap();
+            request
+        });
+        (port, server)
+    }
+
+    #[test]
+    fn follows_redirects_without_handing_on_headers() {
+        let (file_port, file_server) =
+            serve("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello".to_string());
+        let (manifest_port, manifest_server) = serve(format!(
+            "HTTP/1.1 302 Found\r\nLocation: //127.0.0.1:{}/1_users.cql\r\nContent-Length: 0\r\n\r\n",
+            file_port
+        ));
+
+        let agent = ureq::AgentBuilder::new().redirects(0).build();
+        let headers = [("X-Token".to_string(), "secret".to_string())];
+        let url = format!("http://127.0.0.1:{}/1_users.cql", manifest_port);
+        assert_eq!(get(&agent, &url, &headers).unwrap(), b"hello");
+        assert!(manifest_server.join().unwrap().contains("X-Token: secret"));
+        assert!(!file_server.join().unwrap().contains("X-Token"));
+    }
+
+    #[test]
+    fn fails_on_truncated_bodies() {
+        let (port, server) =
+            serve("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhello".to_string());
+        let agent = ureq::AgentBuilder::new().redirects(0).build();
+        let url = format!("http://127.0.0.1:{}/1_users.cql", port);
+        assert!(get(&agent, &url, &[]).is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn fetches_only_http_urls() {
+        let agent = ureq::AgentBuilder::new().build();
+        assert!(get(&agent, "ftp://a.example/m.json", &[]).is_err());
+        assert!(get(&agent, "a.example/m.json", &[]).is_err());
+    }
+}
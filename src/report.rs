@@ -0,0 +1,139 @@
+use crate::error::Error;
+use crate::migration::{Migration, MigrationKind};
+use crate::plan::SkippedMigration;
+use std::error::Error as _;
+use std::fmt;
+use std::time::Duration;
+
+/// Outcome of a run, returned by [`Migrator::run`](crate::Migrator::run)
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MigrationReport {
+    /// Migrations executed by the run, in the order they were applied
+    pub applied: Vec<ExecutedMigration>,
+    /// Pending migrations the run intentionally left alone
+    pub skipped: Vec<SkippedMigration>,
+    /// The migration that stopped the run, if any; none if a hook did
+    pub failed: Option<FailedMigration>,
+}
+
+/// A migration executed successfully during a run
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExecutedMigration {
+    pub kind: MigrationKind,
+    /// Always 0 for repeatable migrations
+    pub version: i64,
+    pub description: String,
+    /// Set when the migration was applied before with a different checksum
+    pub reapplied: bool,
+    pub duration: Duration,
+}
+
+/// A migration whose execution failed
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FailedMigration {
+    pub kind: MigrationKind,
+    /// Always 0 for repeatable migrations
+    pub version: i64,
+    pub description: String,
+    pub duration: Duration,
+    pub error: String,
+}
+
+/// Error of a run stopped by a failing migration
+///
+/// Carries the report of everything done up to the failure, returned as
+/// [`Error::RunFailed`]:
+///
+/// ```no_run
+/// # async fn migrate(runner: scylla_migrate::Migrator<'_>) {
+/// use scylla_migrate::Error;
+///
+/// if let Err(Error::RunFailed(failed)) = runner.run().await {
+///     eprintln!("{} migrations applied before the failure", failed.report.applied.len());
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RunFailed {
+    pub report: MigrationReport,
+    /// Why the failed migration failed, or the hook that stopped the run
+    pub error: Error,
+}
+
+impl MigrationReport {
+    pub(crate) fn push_applied(
+        &mut self,
+        migration: &Migration,
+        reapplied: bool,
+        duration: Duration,
+    ) {
+        self.applied.push(ExecutedMigration {
+            kind: migration.kind,
+            version: migration.version,
+            description: migration.description.to_string(),
+            reapplied,
+            duration,
+        });
+    }
+
+    pub(crate) fn fail(mut self, migration: &Migration, duration: Duration, error: Error) -> Error {
+        self.failed = Some(FailedMigration {
+            kind: migration.kind,
+            version: migration.version,
+            description: migration.description.to_string(),
+            duration,
+            error: error_chain(&error),
+        });
+        Error::RunFailed(Box::new(RunFailed {
+            report: self,
+            error,
+        }))
+    }
+
+    /// Stops the run for a reason other than a failed migration
+    pub(crate) fn abort(self, error: Error) -> Error {
+        Error::RunFailed(Box::new(RunFailed {
+            report: self,
+            error,
+        }))
+    }
+}
+
+impl fmt::Display for RunFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.report.failed {
+            Some(failed) => match failed.kind {
+                MigrationKind::Versioned => write!(
+                    f,
+                    "Migration {}/migrate {} failed",
+                    failed.version, failed.description
+                ),
+                MigrationKind::Repeatable => {
+                    write!(f, "Migration repeatable {} failed", failed.description)
+                }
+            },
+            None => write!(f, "Migration run failed"),
+        }
+    }
+}
+
+impl std::error::Error for RunFailed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Formats an error followed by all of its causes
+pub(crate) fn error_chain(error: &Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(err) = source {
+        chain.push_str(": ");
+        chain.push_str(&err.to_string());
+        source = err.source();
+    }
+    chain
+}
@@ -0,0 +1,107 @@
+use scylla::transport::errors::{DbError, QueryError};
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+/// How often queries failing with transient errors are retried
+///
+/// Timeouts, overloaded and unavailable nodes and broken connections are
+/// retried with exponential backoff; other errors fail right away. Retries are
+/// off by default.
+///
+/// A statement that timed out may still have been applied, so a retried DDL
+/// statement should be idempotent, e.g. `CREATE TABLE IF NOT EXISTS`.
+///
+/// # Example
+/// ```
+/// use scylla_migrate::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let retry = RetryPolicy::new(5).backoff(Duration::from_millis(200));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Makes up to `max_attempts` attempts, waiting 100ms before the first retry
+    /// and twice as long before every further one, at most 10s
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+
+    /// Never retries
+    pub fn none() -> Self {
+        Self::new(1)
+    }
+
+    /// Sets the wait before the first retry
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the longest wait between two attempts
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Runs `query` until it succeeds, fails permanently or runs out of attempts
+    pub(crate) async fn run<T, F, Fut>(&self, mut query: F) -> Result<T, QueryError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, QueryError>>,
+    {
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+        loop {
+            match query().await {
+                Err(err) if attempt < self.max_attempts && is_transient(&err) => {
+                    warn!(
+                        "Retrying in {:?} after attempt {} of {} failed: {}",
+                        backoff, attempt, self.max_attempts, err
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// Returns true for errors that may go away when the query is sent again
+fn is_transient(err: &QueryError) -> bool {
+    match err {
+        QueryError::DbError(db_error, _) => matches!(
+            db_error,
+            DbError::Overloaded
+                | DbError::Unavailable { .. }
+                | DbError::ReadTimeout { .. }
+                | DbError::WriteTimeout { .. }
+                | DbError::IsBootstrapping
+                | DbError::RateLimitReached { .. }
+        ),
+        QueryError::TimeoutError
+        | QueryError::RequestTimeout(_)
+        | QueryError::BrokenConnection(_)
+        | QueryError::ConnectionPoolError(_)
+        | QueryError::UnableToAllocStreamId => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,650 @@
+use crate::drift::ImpliedSchema;
+use crate::error::{Error, Result};
+use crate::migration::statements;
+use crate::tenant::{existing_keyspaces, SYSTEM_KEYSPACES};
+use scylla::Session;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Keyspaces of a cluster with their types, tables and views, as read from
+/// `system_schema`
+///
+/// Everything is kept in name order, so two reads of the same schema are equal
+/// and display the same. The [`Display`](fmt::Display) output is a CQL script
+/// recreating the schema, in the style of `DESCRIBE`.
+///
+/// # Example
+/// ```no_run
+/// use scylla::Session;
+/// use scylla_migrate::Schema;
+///
+/// async fn dump(session: &Session) -> scylla_migrate::Result<()> {
+///     let schema = Schema::read(session, &["app".to_string()]).await?;
+///     std::fs::write("schema.cql", schema.to_string()).unwrap();
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    pub keyspaces: BTreeMap<String, KeyspaceSchema>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyspaceSchema {
+    /// Replication strategy options, `class` included
+    pub replication: BTreeMap<String, String>,
+    pub durable_writes: bool,
+    pub types: BTreeMap<String, UserType>,
+    pub tables: BTreeMap<String, TableSchema>,
+    /// Materialized views, without the ones backing secondary indexes
+    pub views: BTreeMap<String, ViewSchema>,
+}
+
+/// A user-defined type
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserType {
+    /// Names and types of the fields, in declaration order
+    pub fields: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableSchema {
+    /// Partition key columns, then clustering columns, in key order, then the
+    /// other columns by name
+    pub columns: Vec<Column>,
+    /// Table options such as `compaction` or `gc_grace_seconds`, as CQL literals
+    pub options: BTreeMap<String, String>,
+    /// Secondary indexes by name
+    pub indexes: BTreeMap<String, Index>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    /// The type as written in CQL, e.g. `frozen<list<text>>`
+    pub cql_type: String,
+    pub kind: ColumnKind,
+    /// Whether a clustering column is sorted in descending order
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColumnKind {
+    PartitionKey,
+    Clustering,
+    Static,
+    Regular,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    /// The indexed column or expression, e.g. `email` or `keys(tags)`
+    pub target: String,
+    /// Implementation class of a custom index
+    pub class: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ViewSchema {
+    pub base_table: String,
+    pub where_clause: String,
+    /// Whether the view selects every column of its base table
+    pub include_all_columns: bool,
+    /// Columns and options of the view; views have no indexes
+    pub table: TableSchema,
+}
+
+/// Options of `system_schema.tables` and `system_schema.views` included in a [`TableSchema`]
+const TABLE_OPTIONS: &str =
+    "caching, comment, compaction, compression, default_time_to_live, gc_grace_seconds";
+
+type OptionsRow = (
+    String,
+    Option<HashMap<String, String>>,
+    Option<String>,
+    Option<HashMap<String, String>>,
+    Option<HashMap<String, String>>,
+    Option<i32>,
+    Option<i32>,
+);
+
+impl Schema {
+    /// Reads the schema of `keyspaces`, or of all keyspaces but the system ones if empty
+    pub async fn read(session: &Session, keyspaces: &[String]) -> Result<Schema> {
+        let existing = existing_keyspaces(session).await?;
+        let keyspaces: Vec<String> = if keyspaces.is_empty() {
+            existing
+                .into_iter()
+                .filter(|ks| !SYSTEM_KEYSPACES.contains(&ks.as_str()))
+                .collect()
+        } else {
+            if let Some(missing) = keyspaces.iter().find(|ks| !existing.contains(*ks)) {
+                return Err(Error::UnknownKeyspace(missing.clone()));
+            }
+            keyspaces.to_vec()
+        };
+
+        let mut schema = Schema::default();
+        for keyspace in keyspaces {
+            let keyspace_schema = read_keyspace(session, &keyspace).await?;
+            schema.keyspaces.insert(keyspace, keyspace_schema);
+        }
+        Ok(schema)
+    }
+
+    /// Reads the schema a CQL script creates, such as the output of
+    /// [`Display`](fmt::Display)
+    ///
+    /// The DDL statements are replayed in order, the way
+    /// [`Migrator::detect_drift`](crate::Migrator::detect_drift) replays
+    /// migrations; anything else is skipped. Unqualified names belong to
+    /// `keyspace` until a `USE` statement.
+    pub fn parse(cql: &str, keyspace: Option<&str>) -> Schema {
+        let mut implied = ImpliedSchema::new(keyspace.map(str::to_string)).with_definitions();
+        for statement in statements(cql) {
+            implied.apply(statement);
+        }
+        implied.schema
+    }
+}
+
+async fn read_keyspace(session: &Session, keyspace: &str) -> Result<KeyspaceSchema> {
+    let (replication, durable_writes): (HashMap<String, String>, Option<bool>) = session
+        .query_unpaged(
+            "SELECT replication, durable_writes FROM system_schema.keyspaces WHERE keyspace_name = ?",
+            (keyspace,),
+        )
+        .await?
+        .into_rows_result()?
+        .first_row()?;
+
+    let mut schema = KeyspaceSchema {
+        replication: replication.into_iter().collect(),
+        durable_writes: durable_writes.unwrap_or(true),
+        ..KeyspaceSchema::default()
+    };
+
+    let query_rows = session
+        .query_unpaged(
+            "SELECT type_name, field_names, field_types FROM system_schema.types WHERE keyspace_name = ?",
+            (keyspace,),
+        )
+        .await?
+        .into_rows_result()?;
+    for row in query_rows.rows()? {
+        let (name, names, types): (String, Vec<String>, Vec<String>) = row?;
+        let fields = names.into_iter().zip(types).collect();
+        schema.types.insert(name, UserType { fields });
+    }
+
+    let mut columns = read_columns(session, keyspace).await?;
+    let mut table = |name: &str, options| TableSchema {
+        columns: columns.remove(name).unwrap_or_default(),
+        options,
+        indexes: BTreeMap::new(),
+    };
+
+    for (name, options) in read_options(session, keyspace, "tables", "table_name").await? {
+        let table_schema = table(&name, options);
+        schema.tables.insert(name, table_schema);
+    }
+
+    let mut view_options = read_options(session, keyspace, "views", "view_name").await?;
+    let query_rows = session
+        .query_unpaged(
+            r#"
+                SELECT view_name, base_table_name, where_clause, include_all_columns
+                    FROM system_schema.views WHERE keyspace_name = ?
+            "#,
+            (keyspace,),
+        )
+        .await?
+        .into_rows_result()?;
+    for row in query_rows.rows()? {
+        let (name, base_table, where_clause, include_all_columns): (
+            String,
+            String,
+            Option<String>,
+            Option<bool>,
+        ) = row?;
+        let options = view_options.remove(&name).unwrap_or_default();
+        let view = ViewSchema {
+            base_table,
+            where_clause: where_clause.unwrap_or_default(),
+            include_all_columns: include_all_columns.unwrap_or(false),
+            table: table(&name, options),
+        };
+        schema.views.insert(name, view);
+    }
+
+    let query_rows = session
+        .query_unpaged(
+            "SELECT table_name, index_name, kind, options FROM system_schema.indexes WHERE keyspace_name = ?",
+            (keyspace,),
+        )
+        .await?
+        .into_rows_result()?;
+    for row in query_rows.rows()? {
+        let (table, name, kind, options): (String, String, String, HashMap<String, String>) = row?;
+        // Secondary indexes are backed by a view of their own
+        schema.views.remove(&format!("{}_index", name));
+        let index = Index {
+            target: options
+                .get("target")
+                .map(|t| index_target(t))
+                .unwrap_or_default(),
+            class: (kind == "CUSTOM")
+                .then(|| options.get("class_name").cloned())
+                .flatten(),
+        };
+        if let Some(table) = schema.tables.get_mut(&table) {
+            table.indexes.insert(name, index);
+        }
+    }
+
+    Ok(schema)
+}
+
+/// Returns the columns of every table and view of a keyspace, in [`TableSchema::columns`] order
+async fn read_columns(session: &Session, keyspace: &str) -> Result<HashMap<String, Vec<Column>>> {
+    let query_rows = session
+        .query_unpaged(
+            r#"
+                SELECT table_name, column_name, clustering_order, kind, position, type
+                    FROM system_schema.columns WHERE keyspace_name = ?
+            "#,
+            (keyspace,),
+        )
+        .await?
+        .into_rows_result()?;
+
+    let mut tables: HashMap<String, Vec<(i32, Column)>> = HashMap::new();
+    for row in query_rows.rows()? {
+        let (table, name, order, kind, position, cql_type): (
+            String,
+            String,
+            String,
+            String,
+            i32,
+            String,
+        ) = row?;
+        let kind = match kind.as_str() {
+            "partition_key" => ColumnKind::PartitionKey,
+            "clustering" => ColumnKind::Clustering,
+            "static" => ColumnKind::Static,
+            _ => ColumnKind::Regular,
+        };
+        tables.entry(table).or_default().push((
+            position,
+            Column {
+                name,
+                cql_type,
+                kind,
+                descending: order == "desc",
+            },
+        ));
+    }
+
+    Ok(tables
+        .into_iter()
+        .map(|(table, mut columns)| {
+            columns.sort_by(|(a_pos, a), (b_pos, b)| {
+                let key_position = |pos, column: &Column| match column.kind {
+                    ColumnKind::PartitionKey | ColumnKind::Clustering => pos,
+                    ColumnKind::Static | ColumnKind::Regular => 0,
+                };
+                (
+                    a.kind.min(ColumnKind::Static),
+                    key_position(*a_pos, a),
+                    &a.name,
+                )
+                    .cmp(&(
+                        b.kind.min(ColumnKind::Static),
+                        key_position(*b_pos, b),
+                        &b.name,
+                    ))
+            });
+            (
+                table,
+                columns.into_iter().map(|(_, column)| column).collect(),
+            )
+        })
+        .collect())
+}
+
+/// Returns the [`TABLE_OPTIONS`] of the tables or views of a keyspace as CQL
+/// literals, keyed by table and option name
+async fn read_options(
+    session: &Session,
+    keyspace: &str,
+    table: &str,
+    name_column: &str,
+) -> Result<BTreeMap<String, BTreeMap<String, String>>> {
+    let query_rows = session
+        .query_unpaged(
+            format!(
+                "SELECT {}, {} FROM system_schema.{} WHERE keyspace_name = ?",
+                name_column, TABLE_OPTIONS, table
+            ),
+            (keyspace,),
+        )
+        .await?
+        .into_rows_result()?;
+
+    let mut tables = BTreeMap::new();
+    for row in query_rows.rows()? {
+        let (name, caching, comment, compaction, compression, ttl, gc_grace): OptionsRow = row?;
+        let mut options = BTreeMap::new();
+        let maps = [
+            ("caching", caching),
+            ("compaction", compaction),
+            ("compression", compression),
+        ];
+        for (option, map) in maps {
+            if let Some(map) = map {
+                options.insert(option.to_string(), map_literal(map.into_iter().collect()));
+            }
+        }
+        if let Some(comment) = comment {
+            options.insert("comment".to_string(), string_literal(&comment));
+        }
+        for (option, value) in [
+            ("default_time_to_live", ttl),
+            ("gc_grace_seconds", gc_grace),
+        ] {
+            if let Some(value) = value {
+                options.insert(option.to_string(), value.to_string());
+            }
+        }
+        tables.insert(name, options);
+    }
+    Ok(tables)
+}
+
+/// Turns the target of a local index, `{"pk":["a"],"ck":["b"]}`, into CQL, `(a), b`
+fn index_target(target: &str) -> String {
+    let Ok(serde_json::Value::Object(local)) = serde_json::from_str(target) else {
+        return target.to_string();
+    };
+    let names = |key| {
+        local
+            .get(key)
+            .and_then(serde_json::Value::as_array)
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(serde_json::Value::as_str)
+                    .map(ident)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+    };
+    let mut parts = vec![format!("({})", names("pk").join(", "))];
+    parts.extend(names("ck"));
+    parts.join(", ")
+}
+
+/// Quotes an identifier unless it is lowercase and unreserved by its form
+pub(crate) fn ident(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+pub(crate) fn string_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Formats a map as a CQL literal, `class` first as `DESCRIBE` does
+pub(crate) fn map_literal(map: BTreeMap<String, String>) -> String {
+    let (class, rest): (Vec<_>, Vec<_>) = map.iter().partition(|(key, _)| *key == "class");
+    let entries: Vec<_> = class
+        .into_iter()
+        .chain(rest)
+        .map(|(key, value)| format!("{}: {}", string_literal(key), string_literal(value)))
+        .collect();
+    format!("{{{}}}", entries.join(", "))
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, keyspace)) in self.keyspaces.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write_keyspace(f, name, keyspace)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_keyspace(
+    f: &mut fmt::Formatter<'_>,
+    name: &str,
+    keyspace: &KeyspaceSchema,
+) -> fmt::Result {
+    writeln!(f, "{}", create_keyspace(name, keyspace, false))?;
+    for (type_name, user_type) in types_in_dependency_order(&keyspace.types) {
+        writeln!(f, "\n{}", create_type(name, type_name, user_type, false))?;
+    }
+    for (table_name, table) in &keyspace.tables {
+        writeln!(f, "\n{}", create_table(name, table_name, table, false))?;
+        for (index, definition) in &table.indexes {
+            writeln!(
+                f,
+                "\n{}",
+                create_index(name, table_name, index, definition, false)
+            )?;
+        }
+    }
+    for (view_name, view) in &keyspace.views {
+        writeln!(f, "\n{}", create_view(name, view_name, view, false))?;
+    }
+    Ok(())
+}
+
+fn if_not_exists(if_not_exists: bool) -> &'static str {
+    if if_not_exists {
+        " IF NOT EXISTS"
+    } else {
+        ""
+    }
+}
+
+pub(crate) fn create_keyspace(name: &str, keyspace: &KeyspaceSchema, guarded: bool) -> String {
+    format!(
+        "CREATE KEYSPACE{} {} WITH replication = {} AND durable_writes = {};",
+        if_not_exists(guarded),
+        ident(name),
+        map_literal(keyspace.replication.clone()),
+        keyspace.durable_writes
+    )
+}
+
+pub(crate) fn create_type(
+    keyspace: &str,
+    name: &str,
+    user_type: &UserType,
+    guarded: bool,
+) -> String {
+    let fields: Vec<_> = user_type
+        .fields
+        .iter()
+        .map(|(field, cql_type)| format!("    {} {}", ident(field), cql_type))
+        .collect();
+    format!(
+        "CREATE TYPE{} {}.{} (\n{}\n);",
+        if_not_exists(guarded),
+        ident(keyspace),
+        ident(name),
+        fields.join(",\n")
+    )
+}
+
+pub(crate) fn create_table(
+    keyspace: &str,
+    name: &str,
+    table: &TableSchema,
+    guarded: bool,
+) -> String {
+    let mut cql = format!(
+        "CREATE TABLE{} {}.{} (\n",
+        if_not_exists(guarded),
+        ident(keyspace),
+        ident(name)
+    );
+    for column in &table.columns {
+        let is_static = if column.kind == ColumnKind::Static {
+            " static"
+        } else {
+            ""
+        };
+        cql.push_str(&format!(
+            "    {} {}{},\n",
+            ident(&column.name),
+            column.cql_type,
+            is_static
+        ));
+    }
+    cql.push_str(&format!("    PRIMARY KEY ({})\n)", primary_key(table)));
+    cql.push_str(&options(table, " WITH"));
+    cql.push(';');
+    cql
+}
+
+pub(crate) fn create_index(
+    keyspace: &str,
+    table: &str,
+    name: &str,
+    index: &Index,
+    guarded: bool,
+) -> String {
+    let (custom, using) = match &index.class {
+        Some(class) => (" CUSTOM", format!(" USING {}", string_literal(class))),
+        None => ("", String::new()),
+    };
+    format!(
+        "CREATE{} INDEX{} {} ON {}.{} ({}){};",
+        custom,
+        if_not_exists(guarded),
+        ident(name),
+        ident(keyspace),
+        ident(table),
+        index.target,
+        using
+    )
+}
+
+pub(crate) fn create_view(keyspace: &str, name: &str, view: &ViewSchema, guarded: bool) -> String {
+    let ks = ident(keyspace);
+    let columns = if view.include_all_columns {
+        "*".to_string()
+    } else {
+        let names: Vec<_> = view.table.columns.iter().map(|c| ident(&c.name)).collect();
+        names.join(", ")
+    };
+    format!(
+        "CREATE MATERIALIZED VIEW{} {}.{} AS\n    SELECT {}\n    FROM {}.{}\n    WHERE {}\n    PRIMARY KEY ({}){};",
+        if_not_exists(guarded),
+        ks,
+        ident(name),
+        columns,
+        ks,
+        ident(&view.base_table),
+        view.where_clause,
+        primary_key(&view.table),
+        options(&view.table, "\n    WITH")
+    )
+}
+
+/// Formats the primary key of a table, `(a, b), c` or `a, c`
+fn primary_key(table: &TableSchema) -> String {
+    let names = |kind| {
+        table
+            .columns
+            .iter()
+            .filter(move |c| c.kind == kind)
+            .map(|c| ident(&c.name))
+    };
+    let partition_key: Vec<_> = names(ColumnKind::PartitionKey).collect();
+    let partition_key = match partition_key.as_slice() {
+        [single] => single.clone(),
+        _ => format!("({})", partition_key.join(", ")),
+    };
+    std::iter::once(partition_key)
+        .chain(names(ColumnKind::Clustering))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Formats the clustering order and options of a table as `WITH ... AND ...`,
+/// starting with `with`, or nothing if there are none
+fn options(table: &TableSchema, with: &str) -> String {
+    let mut clauses = Vec::new();
+    let clustering: Vec<_> = table
+        .columns
+        .iter()
+        .filter(|c| c.kind == ColumnKind::Clustering)
+        .map(|c| {
+            format!(
+                "{} {}",
+                ident(&c.name),
+                if c.descending { "DESC" } else { "ASC" }
+            )
+        })
+        .collect();
+    if !clustering.is_empty() {
+        clauses.push(format!("CLUSTERING ORDER BY ({})", clustering.join(", ")));
+    }
+    clauses.extend(
+        table
+            .options
+            .iter()
+            .map(|(option, value)| format!("{} = {}", option, value)),
+    );
+
+    let mut cql = String::new();
+    for (i, clause) in clauses.iter().enumerate() {
+        if i == 0 {
+            cql.push_str(&format!("{} {}", with, clause));
+        } else {
+            cql.push_str(&format!("\n    AND {}", clause));
+        }
+    }
+    cql
+}
+
+/// Orders types by name, but after the types their fields use
+pub(crate) fn types_in_dependency_order(
+    types: &BTreeMap<String, UserType>,
+) -> Vec<(&String, &UserType)> {
+    let uses = |user_type: &UserType, name: &str| {
+        user_type.fields.iter().any(|(_, cql_type)| {
+            cql_type
+                .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .any(|word| word == name)
+        })
+    };
+
+    let mut remaining: Vec<_> = types.iter().collect();
+    let mut ordered = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let next = remaining
+            .iter()
+            .position(|(name, user_type)| {
+                !remaining
+                    .iter()
+                    .any(|(other, _)| other != name && uses(user_type, other))
+            })
+            // A cycle can't be created, but never loop on one
+            .unwrap_or(0);
+        ordered.push(remaining.remove(next));
+    }
+    ordered
+}
@@ -0,0 +1,986 @@
+use crate::error::{Error, Result};
+use crate::schema::{ident, string_literal};
+use scylla::frame::response::result::{ColumnSpec, ColumnType, CqlValue};
+use scylla::frame::value::{
+    CqlDate, CqlDecimal, CqlDuration, CqlTime, CqlTimestamp, CqlTimeuuid, CqlVarint,
+};
+use scylla::Session;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The outcome of [`Migrator::seed_csv`](crate::Migrator::seed_csv)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Seed {
+    /// Rows inserted
+    pub rows: usize,
+    /// Batches the rows were sent in
+    pub batches: usize,
+}
+
+/// A record of a CSV document with the line it starts on
+///
+/// Empty unquoted fields are `None`, to be inserted as null.
+pub(crate) struct Record {
+    pub line: usize,
+    pub fields: Vec<Option<String>>,
+}
+
+/// Splits `csv` into records as described in RFC 4180
+///
+/// Fields may be quoted with `"`, which allows separators, line breaks and
+/// doubled quotes within them. Blank lines are skipped.
+pub(crate) fn records(csv: &str) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut chars = csv.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        loop {
+            let mut field = String::new();
+            let mut quoted = false;
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => {
+                            return Err(Error::Seed {
+                                line: start,
+                                reason: "unterminated quoted field".to_string(),
+                            })
+                        }
+                    }
+                }
+            }
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c == '\n' || c == '\r' {
+                    break;
+                }
+                if quoted {
+                    return Err(Error::Seed {
+                        line,
+                        reason: "unexpected characters after a quoted field".to_string(),
+                    });
+                }
+                field.push(c);
+                chars.next();
+            }
+            fields.push((quoted || !field.is_empty()).then_some(field));
+
+            match chars.next() {
+                Some(',') => continue,
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                _ => {}
+            }
+            line += 1;
+            break;
+        }
+        if !matches!(fields.as_slice(), [None]) {
+            records.push(Record {
+                line: start,
+                fields,
+            });
+        }
+    }
+    Ok(records)
+}
+
+/// Reads the types of the columns of `keyspace.table`, by column name
+pub(crate) async fn column_types(
+    session: &Session,
+    keyspace: &str,
+    table: &str,
+) -> Result<HashMap<String, String>> {
+    let query_rows = session
+        .query_unpaged(
+            "SELECT column_name, type FROM system_schema.columns WHERE keyspace_name = ? AND table_name = ?",
+            (keyspace, table),
+        )
+        .await?
+        .into_rows_result()?;
+    let mut columns = HashMap::new();
+    for row in query_rows.rows()? {
+        let (name, cql_type): (String, String) = row?;
+        columns.insert(name, cql_type);
+    }
+    if columns.is_empty() {
+        return Err(Error::UnknownTable(format!("{}.{}", keyspace, table)));
+    }
+    Ok(columns)
+}
+
+/// Converts the fields of a record to values of the bind markers of a
+/// prepared INSERT statement
+pub(crate) fn values(columns: &[ColumnSpec<'_>], record: &Record) -> Result<Vec<Option<CqlValue>>> {
+    if record.fields.len() != columns.len() {
+        return Err(Error::Seed {
+            line: record.line,
+            reason: format!(
+                "expected {} fields, found {}",
+                columns.len(),
+                record.fields.len()
+            ),
+        });
+    }
+    columns
+        .iter()
+        .zip(&record.fields)
+        .map(|(column, field)| {
+            value(column.typ(), field.as_deref()).map_err(|reason| Error::Seed {
+                line: record.line,
+                reason: format!("column {}: {}", column.name(), reason),
+            })
+        })
+        .collect()
+}
+
+/// Converts a CSV field to a value of `typ`
+///
+/// Collections, tuples and user-defined types are written as their CQL
+/// literal, e.g. `{'admin', 'ops'}`, and converted element by element.
+pub(crate) fn value(typ: &ColumnType<'_>, field: Option<&str>) -> Result<Option<CqlValue>, String> {
+    let Some(field) = field else {
+        return Ok(None);
+    };
+    match typ {
+        ColumnType::Ascii if !field.is_ascii() => Err(format!("{} is not a valid ascii", field)),
+        ColumnType::Ascii => Ok(Some(CqlValue::Ascii(field.to_string()))),
+        ColumnType::Text => Ok(Some(CqlValue::Text(field.to_string()))),
+        _ if field.trim().is_empty() => Ok(None),
+        ColumnType::List(_)
+        | ColumnType::Set(_)
+        | ColumnType::Map(_, _)
+        | ColumnType::Tuple(_)
+        | ColumnType::UserDefinedType { .. } => {
+            let mut parser = Parser {
+                chars: field.chars().peekable(),
+            };
+            let term = parser.term()?;
+            parser.skip_whitespace();
+            if parser.chars.peek().is_some() {
+                return Err(format!("unexpected characters after {}", field.trim()));
+            }
+            term_value(typ, term)
+        }
+        _ => scalar(typ, field.trim()).map(Some),
+    }
+}
+
+/// A term of a CQL literal
+enum Term {
+    /// A quoted string, without its quotes
+    String(String),
+    /// A number, UUID, blob, duration, boolean, null or field name
+    Constant(String),
+    /// `[...]`
+    List(Vec<Term>),
+    /// `{...}`, of single terms in sets and of pairs in maps and user-defined
+    /// types
+    Braces(Vec<(Term, Option<Term>)>),
+    /// `(...)`
+    Tuple(Vec<Term>),
+}
+
+impl Term {
+    fn describe(&self) -> String {
+        match self {
+            Term::String(value) => string_literal(value),
+            Term::Constant(value) => value.clone(),
+            Term::List(_) => "a list".to_string(),
+            Term::Braces(_) => "a set, map or user-defined type".to_string(),
+            Term::Tuple(_) => "a tuple".to_string(),
+        }
+    }
+}
+
+/// Splits a CQL literal into its terms
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn term(&mut self) -> Result<Term, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('\'') => {
+                self.chars.next();
+                let mut value = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some('\'') if self.chars.next_if_eq(&'\'').is_some() => value.push('\''),
+                        Some('\'') => return Ok(Term::String(value)),
+                        Some(c) => value.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+            }
+            Some('[') => {
+                self.chars.next();
+                Ok(Term::List(self.terms(']')?))
+            }
+            Some('(') => {
+                self.chars.next();
+                Ok(Term::Tuple(self.terms(')')?))
+            }
+            Some('{') => {
+                self.chars.next();
+                let mut entries = Vec::new();
+                self.skip_whitespace();
+                if self.chars.next_if_eq(&'}').is_some() {
+                    return Ok(Term::Braces(entries));
+                }
+                loop {
+                    let key = self.term()?;
+                    self.skip_whitespace();
+                    let value = match self.chars.next_if_eq(&':') {
+                        Some(_) => Some(self.term()?),
+                        None => None,
+                    };
+                    entries.push((key, value));
+                    if self.separator('}')? {
+                        return Ok(Term::Braces(entries));
+                    }
+                }
+            }
+            _ => {
+                let mut value = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| !c.is_whitespace() && !",:[](){}'".contains(*c))
+                {
+                    value.push(c);
+                }
+                if value.is_empty() {
+                    return Err(match self.chars.peek() {
+                        Some(c) => format!("unexpected {:?}", c),
+                        None => "unexpected end of literal".to_string(),
+                    });
+                }
+                Ok(Term::Constant(value))
+            }
+        }
+    }
+
+    /// Reads the comma-separated terms up to `close`
+    fn terms(&mut self, close: char) -> Result<Vec<Term>, String> {
+        let mut terms = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&close).is_some() {
+            return Ok(terms);
+        }
+        loop {
+            terms.push(self.term()?);
+            if self.separator(close)? {
+                return Ok(terms);
+            }
+        }
+    }
+
+    /// Reads a comma, or `close` and returns true
+    fn separator(&mut self, close: char) -> Result<bool, String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(',') => Ok(false),
+            Some(c) if c == close => Ok(true),
+            Some(c) => Err(format!("expected ',' or {:?}, found {:?}", close, c)),
+            None => Err(format!("expected {:?}", close)),
+        }
+    }
+}
+
+/// Converts a term of a CQL literal to a value of `typ`
+fn term_value(typ: &ColumnType<'_>, term: Term) -> Result<Option<CqlValue>, String> {
+    let element = |typ: &ColumnType<'_>, term: Term| {
+        term_value(typ, term)?.ok_or_else(|| "collections can't contain null".to_string())
+    };
+    match (typ, term) {
+        (_, Term::Constant(value)) if value.eq_ignore_ascii_case("null") => Ok(None),
+        (ColumnType::List(typ), Term::List(terms)) => Ok(Some(CqlValue::List(
+            terms
+                .into_iter()
+                .map(|term| element(typ, term))
+                .collect::<Result<_, _>>()?,
+        ))),
+        (ColumnType::Set(typ), Term::Braces(entries))
+            if entries.iter().all(|(_, value)| value.is_none()) =>
+        {
+            Ok(Some(CqlValue::Set(
+                entries
+                    .into_iter()
+                    .map(|(term, _)| element(typ, term))
+                    .collect::<Result<_, _>>()?,
+            )))
+        }
+        (ColumnType::Map(key, value), Term::Braces(entries))
+            if entries.iter().all(|(_, value)| value.is_some()) =>
+        {
+            let mut pairs = Vec::new();
+            for (k, v) in entries {
+                pairs.push((element(key, k)?, element(value, v.unwrap())?));
+            }
+            Ok(Some(CqlValue::Map(pairs)))
+        }
+        (ColumnType::Tuple(types), Term::Tuple(terms)) if types.len() == terms.len() => {
+            Ok(Some(CqlValue::Tuple(
+                types
+                    .iter()
+                    .zip(terms)
+                    .map(|(typ, term)| term_value(typ, term))
+                    .collect::<Result<_, _>>()?,
+            )))
+        }
+        (ColumnType::Tuple(types), Term::Tuple(terms)) => Err(format!(
+            "expected a tuple of {} elements, found {}",
+            types.len(),
+            terms.len()
+        )),
+        (
+            ColumnType::UserDefinedType {
+                type_name,
+                keyspace,
+                field_types,
+            },
+            Term::Braces(entries),
+        ) => {
+            let mut given = HashMap::new();
+            for (name, value) in entries {
+                let (Term::Constant(name) | Term::String(name)) = name else {
+                    return Err(format!("expected a field name, found {}", name.describe()));
+                };
+                let Some(value) = value else {
+                    return Err(format!("expected a value for field {}", name));
+                };
+                given.insert(name, value);
+            }
+            let mut fields = Vec::new();
+            for (name, typ) in field_types {
+                let value = match given.remove(name.as_ref()) {
+                    Some(term) => term_value(typ, term)?,
+                    None => None,
+                };
+                fields.push((name.to_string(), value));
+            }
+            if let Some(name) = given.keys().min() {
+                return Err(format!("{} has no field {}", type_name, name));
+            }
+            Ok(Some(CqlValue::UserDefinedType {
+                keyspace: keyspace.to_string(),
+                type_name: type_name.to_string(),
+                fields,
+            }))
+        }
+        (
+            ColumnType::List(_)
+            | ColumnType::Set(_)
+            | ColumnType::Map(_, _)
+            | ColumnType::Tuple(_)
+            | ColumnType::UserDefinedType { .. },
+            term,
+        ) => Err(format!(
+            "expected a {} literal, found {}",
+            type_name(typ),
+            term.describe()
+        )),
+        (_, Term::String(value) | Term::Constant(value)) => scalar(typ, &value).map(Some),
+        (_, term) => Err(format!(
+            "expected a {}, found {}",
+            type_name(typ),
+            term.describe()
+        )),
+    }
+}
+
+/// Returns the CQL name of `typ`, without its arguments
+pub(crate) fn type_name(typ: &ColumnType<'_>) -> String {
+    match typ {
+        ColumnType::Custom(name) => name.to_string(),
+        ColumnType::Ascii => "ascii".to_string(),
+        ColumnType::Boolean => "boolean".to_string(),
+        ColumnType::Blob => "blob".to_string(),
+        ColumnType::Counter => "counter".to_string(),
+        ColumnType::Date => "date".to_string(),
+        ColumnType::Decimal => "decimal".to_string(),
+        ColumnType::Double => "double".to_string(),
+        ColumnType::Duration => "duration".to_string(),
+        ColumnType::Float => "float".to_string(),
+        ColumnType::Int => "int".to_string(),
+        ColumnType::BigInt => "bigint".to_string(),
+        ColumnType::Text => "text".to_string(),
+        ColumnType::Timestamp => "timestamp".to_string(),
+        ColumnType::Inet => "inet".to_string(),
+        ColumnType::List(_) => "list".to_string(),
+        ColumnType::Map(_, _) => "map".to_string(),
+        ColumnType::Set(_) => "set".to_string(),
+        ColumnType::UserDefinedType { type_name, .. } => type_name.to_string(),
+        ColumnType::SmallInt => "smallint".to_string(),
+        ColumnType::TinyInt => "tinyint".to_string(),
+        ColumnType::Time => "time".to_string(),
+        ColumnType::Timeuuid => "timeuuid".to_string(),
+        ColumnType::Tuple(_) => "tuple".to_string(),
+        ColumnType::Uuid => "uuid".to_string(),
+        ColumnType::Varint => "varint".to_string(),
+    }
+}
+
+/// Converts the text of a value of a scalar type
+fn scalar(typ: &ColumnType<'_>, value: &str) -> Result<CqlValue, String> {
+    let invalid = || format!("{} is not a valid {}", value, type_name(typ));
+    let converted = match typ {
+        ColumnType::Ascii if value.is_ascii() => Some(CqlValue::Ascii(value.to_string())),
+        ColumnType::Text => Some(CqlValue::Text(value.to_string())),
+        ColumnType::Boolean => match value.to_ascii_lowercase().as_str() {
+            "true" | "1" => Some(CqlValue::Boolean(true)),
+            "false" | "0" => Some(CqlValue::Boolean(false)),
+            _ => None,
+        },
+        ColumnType::TinyInt => value.parse().ok().map(CqlValue::TinyInt),
+        ColumnType::SmallInt => value.parse().ok().map(CqlValue::SmallInt),
+        ColumnType::Int => value.parse().ok().map(CqlValue::Int),
+        ColumnType::BigInt => value.parse().ok().map(CqlValue::BigInt),
+        ColumnType::Varint => {
+            varint(value).map(|bytes| CqlValue::Varint(CqlVarint::from_signed_bytes_be(bytes)))
+        }
+        ColumnType::Float => value.parse().ok().map(CqlValue::Float),
+        ColumnType::Double => value.parse().ok().map(CqlValue::Double),
+        ColumnType::Decimal => decimal(value).map(|(bytes, scale)| {
+            CqlValue::Decimal(CqlDecimal::from_signed_be_bytes_and_exponent(bytes, scale))
+        }),
+        ColumnType::Uuid => Uuid::parse_str(value).ok().map(CqlValue::Uuid),
+        ColumnType::Timeuuid => Uuid::parse_str(value)
+            .ok()
+            .map(|uuid| CqlValue::Timeuuid(CqlTimeuuid::from(uuid))),
+        // Milliseconds since the epoch, or a date and time like 2024-01-31 12:00:00+0000
+        ColumnType::Timestamp => match value.parse() {
+            Ok(millis) => Some(CqlValue::Timestamp(CqlTimestamp(millis))),
+            Err(_) => timestamp(value).map(|millis| CqlValue::Timestamp(CqlTimestamp(millis))),
+        },
+        // Days since 2^31 days before the epoch, or a date like 2024-01-31
+        ColumnType::Date => match value.parse() {
+            Ok(days) => Some(CqlValue::Date(CqlDate(days))),
+            Err(_) => date(value).and_then(|date| {
+                let days = i64::from(date.to_julian_day() - UNIX_EPOCH_JULIAN_DAY) + (1 << 31);
+                u32::try_from(days)
+                    .ok()
+                    .map(|days| CqlValue::Date(CqlDate(days)))
+            }),
+        },
+        // Nanoseconds since midnight, or a time like 12:30:00.5
+        ColumnType::Time => match value.parse() {
+            Ok(nanos) => Some(CqlValue::Time(CqlTime(nanos))),
+            Err(_) => time_of_day(value).map(|time| {
+                let (hours, minutes, seconds, nanos) = time.as_hms_nano();
+                let seconds = hours as i64 * 3600 + minutes as i64 * 60 + seconds as i64;
+                CqlValue::Time(CqlTime(seconds * 1_000_000_000 + nanos as i64))
+            }),
+        },
+        ColumnType::Inet => value.parse().ok().map(CqlValue::Inet),
+        ColumnType::Blob => blob(value).map(CqlValue::Blob),
+        ColumnType::Duration => duration(value).map(CqlValue::Duration),
+        ColumnType::Counter => return Err("counter columns can't be inserted".to_string()),
+        ColumnType::Custom(name) => return Err(format!("{} columns can't be seeded", name)),
+        _ => None,
+    };
+    converted.ok_or_else(invalid)
+}
+
+/// The Julian day of 1970-01-01
+const UNIX_EPOCH_JULIAN_DAY: i32 = 2_440_588;
+
+/// Returns the big-endian two's complement bytes of a decimal integer
+fn varint(value: &str) -> Option<Vec<u8>> {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut bytes = vec![0u8];
+    for digit in digits.bytes() {
+        let mut carry = u32::from(digit - b'0');
+        for byte in bytes.iter_mut().rev() {
+            let product = u32::from(*byte) * 10 + carry;
+            *byte = product as u8;
+            carry = product >> 8;
+        }
+        while carry > 0 {
+            bytes.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+    // Keep a leading zero for the sign of positive values
+    if bytes[0] & 0x80 != 0 {
+        bytes.insert(0, 0);
+    }
+    if negative {
+        for byte in bytes.iter_mut() {
+            *byte = !*byte;
+        }
+        for byte in bytes.iter_mut().rev() {
+            *byte = byte.wrapping_add(1);
+            if *byte != 0 {
+                break;
+            }
+        }
+    }
+
+    let redundant = bytes
+        .windows(2)
+        .take_while(|pair| {
+            (pair[0] == 0 && pair[1] & 0x80 == 0) || (pair[0] == 0xff && pair[1] & 0x80 != 0)
+        })
+        .count();
+    bytes.drain(..redundant);
+    Some(bytes)
+}
+
+/// Returns the unscaled bytes and scale of a decimal number like -12.50 or
+/// 1.5e3
+fn decimal(value: &str) -> Option<(Vec<u8>, i32)> {
+    let (mantissa, exponent) = match value.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().ok()?),
+        None => (value, 0),
+    };
+    let (sign, mantissa) = match mantissa.strip_prefix(['-', '+']) {
+        Some(unsigned) => (&mantissa[..1], unsigned),
+        None => ("", mantissa),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if integer.is_empty() && fraction.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let unscaled = format!("{}{}{}", sign, integer, fraction);
+    let scale = i32::try_from(fraction.len()).ok()?.checked_sub(exponent)?;
+    Some((varint(&unscaled)?, scale))
+}
+
+/// Parses a date like 2024-01-31
+fn date(value: &str) -> Option<time::Date> {
+    let mut parts = value.splitn(3, '-');
+    if value.starts_with('-') {
+        return None;
+    }
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse::<u8>().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    time::Date::from_calendar_date(year, month.try_into().ok()?, day).ok()
+}
+
+/// Parses a time of day like 12:30, 12:30:00 or 12:30:00.123456789
+fn time_of_day(value: &str) -> Option<time::Time> {
+    let (value, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let mut parts = value.split(':');
+    let hour = parts.next()?.parse().ok()?;
+    let minute = parts.next()?.parse().ok()?;
+    let second = parts.next().map_or(Some(0), |second| second.parse().ok())?;
+    if parts.next().is_some() || fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let nanos = format!("{:0<9}", fraction).parse().ok()?;
+    time::Time::from_hms_nano(hour, minute, second, nanos).ok()
+}
+
+/// Parses a date and time like 2024-01-31 12:00:00+0000 into milliseconds
+/// since the epoch
+///
+/// The date may be followed by a time, separated by a space or `T`, and the
+/// time by an offset (`Z`, `+hh`, `+hhmm` or `+hh:mm`). Without an offset the
+/// time is in UTC.
+fn timestamp(value: &str) -> Option<i64> {
+    let (date_part, rest) = match value.split_once(['T', ' ']) {
+        Some((date_part, rest)) => (date_part, rest.trim()),
+        None => (value, ""),
+    };
+    let date = date(date_part)?;
+    let (time_part, zone) = match rest.find(['Z', 'z', '+', '-']) {
+        Some(index) => (rest[..index].trim(), &rest[index..]),
+        None => (rest, ""),
+    };
+    let time = match time_part {
+        "" if !zone.is_empty() => return None,
+        "" => time::Time::MIDNIGHT,
+        time_part => time_of_day(time_part)?,
+    };
+    let offset = match zone {
+        "" | "Z" | "z" => time::UtcOffset::UTC,
+        zone => {
+            let (sign, digits) = zone.split_at(1);
+            let digits = digits.replace(':', "");
+            if !digits.bytes().all(|b| b.is_ascii_digit()) || ![2, 4].contains(&digits.len()) {
+                return None;
+            }
+            let hours: i8 = digits[..2].parse().ok()?;
+            let minutes: i8 = digits.get(2..).map_or(Some(0), |m| m.parse().ok())?;
+            let sign = if sign == "-" { -1 } else { 1 };
+            time::UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()?
+        }
+    };
+    let nanos = time::PrimitiveDateTime::new(date, time)
+        .assume_offset(offset)
+        .unix_timestamp_nanos();
+    i64::try_from(nanos / 1_000_000).ok()
+}
+
+/// Parses the hex digits of a blob like 0xcafe
+fn blob(value: &str) -> Option<Vec<u8>> {
+    let digits = value.strip_prefix("0x")?;
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses a duration of units like 1h30m or -2mo1w
+///
+/// The units are `y`, `mo`, `w`, `d`, `h`, `m`, `s`, `ms`, `us` or `µs`, and
+/// `ns`.
+fn duration(value: &str) -> Option<CqlDuration> {
+    let (sign, mut rest) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value),
+    };
+    if rest.is_empty() {
+        return None;
+    }
+    let (mut months, mut days, mut nanoseconds) = (0i32, 0i32, 0i64);
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let amount: i64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit = rest[..unit_len].to_ascii_lowercase();
+        rest = &rest[unit_len..];
+        let scaled = |by: i64| amount.checked_mul(by);
+        let small = |by: i64| scaled(by).and_then(|n| i32::try_from(n).ok());
+        match unit.as_str() {
+            "y" => months = months.checked_add(small(12)?)?,
+            "mo" => months = months.checked_add(small(1)?)?,
+            "w" => days = days.checked_add(small(7)?)?,
+            "d" => days = days.checked_add(small(1)?)?,
+            "h" => nanoseconds = nanoseconds.checked_add(scaled(3_600_000_000_000)?)?,
+            "m" => nanoseconds = nanoseconds.checked_add(scaled(60_000_000_000)?)?,
+            "s" => nanoseconds = nanoseconds.checked_add(scaled(1_000_000_000)?)?,
+            "ms" => nanoseconds = nanoseconds.checked_add(scaled(1_000_000)?)?,
+            "us" | "µs" => nanoseconds = nanoseconds.checked_add(scaled(1_000)?)?,
+            "ns" => nanoseconds = nanoseconds.checked_add(scaled(1)?)?,
+            _ => return None,
+        }
+    }
+    Some(CqlDuration {
+        months: sign * months,
+        days: sign * days,
+        nanoseconds: sign as i64 * nanoseconds,
+    })
+}
+
+/// Returns an INSERT statement with a bind marker for each of `columns`
+pub(crate) fn insert_statement(
+    keyspace: &str,
+    table: &str,
+    columns: &[&str],
+    if_not_exists: bool,
+) -> String {
+    let names: Vec<_> = columns.iter().map(|name| ident(name)).collect();
+    format!(
+        "INSERT INTO {}.{} ({}) VALUES ({}){}",
+        ident(keyspace),
+        ident(table),
+        names.join(", "),
+        vec!["?"; columns.len()].join(", "),
+        if if_not_exists { " IF NOT EXISTS" } else { "" }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scylla::frame::response::result::TableSpec;
+    use std::borrow::Cow;
+
+    fn fields(csv: &str) -> Vec<(usize, Vec<Option<String>>)> {
+        records(csv)
+            .unwrap()
+            .into_iter()
+            .map(|record| (record.line, record.fields))
+            .collect()
+    }
+
+    fn field(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn splits_records_and_fields() {
+        assert_eq!(
+            fields("id,name\n1,\n\n2,\"\"\n"),
+            vec![
+                (1, vec![field("id"), field("name")]),
+                (2, vec![field("1"), None]),
+                (4, vec![field("2"), field("")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_line_breaks_and_doubled_quotes_in_quoted_fields() {
+        assert_eq!(
+            fields("\"a\nb\",\"say \"\"hi\"\"\"\n2,x"),
+            vec![
+                (1, vec![field("a\nb"), field("say \"hi\"")]),
+                (3, vec![field("2"), field("x")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_records_at_crlf() {
+        assert_eq!(
+            fields("id,name\r\n1,\"a\r\nb\"\r\n"),
+            vec![
+                (1, vec![field("id"), field("name")]),
+                (2, vec![field("1"), field("a\r\nb")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_quoted_fields() {
+        let reason = |csv| match records(csv) {
+            Err(Error::Seed { line, reason }) => (line, reason),
+            _ => panic!("{:?} should be rejected", csv),
+        };
+        assert_eq!(
+            reason("id\n\"open\nstill open"),
+            (2, "unterminated quoted field".to_string())
+        );
+        assert_eq!(
+            reason("\"a\"b,c"),
+            (1, "unexpected characters after a quoted field".to_string())
+        );
+    }
+
+    #[test]
+    fn converts_scalars() {
+        let value = |typ, field| value(&typ, Some(field)).unwrap();
+        assert_eq!(value(ColumnType::Int, " 42 "), Some(CqlValue::Int(42)));
+        assert_eq!(
+            value(ColumnType::Text, " a "),
+            Some(CqlValue::Text(" a ".into()))
+        );
+        assert_eq!(
+            value(ColumnType::Text, ""),
+            Some(CqlValue::Text(String::new()))
+        );
+        assert_eq!(value(ColumnType::BigInt, ""), None);
+        assert_eq!(
+            value(ColumnType::Boolean, "1"),
+            Some(CqlValue::Boolean(true))
+        );
+        assert_eq!(
+            value(ColumnType::Timestamp, "2024-01-31 12:00:00+0100"),
+            Some(CqlValue::Timestamp(CqlTimestamp(1_706_698_800_000)))
+        );
+        assert_eq!(
+            value(ColumnType::Timestamp, "2024-01-31T11:00:00.5Z"),
+            Some(CqlValue::Timestamp(CqlTimestamp(1_706_698_800_500)))
+        );
+        assert_eq!(
+            value(ColumnType::Date, "1970-01-02"),
+            Some(CqlValue::Date(CqlDate((1 << 31) + 1)))
+        );
+        assert_eq!(
+            value(ColumnType::Time, "00:01:00.25"),
+            Some(CqlValue::Time(CqlTime(60_250_000_000)))
+        );
+        assert_eq!(
+            value(ColumnType::Duration, "-1y2d1h30m"),
+            Some(CqlValue::Duration(CqlDuration {
+                months: -12,
+                days: -2,
+                nanoseconds: -5_400_000_000_000,
+            }))
+        );
+        assert_eq!(
+            value(ColumnType::Blob, "0xcafe"),
+            Some(CqlValue::Blob(vec![0xca, 0xfe]))
+        );
+        assert_eq!(
+            value(ColumnType::Decimal, "-12.50"),
+            Some(CqlValue::Decimal(
+                CqlDecimal::from_signed_be_bytes_and_exponent(vec![0xfb, 0x1e], 2)
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_scalars() {
+        let reason = |typ, field| value(&typ, Some(field)).unwrap_err();
+        assert_eq!(
+            reason(ColumnType::TinyInt, "300"),
+            "300 is not a valid tinyint"
+        );
+        assert_eq!(reason(ColumnType::Uuid, "x"), "x is not a valid uuid");
+        assert_eq!(
+            reason(ColumnType::Blob, "0xabc"),
+            "0xabc is not a valid blob"
+        );
+        assert_eq!(
+            reason(ColumnType::Timestamp, "2024-02-30"),
+            "2024-02-30 is not a valid timestamp"
+        );
+        assert_eq!(
+            reason(ColumnType::Counter, "1"),
+            "counter columns can't be inserted"
+        );
+    }
+
+    #[test]
+    fn encodes_varints_in_twos_complement() {
+        let cases: [(&str, &[u8]); 8] = [
+            ("0", &[0x00]),
+            ("127", &[0x7f]),
+            ("128", &[0x00, 0x80]),
+            ("+256", &[0x01, 0x00]),
+            ("-1", &[0xff]),
+            ("-128", &[0x80]),
+            ("-129", &[0xff, 0x7f]),
+            ("-0", &[0x00]),
+        ];
+        for (value, bytes) in cases {
+            assert_eq!(varint(value).as_deref(), Some(bytes), "{}", value);
+        }
+        assert_eq!(
+            varint("18446744073709551616"),
+            Some(vec![0x01, 0, 0, 0, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(varint("1e3"), None);
+        assert_eq!(varint("-"), None);
+    }
+
+    #[test]
+    fn converts_collection_literals() {
+        let list = ColumnType::List(Box::new(ColumnType::Int));
+        assert_eq!(
+            value(&list, Some("[1, 2]")).unwrap(),
+            Some(CqlValue::List(vec![CqlValue::Int(1), CqlValue::Int(2)]))
+        );
+        let set = ColumnType::Set(Box::new(ColumnType::Text));
+        assert_eq!(
+            value(&set, Some("{'admin', 'it''s'}")).unwrap(),
+            Some(CqlValue::Set(vec![
+                CqlValue::Text("admin".into()),
+                CqlValue::Text("it's".into())
+            ]))
+        );
+        let map = ColumnType::Map(Box::new(ColumnType::Text), Box::new(ColumnType::BigInt));
+        assert_eq!(
+            value(&map, Some("{'a': 1}")).unwrap(),
+            Some(CqlValue::Map(vec![(
+                CqlValue::Text("a".into()),
+                CqlValue::BigInt(1)
+            )]))
+        );
+        let tuple = ColumnType::Tuple(vec![ColumnType::Int, ColumnType::Text]);
+        assert_eq!(
+            value(&tuple, Some("(1, null)")).unwrap(),
+            Some(CqlValue::Tuple(vec![Some(CqlValue::Int(1)), None]))
+        );
+        assert_eq!(
+            value(&list, Some("{1}")).unwrap_err(),
+            "expected a list literal, found a set, map or user-defined type"
+        );
+        assert_eq!(
+            value(&list, Some("[1, null]")).unwrap_err(),
+            "collections can't contain null"
+        );
+        assert_eq!(
+            value(&tuple, Some("(1)")).unwrap_err(),
+            "expected a tuple of 2 elements, found 1"
+        );
+    }
+
+    #[test]
+    fn orders_the_fields_of_user_defined_types() {
+        let address = ColumnType::UserDefinedType {
+            type_name: Cow::Borrowed("address"),
+            keyspace: Cow::Borrowed("app"),
+            field_types: vec![
+                (Cow::Borrowed("street"), ColumnType::Text),
+                (Cow::Borrowed("zip"), ColumnType::Int),
+                (Cow::Borrowed("city"), ColumnType::Text),
+            ],
+        };
+        assert_eq!(
+            value(&address, Some("{zip: 1000, street: 'Main'}")).unwrap(),
+            Some(CqlValue::UserDefinedType {
+                keyspace: "app".into(),
+                type_name: "address".into(),
+                fields: vec![
+                    ("street".into(), Some(CqlValue::Text("Main".into()))),
+                    ("zip".into(), Some(CqlValue::Int(1000))),
+                    ("city".into(), None),
+                ],
+            })
+        );
+        assert_eq!(
+            value(&address, Some("{country: 'KE'}")).unwrap_err(),
+            "address has no field country"
+        );
+    }
+
+    #[test]
+    fn converts_records_by_column() {
+        let table = || TableSpec::borrowed("app", "users");
+        let columns = [
+            ColumnSpec::borrowed("id", ColumnType::Int, table()),
+            ColumnSpec::borrowed("name", ColumnType::Text, table()),
+        ];
+        let record = |line, fields: &[Option<&str>]| Record {
+            line,
+            fields: fields.iter().map(|f| f.map(str::to_string)).collect(),
+        };
+        assert_eq!(
+            values(&columns, &record(2, &[Some("1"), None])).unwrap(),
+            vec![Some(CqlValue::Int(1)), None]
+        );
+        let reason = |record| match values(&columns, &record) {
+            Err(Error::Seed { line, reason }) => (line, reason),
+            _ => panic!("the record should be rejected"),
+        };
+        assert_eq!(
+            reason(record(3, &[Some("x"), None])),
+            (3, "column id: x is not a valid int".to_string())
+        );
+        assert_eq!(
+            reason(record(4, &[Some("1")])),
+            (4, "expected 2 fields, found 1".to_string())
+        );
+    }
+}
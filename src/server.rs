@@ -0,0 +1,285 @@
+use crate::error::Result;
+use crate::session::Execute;
+use scylla::query::Query;
+use scylla::transport::errors::{DbError, QueryError};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// The database a cluster runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Product {
+    Scylla,
+    Cassandra,
+}
+
+/// A release version like `5.4.1`, compared component by component
+///
+/// Missing components are 0, so `5.4` is `5.4.0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReleaseVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+/// The database and release version of the node a session is connected to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Server {
+    pub product: Product,
+    pub version: ReleaseVersion,
+}
+
+/// A server a migration requires, from a directive like `requires=scylla >= 5.4`
+///
+/// See [`Directives::requires`](crate::Directives::requires).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Requirement {
+    pub product: Product,
+    pub comparison: Comparison,
+    pub version: ReleaseVersion,
+}
+
+/// How the version of a [`Requirement`] or the rows of an [`Assertion`](crate::Assertion) are compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl ReleaseVersion {
+    /// Parses the leading `major.minor.patch` of a version, ignoring what
+    /// follows, e.g. the build of `5.4.0-0.20231231.c9d6b3a`
+    pub fn parse(version: &str) -> Option<Self> {
+        let end = version
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(version.len());
+        let mut components = version[..end].split('.').map(str::parse::<u64>);
+        let major = components.next()?.ok()?;
+        let mut next = || components.next().unwrap_or(Ok(0)).ok();
+        Some(Self {
+            major,
+            minor: next()?,
+            patch: next()?,
+        })
+    }
+}
+
+impl Server {
+    /// Asks the node the session is connected to what it runs
+    ///
+    /// ScyllaDB is told apart by its `system.versions` table, as it reports a
+    /// Cassandra version in `system.local` for compatibility.
+    pub async fn detect(session: &dyn Execute) -> Result<Self> {
+        let scylla = session
+            .query(
+                Query::new("SELECT version FROM system.versions WHERE key = 'local'"),
+                &[],
+            )
+            .await;
+        let (product, version) = match scylla {
+            Ok(rows) => {
+                let (version,): (String,) = rows.first_row()?;
+                (Product::Scylla, version)
+            }
+            Err(error) if is_missing_table(&error) => {
+                let (version,): (String,) = session
+                    .query(Query::new("SELECT release_version FROM system.local"), &[])
+                    .await?
+                    .first_row()?;
+                (Product::Cassandra, version)
+            }
+            Err(error) => return Err(error.into()),
+        };
+        Ok(Self {
+            product,
+            // An unparsable version is taken for 0.0.0, meeting only upper bounds
+            version: ReleaseVersion::parse(&version).unwrap_or(ReleaseVersion {
+                major: 0,
+                minor: 0,
+                patch: 0,
+            }),
+        })
+    }
+}
+
+/// Whether a query failed because the table doesn't exist, which Cassandra
+/// reports as an invalid request
+fn is_missing_table(error: &QueryError) -> bool {
+    matches!(error, QueryError::DbError(DbError::Invalid, _))
+}
+
+impl Requirement {
+    /// Parses a requirement like `scylla >= 5.4` or `cassandra < 4`
+    pub(crate) fn parse(requirement: &str) -> Option<Self> {
+        let requirement = requirement.trim();
+        let end = requirement
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(requirement.len());
+        let product = match requirement[..end].to_ascii_lowercase().as_str() {
+            "scylla" | "scylladb" => Product::Scylla,
+            "cassandra" => Product::Cassandra,
+            _ => return None,
+        };
+        let (comparison, version) = Comparison::parse_prefix(requirement[end..].trim())?;
+        Some(Self {
+            product,
+            comparison,
+            version: ReleaseVersion::parse(version.trim())?,
+        })
+    }
+
+    /// Returns true if `server` runs the product in a matching version
+    pub fn is_met_by(&self, server: &Server) -> bool {
+        server.product == self.product && self.comparison.holds(server.version.cmp(&self.version))
+    }
+}
+
+impl Comparison {
+    /// Parses the operator at the start of `value`, returning the rest
+    pub(crate) fn parse_prefix(value: &str) -> Option<(Self, &str)> {
+        [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ]
+        .into_iter()
+        .find_map(|(operator, comparison)| {
+            value.strip_prefix(operator).map(|rest| (comparison, rest))
+        })
+    }
+
+    /// Returns true if a value ordered like `ordering` against the compared one matches
+    pub fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Less => ordering.is_lt(),
+            Comparison::LessOrEqual => ordering.is_le(),
+            Comparison::Equal => ordering.is_eq(),
+            Comparison::GreaterOrEqual => ordering.is_ge(),
+            Comparison::Greater => ordering.is_gt(),
+        }
+    }
+}
+
+impl fmt::Display for Product {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Product::Scylla => write!(f, "scylla"),
+            Product::Cassandra => write!(f, "cassandra"),
+        }
+    }
+}
+
+impl fmt::Display for ReleaseVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl fmt::Display for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.product, self.version)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Equal => "=",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Greater => ">",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.product, self.comparison, self.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_only_for_missing_tables() {
+        let unconfigured =
+            QueryError::DbError(DbError::Invalid, "unconfigured table versions".into());
+        assert!(is_missing_table(&unconfigured));
+
+        let unauthorized =
+            QueryError::DbError(DbError::Unauthorized, "no SELECT permission".into());
+        assert!(!is_missing_table(&unauthorized));
+        assert!(!is_missing_table(&QueryError::EmptyPlan));
+    }
+
+    fn version(major: u64, minor: u64, patch: u64) -> ReleaseVersion {
+        ReleaseVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    #[test]
+    fn parses_release_versions() {
+        assert_eq!(ReleaseVersion::parse("5.4.1"), Some(version(5, 4, 1)));
+        assert_eq!(ReleaseVersion::parse("5.4"), Some(version(5, 4, 0)));
+        assert_eq!(ReleaseVersion::parse("4"), Some(version(4, 0, 0)));
+        assert_eq!(
+            ReleaseVersion::parse("5.4.0-0.20231231.c9d6b3a"),
+            Some(version(5, 4, 0))
+        );
+        assert_eq!(ReleaseVersion::parse(""), None);
+        assert_eq!(ReleaseVersion::parse("v5.4"), None);
+        assert_eq!(ReleaseVersion::parse("5..4"), None);
+    }
+
+    #[test]
+    fn parses_requirements() {
+        assert_eq!(
+            Requirement::parse(" ScyllaDB >= 5.4 "),
+            Some(Requirement {
+                product: Product::Scylla,
+                comparison: Comparison::GreaterOrEqual,
+                version: version(5, 4, 0),
+            })
+        );
+        assert_eq!(
+            Requirement::parse("cassandra<4"),
+            Some(Requirement {
+                product: Product::Cassandra,
+                comparison: Comparison::Less,
+                version: version(4, 0, 0),
+            })
+        );
+        for requirement in ["", "scylla", "scylla 5.4", "postgres >= 15", "scylla >= x"] {
+            assert_eq!(Requirement::parse(requirement), None, "{}", requirement);
+        }
+    }
+
+    #[test]
+    fn compares_requirements_with_servers() {
+        let requirement = Requirement::parse("scylla >= 5.4").unwrap();
+        let server = |product, version| Server { product, version };
+        assert!(requirement.is_met_by(&server(Product::Scylla, version(5, 4, 0))));
+        assert!(requirement.is_met_by(&server(Product::Scylla, version(6, 0, 0))));
+        assert!(!requirement.is_met_by(&server(Product::Scylla, version(5, 2, 9))));
+        assert!(!requirement.is_met_by(&server(Product::Cassandra, version(5, 4, 0))));
+    }
+}
@@ -0,0 +1,606 @@
+use crate::error::{Error, Result};
+use crate::view_build;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{BoxStream, StreamExt, TryChunksError, TryStreamExt};
+use scylla::deserialize::{DeserializationError, DeserializeRow, FrameSlice};
+use scylla::frame::response::result::{ColumnSpec, ColumnType, CqlValue, Row, TableSpec};
+use scylla::prepared_statement::PreparedStatement;
+use scylla::query::Query;
+use scylla::serialize::row::SerializeRow;
+use scylla::serialize::value::SerializeValue;
+use scylla::serialize::writers::CellWriter;
+use scylla::serialize::SerializationError;
+use scylla::statement::Consistency;
+use scylla::transport::errors::QueryError;
+use scylla::transport::iterator::NextRowError;
+use scylla::transport::query_result::IntoRowsResultError;
+use scylla::{CachingSession, QueryResult, Session};
+use scylla_cql::types::deserialize::result::{RawRowIterator, TypedRowIterator};
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The requests a migrator makes of a cluster
+///
+/// Implemented for [`Session`] and [`CachingSession`], whose cache is used for
+/// the statements the migrator prepares, and by
+/// [`MockSession`](crate::test::MockSession), which stands in for a cluster in
+/// unit tests. Results are returned as [`Rows`] and statements are prepared
+/// into a [`Prepared`], both of which can be made without a cluster.
+///
+/// Running migrations, reading their status and writing their history only go
+/// through this trait. What hands the driver's session to user code, such as
+/// hooks and [`CodeMigration`](crate::CodeMigration)s, and what reads the
+/// schema, such as drift detection and seeding, needs the [`Session`] of
+/// [`Execute::session`] and fails with [`Error::SessionRequired`] without one.
+#[async_trait]
+pub trait Execute: Send + Sync {
+    /// Executes an unprepared statement with bound values
+    async fn query(
+        &self,
+        statement: Query,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError>;
+
+    /// Prepares a statement to be run with [`Execute::execute`]
+    async fn prepare(&self, statement: &str) -> Result<Prepared, QueryError>;
+
+    /// Executes a prepared statement with bound values
+    async fn execute(
+        &self,
+        prepared: &Prepared,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError>;
+
+    /// Executes a prepared statement with bound values, returning its rows
+    /// page by page
+    async fn execute_paged(
+        &self,
+        prepared: &Prepared,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Pages, QueryError>;
+
+    /// Waits until all nodes agree on the schema
+    async fn await_schema_agreement(&self) -> Result<(), QueryError>;
+
+    /// Waits for the view or index `statement` creates to be built, for up to
+    /// `timeout`, see [`MigratorBuilder::wait_for_builds`](crate::MigratorBuilder::wait_for_builds)
+    async fn wait_for_build(&self, statement: &str, timeout: Duration) -> Result<()>;
+
+    /// Returns the driver's session behind this, if any
+    fn session(&self) -> Option<&Session> {
+        None
+    }
+}
+
+/// The pages of rows of [`Execute::execute_paged`]
+pub type Pages = BoxStream<'static, Result<Rows, QueryError>>;
+
+/// A statement prepared by an [`Execute`]
+///
+/// Holds the driver's [`PreparedStatement`] when prepared on a cluster, and
+/// only the statement otherwise.
+#[derive(Debug, Clone)]
+pub struct Prepared {
+    statement: String,
+    consistency: Option<Consistency>,
+    timeout: Option<Duration>,
+    prepared: Option<PreparedStatement>,
+}
+
+impl Prepared {
+    /// Returns `statement` unprepared, for implementations of [`Execute`]
+    /// without a cluster
+    pub fn new(statement: impl Into<String>) -> Self {
+        Self {
+            statement: statement.into(),
+            consistency: None,
+            timeout: None,
+            prepared: None,
+        }
+    }
+
+    pub fn statement(&self) -> &str {
+        &self.statement
+    }
+
+    pub fn consistency(&self) -> Option<Consistency> {
+        self.consistency
+    }
+
+    pub fn set_consistency(&mut self, consistency: Consistency) {
+        self.consistency = Some(consistency);
+        if let Some(prepared) = &mut self.prepared {
+            prepared.set_consistency(consistency);
+        }
+    }
+
+    pub fn request_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+        if let Some(prepared) = &mut self.prepared {
+            prepared.set_request_timeout(timeout);
+        }
+    }
+
+    /// Returns the statement as prepared on the cluster, if it was
+    pub fn prepared(&self) -> Option<&PreparedStatement> {
+        self.prepared.as_ref()
+    }
+
+    /// Returns the statement as an unprepared query
+    fn query(&self) -> Query {
+        let mut query = Query::new(self.statement.as_str());
+        if let Some(consistency) = self.consistency {
+            query.set_consistency(consistency);
+        }
+        query.set_request_timeout(self.timeout);
+        query
+    }
+}
+
+impl From<PreparedStatement> for Prepared {
+    fn from(prepared: PreparedStatement) -> Self {
+        Self {
+            statement: prepared.get_statement().to_string(),
+            consistency: prepared.get_consistency(),
+            timeout: prepared.get_request_timeout(),
+            prepared: Some(prepared),
+        }
+    }
+}
+
+/// The rows a statement executed through an [`Execute`] returned
+///
+/// Rows are kept serialized as the cluster sends them and deserialized into
+/// any type implementing the driver's [`DeserializeRow`], so they can be
+/// typed like those of a [`QueryRowsResult`](scylla::QueryRowsResult). A
+/// statement that returns no rows, such as an `INSERT`, returns empty rows.
+#[derive(Debug, Clone, Default)]
+pub struct Rows {
+    specs: Vec<ColumnSpec<'static>>,
+    count: usize,
+    raw: Bytes,
+}
+
+impl Rows {
+    /// Returns the rows `rows` of the columns named and typed by `columns`
+    ///
+    /// ```
+    /// use scylla::frame::response::result::{ColumnType, CqlValue};
+    /// use scylla_migrate::Rows;
+    ///
+    /// let rows = Rows::new(
+    ///     &[("name", ColumnType::Text)],
+    ///     &[vec![Some(CqlValue::Text("ada".to_string()))]],
+    /// )
+    /// .unwrap();
+    /// assert_eq!(rows.rows::<(String,)>().unwrap(), [("ada".to_string(),)]);
+    /// ```
+    pub fn new(
+        columns: &[(&str, ColumnType<'static>)],
+        rows: &[Vec<Option<CqlValue>>],
+    ) -> Result<Self, SerializationError> {
+        let specs: Vec<_> = columns
+            .iter()
+            .map(|(name, typ)| {
+                ColumnSpec::owned(
+                    name.to_string(),
+                    typ.clone(),
+                    TableSpec::owned(String::new(), String::new()),
+                )
+            })
+            .collect();
+        let mut raw = Vec::new();
+        for row in rows {
+            if row.len() != specs.len() {
+                return Err(SerializationError::new(RowLengthError {
+                    expected: specs.len(),
+                    found: row.len(),
+                }));
+            }
+            for (spec, value) in specs.iter().zip(row) {
+                let writer = CellWriter::new(&mut raw);
+                match value {
+                    Some(value) => value.serialize(spec.typ(), writer)?,
+                    None => writer.set_null(),
+                };
+            }
+        }
+        Ok(Self {
+            specs,
+            count: rows.len(),
+            raw: Bytes::from(raw),
+        })
+    }
+
+    /// Returns the result of a lightweight transaction, which was applied or not
+    pub fn applied(applied: bool) -> Self {
+        Self::new(
+            &[("[applied]", ColumnType::Boolean)],
+            &[vec![Some(CqlValue::Boolean(applied))]],
+        )
+        .expect("a boolean serializes as a boolean")
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Deserializes every row into `R`
+    pub fn rows<R>(&self) -> Result<Vec<R>>
+    where
+        R: for<'frame, 'metadata> DeserializeRow<'frame, 'metadata>,
+    {
+        self.typed()?.collect::<Result<_, _>>().map_err(Error::from)
+    }
+
+    /// Deserializes the first row into `R`, if there is one
+    pub fn maybe_first_row<R>(&self) -> Result<Option<R>>
+    where
+        R: for<'frame, 'metadata> DeserializeRow<'frame, 'metadata>,
+    {
+        Ok(self.typed()?.next().transpose()?)
+    }
+
+    /// Deserializes the first row into `R`, failing if there is none
+    pub fn first_row<R>(&self) -> Result<R>
+    where
+        R: for<'frame, 'metadata> DeserializeRow<'frame, 'metadata>,
+    {
+        self.maybe_first_row()?
+            .ok_or_else(|| Error::Result("the query returned no rows".into()))
+    }
+
+    /// Returns an iterator deserializing the rows into `R`
+    ///
+    /// The rows of a statement that returns none have no columns either, so
+    /// they are empty whatever `R`.
+    fn typed<'s, R>(
+        &'s self,
+    ) -> Result<std::iter::Flatten<std::option::IntoIter<TypedRowIterator<'s, 's, R>>>>
+    where
+        R: DeserializeRow<'s, 's>,
+    {
+        let typed = if self.specs.is_empty() {
+            None
+        } else {
+            let raw = RawRowIterator::new(self.count, &self.specs, FrameSlice::new(&self.raw));
+            Some(TypedRowIterator::<R>::new(raw)?)
+        };
+        Ok(typed.into_iter().flatten())
+    }
+
+    /// Takes the rows of a result of the driver
+    fn from_result(result: QueryResult) -> Result<Self, QueryError> {
+        let rows = match result.into_rows_result() {
+            Ok(rows) => rows,
+            Err(IntoRowsResultError::ResultNotRows(_)) => return Ok(Self::default()),
+            Err(err) => return Err(deserialization_error(err)),
+        };
+        let columns: Vec<_> = rows
+            .column_specs()
+            .iter()
+            .map(|spec| (spec.name().to_string(), spec.typ().clone().into_owned()))
+            .collect();
+        let rows = rows
+            .rows::<Row>()
+            .map_err(deserialization_error)?
+            .map(|row| row.map(|row| row.columns))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(deserialization_error)?;
+        Self::from_columns(columns, &rows)
+    }
+
+    /// Takes the rows of a result of the driver already deserialized
+    fn from_columns(
+        columns: Vec<(String, ColumnType<'static>)>,
+        rows: &[Vec<Option<CqlValue>>],
+    ) -> Result<Self, QueryError> {
+        let columns: Vec<_> = columns
+            .iter()
+            .map(|(name, typ)| (name.as_str(), typ.clone()))
+            .collect();
+        Self::new(&columns, rows).map_err(deserialization_error)
+    }
+}
+
+/// The error of [`Rows::new`] for a row with a different number of values than columns
+#[derive(Debug, thiserror::Error)]
+#[error("expected {expected} values in a row, found {found}")]
+struct RowLengthError {
+    expected: usize,
+    found: usize,
+}
+
+/// Reports that the rows of a result could not be taken from the driver
+fn deserialization_error(err: impl std::error::Error + Send + Sync + 'static) -> QueryError {
+    QueryError::from(NextRowError::from(DeserializationError::new(err)))
+}
+
+#[async_trait]
+impl Execute for Session {
+    async fn query(
+        &self,
+        statement: Query,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError> {
+        Rows::from_result(self.query_unpaged(statement, values).await?)
+    }
+
+    async fn prepare(&self, statement: &str) -> Result<Prepared, QueryError> {
+        Ok(Session::prepare(self, statement).await?.into())
+    }
+
+    async fn execute(
+        &self,
+        prepared: &Prepared,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError> {
+        let result = match prepared.prepared() {
+            Some(statement) => self.execute_unpaged(statement, values).await?,
+            None => self.query_unpaged(prepared.query(), values).await?,
+        };
+        Rows::from_result(result)
+    }
+
+    async fn execute_paged(
+        &self,
+        prepared: &Prepared,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Pages, QueryError> {
+        let (pager, page_size) = match prepared.prepared() {
+            Some(statement) => (
+                self.execute_iter(statement.clone(), values).await?,
+                statement.get_page_size(),
+            ),
+            None => {
+                let query = prepared.query();
+                let page_size = query.get_page_size();
+                (self.query_iter(query, values).await?, page_size)
+            }
+        };
+        let columns: Vec<_> = pager
+            .column_specs()
+            .iter()
+            .map(|spec| (spec.name().to_string(), spec.typ().clone().into_owned()))
+            .collect();
+        let pages = pager
+            .rows_stream::<Row>()
+            .map_err(deserialization_error)?
+            .map_ok(|row| row.columns)
+            .try_chunks(page_size.max(1) as usize)
+            .map(move |page| {
+                let page = page.map_err(|TryChunksError(_, err)| err)?;
+                Rows::from_columns(columns.clone(), &page)
+            });
+        Ok(pages.boxed())
+    }
+
+    async fn await_schema_agreement(&self) -> Result<(), QueryError> {
+        Session::await_schema_agreement(self).await?;
+        Ok(())
+    }
+
+    async fn wait_for_build(&self, statement: &str, timeout: Duration) -> Result<()> {
+        view_build::wait_for(self, statement, timeout).await
+    }
+
+    fn session(&self) -> Option<&Session> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl<S> Execute for CachingSession<S>
+where
+    S: Clone + BuildHasher + Send + Sync,
+{
+    async fn query(
+        &self,
+        statement: Query,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError> {
+        Execute::query(self.get_session(), statement, values).await
+    }
+
+    async fn prepare(&self, statement: &str) -> Result<Prepared, QueryError> {
+        Ok(self
+            .add_prepared_statement(&Query::new(statement))
+            .await?
+            .into())
+    }
+
+    async fn execute(
+        &self,
+        prepared: &Prepared,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError> {
+        Execute::execute(self.get_session(), prepared, values).await
+    }
+
+    async fn execute_paged(
+        &self,
+        prepared: &Prepared,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Pages, QueryError> {
+        self.get_session().execute_paged(prepared, values).await
+    }
+
+    async fn await_schema_agreement(&self) -> Result<(), QueryError> {
+        Execute::await_schema_agreement(self.get_session()).await
+    }
+
+    async fn wait_for_build(&self, statement: &str, timeout: Duration) -> Result<()> {
+        self.get_session().wait_for_build(statement, timeout).await
+    }
+
+    fn session(&self) -> Option<&Session> {
+        Some(self.get_session())
+    }
+}
+
+#[async_trait]
+impl<T: Execute + ?Sized> Execute for Arc<T> {
+    async fn query(
+        &self,
+        statement: Query,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError> {
+        (**self).query(statement, values).await
+    }
+
+    async fn prepare(&self, statement: &str) -> Result<Prepared, QueryError> {
+        (**self).prepare(statement).await
+    }
+
+    async fn execute(
+        &self,
+        prepared: &Prepared,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError> {
+        (**self).execute(prepared, values).await
+    }
+
+    async fn execute_paged(
+        &self,
+        prepared: &Prepared,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Pages, QueryError> {
+        (**self).execute_paged(prepared, values).await
+    }
+
+    async fn await_schema_agreement(&self) -> Result<(), QueryError> {
+        (**self).await_schema_agreement().await
+    }
+
+    async fn wait_for_build(&self, statement: &str, timeout: Duration) -> Result<()> {
+        (**self).wait_for_build(statement, timeout).await
+    }
+
+    fn session(&self) -> Option<&Session> {
+        (**self).session()
+    }
+}
+
+/// The session of a [`Migrator`](crate::Migrator), borrowed from the caller
+/// or shared with it
+#[derive(Clone)]
+pub(crate) enum SessionRef<'a> {
+    Borrowed(&'a dyn Execute),
+    Shared(Arc<dyn Execute>),
+}
+
+impl SessionRef<'_> {
+    fn get(&self) -> &dyn Execute {
+        match self {
+            SessionRef::Borrowed(session) => *session,
+            SessionRef::Shared(session) => &**session,
+        }
+    }
+
+    /// Returns the driver's session, failing with [`Error::SessionRequired`]
+    /// for `what` without one
+    pub fn require(&self, what: &'static str) -> Result<&Session> {
+        self.get().session().ok_or(Error::SessionRequired(what))
+    }
+}
+
+#[async_trait]
+impl Execute for SessionRef<'_> {
+    async fn query(
+        &self,
+        statement: Query,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError> {
+        self.get().query(statement, values).await
+    }
+
+    async fn prepare(&self, statement: &str) -> Result<Prepared, QueryError> {
+        self.get().prepare(statement).await
+    }
+
+    async fn execute(
+        &self,
+        prepared: &Prepared,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Rows, QueryError> {
+        self.get().execute(prepared, values).await
+    }
+
+    async fn execute_paged(
+        &self,
+        prepared: &Prepared,
+        values: &(dyn SerializeRow + Sync),
+    ) -> Result<Pages, QueryError> {
+        self.get().execute_paged(prepared, values).await
+    }
+
+    async fn await_schema_agreement(&self) -> Result<(), QueryError> {
+        self.get().await_schema_agreement().await
+    }
+
+    async fn wait_for_build(&self, statement: &str, timeout: Duration) -> Result<()> {
+        self.get().wait_for_build(statement, timeout).await
+    }
+
+    fn session(&self) -> Option<&Session> {
+        self.get().session()
+    }
+}
+
+impl fmt::Debug for SessionRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.session() {
+            Some(session) => fmt::Debug::fmt(session, f),
+            None => f.write_str("SessionRef"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserializes_the_rows_it_was_made_of() {
+        let rows = Rows::new(
+            &[("version", ColumnType::BigInt), ("host", ColumnType::Text)],
+            &[
+                vec![Some(CqlValue::BigInt(1)), Some(CqlValue::Text("a".into()))],
+                vec![Some(CqlValue::BigInt(2)), None],
+            ],
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows.rows::<(i64, Option<String>)>().unwrap(),
+            [(1, Some("a".to_string())), (2, None)]
+        );
+        assert_eq!(rows.first_row::<(i64, Option<String>)>().unwrap().0, 1);
+        assert!(rows.rows::<(String, String)>().is_err());
+    }
+
+    #[test]
+    fn has_no_rows_by_default() {
+        let rows = Rows::default();
+        assert!(rows.is_empty());
+        assert_eq!(rows.maybe_first_row::<(bool,)>().unwrap(), None);
+        assert!(rows.first_row::<(bool,)>().is_err());
+        assert_eq!(Rows::applied(true).first_row::<(bool,)>().unwrap(), (true,));
+    }
+
+    #[test]
+    fn rejects_rows_of_the_wrong_length() {
+        assert!(Rows::new(&[("version", ColumnType::BigInt)], &[vec![]]).is_err());
+    }
+}
@@ -0,0 +1,533 @@
+use crate::error::{Error, Result};
+use crate::migration::Migration;
+use async_trait::async_trait;
+use futures::{stream, StreamExt, TryStreamExt};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::thread;
+use tokio::fs;
+
+/// Files [`read_dir`] reads at once
+const READ_CONCURRENCY: usize = 16;
+
+/// Migrations a thread checksums at least, fewer aren't worth a thread
+const CHECKSUMS_PER_THREAD: usize = 32;
+
+/// Provides the migrations a [`Migrator`](crate::Migrator) works with
+///
+/// Implement this to load migrations from somewhere other than a directory or
+/// the binary, e.g. a database, a configuration service or generated code.
+///
+/// # Example
+/// ```
+/// use async_trait::async_trait;
+/// use scylla_migrate::{Migration, MigrationSource};
+///
+/// struct Generated;
+///
+/// #[async_trait]
+/// impl MigrationSource for Generated {
+///     async fn migrations(&self) -> scylla_migrate::Result<Vec<Migration>> {
+///         Ok(vec![Migration::new(
+///             1,
+///             "create_users".into(),
+///             "CREATE TABLE IF NOT EXISTS users (id uuid PRIMARY KEY)".into(),
+///         )])
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait MigrationSource: Send + Sync {
+    /// Returns all available migrations, in any order
+    ///
+    /// Wrap failures of the underlying storage in [`Error::Source`].
+    async fn migrations(&self) -> Result<Vec<Migration>>;
+}
+
+/// Reads migrations from the .cql files of a directory
+///
+/// With the `templates` feature, `.cql.tera` files are rendered with
+/// [Tera](https://keats.github.io/tera/) and then treated like the `.cql` file
+/// of the same name. Other `.tera` files are not migrations, but can be
+/// included, imported or extended by them.
+#[derive(Debug, Clone)]
+pub struct FileSource {
+    path: PathBuf,
+    #[cfg(feature = "templates")]
+    context: tera::Context,
+}
+
+impl FileSource {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            #[cfg(feature = "templates")]
+            context: tera::Context::new(),
+        }
+    }
+
+    /// Sets the values templates are rendered with
+    ///
+    /// [`MigratorBuilder::build`](crate::MigratorBuilder::build) passes the
+    /// [`variable`](crate::MigratorBuilder::variable)s of the runner.
+    #[cfg(feature = "templates")]
+    pub fn template_context(mut self, context: tera::Context) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Replaces `.cql.tera` files by their rendered `.cql` counterparts
+    #[cfg(feature = "templates")]
+    fn render(&self, files: Vec<File>) -> Result<Vec<File>> {
+        let (templates, mut files): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|(filename, _)| filename.ends_with(".tera"));
+        if templates.is_empty() {
+            return Ok(files);
+        }
+
+        let template_error = |source| Error::Template(Box::new(source));
+        let mut tera = tera::Tera::default();
+        tera.add_raw_templates(
+            templates
+                .iter()
+                .map(|(filename, content)| (filename.as_ref(), content.as_ref())),
+        )
+        .map_err(template_error)?;
+
+        for (filename, _) in &templates {
+            let Some(name) = filename.strip_suffix(".tera") else {
+                continue;
+            };
+            if !name.ends_with(".cql") {
+                continue;
+            }
+            let cql = tera
+                .render(filename, &self.context)
+                .map_err(template_error)?;
+            files.push((Cow::Owned(name.to_string()), Cow::Owned(cql)));
+        }
+
+        Ok(files)
+    }
+}
+
+#[async_trait]
+impl MigrationSource for FileSource {
+    async fn migrations(&self) -> Result<Vec<Migration>> {
+        let files = read_dir(&self.path).await?;
+        #[cfg(feature = "templates")]
+        let files = self.render(files)?;
+        load_files(files).await
+    }
+}
+
+/// Serves migrations constructed in code
+///
+/// Useful for CQL generated at runtime, e.g. per tenant, which still gets
+/// versioned, checksummed and tracked like migration files.
+#[derive(Debug, Clone, Default)]
+pub struct StaticSource {
+    migrations: Vec<Migration>,
+}
+
+impl StaticSource {
+    pub fn new(migrations: Vec<Migration>) -> Self {
+        Self { migrations }
+    }
+
+    /// Adds a migration to the source
+    pub fn with(mut self, migration: Migration) -> Self {
+        self.migrations.push(migration);
+        self
+    }
+}
+
+impl From<Vec<Migration>> for StaticSource {
+    fn from(migrations: Vec<Migration>) -> Self {
+        Self::new(migrations)
+    }
+}
+
+#[async_trait]
+impl MigrationSource for StaticSource {
+    async fn migrations(&self) -> Result<Vec<Migration>> {
+        Ok(self.migrations.clone())
+    }
+}
+
+/// Migration files compiled into the binary
+///
+/// Usually created with `embed_migrations!`, which requires the `embed`
+/// feature, but can also be assembled by hand.
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddedMigrations {
+    files: &'static [(&'static str, &'static str)],
+}
+
+impl EmbeddedMigrations {
+    /// Wraps `(filename, content)` pairs
+    ///
+    /// Filenames follow the same rules as files in a migrations directory.
+    pub const fn new(files: &'static [(&'static str, &'static str)]) -> Self {
+        Self { files }
+    }
+
+    /// Returns the embedded `(filename, content)` pairs
+    pub fn files(&self) -> &'static [(&'static str, &'static str)] {
+        self.files
+    }
+}
+
+#[async_trait]
+impl MigrationSource for EmbeddedMigrations {
+    async fn migrations(&self) -> Result<Vec<Migration>> {
+        load_files(
+            self.files
+                .iter()
+                .map(|(filename, cql)| (Cow::Borrowed(*filename), Cow::Borrowed(*cql)))
+                .collect(),
+        )
+        .await
+    }
+}
+
+/// Migration files held in memory, e.g. assets embedded by
+/// [`include_dir`](https://docs.rs/include_dir) or
+/// [`rust-embed`](https://docs.rs/rust-embed)
+///
+/// Takes `(path, content)` pairs, such as the files of an embedded directory,
+/// keeping the `.cql` ones. Only the last component of a path is its filename,
+/// so migrations can sit in a subdirectory of the assets. Filenames follow the
+/// same rules as files in a migrations directory; templates aren't rendered.
+///
+/// With the `include-dir` feature, an embedded directory converts into a
+/// source with `From`, and with the `rust-embed` feature, an asset type is
+/// taken by [`from_embed`](Self::from_embed).
+///
+/// # Example
+/// ```ignore
+/// use include_dir::{include_dir, Dir};
+/// use scylla_migrate::AssetSource;
+///
+/// static ASSETS: Dir = include_dir!("$CARGO_MANIFEST_DIR/assets");
+///
+/// let migrations = ASSETS.get_dir("migrations").expect("migrations are embedded");
+/// let source = AssetSource::from(migrations);
+///
+/// // Or, with an asset type deriving `rust_embed::Embed`
+/// let source = AssetSource::from_embed::<Assets>();
+///
+/// // Or, from any `(path, content)` pairs
+/// let source = AssetSource::from_bytes(
+///     migrations
+///         .files()
+///         .map(|file| (file.path().to_string_lossy(), file.contents())),
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AssetSource {
+    files: Vec<(String, Cow<'static, [u8]>)>,
+}
+
+impl AssetSource {
+    /// Wraps `(path, content)` pairs of text files
+    pub fn new<P, C>(files: impl IntoIterator<Item = (P, C)>) -> Self
+    where
+        P: AsRef<str>,
+        C: Into<Cow<'static, str>>,
+    {
+        Self::from_bytes(files.into_iter().map(|(path, content)| {
+            let content = match content.into() {
+                Cow::Borrowed(content) => Cow::Borrowed(content.as_bytes()),
+                Cow::Owned(content) => Cow::Owned(content.into_bytes()),
+            };
+            (path, content)
+        }))
+    }
+
+    /// Wraps `(path, content)` pairs of raw files, which must be UTF-8 if
+    /// they are migrations
+    pub fn from_bytes<P, C>(files: impl IntoIterator<Item = (P, C)>) -> Self
+    where
+        P: AsRef<str>,
+        C: Into<Cow<'static, [u8]>>,
+    {
+        let files = files
+            .into_iter()
+            .filter_map(|(path, content)| {
+                let filename = path.as_ref().rsplit(['/', '\\']).next()?;
+                filename
+                    .ends_with(".cql")
+                    .then(|| (filename.to_string(), content.into()))
+            })
+            .collect();
+        Self { files }
+    }
+
+    /// Takes the files of the assets `E` embeds
+    #[cfg(feature = "rust-embed")]
+    pub fn from_embed<E: rust_embed::RustEmbed>() -> Self {
+        Self::from_bytes(E::iter().filter_map(|path| {
+            let file = E::get(&path)?;
+            Some((path, file.data))
+        }))
+    }
+}
+
+#[cfg(feature = "include-dir")]
+impl From<&include_dir::Dir<'_>> for AssetSource {
+    /// Takes the files of `dir` and its subdirectories, copying their contents
+    fn from(dir: &include_dir::Dir<'_>) -> Self {
+        let mut files = Vec::new();
+        let mut dirs = vec![dir];
+        while let Some(dir) = dirs.pop() {
+            files.extend(dir.files().map(|file| {
+                let path = file.path().to_string_lossy().into_owned();
+                (path, file.contents().to_vec())
+            }));
+            dirs.extend(dir.dirs());
+        }
+        Self::from_bytes(files)
+    }
+}
+
+#[async_trait]
+impl MigrationSource for AssetSource {
+    async fn migrations(&self) -> Result<Vec<Migration>> {
+        let files = self
+            .files
+            .iter()
+            .map(|(filename, content)| {
+                let invalid = || Error::Parse {
+                    name: filename.clone(),
+                    reason: "file is not valid UTF-8",
+                };
+                let cql = match content {
+                    Cow::Borrowed(content) => {
+                        Cow::Borrowed(std::str::from_utf8(content).map_err(|_| invalid())?)
+                    }
+                    Cow::Owned(content) => {
+                        Cow::Owned(String::from_utf8(content.clone()).map_err(|_| invalid())?)
+                    }
+                };
+                Ok((Cow::Owned(filename.clone()), cql))
+            })
+            .collect::<Result<Vec<File>>>()?;
+        load_files(files).await
+    }
+}
+
+/// A `(filename, content)` pair
+pub(crate) type File = (Cow<'static, str>, Cow<'static, str>);
+
+/// Reads all .cql files of a directory, and .tera files with the `templates` feature
+///
+/// Up to [`READ_CONCURRENCY`] files are read at once, in no particular order.
+pub(crate) async fn read_dir(path: &Path) -> Result<Vec<File>> {
+    read_files(path, cfg!(feature = "templates")).await
+}
+
+/// Like [`read_dir`], reading .tera files along with .cql ones if `templates`
+pub(crate) async fn read_files(path: &Path, templates: bool) -> Result<Vec<File>> {
+    let io_error = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut entries = fs::read_dir(path).await.map_err(io_error)?;
+
+    let mut paths = Vec::new();
+
+    while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+        if let Ok(meta) = entry.metadata().await {
+            if !meta.is_file() {
+                continue;
+            }
+
+            let path = entry.path();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("cql") => {}
+                Some("tera") if templates => {}
+                _ => continue,
+            }
+
+            let filename = entry.file_name().to_string_lossy().into_owned();
+            paths.push((filename, path));
+        }
+    }
+
+    stream::iter(paths)
+        .map(|(filename, path)| async move {
+            let cql = fs::read_to_string(&path)
+                .await
+                .map_err(|source| Error::Io { path, source })?;
+            Ok((Cow::Owned(filename), Cow::Owned(cql)))
+        })
+        .buffer_unordered(READ_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+/// Like [`from_files`], but checksums large sets of files on a blocking
+/// thread, so the runtime isn't held up
+///
+/// Sources call this from [`MigrationSource::migrations`], which may run on
+/// a runtime worker.
+pub(crate) async fn load_files(files: Vec<File>) -> Result<Vec<Migration>> {
+    if files.len() < CHECKSUMS_PER_THREAD {
+        return from_files(files);
+    }
+    tokio::task::spawn_blocking(move || from_files(files))
+        .await
+        .unwrap_or_else(|err| panic::resume_unwind(err.into_panic()))
+}
+
+/// Turns `(filename, content)` pairs of .cql files into sorted migrations
+///
+/// Pairs up `.up.cql` and `.down.cql` files and puts repeatable migrations
+/// after all versioned ones. Large sets of files are checksummed on several
+/// threads.
+pub(crate) fn from_files(files: impl IntoIterator<Item = File>) -> Result<Vec<Migration>> {
+    // Keyed by version so pairs are matched up and migrations come out sorted
+    let mut ups: BTreeMap<i64, (Cow<str>, Cow<str>)> = BTreeMap::new();
+    let mut downs = BTreeMap::new();
+    let mut repeatables = BTreeMap::new();
+    let mut duplicates: BTreeMap<i64, Vec<String>> = BTreeMap::new();
+
+    for (filename, cql) in files {
+        let is_down = filename.ends_with(".down.cql");
+
+        if filename.starts_with("R__") {
+            if is_down {
+                return Err(Error::Parse {
+                    name: filename.into_owned(),
+                    reason: "repeatable migrations cannot have a down migration",
+                });
+            }
+            repeatables.insert(filename, cql);
+            continue;
+        }
+
+        let Some(version) = version_of(&filename) else {
+            return Err(Error::Parse {
+                name: filename.into_owned(),
+                reason: "file name does not start with a version",
+            });
+        };
+
+        let files = if is_down { &mut downs } else { &mut ups };
+        if let Some((existing, _)) = files.get(&version) {
+            duplicates
+                .entry(version)
+                .or_insert_with(|| vec![existing.to_string()])
+                .push(filename.into_owned());
+            continue;
+        }
+        files.insert(version, (filename, cql));
+    }
+
+    if let Some((version, mut names)) = duplicates.pop_first() {
+        names.sort();
+        return Err(Error::DuplicateVersion { version, names });
+    }
+
+    if let Some((_, (filename, _))) = downs.iter().find(|(v, _)| !ups.contains_key(*v)) {
+        return Err(Error::Parse {
+            name: filename.to_string(),
+            reason: "down migration has no matching up migration",
+        });
+    }
+
+    let versioned = ups
+        .into_iter()
+        .map(|(version, up)| (version, up, downs.remove(&version)))
+        .collect();
+    let mut migrations = parallel_map(versioned, |(version, (filename, cql), down)| {
+        let migration = Migration::new(version, filename, cql);
+        match down {
+            Some((_, down)) => migration.with_down(down),
+            None => migration,
+        }
+    });
+
+    // Repeatable migrations go last, they may depend on any versioned one
+    migrations.extend(parallel_map(
+        repeatables.into_iter().collect(),
+        |(filename, cql)| Migration::repeatable(filename, cql),
+    ));
+
+    Ok(migrations)
+}
+
+/// Maps `items` in order, spread over the available cores when there are
+/// enough of them
+fn parallel_map<T: Send, U: Send>(items: Vec<T>, map: impl Fn(T) -> U + Sync) -> Vec<U> {
+    let threads = thread::available_parallelism()
+        .map_or(1, usize::from)
+        .min(items.len() / CHECKSUMS_PER_THREAD);
+    if threads <= 1 {
+        return items.into_iter().map(map).collect();
+    }
+
+    let chunk_size = items.len().div_ceil(threads);
+    let mut items = items.into_iter();
+    let chunks: Vec<Vec<T>> = (0..threads)
+        .map(|_| items.by_ref().take(chunk_size).collect())
+        .collect();
+    let map = &map;
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| scope.spawn(move || chunk.into_iter().map(map).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|err| panic::resume_unwind(err))
+            })
+            .collect()
+    })
+}
+
+/// Returns the version a versioned migration file name starts with
+pub(crate) fn version_of(filename: &str) -> Option<i64> {
+    filename.split('_').next()?.parse().ok()
+}
+
+#[cfg(all(test, feature = "include-dir"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn takes_the_migrations_of_an_embedded_directory() {
+        use include_dir::{Dir, DirEntry, File};
+
+        static NESTED: [DirEntry<'static>; 1] = [DirEntry::File(File::new(
+            "migrations/tenants/20240102000000_tenants.cql",
+            b"CREATE TABLE app.tenants (id uuid PRIMARY KEY);",
+        ))];
+        static ENTRIES: [DirEntry<'static>; 3] = [
+            DirEntry::File(File::new(
+                "migrations/20240101000000_users.cql",
+                b"CREATE TABLE app.users (id uuid PRIMARY KEY);",
+            )),
+            DirEntry::File(File::new("migrations/README.md", b"# Migrations")),
+            DirEntry::Dir(Dir::new("migrations/tenants", &NESTED)),
+        ];
+        let dir = Dir::new("migrations", &ENTRIES);
+
+        let mut versions: Vec<_> = AssetSource::from(&dir)
+            .migrations()
+            .await
+            .unwrap()
+            .iter()
+            .map(|migration| migration.version)
+            .collect();
+        versions.sort();
+        assert_eq!(versions, [20240101000000, 20240102000000]);
+    }
+}
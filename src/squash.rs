@@ -0,0 +1,200 @@
+use crate::error::{Error, Result};
+use crate::migration::MigrationKind;
+use crate::source::{from_files, read_dir, version_of};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// The outcome of [`squash`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Squash {
+    /// The migration file the squashed ones were merged into
+    pub path: PathBuf,
+    /// Versions of the squashed migrations, oldest first
+    pub versions: Vec<i64>,
+    /// The files that were removed, down scripts included
+    pub removed: Vec<PathBuf>,
+}
+
+/// Merges the versioned migrations of a directory up to and including `version`
+/// into a single migration file of that version
+///
+/// The up scripts are concatenated in version order, each under a comment
+/// naming its file, into `<version>_squashed.cql`; their files and down scripts
+/// are removed. The squashed migration has no down script. Templates and
+/// migrations with assertions or directives other than `description` can't be
+/// squashed.
+///
+/// Databases that applied the squashed migrations need their history
+/// rewritten with [`Migrator::squash_history`](crate::Migrator::squash_history).
+pub async fn squash(path: impl AsRef<Path>, version: i64) -> Result<Squash> {
+    let path = path.as_ref();
+    let squash_error = |reason| Error::Squash { version, reason };
+
+    let files = read_dir(path).await?;
+    let mut squashed = Vec::new();
+    for (filename, _) in &files {
+        if filename.starts_with("R__") || version_of(filename).is_none_or(|v| v > version) {
+            continue;
+        }
+        if filename.ends_with(".tera") {
+            return Err(squash_error("templates can't be squashed"));
+        }
+        squashed.push(filename.to_string());
+    }
+
+    let migrations = from_files(
+        files
+            .into_iter()
+            .filter(|(filename, _)| filename.ends_with(".cql")),
+    )?;
+    let migrations: Vec<_> = migrations
+        .into_iter()
+        .filter(|m| m.kind == MigrationKind::Versioned && m.version <= version)
+        .collect();
+    if !migrations.iter().any(|m| m.version == version) {
+        return Err(Error::UnknownVersion(version));
+    }
+    if migrations.len() < 2 {
+        return Err(squash_error(
+            "no older migrations are left to squash into it",
+        ));
+    }
+
+    let mut cql = format!(
+        "-- Squashed migrations {} to {}\n",
+        migrations[0].version, version
+    );
+    for migration in &migrations {
+        let directives = migration.directives()?;
+        if directives.consistency.is_some()
+            || directives.timeout.is_some()
+            || directives.delay.is_some()
+            || directives.no_split
+            || !directives.environments.is_empty()
+            || !directives.depends_on.is_empty()
+            || directives.requires.is_some()
+        {
+            return Err(squash_error(
+                "migrations with consistency, timeout, delay, no-split, env, depends-on or requires directives can't be squashed",
+            ));
+        }
+        // Checked after the squashed migration, later statements would change their outcome
+        if !directives.asserts.is_empty() {
+            return Err(squash_error("migrations with assertions can't be squashed"));
+        }
+
+        let body = without_directives(&migration.cql);
+        cql.push_str(&format!("\n-- {}\n{}\n", migration.description, body));
+        // The next script must not continue an unterminated last statement
+        if !body.ends_with(';') {
+            cql.push_str(";\n");
+        }
+    }
+
+    let target = path.join(format!("{}_squashed.cql", version));
+    fs::write(&target, cql).await.map_err(|source| Error::Io {
+        path: target.clone(),
+        source,
+    })?;
+
+    squashed.sort();
+    let mut removed = Vec::new();
+    for filename in squashed {
+        let file = path.join(filename);
+        if file == target {
+            continue;
+        }
+        fs::remove_file(&file).await.map_err(|source| Error::Io {
+            path: file.clone(),
+            source,
+        })?;
+        removed.push(file);
+    }
+
+    Ok(Squash {
+        path: target,
+        versions: migrations.iter().map(|m| m.version).collect(),
+        removed,
+    })
+}
+
+/// Drops the `scylla-migrate:` directive comments of a script, trimmed
+fn without_directives(cql: &str) -> Cow<'_, str> {
+    let is_directive = |line: &str| {
+        line.trim()
+            .strip_prefix("--")
+            .or_else(|| line.trim().strip_prefix("//"))
+            .is_some_and(|comment| comment.trim().starts_with("scylla-migrate:"))
+    };
+    if !cql.lines().any(is_directive) {
+        return Cow::Borrowed(cql.trim());
+    }
+
+    Cow::Owned(
+        cql.lines()
+            .filter(|line| !is_directive(line))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim()
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, content) in files {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        }
+        dir
+    }
+
+    #[tokio::test]
+    async fn merges_migrations_in_version_order() {
+        let dir = dir(&[
+            (
+                "1_users.up.cql",
+                "-- scylla-migrate: description=users\nCREATE TABLE users (id int PRIMARY KEY)",
+            ),
+            ("1_users.down.cql", "DROP TABLE users;"),
+            ("2_posts.cql", "CREATE TABLE posts (id int PRIMARY KEY);"),
+            ("3_tags.cql", "CREATE TABLE tags (id int PRIMARY KEY);"),
+        ]);
+        let squash = squash(dir.path(), 2).await.unwrap();
+        assert_eq!(squash.versions, [1, 2]);
+        assert_eq!(squash.removed.len(), 3);
+        let cql = std::fs::read_to_string(&squash.path).unwrap();
+        assert_eq!(
+            cql,
+            "-- Squashed migrations 1 to 2\n\n-- 1_users.up.cql\nCREATE TABLE users (id int PRIMARY KEY)\n;\n\n-- 2_posts.cql\nCREATE TABLE posts (id int PRIMARY KEY);\n"
+        );
+        assert!(dir.path().join("3_tags.cql").exists());
+    }
+
+    #[tokio::test]
+    async fn refuses_dependencies_requirements_and_assertions() {
+        for header in [
+            "-- scylla-migrate: depends-on=1",
+            "-- scylla-migrate: requires=scylla >= 5.4",
+            "-- assert: count >= 1: SELECT id FROM users",
+        ] {
+            let dir = dir(&[
+                ("1_users.cql", "CREATE TABLE users (id int PRIMARY KEY);"),
+                (
+                    "2_posts.cql",
+                    &format!("{}\nCREATE TABLE posts (id int PRIMARY KEY);", header),
+                ),
+            ]);
+            assert!(
+                matches!(squash(dir.path(), 2).await, Err(Error::Squash { .. })),
+                "{}",
+                header
+            );
+            assert!(!dir.path().join("2_squashed.cql").exists());
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::migration::MigrationKind;
+use std::fmt;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Applied and pending migrations, merged into a single view
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Status {
+    pub migrations: Vec<MigrationStatus>,
+}
+
+/// State of a single migration version
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MigrationStatus {
+    pub kind: MigrationKind,
+    /// Always 0 for repeatable migrations
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+    /// Recorded checksum for applied migrations, local checksum otherwise
+    pub checksum: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "time::serde::rfc3339::option"))]
+    pub applied_at: Option<OffsetDateTime>,
+    /// How long the migration took to apply, for migrations recorded with it
+    pub execution_time_ms: Option<i64>,
+    /// Who applied the migration, the cluster user or else the OS user
+    pub applied_by: Option<String>,
+    /// Host of the runner that applied the migration
+    pub host: Option<String>,
+    /// Why the last attempt failed, for failed migrations
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MigrationState {
+    /// Applied and unchanged since
+    Applied,
+    /// Present locally but not applied yet
+    Pending,
+    /// Applied, but the local file has been modified since
+    Changed,
+    /// Applied, but no longer present locally
+    Missing,
+    /// Not applied, the last attempt failed
+    Failed,
+}
+
+impl Status {
+    /// Returns the migrations that have not been applied yet, failed ones included
+    pub fn pending(&self) -> impl Iterator<Item = &MigrationStatus> {
+        self.migrations
+            .iter()
+            .filter(|m| matches!(m.state, MigrationState::Pending | MigrationState::Failed))
+    }
+}
+
+impl fmt::Display for MigrationState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MigrationState::Applied => "applied",
+            MigrationState::Pending => "pending",
+            MigrationState::Changed => "changed",
+            MigrationState::Missing => "missing",
+            MigrationState::Failed => "failed",
+        })
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.migrations.is_empty() {
+            return writeln!(f, "No migrations found");
+        }
+
+        writeln!(
+            f,
+            "{:<16} {:<8} {:<16} {:<25} DESCRIPTION",
+            "VERSION", "STATE", "CHECKSUM", "APPLIED AT"
+        )?;
+
+        for migration in &self.migrations {
+            let applied_at = migration
+                .applied_at
+                .and_then(|at| at.format(&Rfc3339).ok())
+                .unwrap_or_else(|| "-".to_string());
+
+            let version = match migration.kind {
+                MigrationKind::Versioned => migration.version.to_string(),
+                MigrationKind::Repeatable => "R".to_string(),
+            };
+
+            writeln!(
+                f,
+                "{:<16} {:<8} {:<16} {:<25} {}",
+                version,
+                migration.state,
+                short_checksum(&migration.checksum),
+                applied_at,
+                migration.description
+            )?;
+            if let Some(error) = &migration.error {
+                writeln!(f, "{:<16} error: {}", "", error)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Hex encodes the first 8 bytes of a checksum, which is plenty to tell them apart
+pub(crate) fn short_checksum(checksum: &[u8]) -> String {
+    checksum
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
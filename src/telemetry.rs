@@ -0,0 +1,81 @@
+#[cfg(feature = "otel")]
+use crate::report::error_chain;
+#[cfg(feature = "otel")]
+use crate::token::{tokens, Token};
+use tracing::Span;
+
+/// Declares a span with the fields of the OpenTelemetry attributes recorded by
+/// this module
+///
+/// With the `otel` feature, they are filled in with what
+/// [`tracing-opentelemetry`](https://docs.rs/tracing-opentelemetry) turns into
+/// the span name, kind, status and database attributes, so the `run`,
+/// `migration` and `statement` spans are exported like those of any database
+/// client. Without it, they stay empty and aren't shown.
+macro_rules! span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        tracing::info_span!(
+            $name,
+            $($($fields)*,)?
+            otel.name = tracing::field::Empty,
+            otel.kind = tracing::field::Empty,
+            otel.status_code = tracing::field::Empty,
+            otel.status_message = tracing::field::Empty,
+            db.system = tracing::field::Empty,
+            db.namespace = tracing::field::Empty,
+            db.query.text = tracing::field::Empty,
+        )
+    };
+}
+pub(crate) use span;
+
+/// Names `span` and marks it as a client span of the cluster
+#[cfg(feature = "otel")]
+pub(crate) fn describe(span: &Span, name: &str, keyspace: Option<&str>) {
+    span.record("otel.name", name);
+    span.record("otel.kind", "client");
+    span.record("db.system", "cassandra");
+    if let Some(keyspace) = keyspace {
+        span.record("db.namespace", keyspace);
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn describe(_span: &Span, _name: &str, _keyspace: Option<&str>) {}
+
+/// Names the span of `statement` after its operation, e.g. `CREATE TABLE`, and
+/// records its text
+#[cfg(feature = "otel")]
+pub(crate) fn describe_statement(span: &Span, statement: &str) {
+    let operation: Vec<String> = tokens(statement)
+        .iter()
+        .take(2)
+        .map_while(|token| match token {
+            Token::Word(word) => Some(word.to_ascii_uppercase()),
+            _ => None,
+        })
+        .collect();
+    let name = match operation.first().map(String::as_str) {
+        // Only DDL is named by its first two words, e.g. CREATE TABLE
+        Some("CREATE" | "ALTER" | "DROP") => operation.join(" "),
+        Some(keyword) => keyword.to_string(),
+        None => "CQL".to_string(),
+    };
+    describe(span, &name, None);
+    span.record("db.query.text", statement);
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn describe_statement(_span: &Span, _statement: &str) {}
+
+/// Sets the status of `span` to an error if `result` is one
+#[cfg(feature = "otel")]
+pub(crate) fn record_result<T>(span: &Span, result: &crate::Result<T>) {
+    if let Err(err) = result {
+        span.record("otel.status_code", "ERROR");
+        span.record("otel.status_message", error_chain(err));
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn record_result<T>(_span: &Span, _result: &crate::Result<T>) {}
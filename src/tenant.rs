@@ -0,0 +1,157 @@
+use crate::error::{Error, Result};
+use crate::session::Execute;
+use scylla::query::Query;
+use std::collections::BTreeSet;
+
+/// The keyspaces of Scylla and Cassandra themselves
+pub(crate) const SYSTEM_KEYSPACES: &[&str] = &[
+    "system",
+    "system_auth",
+    "system_distributed",
+    "system_distributed_everywhere",
+    "system_replicated_keys",
+    "system_schema",
+    "system_traces",
+    "system_views",
+    "system_virtual_schema",
+];
+
+/// The tenant keyspaces a multi-tenant run migrates, one after the other
+///
+/// Every tenant keyspace gets the same migrations, with `${tenant}` replaced by
+/// its name, and its own history table. See [`Migrator::run_tenants`](crate::Migrator::run_tenants).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tenants {
+    /// These keyspaces, which have to exist
+    Keyspaces(Vec<String>),
+    /// All keyspaces whose name matches a pattern, where `*` stands for any
+    /// number of characters and `?` for a single one, e.g. `customer_*`
+    ///
+    /// The system keyspaces, such as `system_schema`, never match; other
+    /// keyspaces starting with `system` do.
+    Matching(String),
+}
+
+impl Tenants {
+    /// Returns the tenant keyspaces, in the order they are migrated
+    ///
+    /// Listed keyspaces keep their order, matching ones are sorted by name.
+    pub async fn keyspaces(&self, session: &dyn Execute) -> Result<Vec<String>> {
+        let existing = existing_keyspaces(session).await?;
+        match self {
+            Tenants::Keyspaces(keyspaces) => {
+                if let Some(missing) = keyspaces.iter().find(|ks| !existing.contains(*ks)) {
+                    return Err(Error::UnknownTenant(missing.clone()));
+                }
+                Ok(keyspaces.clone())
+            }
+            Tenants::Matching(pattern) => Ok(existing
+                .into_iter()
+                .filter(|ks| !SYSTEM_KEYSPACES.contains(&ks.as_str()) && matches(pattern, ks))
+                .collect()),
+        }
+    }
+}
+
+pub(crate) async fn existing_keyspaces(session: &dyn Execute) -> Result<BTreeSet<String>> {
+    let keyspaces = session
+        .query(
+            Query::new("SELECT keyspace_name FROM system_schema.keyspaces"),
+            &[],
+        )
+        .await?
+        .rows::<(String,)>()?
+        .into_iter()
+        .map(|(name,)| name)
+        .collect();
+    Ok(keyspaces)
+}
+
+/// Matches `name` against a pattern of literal characters, `*` and `?`
+pub(crate) fn matches(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = (pattern.as_bytes(), name.as_bytes());
+    // Position after the last `*` seen and the name position it was tried at
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more character and retry
+                Some((after, tried)) => {
+                    star = Some((after, tried + 1));
+                    p = after;
+                    n = tried + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::Rows;
+    use crate::test::MockSession;
+    use scylla::frame::response::result::{ColumnType, CqlValue};
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("customer_*", "customer_"));
+        assert!(matches("customer_*", "customer_acme"));
+        assert!(!matches("customer_*", "customers"));
+        assert!(matches("customer_?", "customer_1"));
+        assert!(!matches("customer_?", "customer_12"));
+        assert!(matches("*_eu", "customer_eu"));
+        assert!(!matches("*_eu", "customer_us"));
+        assert!(matches("c*_*_eu", "customer_acme_eu"));
+        // A later `*` retries after a false start
+        assert!(matches("*ab*cd", "abxabzcd"));
+        assert!(matches("**", ""));
+        assert!(!matches("a", ""));
+        assert!(!matches("", "a"));
+        assert!(matches("app", "app"));
+    }
+
+    #[tokio::test]
+    async fn leaves_out_the_system_keyspaces_by_name() {
+        let keyspaces = [
+            "system",
+            "system_schema",
+            "system_distributed_everywhere",
+            "systems_inc",
+            "app",
+        ];
+        let rows: Vec<_> = keyspaces
+            .iter()
+            .map(|name| vec![Some(CqlValue::Text(name.to_string()))])
+            .collect();
+        let rows = Rows::new(&[("keyspace_name", ColumnType::Text)], &rows).unwrap();
+        let session = MockSession::new().respond("system_schema.keyspaces", rows);
+        assert_eq!(
+            Tenants::Matching("*".to_string())
+                .keyspaces(&session)
+                .await
+                .unwrap(),
+            ["app", "systems_inc"]
+        );
+        assert_eq!(
+            Tenants::Matching("system*".to_string())
+                .keyspaces(&session)
+                .await
+                .unwrap(),
+            ["systems_inc"]
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! Isolated, fully migrated keyspaces for integration tests
+//!
+//! Every [`ScratchKeyspace`] is a keyspace of its own with a unique name, so
+//! tests can run in parallel against one cluster without seeing each other's
+//! data. The migrations are applied to it with their keyspace renamed, and it
+//! is dropped again when the test is done with it.
+//!
+//! ```no_run
+//! use scylla::SessionBuilder;
+//! use scylla_migrate::test::ScratchKeyspace;
+//!
+//! # async fn create_user() -> Result<(), Box<dyn std::error::Error>> {
+//! let scratch = ScratchKeyspace::builder(SessionBuilder::new().known_node("localhost:9042"))
+//!     .rename_keyspace("app")
+//!     .create("migrations")
+//!     .await?;
+//! let users = format!("{}.users", scratch.keyspace());
+//! scratch
+//!     .session()
+//!     .query_unpaged(format!("INSERT INTO {} (id) VALUES (uuid())", users), &[])
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Unit tests that do not need a cluster can run a [`Migrator`] on a
+//! [`MockSession`] instead.
+
+pub use crate::mock::{MockRequest, MockSession};
+
+use crate::error::{BoxError, Error, Result};
+use crate::history::Replication;
+use crate::schema::ident;
+#[cfg(feature = "testcontainers")]
+use crate::ConnectOptions;
+use crate::{Migrator, MigratorBuilder};
+use scylla::{Session, SessionBuilder};
+use std::thread;
+#[cfg(feature = "testcontainers")]
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// A uniquely named keyspace with the migrations applied, dropped on drop
+///
+/// Dropping connects anew from a separate thread, as the runtime of the
+/// session may be the one blocked in the drop, e.g. that of a
+/// `#[tokio::test]`. Call [`ScratchKeyspace::drop_keyspace`] to drop it on
+/// the session instead and see whether that failed.
+pub struct ScratchKeyspace {
+    session: Session,
+    keyspace: String,
+    /// Connects the session again to drop the keyspace
+    builder: SessionBuilder,
+    dropped: bool,
+}
+
+/// Creates [`ScratchKeyspace`]s, see [`ScratchKeyspace::builder`]
+pub struct ScratchKeyspaceBuilder {
+    session: SessionBuilder,
+    migrator: MigratorBuilder,
+    prefix: String,
+    replication: Replication,
+    renames: Vec<String>,
+    variable: Option<String>,
+}
+
+impl ScratchKeyspace {
+    /// Returns a builder connecting with `session`
+    pub fn builder(session: SessionBuilder) -> ScratchKeyspaceBuilder {
+        ScratchKeyspaceBuilder {
+            session,
+            migrator: Migrator::builder(),
+            prefix: "test".to_string(),
+            replication: Replication::default(),
+            renames: Vec::new(),
+            variable: None,
+        }
+    }
+
+    /// Returns the session connected to the cluster of the keyspace
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Returns the name of the keyspace
+    pub fn keyspace(&self) -> &str {
+        &self.keyspace
+    }
+
+    /// Drops the keyspace now, on the session
+    pub async fn drop_keyspace(mut self) -> Result<()> {
+        self.dropped = true;
+        self.session
+            .query_unpaged(
+                format!("DROP KEYSPACE IF EXISTS {}", ident(&self.keyspace)),
+                &[],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+impl ScratchKeyspaceBuilder {
+    /// Configures the migrator applying the migrations, e.g. to register code
+    /// migrations
+    ///
+    /// Its history table is replaced by one in the scratch keyspace.
+    pub fn migrator(mut self, migrator: MigratorBuilder) -> Self {
+        self.migrator = migrator;
+        self
+    }
+
+    /// Sets the start of the keyspace name, `test` by default
+    ///
+    /// A random suffix of 33 characters follows, and keyspace names are
+    /// limited to 48, so the prefix can have up to 15.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Sets the replication of the keyspace, a replication factor of 1 by default
+    pub fn replication(mut self, replication: Replication) -> Self {
+        self.replication = replication;
+        self
+    }
+
+    /// Rewrites the references to `keyspace` in the migrations to the
+    /// scratch keyspace, see [`MigratorBuilder::rename_keyspace`]
+    pub fn rename_keyspace(mut self, keyspace: &str) -> Self {
+        self.renames.push(keyspace.to_string());
+        self
+    }
+
+    /// Sets the `${name}` placeholder of the migrations to the name of the
+    /// scratch keyspace
+    pub fn keyspace_variable(mut self, name: &str) -> Self {
+        self.variable = Some(name.to_string());
+        self
+    }
+
+    /// Creates the keyspace and applies the migrations of `migrations_src` to it
+    ///
+    /// The keyspace exists before the migrations run, so those creating it
+    /// need `IF NOT EXISTS`. If applying them fails, the keyspace is dropped
+    /// again.
+    pub async fn create(self, migrations_src: &str) -> Result<ScratchKeyspace> {
+        let session = self
+            .session
+            .build()
+            .await
+            .map_err(|err| Error::Connect(Box::new(err)))?;
+        let keyspace = format!("{}_{}", self.prefix, Uuid::new_v4().simple());
+        session
+            .query_unpaged(
+                format!(
+                    "CREATE KEYSPACE {} WITH replication = {}",
+                    ident(&keyspace),
+                    self.replication
+                ),
+                &[],
+            )
+            .await?;
+        info!("Created scratch keyspace {}", keyspace);
+        let scratch = ScratchKeyspace {
+            session,
+            keyspace,
+            builder: self.session,
+            dropped: false,
+        };
+
+        let mut migrator = self
+            .migrator
+            .history_table(&format!("{}.migrations", scratch.keyspace));
+        for from in &self.renames {
+            migrator = migrator.rename_keyspace(from, &scratch.keyspace);
+        }
+        if let Some(name) = &self.variable {
+            migrator = migrator.variable(name, &scratch.keyspace);
+        }
+        migrator
+            .build(&scratch.session, migrations_src)
+            .run()
+            .await?;
+        Ok(scratch)
+    }
+}
+
+impl Drop for ScratchKeyspace {
+    fn drop(&mut self) {
+        if self.dropped {
+            return;
+        }
+
+        let builder = self.builder.clone();
+        let cql = format!("DROP KEYSPACE IF EXISTS {}", ident(&self.keyspace));
+        let dropped = thread::spawn(move || -> Result<(), BoxError> {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(async {
+                let session = builder.build().await?;
+                session.query_unpaged(cql, &[]).await?;
+                Ok(())
+            })
+        })
+        .join();
+        match dropped {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => warn!("Failed to drop scratch keyspace {}: {}", self.keyspace, err),
+            Err(_) => warn!("Failed to drop scratch keyspace {}", self.keyspace),
+        }
+    }
+}
+
+/// The image [`ScyllaContainer`] runs unless told otherwise
+#[cfg(feature = "testcontainers")]
+pub const SCYLLA_IMAGE: &str = "scylladb/scylla:6.2";
+
+/// A throwaway ScyllaDB node in a docker container, with the migrations applied
+///
+/// The container is run with the `docker` command line tool, which has to be
+/// installed, and removed again on drop. Starting one takes a while, so tests
+/// sharing a container can give each test a [`ScratchKeyspace`] on it through
+/// [`ScyllaContainer::node`].
+///
+/// ```no_run
+/// use scylla_migrate::test::ScyllaContainer;
+///
+/// # async fn create_user() -> Result<(), Box<dyn std::error::Error>> {
+/// let scylla = ScyllaContainer::start("migrations").await?;
+/// scylla
+///     .session()
+///     .query_unpaged("INSERT INTO app.users (id) VALUES (uuid())", &[])
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "testcontainers")]
+pub struct ScyllaContainer {
+    // Declared first to be closed before the container is removed
+    session: Session,
+    node: String,
+    container: Container,
+}
+
+/// Starts [`ScyllaContainer`]s, see [`ScyllaContainer::builder`]
+#[cfg(feature = "testcontainers")]
+pub struct ScyllaContainerBuilder {
+    image: String,
+    migrator: MigratorBuilder,
+    startup_timeout: Duration,
+}
+
+#[cfg(feature = "testcontainers")]
+impl ScyllaContainer {
+    /// Returns a builder for a container of [`SCYLLA_IMAGE`]
+    pub fn builder() -> ScyllaContainerBuilder {
+        ScyllaContainerBuilder {
+            image: SCYLLA_IMAGE.to_string(),
+            migrator: Migrator::builder(),
+            startup_timeout: Duration::from_secs(120),
+        }
+    }
+
+    /// Starts a container of [`SCYLLA_IMAGE`] and applies the migrations of
+    /// `migrations_src` to it
+    pub async fn start(migrations_src: &str) -> Result<Self> {
+        Self::builder().start(migrations_src).await
+    }
+
+    /// Returns the session connected to the node
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Returns the address the node accepts CQL connections at, e.g. `127.0.0.1:32768`
+    pub fn node(&self) -> &str {
+        &self.node
+    }
+
+    /// Returns the id of the docker container
+    pub fn id(&self) -> &str {
+        &self.container.0
+    }
+}
+
+#[cfg(feature = "testcontainers")]
+impl ScyllaContainerBuilder {
+    /// Sets the image to run, e.g. `scylladb/scylla:5.4`
+    pub fn image(mut self, image: &str) -> Self {
+        self.image = image.to_string();
+        self
+    }
+
+    /// Configures the migrator applying the migrations
+    pub fn migrator(mut self, migrator: MigratorBuilder) -> Self {
+        self.migrator = migrator;
+        self
+    }
+
+    /// Sets how long to wait for the node to accept connections, 2 minutes by default
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    /// Starts the container, waits for the node and applies the migrations of
+    /// `migrations_src`
+    ///
+    /// The node runs in developer mode on a single core, and its CQL port is
+    /// published on a random port of the loopback interface. If anything
+    /// fails, the container is removed again.
+    pub async fn start(self, migrations_src: &str) -> Result<ScyllaContainer> {
+        let id = docker(&[
+            "run",
+            "--detach",
+            "--publish",
+            "127.0.0.1::9042",
+            &self.image,
+            "--smp",
+            "1",
+            "--memory",
+            "750M",
+            "--overprovisioned",
+            "1",
+            "--developer-mode",
+            "1",
+        ])
+        .await?;
+        let container = Container(id);
+        info!("Started ScyllaDB container {}", container.0);
+
+        let ports = docker(&["port", &container.0, "9042/tcp"]).await?;
+        let Some(node) = ports.lines().next().map(str::to_string) else {
+            return Err(Error::Container(format!(
+                "container {} publishes no CQL port",
+                container.0
+            )));
+        };
+        let session = ConnectOptions::new(&node)
+            .wait_for(self.startup_timeout)
+            .connect()
+            .await?;
+        self.migrator.build(&session, migrations_src).run().await?;
+
+        Ok(ScyllaContainer {
+            session,
+            node,
+            container,
+        })
+    }
+}
+
+/// A docker container, removed on drop
+#[cfg(feature = "testcontainers")]
+struct Container(String);
+
+#[cfg(feature = "testcontainers")]
+impl Drop for Container {
+    fn drop(&mut self) {
+        let removed = std::process::Command::new("docker")
+            .args(["rm", "--force", &self.0])
+            .output();
+        if !removed.is_ok_and(|output| output.status.success()) {
+            warn!("Failed to remove ScyllaDB container {}", self.0);
+        }
+    }
+}
+
+/// Runs the docker command line tool, returning its trimmed output
+#[cfg(feature = "testcontainers")]
+async fn docker(args: &[&str]) -> Result<String> {
+    let output = tokio::process::Command::new("docker")
+        .args(args)
+        .output()
+        .await
+        .map_err(|err| Error::Container(format!("failed to run docker: {}", err)))?;
+    if !output.status.success() {
+        return Err(Error::Container(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
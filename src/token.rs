@@ -0,0 +1,142 @@
+use crate::migration::{closing_quote, find};
+
+/// A lexical token of a CQL statement, as far as static checks need them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    /// A keyword, unquoted identifier or number
+    Word(&'a str),
+    /// A double-quoted identifier, without its quotes
+    Identifier(&'a str),
+    /// A string literal or `$$`-delimited body, without its delimiters
+    Literal(&'a str),
+    /// Any other character, such as `(`, `,` or `*`
+    Symbol(char),
+}
+
+impl Token<'_> {
+    /// Returns true for the word `keyword`, ignoring case
+    pub fn is(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+/// Splits a statement into tokens, dropping whitespace and comments
+pub(crate) fn tokens(statement: &str) -> Vec<Token<'_>> {
+    tokenize(statement).0
+}
+
+/// Like [`tokens`], also returning what is left open at the end of the
+/// statement, e.g. a string literal that is never closed
+pub(crate) fn tokenize(statement: &str) -> (Vec<Token<'_>>, Option<&'static str>) {
+    let bytes = statement.as_bytes();
+    let mut tokens = Vec::new();
+    let mut unclosed = None;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'-', Some(b'-')) | (b'/', Some(b'/')) => {
+                i = find(bytes, i + 2, b"\n").map_or(bytes.len(), |end| end + 1);
+            }
+            (b'/', Some(b'*')) => {
+                i = match find(bytes, i + 2, b"*/") {
+                    Some(end) => end + 2,
+                    None => {
+                        unclosed = Some("comment is never closed");
+                        bytes.len()
+                    }
+                };
+            }
+            (b'$', Some(b'$')) => {
+                let end = find(bytes, i + 2, b"$$").unwrap_or_else(|| {
+                    unclosed = Some("$$ function body is never closed");
+                    bytes.len()
+                });
+                tokens.push(Token::Literal(&statement[i + 2..end]));
+                i = (end + 2).min(bytes.len());
+            }
+            (quote @ (b'\'' | b'"'), _) => {
+                let end = closing_quote(bytes, i, quote).unwrap_or_else(|| {
+                    unclosed = Some(match quote {
+                        b'\'' => "string literal is never closed",
+                        _ => "quoted identifier is never closed",
+                    });
+                    bytes.len()
+                });
+                let inner = &statement[i + 1..end.saturating_sub(1).max(i + 1)];
+                tokens.push(match quote {
+                    b'\'' => Token::Literal(inner),
+                    _ => Token::Identifier(inner),
+                });
+                i = end;
+            }
+            (c, _) if c.is_ascii_alphanumeric() || c == b'_' => {
+                let start = i;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                tokens.push(Token::Word(&statement[start..i]));
+            }
+            (c, _) if c.is_ascii_whitespace() => i += 1,
+            _ => {
+                let c = statement[i..].chars().next().unwrap_or_default();
+                tokens.push(Token::Symbol(c));
+                i += c.len_utf8();
+            }
+        }
+    }
+
+    (tokens, unclosed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_statements_into_tokens() {
+        assert_eq!(
+            tokens("SELECT \"Name\", 'it''s' FROM app.t_1 -- trailing\nWHERE x >= 10 /* c */"),
+            [
+                Token::Word("SELECT"),
+                Token::Identifier("Name"),
+                Token::Symbol(','),
+                Token::Literal("it''s"),
+                Token::Word("FROM"),
+                Token::Word("app"),
+                Token::Symbol('.'),
+                Token::Word("t_1"),
+                Token::Word("WHERE"),
+                Token::Word("x"),
+                Token::Symbol('>'),
+                Token::Symbol('='),
+                Token::Word("10"),
+            ]
+        );
+        assert_eq!(
+            tokens("AS $$ return 'a'; $$"),
+            [Token::Word("AS"), Token::Literal(" return 'a'; ")]
+        );
+        assert!(tokens("SELECT").first().unwrap().is("select"));
+    }
+
+    #[test]
+    fn reports_what_is_left_open() {
+        assert_eq!(tokenize("SELECT 'a''b' FROM t /* c */").1, None);
+        assert_eq!(
+            tokenize("SELECT 'a").1,
+            Some("string literal is never closed")
+        );
+        assert_eq!(
+            tokenize("SELECT \"a").1,
+            Some("quoted identifier is never closed")
+        );
+        assert_eq!(tokenize("SELECT 1 /* a").1, Some("comment is never closed"));
+        assert_eq!(
+            tokenize("CREATE FUNCTION f() AS $$ return 1;").1,
+            Some("$$ function body is never closed")
+        );
+        // Comments run to the end of the line, quotes inside them don't count
+        assert_eq!(tokenize("SELECT 1 -- it's").1, None);
+    }
+}
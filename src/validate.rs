@@ -0,0 +1,90 @@
+use crate::migration::MigrationKind;
+use crate::status::{MigrationState, Status};
+use std::fmt;
+
+/// Discrepancies between the applied history and the local migrations
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Validation {
+    pub issues: Vec<ValidationIssue>,
+}
+
+/// An applied versioned migration that no longer matches the local files
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ValidationIssue {
+    pub version: i64,
+    pub description: String,
+    pub discrepancy: Discrepancy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Discrepancy {
+    /// The local migration was modified after it was applied
+    ChecksumMismatch,
+    /// The applied migration is not present locally
+    MissingLocally,
+}
+
+impl Validation {
+    /// Collects the changed and missing versioned migrations of `status`
+    ///
+    /// Repeatable migrations are expected to change and are not checked.
+    pub(crate) fn from_status(status: &Status) -> Self {
+        let issues = status
+            .migrations
+            .iter()
+            .filter(|m| m.kind == MigrationKind::Versioned)
+            .filter_map(|m| {
+                let discrepancy = match m.state {
+                    MigrationState::Changed => Discrepancy::ChecksumMismatch,
+                    MigrationState::Missing => Discrepancy::MissingLocally,
+                    MigrationState::Applied | MigrationState::Pending | MigrationState::Failed => {
+                        return None
+                    }
+                };
+                Some(ValidationIssue {
+                    version: m.version,
+                    description: m.description.clone(),
+                    discrepancy,
+                })
+            })
+            .collect();
+
+        Self { issues }
+    }
+
+    /// Returns true when every applied migration matches its local file
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Discrepancy::ChecksumMismatch => "modified after it was applied",
+            Discrepancy::MissingLocally => "applied but not found locally",
+        })
+    }
+}
+
+impl fmt::Display for Validation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_valid() {
+            return writeln!(f, "All applied migrations match the local migrations");
+        }
+
+        for issue in &self.issues {
+            writeln!(
+                f,
+                "{}/migrate {}: {}",
+                issue.version, issue.description, issue.discrepancy
+            )?;
+        }
+
+        Ok(())
+    }
+}
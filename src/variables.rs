@@ -0,0 +1,68 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env;
+
+/// Values of the `${NAME}` placeholders in migration scripts
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Variables {
+    pub values: BTreeMap<String, String>,
+    /// Whether placeholders without a value fall back to environment variables
+    pub from_env: bool,
+}
+
+impl Variables {
+    fn get(&self, name: &str) -> Option<Cow<'_, str>> {
+        if let Some(value) = self.values.get(name) {
+            return Some(Cow::Borrowed(value));
+        }
+        if self.from_env {
+            return env::var(name).ok().map(Cow::Owned);
+        }
+        None
+    }
+
+    /// Replaces the placeholders in `cql`, returning the name of the first one
+    /// that has no value
+    ///
+    /// Names start with a letter or underscore, followed by letters, digits,
+    /// underscores or dots. `$${NAME}` is left in place as `${NAME}`.
+    pub fn substitute<'a>(&self, cql: &'a str) -> std::result::Result<Cow<'a, str>, String> {
+        if !cql.contains("${") {
+            return Ok(Cow::Borrowed(cql));
+        }
+
+        let mut out = String::with_capacity(cql.len());
+        let mut rest = cql;
+        while let Some(start) = rest.find("${") {
+            let Some(len) = placeholder_len(&rest[start + 2..]) else {
+                out.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                continue;
+            };
+            let name = &rest[start + 2..start + 2 + len];
+            let end = start + 2 + len + 1;
+
+            if rest[..start].ends_with('$') {
+                out.push_str(&rest[..start - 1]);
+                out.push_str(&rest[start..end]);
+            } else {
+                out.push_str(&rest[..start]);
+                out.push_str(&self.get(name).ok_or_else(|| name.to_string())?);
+            }
+            rest = &rest[end..];
+        }
+        out.push_str(rest);
+
+        Ok(Cow::Owned(out))
+    }
+}
+
+/// Returns the length of the name if `text` starts with `NAME}`
+fn placeholder_len(text: &str) -> Option<usize> {
+    let len = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+        .filter(|&len| text[len..].starts_with('}'))?;
+    text[..len]
+        .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        .then_some(len)
+}
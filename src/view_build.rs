@@ -0,0 +1,193 @@
+use crate::error::{Error, Result};
+use crate::server::{Product, Server};
+use crate::token::{tokens, Token};
+use scylla::Session;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio::time;
+use tracing::{info, warn};
+
+/// How long to wait between looking at the progress of a build
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A materialized view or secondary index built in the background after it
+/// was created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Build {
+    pub kind: BuildKind,
+    pub keyspace: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BuildKind {
+    View,
+    Index,
+}
+
+impl Build {
+    /// Returns the view or index `statement` creates, if any
+    ///
+    /// Unqualified names are taken to be in `keyspace`, the session's keyspace.
+    /// Indexes without a name get the one the database gives them,
+    /// `<table>_<column>_idx`.
+    pub fn created_by(statement: &str, keyspace: Option<&str>) -> Option<Self> {
+        let tokens = tokens(statement);
+        let word = |i: usize, keyword: &str| tokens.get(i).is_some_and(|t| t.is(keyword));
+        if !word(0, "CREATE") {
+            return None;
+        }
+
+        let (kind, mut i) = if word(1, "MATERIALIZED") && word(2, "VIEW") {
+            (BuildKind::View, 3)
+        } else if word(1, "INDEX") {
+            (BuildKind::Index, 2)
+        } else if word(1, "CUSTOM") && word(2, "INDEX") {
+            (BuildKind::Index, 3)
+        } else {
+            return None;
+        };
+        if word(i, "IF") && word(i + 1, "NOT") && word(i + 2, "EXISTS") {
+            i += 3;
+        }
+
+        let qualified = |i: usize| {
+            let first = name(tokens.get(i)?)?;
+            match (tokens.get(i + 1), tokens.get(i + 2).and_then(name)) {
+                (Some(Token::Symbol('.')), Some(second)) => Some((Some(first), second, i + 3)),
+                _ => Some((None, first, i + 1)),
+            }
+        };
+        let (keyspace_of, name) = match kind {
+            BuildKind::View => {
+                let (keyspace, view, _) = qualified(i)?;
+                (keyspace, view)
+            }
+            // An index named like the keyword ON has to be quoted
+            BuildKind::Index if word(i, "ON") => {
+                let (keyspace, table, after) = qualified(i + 1)?;
+                let column = default_index_column(&tokens[after..])?;
+                (keyspace, format!("{}_{}_idx", table, column))
+            }
+            BuildKind::Index => {
+                let index = name(tokens.get(i)?)?;
+                let (keyspace, _, _) = qualified(i + 2)?;
+                (keyspace, index)
+            }
+        };
+        Some(Self {
+            kind,
+            keyspace: keyspace_of.or_else(|| keyspace.map(str::to_string))?,
+            name,
+        })
+    }
+
+    /// Waits until the view or index is built on every node, for up to `timeout`
+    pub async fn wait(&self, session: &Session, timeout: Duration) -> Result<()> {
+        let product = Server::detect(session).await?.product;
+        let started = Instant::now();
+        info!("Waiting for {} to be built", self);
+        while !self.is_built(session, product).await? {
+            if started.elapsed() >= timeout {
+                return Err(Error::BuildTimeout {
+                    build: self.to_string(),
+                    timeout,
+                });
+            }
+            time::sleep(POLL_INTERVAL).await;
+        }
+        info!(
+            duration_ms = started.elapsed().as_millis() as u64,
+            "Built {}", self
+        );
+        Ok(())
+    }
+
+    async fn is_built(&self, session: &Session, product: Product) -> Result<bool> {
+        // Cassandra only tracks on each node which of its indexes are built
+        if self.kind == BuildKind::Index && product == Product::Cassandra {
+            let rows = session
+                .query_unpaged(
+                    r#"SELECT index_name FROM system."IndexInfo" WHERE table_name = ? AND index_name = ?"#,
+                    (&self.keyspace, &self.name),
+                )
+                .await?
+                .into_rows_result()?;
+            return Ok(rows.rows_num() > 0);
+        }
+
+        // ScyllaDB builds an index as the view <index>_index
+        let view = match self.kind {
+            BuildKind::View => self.name.clone(),
+            BuildKind::Index => format!("{}_index", self.name),
+        };
+        let query_rows = session
+            .query_unpaged(
+                "SELECT status FROM system_distributed.view_build_status WHERE keyspace_name = ? AND view_name = ?",
+                (&self.keyspace, &view),
+            )
+            .await?
+            .into_rows_result()?;
+        let mut nodes = 0;
+        for row in query_rows.rows()? {
+            let (status,): (Option<String>,) = row?;
+            if status.as_deref() != Some("SUCCESS") {
+                return Ok(false);
+            }
+            nodes += 1;
+        }
+        Ok(nodes >= session.get_cluster_data().get_nodes_info().len())
+    }
+}
+
+/// Waits for the view or index `statement` creates, if any, see [`Build::wait`]
+pub(crate) async fn wait_for(session: &Session, statement: &str, timeout: Duration) -> Result<()> {
+    let keyspace = session.get_keyspace();
+    match Build::created_by(statement, keyspace.as_deref().map(String::as_str)) {
+        Some(build) => build.wait(session, timeout).await,
+        None if creates_build(statement) => {
+            warn!(
+                "Not waiting for the build of an unqualified view or index without a session keyspace: {}",
+                statement
+            );
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Returns true if `statement` creates a view or index, whether or not its
+/// name could be told
+fn creates_build(statement: &str) -> bool {
+    let tokens = tokens(statement);
+    tokens.first().is_some_and(|t| t.is("CREATE"))
+        && tokens
+            .iter()
+            .take(3)
+            .any(|t| t.is("MATERIALIZED") || t.is("INDEX"))
+}
+
+/// Returns the name of an identifier as the database stores it
+fn name(token: &Token<'_>) -> Option<String> {
+    match token {
+        Token::Word(word) => Some(word.to_ascii_lowercase()),
+        Token::Identifier(identifier) => Some(identifier.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns the column of `(column)`, `(keys(column))` and the like
+fn default_index_column(tokens: &[Token<'_>]) -> Option<String> {
+    let close = tokens.iter().position(|t| *t == Token::Symbol(')'))?;
+    tokens[..close].iter().rev().find_map(name)
+}
+
+impl fmt::Display for Build {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            BuildKind::View => "materialized view",
+            BuildKind::Index => "index",
+        };
+        write!(f, "{} {}.{}", kind, self.keyspace, self.name)
+    }
+}